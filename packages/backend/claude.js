@@ -116,14 +116,37 @@ When detect_system shows BrainDrive is already installed (conda_installed=true,
 ## Tool Behavior Notes
 - \`install_conda\`: Downloads and installs Miniconda to ~/BrainDrive/miniconda3 (isolated installation). Returns success with \`already_installed: true\` if the isolated conda is already present. Git and Node are installed via conda env, not separately.
 - \`start_braindrive\`: Automatically finds available ports if defaults are taken. Returns success if already running.
-- \`clone_repo\`: Returns success with \`already_exists: true\` if repo exists. Uses git from the conda environment.
+- \`clone_repo\`: Returns success with \`already_exists: true\` if repo exists. Uses git from the conda environment. Pass \`ref\` only when the user asks for a specific branch, tag or commit; a private repository's token is added automatically, so never ask the user for one in chat.
 - \`create_conda_env\`: Creates env with Python 3.11, nodejs, and git from conda-forge. Returns success with \`already_exists: true\` if env exists. Use force_recreate=true if npm/node is missing.
 - \`install_all_deps\`: Runs backend and frontend dependency installation IN PARALLEL. Returns detailed results for both. Preferred over separate install_backend_deps + install_frontend_deps calls. Uses npm from the conda environment.
 - \`setup_env_file\`: Returns success with \`already_exists: true\` if .env exists.
+- \`run_install_plan\`: Runs a list of the tools above in one call, skipping steps that are already done. With no steps it runs the whole standard install; explain what it will do and get the user's go-ahead first. Its \`steps\` report says which steps completed or were skipped. It resumes by default: install steps a previous run finished with the same arguments are skipped, so after an interrupted install just run it again. Start steps always run.
+- \`verify_installation\`: Use when an existing install fails to start or seems broken. Each failed check names a \`repair\` action; tell the user what is wrong in plain words before repairing.
+- \`repair_installation\`: Runs the suggested repairs and verifies again. Pass \`checks\` to limit it to specific problems.
+- \`uninstall_braindrive\`: Only when the user asks to remove BrainDrive. Call it with \`dry_run: true\` first, show the user what will be removed and the space freed, and ask whether to keep their data and models. Call it again with \`confirmed: true\` only after they approve.
+- \`update_braindrive\`: Only when the user asks to update BrainDrive. It restarts BrainDrive, so tell them first. Returns \`already_up_to_date: true\` when there is nothing new; otherwise \`reinstalled\` says which dependencies were reinstalled.
+- \`build_offline_bundle\` / \`install_offline_bundle\`: Only when the user needs to install on a machine without internet access. Build the bundle on a connected machine with the same OS and architecture, then run \`install_offline_bundle\` on the offline machine instead of the standard install, followed by \`start_braindrive\`. Bundled models only work with an Ollama the user installs separately.
+- \`install_conda\` and \`install_git\` reuse an installer downloaded before (after checking its SHA-256), so retrying them is cheap. \`clear_download_cache\` deletes those copies; use it only when the user asks to free disk space.
+- \`configure_network\`: When downloads, cloning or dependency installs fail with proxy, certificate or TLS errors, call it with no arguments to see the proxy and CA settings in effect, then ask the user for their proxy address and the path of their company's CA certificate file and save them. The settings apply to every later download and command, so retry the failed step afterwards. Proxy passwords are masked in its results.
+- \`configure_mirrors\`: When downloads from repo.anaconda.com, conda-forge, PyPI or npm are slow or blocked, ask the user which mirrors they use and save them. Each list is tried in order before the public source. \`install_conda\`, \`create_conda_env\` and the dependency installs report the mirror that served them in \`mirror\` (\`default\` for the public source).
+- \`set_download_limit\`: When the user says the install is slowing down their connection (a video call, other people on the network), offer to cap downloads and ask how much bandwidth to leave them. The cap applies at once to running downloads and is kept for later installs; 0 removes it. Ollama pulls can't be capped, and their results say \`download_limit_applied: false\` when a cap is set.
+- \`get_install_state\`: Shows how each install step last ended. A step with status \`interrupted\` was cut off when the installer closed; rerun it (or \`run_install_plan\`) rather than assuming it finished.
 - \`install_ollama\`: Starts Ollama if installed. If missing, returns \`download_url\` and \`instructions\` for manual install. After the user finishes, call \`install_ollama\` again to detect and start it.
-- \`pull_ollama_model\`: Requires explicit user confirmation; include \`confirmed: true\` only after the user approves.
+- \`pull_ollama_model\`: Requires explicit user confirmation; include \`confirmed: true\` only after the user approves. If the user asked for a download cap, tell them the model pull is not held to it before asking.
 
 ## Error Recovery
+Failed tool results include \`error\` (a message) and \`error_details.kind\`, which tells you what to do next:
+- \`network\` or \`timeout\`: Transient - tell the user, then retry once
+- \`not_installed\`: A prerequisite is missing (\`component\` says which) - install it first, then retry
+- \`needs_manual_action\`: Only the user can fix this - show them \`instructions\` / \`download_url\` and wait for them
+- \`process_failed\`: A command failed - read \`stderr_tail\` to diagnose before retrying
+- \`partial_failure\`: Some parts worked - only redo the parts listed in \`failed\`
+- \`dependency_failed\`: An install plan step was not run because the step named in \`dependency\` failed - fix that one first
+- \`checksum_mismatch\`: A downloaded installer did not match its published SHA-256 and was deleted without running - retry once; if it fails again, tell the user the download may have been tampered with and stop
+- \`rolled_back\`: An update failed and the previous version (\`restored_commit\`) is running again - explain \`cause\` to the user rather than retrying
+- \`cancelled\`: The user stopped it - do not retry unless they ask
+- \`path_rejected\`, \`invalid_argument\`, \`port_unavailable\`: Fix the input rather than retrying as-is
+
 - If conda not installed: Use \`install_conda\` to automatically install Miniconda to ~/BrainDrive/miniconda3 (no user action needed!)
 - If npm/node not found after create_conda_env: The env may have been created without nodejs. Use \`create_conda_env\` with force_recreate=true to recreate it properly.
 - If start_braindrive fails: Check the error message - it includes log paths for debugging
@@ -265,7 +288,11 @@ export class ClaudeClient {
         input: tool.input,
       });
 
-      const result = await this._executeTool(tool);
+      let result = await this._executeTool(tool);
+      // Keep Claude from immediately retrying something the user stopped
+      if (result && result.status === 'cancelled') {
+        result = { ...result, message: 'The user cancelled this operation. Do not retry it unless they ask.' };
+      }
       toolResults.push({
         type: 'tool_result',
         tool_use_id: tool.id,
@@ -315,7 +342,8 @@ export class ClaudeClient {
             return { error: 'Bootstrapper not connected' };
           }
           // Miniconda installation can take several minutes for download + install
-          const installCondaResult = await this.hub.callBootstrapperTool('install_conda', {}, 600000);
+          const installCondaPayload = input.sha256 ? { sha256: input.sha256 } : {};
+          const installCondaResult = await this.hub.callBootstrapperTool('install_conda', installCondaPayload, 600000);
           return installCondaResult.data || installCondaResult;
         }
 
@@ -324,7 +352,8 @@ export class ClaudeClient {
             return { error: 'Bootstrapper not connected' };
           }
           // Git installation can take several minutes (especially on macOS where user needs to click Install)
-          const installGitResult = await this.hub.callBootstrapperTool('install_git', {}, 660000);
+          const installGitPayload = input.sha256 ? { sha256: input.sha256 } : {};
+          const installGitResult = await this.hub.callBootstrapperTool('install_git', installGitPayload, 660000);
           return installGitResult.data || installGitResult;
         }
 
@@ -363,12 +392,13 @@ export class ClaudeClient {
           }
           const ollamaResult = await this.hub.callBootstrapperTool('install_ollama', {}, 600000);
           const payload = ollamaResult.data || ollamaResult;
-          if (payload && payload.needs_manual_install) {
+          const details = ollamaResult.error_details;
+          if (details && details.kind === 'needs_manual_action') {
             this.hub.sendToBrowser({
               type: 'ollama_install_required',
-              download_url: payload.download_url,
-              instructions: payload.instructions,
-              message: payload.message,
+              download_url: details.download_url,
+              instructions: details.instructions,
+              message: ollamaResult.error,
             });
           } else if (payload && payload.success) {
             this.hub.sendToBrowser({ type: 'ollama_install_cleared' });
@@ -445,6 +475,19 @@ export class ClaudeClient {
           if (input.target_path) {
             payload.target_path = input.target_path;
           }
+          if (input.ref) {
+            payload.ref = input.ref;
+          }
+          if (Number.isInteger(input.depth) && input.depth >= 0) {
+            payload.depth = input.depth;
+          }
+          if (input.submodules) {
+            payload.submodules = true;
+          }
+          // Comes from the backend's environment so it never passes through the conversation
+          if (process.env.BRAINDRIVE_GIT_TOKEN) {
+            payload.token = process.env.BRAINDRIVE_GIT_TOKEN;
+          }
           const cloneResult = await this.hub.callBootstrapperTool('clone_repo', payload, 300000);
           return cloneResult.data || cloneResult;
         }
@@ -562,6 +605,206 @@ export class ClaudeClient {
           const restartResult = await this.hub.callBootstrapperTool('restart_braindrive', {}, 60000);
           return restartResult.data || restartResult;
 
+        case 'run_install_plan': {
+          if (!this.hub.isBootstrapperConnected()) {
+            return { error: 'Bootstrapper not connected' };
+          }
+          const payload = {};
+          if (Array.isArray(input.steps) && input.steps.length > 0) {
+            payload.steps = input.steps;
+            if (process.env.BRAINDRIVE_GIT_TOKEN) {
+              payload.steps = payload.steps.map((step) => (step.tool === 'clone_repo'
+                ? { ...step, token: process.env.BRAINDRIVE_GIT_TOKEN }
+                : step));
+            }
+          }
+          if (typeof input.resume === 'boolean') {
+            payload.resume = input.resume;
+          }
+          // A full install downloads Miniconda and every dependency
+          const planResult = await this.hub.callBootstrapperTool('run_install_plan', payload, 1800000);
+          const startedBraindrive = planResult.success && (planResult.data?.steps || [])
+            .some((step) => step.tool === 'start_braindrive');
+          if (startedBraindrive) {
+            this.session.setBraindriveStatus('running');
+            this.session.setInstallState('completed');
+          }
+          return planResult.data || planResult;
+        }
+
+        case 'get_install_state': {
+          if (!this.hub.isBootstrapperConnected()) {
+            return { error: 'Bootstrapper not connected' };
+          }
+          const stateResult = await this.hub.callBootstrapperTool('get_install_state', {}, 30000);
+          return stateResult.data || stateResult;
+        }
+
+        case 'verify_installation': {
+          if (!this.hub.isBootstrapperConnected()) {
+            return { error: 'Bootstrapper not connected' };
+          }
+          const verifyResult = await this.hub.callBootstrapperTool('verify_installation', {}, 120000);
+          return verifyResult.data || verifyResult;
+        }
+
+        case 'repair_installation': {
+          if (!this.hub.isBootstrapperConnected()) {
+            return { error: 'Bootstrapper not connected' };
+          }
+          const payload = {};
+          if (Array.isArray(input.checks) && input.checks.length > 0) {
+            payload.checks = input.checks;
+          }
+          if (input.sha256) {
+            payload.sha256 = input.sha256;
+          }
+          // May reinstall Miniconda and every dependency
+          const repairResult = await this.hub.callBootstrapperTool('repair_installation', payload, 1800000);
+          return repairResult.data || repairResult;
+        }
+
+        case 'uninstall_braindrive': {
+          if (!this.hub.isBootstrapperConnected()) {
+            return { error: 'Bootstrapper not connected' };
+          }
+          const dryRun = Boolean(input.dry_run);
+          if (!dryRun && !input.confirmed) {
+            return { error: 'User confirmation required before uninstalling. Run with dry_run=true and show the user what will be removed first.' };
+          }
+          const uninstallPayload = {
+            dry_run: dryRun,
+            keep_user_data: Boolean(input.keep_user_data),
+            keep_models: Boolean(input.keep_models),
+          };
+          if (input.repo_path) {
+            uninstallPayload.repo_path = input.repo_path;
+          }
+          const uninstallResult = await this.hub.callBootstrapperTool('uninstall_braindrive', uninstallPayload, 600000);
+          if (!dryRun && uninstallResult.success) {
+            this.session.setBraindriveStatus('stopped');
+            this.session.setInstallState('not_started');
+          }
+          return uninstallResult.data || uninstallResult;
+        }
+
+        case 'update_braindrive': {
+          if (!this.hub.isBootstrapperConnected()) {
+            return { error: 'Bootstrapper not connected' };
+          }
+          const payload = {};
+          if (input.tag) {
+            payload.tag = input.tag;
+          }
+          // May recreate the conda environment and reinstall every dependency
+          const updateResult = await this.hub.callBootstrapperTool('update_braindrive', payload, 1800000);
+          if (updateResult.success) {
+            this.session.setBraindriveStatus('running');
+          }
+          return updateResult.data || updateResult;
+        }
+
+        case 'build_offline_bundle': {
+          if (!this.hub.isBootstrapperConnected()) {
+            return { error: 'Bootstrapper not connected' };
+          }
+          if (!input.output_path) {
+            return { error: 'output_path is required' };
+          }
+          const payload = { output_path: input.output_path };
+          if (input.ref) {
+            payload.ref = input.ref;
+          }
+          if (Array.isArray(input.models) && input.models.length > 0) {
+            payload.models = input.models;
+          }
+          if (input.tarball) {
+            payload.tarball = true;
+          }
+          if (input.sha256) {
+            payload.sha256 = input.sha256;
+          }
+          // Comes from the backend's environment so it never passes through the conversation
+          if (process.env.BRAINDRIVE_GIT_TOKEN) {
+            payload.token = process.env.BRAINDRIVE_GIT_TOKEN;
+          }
+          // Downloads everything the install would, and models can be several GB
+          const bundleResult = await this.hub.callBootstrapperTool('build_offline_bundle', payload, 3600000);
+          return bundleResult.data || bundleResult;
+        }
+
+        case 'install_offline_bundle': {
+          if (!this.hub.isBootstrapperConnected()) {
+            return { error: 'Bootstrapper not connected' };
+          }
+          if (!input.bundle_path) {
+            return { error: 'bundle_path is required' };
+          }
+          const offlineResult = await this.hub.callBootstrapperTool(
+            'install_offline_bundle',
+            { bundle_path: input.bundle_path },
+            1800000
+          );
+          if (offlineResult.success) {
+            this.session.setInstallState('completed');
+          }
+          return offlineResult.data || offlineResult;
+        }
+
+        case 'clear_download_cache': {
+          if (!this.hub.isBootstrapperConnected()) {
+            return { error: 'Bootstrapper not connected' };
+          }
+          const clearResult = await this.hub.callBootstrapperTool('clear_download_cache', {}, 60000);
+          return clearResult.data || clearResult;
+        }
+
+        case 'configure_network': {
+          if (!this.hub.isBootstrapperConnected()) {
+            return { error: 'Bootstrapper not connected' };
+          }
+          const payload = {};
+          for (const field of ['http_proxy', 'https_proxy', 'no_proxy', 'ca_bundle']) {
+            if (typeof input[field] === 'string') {
+              payload[field] = input[field];
+            }
+          }
+          if (input.clear) {
+            payload.clear = true;
+          }
+          const networkResult = await this.hub.callBootstrapperTool('configure_network', payload, 30000);
+          return networkResult.data || networkResult;
+        }
+
+        case 'configure_mirrors': {
+          if (!this.hub.isBootstrapperConnected()) {
+            return { error: 'Bootstrapper not connected' };
+          }
+          const payload = {};
+          for (const ecosystem of ['miniconda', 'conda', 'pypi', 'npm']) {
+            if (Array.isArray(input[ecosystem])) {
+              payload[ecosystem] = input[ecosystem];
+            }
+          }
+          if (input.clear) {
+            payload.clear = true;
+          }
+          const mirrorsResult = await this.hub.callBootstrapperTool('configure_mirrors', payload, 30000);
+          return mirrorsResult.data || mirrorsResult;
+        }
+
+        case 'set_download_limit': {
+          if (!this.hub.isBootstrapperConnected()) {
+            return { error: 'Bootstrapper not connected' };
+          }
+          const payload = {};
+          if (typeof input.limit_mbps === 'number') {
+            payload.limit_mbps = input.limit_mbps;
+          }
+          const limitResult = await this.hub.callBootstrapperTool('set_download_limit', payload, 30000);
+          return limitResult.data || limitResult;
+        }
+
         default:
           return { error: `Unknown tool: ${name}` };
       }
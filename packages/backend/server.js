@@ -38,6 +38,7 @@ app.get('/health', (req, res) => {
     status: 'ok',
     session: session.getStatus(),
     bootstrapperConnected: hub.isBootstrapperConnected(),
+    bootstrapper: hub.bootstrapperInfo,
   });
 });
 
@@ -57,22 +58,34 @@ wss.on('connection', (ws) => {
         console.log('Session reset for new browser connection');
 
         hub.setBrowserSocket(ws);
-        ws.send(JSON.stringify({
-          type: 'status_update',
-          bootstrapper_connected: hub.isBootstrapperConnected(),
-        }));
+        ws.send(JSON.stringify(hub.statusUpdate()));
         console.log('Browser connected');
         return;
       }
 
       if (message.type === 'bootstrapper_connect') {
-        hub.setBootstrapperSocket(ws);
-        // Notify browser that bootstrapper is connected
-        hub.sendToBrowser({
-          type: 'status_update',
-          bootstrapper_connected: true,
-        });
-        console.log('Bootstrapper connected');
+        const paired = hub.registerBootstrapper(ws, message);
+        // Notify browser that bootstrapper is connected, or needs pairing first
+        hub.sendToBrowser(hub.statusUpdate());
+        console.log(paired ? 'Bootstrapper connected' : 'Bootstrapper connected, waiting for pairing');
+        return;
+      }
+
+      // Handle pairing code entered in the browser
+      if (message.type === 'pair_bootstrapper') {
+        const code = String(message.code ?? '').replace(/\D/g, '');
+        const result = await hub.pairBootstrapper(code);
+        ws.send(JSON.stringify({ type: 'pairing_result', ...result }));
+        if (result.success) {
+          hub.sendToBrowser(hub.statusUpdate());
+          console.log('Bootstrapper paired');
+        }
+        return;
+      }
+
+      // Handle the bootstrapper's answer to a pairing attempt
+      if (message.type === 'pair_result') {
+        hub.handlePairResult(ws, message);
         return;
       }
 
@@ -83,6 +96,20 @@ wss.on('connection', (ws) => {
         return;
       }
 
+      // Handle cancel requests from browser for a running tool call
+      if (message.type === 'cancel_tool_call') {
+        if (message.id && hub.hasPendingCall(message.id)) {
+          console.log('Cancelling tool call:', message.id);
+          hub.cancelBootstrapperTool(message.id);
+        }
+        return;
+      }
+
+      // Everything below comes from the bootstrapper; ignore unpaired ones
+      if ((message.type === 'progress' || message.type === 'tool_result') && !hub.isPairedBootstrapper(ws)) {
+        return;
+      }
+
       // Handle progress updates from bootstrapper - forward to browser
       if (message.type === 'progress') {
         hub.sendToBrowser({
@@ -19,19 +19,29 @@ export const TOOLS = [
   },
   {
     name: 'install_conda',
-    description: 'Install Miniconda automatically if conda is not already installed. Downloads and installs Miniconda to ~/BrainDrive/miniconda3 (isolated from any system conda) without requiring sudo or terminal access. Use this when detect_system shows conda_installed is false. Shows download progress in the UI.',
+    description: 'Install Miniconda automatically if conda is not already installed. Downloads and installs Miniconda to ~/BrainDrive/miniconda3 (isolated from any system conda) without requiring sudo or terminal access. Use this when detect_system shows conda_installed is false. Shows download progress in the UI. The installer is checked against a pinned SHA-256; if none is pinned for this platform the call fails with needs_manual_action, and can be retried with the digest from the Miniconda release page.',
     input_schema: {
       type: 'object',
-      properties: {},
+      properties: {
+        sha256: {
+          type: 'string',
+          description: 'SHA-256 the Miniconda installer must have, from its release page (only needed when none is pinned)',
+        },
+      },
       required: [],
     },
   },
   {
     name: 'install_git',
-    description: 'DEPRECATED: Git is now included in the conda environment. Only use this if you need system git before conda is installed. On macOS, triggers Xcode CLI tools dialog. On Windows, downloads installer silently.',
+    description: 'DEPRECATED: Git is now included in the conda environment. Only use this if you need system git before conda is installed. On macOS, triggers Xcode CLI tools dialog. On Windows, downloads installer silently after checking it against a pinned SHA-256.',
     input_schema: {
       type: 'object',
-      properties: {},
+      properties: {
+        sha256: {
+          type: 'string',
+          description: 'SHA-256 the Git for Windows installer must have, from its release page (only needed when none is pinned)',
+        },
+      },
       required: [],
     },
   },
@@ -77,7 +87,7 @@ export const TOOLS = [
   },
   {
     name: 'pull_ollama_model',
-    description: 'Download an audited Ollama model (e.g., qwen2.5:1.5b). Requires explicit user confirmation before calling.',
+    description: 'Download an audited Ollama model (e.g., qwen2.5:1.5b). Requires explicit user confirmation before calling. Ollama downloads the model itself, so the set_download_limit cap does not apply; when a cap is set the result says download_limit_applied: false. Warn the user that the pull uses their full bandwidth before asking to confirm.',
     input_schema: {
       type: 'object',
       properties: {
@@ -117,7 +127,7 @@ export const TOOLS = [
   },
   {
     name: 'clone_repo',
-    description: 'Clone the BrainDrive repository from GitHub. This should be done early in the installation process. Uses shallow clone for faster download.',
+    description: 'Clone the BrainDrive repository from GitHub. This should be done early in the installation process. Uses shallow clone for faster download. Checks out the repository\'s default branch unless a ref is given.',
     input_schema: {
       type: 'object',
       properties: {
@@ -129,6 +139,18 @@ export const TOOLS = [
           type: 'string',
           description: 'Where to clone the repo (defaults to ~/BrainDrive)',
         },
+        ref: {
+          type: 'string',
+          description: 'Branch, tag or full 40-character commit hash to check out',
+        },
+        depth: {
+          type: 'integer',
+          description: 'Commits of history to fetch (default 1); 0 fetches the full history',
+        },
+        submodules: {
+          type: 'boolean',
+          description: 'Also check out git submodules',
+        },
       },
       required: [],
     },
@@ -255,4 +277,265 @@ export const TOOLS = [
       required: [],
     },
   },
+  {
+    name: 'run_install_plan',
+    description: 'Run several installation tools in one call, in dependency order. Steps that are already done (e.g. conda installed, repo cloned) are skipped. Without steps, runs the standard install: install_conda, create_conda_env, clone_repo, setup_env_file, install_all_deps, start_braindrive. Returns a per-step report.',
+    input_schema: {
+      type: 'object',
+      properties: {
+        steps: {
+          type: 'array',
+          description: 'Steps to run (defaults to the standard install)',
+          items: {
+            type: 'object',
+            properties: {
+              tool: {
+                type: 'string',
+                enum: [
+                  'install_conda',
+                  'install_git',
+                  'create_conda_env',
+                  'clone_repo',
+                  'setup_env_file',
+                  'install_all_deps',
+                  'install_ollama',
+                  'start_ollama',
+                  'start_braindrive',
+                ],
+                description: 'Tool to run; other properties are passed to it as its arguments',
+              },
+              id: {
+                type: 'string',
+                description: 'Name for depends_on (defaults to the tool name)',
+              },
+              depends_on: {
+                type: 'array',
+                items: { type: 'string' },
+                description: 'Ids of steps that must finish first',
+              },
+            },
+            required: ['tool'],
+          },
+        },
+        resume: {
+          type: 'boolean',
+          description: 'Skip install steps an earlier run already finished with the same arguments, per the install state (default true). Starting services and force_recreate always run. Set false to recheck every step.',
+        },
+      },
+      required: [],
+    },
+  },
+  {
+    name: 'get_install_state',
+    description: 'Read the installer\'s record of how each install step last ended (succeeded, failed, cancelled, or interrupted because the installer was closed), with timestamps and reported versions. Use it after a reconnect or restart to see where a previous install stopped.',
+    input_schema: {
+      type: 'object',
+      properties: {},
+      required: [],
+    },
+  },
+  {
+    name: 'verify_installation',
+    description: 'Check every part of an existing installation: Miniconda, the BrainDriveDev environment (python, node, npm, git), the repository (git fsck/status), backend packages, frontend node_modules, backend/.env keys and the default ports. Returns each check with its status and, for failures, the repair action that fixes it. Use when BrainDrive is installed but won\'t start or behaves oddly.',
+    input_schema: {
+      type: 'object',
+      properties: {},
+      required: [],
+    },
+  },
+  {
+    name: 'repair_installation',
+    description: 'Run the repair actions verify_installation suggests (reinstall conda, recreate the environment, re-fetch the repository, restore .env keys, reinstall dependencies), then verify again. Can take several minutes.',
+    input_schema: {
+      type: 'object',
+      properties: {
+        checks: {
+          type: 'array',
+          items: { type: 'string' },
+          description: 'Only repair these checks (ids from verify_installation, e.g. "frontend_deps"); defaults to every failed check',
+        },
+        sha256: {
+          type: 'string',
+          description: 'SHA-256 the Miniconda installer must have, from its release page (only needed when a conda repair fails because none is pinned)',
+        },
+      },
+      required: [],
+    },
+  },
+  {
+    name: 'uninstall_braindrive',
+    description: 'Stop BrainDrive and remove everything the installer created: the ~/BrainDrive checkout, its Miniconda and BrainDriveDev environment, installer scripts, downloads and logs, and the Ollama models the installer pulled. Call with dry_run=true first to list what would be removed and how much space it frees. Removing requires explicit user confirmation.',
+    input_schema: {
+      type: 'object',
+      properties: {
+        dry_run: {
+          type: 'boolean',
+          description: 'Only list what would be removed, with sizes',
+        },
+        confirmed: {
+          type: 'boolean',
+          description: 'Set to true only after the user has seen the dry-run list and explicitly approved removing it.',
+        },
+        keep_user_data: {
+          type: 'boolean',
+          description: 'Keep BrainDrive settings (.env), database and uploads',
+        },
+        keep_models: {
+          type: 'boolean',
+          description: 'Keep the Ollama models the installer pulled',
+        },
+        repo_path: {
+          type: 'string',
+          description: 'Path to the BrainDrive repo, if it was cloned somewhere other than ~/BrainDrive',
+        },
+      },
+      required: [],
+    },
+  },
+  {
+    name: 'update_braindrive',
+    description: 'Update an installed BrainDrive to the latest commit of its branch, or to a release tag: stops BrainDrive, moves the checkout, reinstalls only the dependencies whose requirements changed and starts it again. If any of that fails it restores the previous version and fails with kind "rolled_back". Can take several minutes.',
+    input_schema: {
+      type: 'object',
+      properties: {
+        tag: {
+          type: 'string',
+          description: 'Release tag to check out, e.g. "v1.2.0"; defaults to the latest commit of the current branch',
+        },
+      },
+      required: [],
+    },
+  },
+  {
+    name: 'build_offline_bundle',
+    description: 'Collect everything the install downloads (Miniconda installer, conda packages, the BrainDrive repository, Python wheels, npm packages and optionally Ollama models) into a bundle for installing on a machine without internet access. Run on a connected machine with the same OS and architecture as the offline one; needs conda installed here. Can take a long time and several GB.',
+    input_schema: {
+      type: 'object',
+      properties: {
+        output_path: {
+          type: 'string',
+          description: 'Absolute or ~/ path of the bundle directory to create, e.g. a USB drive; must not exist or be empty',
+        },
+        ref: {
+          type: 'string',
+          description: 'Branch, tag or full commit hash to bundle; defaults to the default branch',
+        },
+        models: {
+          type: 'array',
+          items: { type: 'string' },
+          description: 'Ollama models already pulled on this machine to include, e.g. ["llama3.2:1b"]',
+        },
+        tarball: {
+          type: 'boolean',
+          description: 'Write a single <output_path>.tar.gz file instead of a directory',
+        },
+        sha256: {
+          type: 'string',
+          description: 'SHA-256 the Miniconda installer must have, from its release page (only needed when none is pinned)',
+        },
+      },
+      required: ['output_path'],
+    },
+  },
+  {
+    name: 'install_offline_bundle',
+    description: 'Install BrainDrive from a bundle made by build_offline_bundle, with no network access: Miniconda, the conda environment, the repository, backend and frontend dependencies, the .env file and any bundled Ollama models. Steps already done are skipped. Does not install Ollama itself or start BrainDrive.',
+    input_schema: {
+      type: 'object',
+      properties: {
+        bundle_path: {
+          type: 'string',
+          description: 'Absolute or ~/ path of the bundle directory or .tar.gz file',
+        },
+      },
+      required: ['bundle_path'],
+    },
+  },
+  {
+    name: 'clear_download_cache',
+    description: 'Delete the installers kept from earlier downloads (Miniconda, Git for Windows) and any partial downloads, returning the space freed. Installers are reused from this cache on retries and reinstalls, so only clear it when the user asks to free space or a cached installer seems to be the problem.',
+    input_schema: {
+      type: 'object',
+      properties: {},
+      required: [],
+    },
+  },
+  {
+    name: 'configure_network',
+    description: 'Show or change the proxy and CA certificate settings the bootstrapper uses for every download, git, conda, pip and npm. Call it with no arguments to see the settings in effect and where each came from (saved, or an environment variable such as HTTPS_PROXY). Use it when downloads fail with proxy or certificate errors, e.g. on a corporate network that intercepts TLS. Fields left out are unchanged; an empty string removes a saved value.',
+    input_schema: {
+      type: 'object',
+      properties: {
+        http_proxy: {
+          type: 'string',
+          description: 'Proxy for http:// URLs, e.g. http://proxy.example.com:3128',
+        },
+        https_proxy: {
+          type: 'string',
+          description: 'Proxy for https:// URLs; usually the same as http_proxy',
+        },
+        no_proxy: {
+          type: 'string',
+          description: 'Comma-separated hosts and domains to reach without the proxy, e.g. .corp.example.com,10.0.0.1',
+        },
+        ca_bundle: {
+          type: 'string',
+          description: "Absolute or ~/ path of a PEM file with the proxy's CA certificates",
+        },
+        clear: {
+          type: 'boolean',
+          description: 'Remove every saved setting before applying the others',
+        },
+      },
+      required: [],
+    },
+  },
+  {
+    name: 'configure_mirrors',
+    description: 'Show or replace the mirrors tried, in order, before the public sources for the Miniconda installer, conda packages (conda-forge), PyPI and npm. Call it with no arguments to see the saved mirrors. Use it when downloads from the public sources are slow or blocked, e.g. in regions that need a local mirror. Each list replaces the saved one; an empty list removes it. Install results report which mirror served them in `mirror`.',
+    input_schema: {
+      type: 'object',
+      properties: {
+        miniconda: {
+          type: 'array',
+          items: { type: 'string' },
+          description: 'URLs of directories holding the Miniconda3-latest-* installers',
+        },
+        conda: {
+          type: 'array',
+          items: { type: 'string' },
+          description: 'Channel URLs mirroring conda-forge',
+        },
+        pypi: {
+          type: 'array',
+          items: { type: 'string' },
+          description: 'PyPI simple index URLs',
+        },
+        npm: {
+          type: 'array',
+          items: { type: 'string' },
+          description: 'npm registry URLs',
+        },
+        clear: {
+          type: 'boolean',
+          description: 'Remove every saved list before applying the others',
+        },
+      },
+      required: [],
+    },
+  },
+  {
+    name: 'set_download_limit',
+    description: 'Show or change the cap on download bandwidth, in megabits per second, shared by every installer download. Call it with no arguments to see the current cap. Downloads already running adjust within a second. Ollama model pulls are not capped.',
+    input_schema: {
+      type: 'object',
+      properties: {
+        limit_mbps: {
+          type: 'number',
+          description: 'Cap in Mbps (at least 0.1); 0 removes the cap',
+        },
+      },
+      required: [],
+    },
+  },
 ];
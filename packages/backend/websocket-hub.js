@@ -1,8 +1,47 @@
+import { createHash, timingSafeEqual } from 'crypto';
+
+/**
+ * Derive a pairing proof or session token from the bootstrapper's nonce and the
+ * code the user typed. Must match the derivation in the bootstrapper's pairing.rs.
+ */
+function derivePairingValue(label, nonce, code) {
+  return createHash('sha256').update(`${label}\0${nonce}\0${code}`).digest('hex');
+}
+
+function tokensEqual(a, b) {
+  const left = Buffer.from(a);
+  const right = Buffer.from(b);
+  return left.length === right.length && timingSafeEqual(left, right);
+}
+
+/**
+ * Version and capabilities a bootstrapper announced in bootstrapper_connect
+ * Bootstrappers older than the handshake send none of these fields
+ * @param {object} message - bootstrapper_connect message
+ */
+function bootstrapperInfo(message) {
+  return {
+    protocolVersion: Number.isInteger(message.protocol_version) ? message.protocol_version : null,
+    version: typeof message.bootstrapper_version === 'string' ? message.bootstrapper_version : null,
+    os: typeof message.os === 'string' ? message.os : null,
+    arch: typeof message.arch === 'string' ? message.arch : null,
+    capabilities: Array.isArray(message.capabilities) ? message.capabilities : null,
+  };
+}
+
 export class WebSocketHub {
   constructor(session) {
     this.session = session;
     this.browserSocket = null;
+    // Paired bootstrapper: the only socket tool calls are sent to
     this.bootstrapperSocket = null;
+    this.bootstrapperInfo = null;
+    this.sessionToken = null;
+    // Bootstrapper that connected without a valid token, waiting for the user's code
+    this.candidateSocket = null;
+    this.candidateNonce = null;
+    this.candidateInfo = null;
+    this.pendingPairing = null;
     this.pendingCalls = new Map();
   }
 
@@ -16,6 +55,111 @@ export class WebSocketHub {
     this.session.setBootstrapperConnected(true);
   }
 
+  /**
+   * Handle bootstrapper_connect: resume the paired session or wait for pairing
+   * @param {WebSocket} ws - Bootstrapper socket
+   * @param {object} message - bootstrapper_connect message
+   * @returns {boolean} - Whether the socket was accepted as the paired bootstrapper
+   */
+  registerBootstrapper(ws, message) {
+    const info = bootstrapperInfo(message);
+    console.log(
+      `Bootstrapper ${info.version || 'unknown version'} (protocol ${info.protocolVersion ?? 'unknown'}, ` +
+      `${info.os || '?'}/${info.arch || '?'})`
+    );
+
+    const token = typeof message.session_token === 'string' ? message.session_token : null;
+    if (token && this.sessionToken && tokensEqual(token, this.sessionToken)) {
+      this.bootstrapperInfo = info;
+      this.setBootstrapperSocket(ws);
+      this.resendPendingCalls();
+      return true;
+    }
+
+    if (token) {
+      // Token from a session we no longer know (e.g. after a restart)
+      ws.send(JSON.stringify({ type: 'pairing_required' }));
+    }
+    this.candidateSocket = ws;
+    this.candidateNonce = typeof message.pairing_nonce === 'string' ? message.pairing_nonce : null;
+    this.candidateInfo = info;
+    return false;
+  }
+
+  isPairedBootstrapper(ws) {
+    return ws === this.bootstrapperSocket;
+  }
+
+  isPairingRequired() {
+    return this.candidateSocket !== null &&
+           this.candidateSocket.readyState === 1;
+  }
+
+  statusUpdate() {
+    return {
+      type: 'status_update',
+      bootstrapper_connected: this.isBootstrapperConnected(),
+      pairing_required: this.isPairingRequired(),
+    };
+  }
+
+  /**
+   * Pair the waiting bootstrapper using the code the user typed in the browser
+   * @param {string} code - Code shown in the bootstrapper window
+   * @param {number} timeoutMs - How long to wait for the bootstrapper's answer
+   * @returns {Promise<{success: boolean, error?: string}>}
+   */
+  async pairBootstrapper(code, timeoutMs = 10000) {
+    const ws = this.candidateSocket;
+    const nonce = this.candidateNonce;
+    if (!ws || ws.readyState !== 1 || !nonce) {
+      return { success: false, error: 'No bootstrapper is waiting to be paired' };
+    }
+    if (this.pendingPairing) {
+      return { success: false, error: 'A pairing attempt is already in progress' };
+    }
+
+    const result = await new Promise((resolve) => {
+      const timeout = setTimeout(() => {
+        this.pendingPairing = null;
+        resolve({ success: false, error: 'Bootstrapper did not answer the pairing request' });
+      }, timeoutMs);
+
+      this.pendingPairing = { ws, resolve, timeout };
+      ws.send(JSON.stringify({
+        type: 'pair',
+        proof: derivePairingValue('braindrive-pairing-proof', nonce, code),
+      }));
+    });
+
+    if (!result.success) {
+      // The bootstrapper replaces its code after too many misses
+      if (ws === this.candidateSocket && typeof result.pairing_nonce === 'string') {
+        this.candidateNonce = result.pairing_nonce;
+      }
+      return { success: false, error: result.error || 'Pairing failed' };
+    }
+
+    this.sessionToken = derivePairingValue('braindrive-session-token', nonce, code);
+    if (ws === this.candidateSocket) {
+      this.bootstrapperInfo = this.candidateInfo;
+      this.candidateSocket = null;
+      this.candidateNonce = null;
+      this.candidateInfo = null;
+    }
+    this.setBootstrapperSocket(ws);
+    return { success: true };
+  }
+
+  handlePairResult(ws, message) {
+    const pending = this.pendingPairing;
+    if (pending && pending.ws === ws) {
+      clearTimeout(pending.timeout);
+      this.pendingPairing = null;
+      pending.resolve(message);
+    }
+  }
+
   isBootstrapperConnected() {
     return this.bootstrapperSocket !== null &&
            this.bootstrapperSocket.readyState === 1; // WebSocket.OPEN
@@ -34,14 +178,19 @@ export class WebSocketHub {
     }
     if (ws === this.bootstrapperSocket) {
       this.bootstrapperSocket = null;
+      this.bootstrapperInfo = null;
       this.session.setBootstrapperConnected(false);
       // Notify browser
-      this.sendToBrowser({
-        type: 'status_update',
-        bootstrapper_connected: false,
-      });
+      this.sendToBrowser(this.statusUpdate());
       console.log('Bootstrapper disconnected');
     }
+    if (ws === this.candidateSocket) {
+      this.candidateSocket = null;
+      this.candidateNonce = null;
+      this.candidateInfo = null;
+      this.handlePairResult(ws, { success: false, error: 'Bootstrapper disconnected' });
+      this.sendToBrowser(this.statusUpdate());
+    }
   }
 
   sendToBrowser(message) {
@@ -56,6 +205,28 @@ export class WebSocketHub {
     }
   }
 
+  /**
+   * Whether the paired bootstrapper handles a tool type
+   * Bootstrappers that don't announce capabilities are assumed to handle everything
+   * @param {string} type - Tool type
+   */
+  bootstrapperSupports(type) {
+    const capabilities = this.bootstrapperInfo?.capabilities;
+    return !capabilities || capabilities.includes(type);
+  }
+
+  /**
+   * Send unanswered tool calls again after the paired bootstrapper reconnects.
+   * Their results may have been lost with the old socket. The bootstrapper
+   * recognizes the ids: it attaches to calls still running and replays finished ones.
+   */
+  resendPendingCalls() {
+    for (const [id, pending] of this.pendingCalls) {
+      console.log(`Resending tool call ${id} after reconnect`);
+      this.sendToBootstrapper({ ...pending.message, session_token: this.sessionToken });
+    }
+  }
+
   hasPendingCall(id) {
     return this.pendingCalls.has(id);
   }
@@ -70,8 +241,8 @@ export class WebSocketHub {
   }
 
   /**
-   * Call a tool on the bootstrapper and wait for response
-   * @param {string} type - Tool type (e.g., 'detect_system', 'install_conda_env')
+   * Call a tool on the bootstrapper and wait for response
+   * @param {string} type - Tool type (e.g., 'detect_system', 'install_conda_env')
    * @param {object} params - Tool parameters
    * @param {number} timeoutMs - Timeout in milliseconds
    * @returns {Promise<object>} - Tool result
@@ -80,25 +251,44 @@ export class WebSocketHub {
     if (!this.isBootstrapperConnected()) {
       throw new Error('Bootstrapper not connected');
     }
+    if (!this.bootstrapperSupports(type)) {
+      const version = this.bootstrapperInfo?.version || 'unknown';
+      throw new Error(`The connected bootstrapper (version ${version}) does not support ${type}. Please update the BrainDrive bootstrapper.`);
+    }
 
     const id = `tool_${Date.now()}_${Math.random().toString(36).substr(2, 9)}`;
 
     return new Promise((resolve, reject) => {
       const timeout = setTimeout(() => {
         this.pendingCalls.delete(id);
+        // Stop the work on the bootstrapper too; nobody is waiting for it anymore
+        this.cancelBootstrapperTool(id);
         reject(new Error(`Tool call ${type} timed out after ${timeoutMs}ms`));
       }, timeoutMs);
 
-      this.pendingCalls.set(id, { resolve, reject, timeout });
+      const message = { type, id, ...params };
+      this.pendingCalls.set(id, { resolve, reject, timeout, message });
 
       this.sendToBootstrapper({
-        type,
-        id,
-        ...params,
+        ...message,
+        session_token: this.sessionToken,
       });
     });
   }
 
+  /**
+   * Ask the bootstrapper to abort an in-flight tool call
+   * The pending call still resolves, with a tool_result whose status is 'cancelled'
+   * @param {string} id - Tool call id
+   */
+  cancelBootstrapperTool(id) {
+    this.sendToBootstrapper({
+      type: 'cancel',
+      id,
+      session_token: this.sessionToken,
+    });
+  }
+
   /**
    * Stream command output from bootstrapper to browser
    * @param {string} output - Output line
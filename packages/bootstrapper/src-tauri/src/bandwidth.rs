@@ -0,0 +1,170 @@
+//! Cap on download bandwidth
+//!
+//! Large downloads otherwise take the whole connection, which users notice
+//! when they are in a video call. Every chunk our downloads receive, over any
+//! number of connections, draws from one token bucket filled at the cap. The
+//! cap is saved in `~/.braindrive-installer/bandwidth.json` by
+//! `set_download_limit` and read again every second, so changing it (from the
+//! app or the CLI) slows down or speeds up downloads already running. The curl
+//! fallback is given the cap as `--limit-rate` when it starts. Ollama has no
+//! bandwidth setting, so model pulls are not capped.
+
+use crate::dispatcher::sleep_or_cancel;
+use crate::error::DispatchError;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+const SETTINGS_FILE: &str = "bandwidth.json";
+
+/// How often the saved cap is read again
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How much unused allowance a download can burst with, in seconds of the cap
+const BURST_SECS: f64 = 0.5;
+
+/// Lowest cap accepted; below it downloads would look stalled
+const MIN_LIMIT_MBPS: f64 = 0.1;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BandwidthSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_bytes_per_second: Option<u64>,
+}
+
+/// The saved cap, as last read
+struct CachedLimit {
+    limit: Option<u64>,
+    read_at: Option<Instant>,
+}
+
+static LIMIT: Mutex<CachedLimit> = Mutex::new(CachedLimit { limit: None, read_at: None });
+
+static BUCKET: Mutex<Bucket> = Mutex::new(Bucket { tokens: 0.0, refilled_at: None });
+
+/// Bytes that can be received now; negative while downloads wait for allowance
+struct Bucket {
+    tokens: f64,
+    refilled_at: Option<Instant>,
+}
+
+impl Bucket {
+    /// Take `bytes`, returning how long to wait before receiving more
+    fn take(&mut self, bytes: u64, now: Instant, limit: Option<u64>) -> Option<Duration> {
+        let Some(rate) = limit.map(|limit| limit as f64) else {
+            self.tokens = 0.0;
+            self.refilled_at = Some(now);
+            return None;
+        };
+        let elapsed = self.refilled_at.map_or(0.0, |at| now.saturating_duration_since(at).as_secs_f64());
+        self.tokens = (self.tokens + rate * elapsed).min(rate * BURST_SECS);
+        self.refilled_at = Some(now);
+        self.tokens -= bytes as f64;
+        (self.tokens < 0.0).then(|| Duration::from_secs_f64(-self.tokens / rate))
+    }
+}
+
+/// Current cap in bytes per second, `None` for unlimited
+pub fn limit() -> Option<u64> {
+    let mut cached = LIMIT.lock().unwrap_or_else(|e| e.into_inner());
+    if cached.read_at.is_none_or(|at| at.elapsed() >= RELOAD_INTERVAL) {
+        cached.limit = load_settings().max_bytes_per_second;
+        cached.read_at = Some(Instant::now());
+    }
+    cached.limit
+}
+
+/// Account for `bytes` just received, waiting while downloads are over the cap
+pub async fn throttle(bytes: usize, cancel: &CancellationToken) -> Result<(), DispatchError> {
+    let limit = limit();
+    let wait = BUCKET
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take(bytes as u64, Instant::now(), limit);
+    match wait {
+        Some(wait) => sleep_or_cancel(wait, cancel).await,
+        None => Ok(()),
+    }
+}
+
+/// Show or change the cap, in megabits per second; 0 removes it
+pub async fn set_download_limit(limit_mbps: Option<f64>) -> Result<Value, DispatchError> {
+    if let Some(mbps) = limit_mbps {
+        if !mbps.is_finite() || mbps < 0.0 || (mbps > 0.0 && mbps < MIN_LIMIT_MBPS) {
+            return Err(DispatchError::invalid_argument(format!(
+                "Download limit must be 0 (no limit) or at least {} Mbps",
+                MIN_LIMIT_MBPS
+            )));
+        }
+        let max_bytes_per_second = (mbps > 0.0).then(|| (mbps * 1_000_000.0 / 8.0) as u64);
+        save_settings(&BandwidthSettings { max_bytes_per_second })?;
+        // Applies to running downloads from their next chunk
+        let mut cached = LIMIT.lock().unwrap_or_else(|e| e.into_inner());
+        cached.limit = max_bytes_per_second;
+        cached.read_at = Some(Instant::now());
+    }
+
+    let current = limit();
+    let message = match (limit_mbps.is_some(), current) {
+        (true, Some(_)) => "Download limit saved",
+        (true, None) => "Download limit removed",
+        (false, Some(_)) => "Downloads are limited",
+        (false, None) => "Downloads are not limited",
+    };
+    Ok(json!({
+        "success": true,
+        "message": message,
+        "limit_mbps": current.map(|bytes| bytes as f64 * 8.0 / 1_000_000.0),
+        "max_bytes_per_second": current,
+        "applies_to_ollama": false,
+    }))
+}
+
+fn settings_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".braindrive-installer").join(SETTINGS_FILE))
+}
+
+fn load_settings() -> BandwidthSettings {
+    settings_path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save_settings(settings: &BandwidthSettings) -> Result<(), DispatchError> {
+    let path = settings_path().ok_or("Could not determine home directory")?;
+    let text = serde_json::to_string_pretty(settings).map_err(|e| DispatchError::internal(e.to_string()))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| DispatchError::internal(format!("Failed to create {}: {}", dir.display(), e)))?;
+    }
+    std::fs::write(&path, text).map_err(|e| DispatchError::internal(format!("Failed to write {}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_paces_to_the_limit() {
+        let start = Instant::now();
+        let mut bucket = Bucket { tokens: 0.0, refilled_at: None };
+
+        // 1000 bytes/s: a 500 byte chunk puts the bucket half a second behind
+        assert_eq!(bucket.take(500, start, Some(1000)), Some(Duration::from_millis(500)));
+        // Having waited, the next chunk only waits for itself
+        let later = start + Duration::from_millis(500);
+        assert_eq!(bucket.take(250, later, Some(1000)), Some(Duration::from_millis(250)));
+
+        // Idle time banks at most BURST_SECS of allowance
+        let idle = later + Duration::from_secs(10);
+        assert_eq!(bucket.take(500, idle, Some(1000)), None);
+        assert!(bucket.take(100, idle, Some(1000)).is_some());
+
+        // Lifting the cap mid-download releases it at once
+        assert_eq!(bucket.take(1_000_000, idle, None), None);
+    }
+}
@@ -0,0 +1,5 @@
+//! Headless installer CLI; see `braindrive_bootstrapper_lib::cli`
+
+fn main() -> std::process::ExitCode {
+    braindrive_bootstrapper_lib::cli::main()
+}
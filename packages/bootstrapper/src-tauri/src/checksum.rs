@@ -0,0 +1,188 @@
+//! SHA-256 checks for downloaded installers
+//!
+//! Downloads are hashed while they stream to disk (or read back after the
+//! curl fallback) and compared with the digest the caller expects, falling
+//! back to the built-in manifest below. A mismatching file is deleted before
+//! anything runs it, and a download with neither digest is refused, since
+//! every download here is an installer about to run.
+
+use crate::error::DispatchError;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::Path;
+
+/// Published SHA-256 digests of installers, by URL
+///
+/// Only versioned URLs belong here: the file behind a `-latest-` URL changes
+/// with every release, so a digest for it would start failing installs at the
+/// next one. Copy digests from the vendor's release page, never from a
+/// download.
+const KNOWN_DIGESTS: &[(&str, &str)] = &[
+    (
+        "https://github.com/git-for-windows/git/releases/download/v2.43.0.windows.1/Git-2.43.0-64-bit.exe",
+        "a6058d7c4c16bfa5bcd6fde051a92de8c68535fd7ebade55fc0ab1c41be3c8d5",
+    ),
+    (
+        "https://repo.anaconda.com/miniconda/Miniconda3-py311_23.11.0-2-MacOSX-arm64.sh",
+        "5694c382e6056d62ed874f22692224c4f53bca22e8135b6f069111e081be07aa",
+    ),
+    (
+        "https://repo.anaconda.com/miniconda/Miniconda3-py311_23.11.0-2-MacOSX-x86_64.sh",
+        "2b7f9e46308c28c26dd83abad3e72121ef63916eaf17b63723b5a1f728dc3032",
+    ),
+    (
+        "https://repo.anaconda.com/miniconda/Miniconda3-py311_23.11.0-2-Linux-x86_64.sh",
+        "c9ae82568e9665b1105117b4b1e499607d2a920f0aea6f94410e417a0eff1b9c",
+    ),
+    (
+        "https://repo.anaconda.com/miniconda/Miniconda3-py311_23.11.0-2-Linux-aarch64.sh",
+        "decd447fb99dbd0fc5004481ec9bf8c04f9ba28b35a9292afe49ecefe400237f",
+    ),
+];
+
+/// Hex digits in a SHA-256 digest
+const DIGEST_LEN: usize = 64;
+
+/// Digest the manifest lists for `url`
+pub fn known_digest(url: &str) -> Option<&'static str> {
+    KNOWN_DIGESTS
+        .iter()
+        .find(|(known_url, _)| *known_url == url)
+        .map(|(_, digest)| *digest)
+}
+
+/// Digest a download of `url` must have: `given`, else the manifest's
+pub fn expected_digest(url: &str, given: Option<&str>) -> Result<String, DispatchError> {
+    match given.or_else(|| known_digest(url)) {
+        Some(digest) => normalize_digest(digest),
+        None => Err(DispatchError::needs_manual_action(
+            format!("No SHA-256 is pinned for {}, so it won't be run", url),
+            Some("Pass the SHA-256 published on the vendor's release page as sha256".to_string()),
+            Some(url),
+        )),
+    }
+}
+
+/// Lowercase hex digest, accepting a `sha256:` prefix
+pub fn normalize_digest(digest: &str) -> Result<String, DispatchError> {
+    let digest = digest.trim();
+    let digest = digest.strip_prefix("sha256:").unwrap_or(digest);
+    if digest.len() == DIGEST_LEN && digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(digest.to_ascii_lowercase())
+    } else {
+        Err(DispatchError::invalid_argument(format!("Not a SHA-256 digest: {}", digest)))
+    }
+}
+
+/// Running digest of a download
+#[derive(Default)]
+pub struct DownloadHasher(Sha256);
+
+impl DownloadHasher {
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.0.update(chunk);
+    }
+
+    /// Add a file's contents, e.g. the part of a download fetched earlier
+    pub fn update_from_file(&mut self, path: &Path) -> Result<(), String> {
+        let mut file = std::fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let read = file
+                .read(&mut buffer)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            if read == 0 {
+                return Ok(());
+            }
+            self.update(&buffer[..read]);
+        }
+    }
+
+    pub fn finish(self) -> String {
+        format!("{:x}", self.0.finalize())
+    }
+}
+
+/// Digest of a file already on disk
+pub fn file_digest(path: &Path) -> Result<String, String> {
+    let mut hasher = DownloadHasher::default();
+    hasher.update_from_file(path)?;
+    Ok(hasher.finish())
+}
+
+/// Delete `path` and fail unless `actual` is the expected digest
+pub fn verify(url: &str, path: &Path, actual: &str, expected: &str) -> Result<(), DispatchError> {
+    let expected = normalize_digest(expected)?;
+    if actual == expected {
+        tracing::info!(url, sha256 = actual, "Download SHA-256 verified");
+        return Ok(());
+    }
+    tracing::error!(url, expected = %expected, actual, "Download SHA-256 mismatch");
+    let _ = std::fs::remove_file(path);
+    Err(DispatchError::checksum_mismatch(url, &expected, actual))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn test_streamed_and_file_digests_agree() {
+        let mut hasher = DownloadHasher::default();
+        hasher.update(b"a");
+        hasher.update(b"bc");
+        assert_eq!(hasher.finish(), ABC_SHA256);
+
+        let path = std::env::temp_dir().join(format!("braindrive-checksum-test-{}", std::process::id()));
+        std::fs::write(&path, b"abc").unwrap();
+        assert_eq!(file_digest(&path).unwrap(), ABC_SHA256);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_mismatch_deletes_the_download() {
+        let path = std::env::temp_dir().join(format!("braindrive-checksum-mismatch-{}", std::process::id()));
+        std::fs::write(&path, b"abc").unwrap();
+
+        let expected = format!("sha256:{}", ABC_SHA256.to_ascii_uppercase());
+        assert!(verify("https://example.com/a", &path, ABC_SHA256, &expected).is_ok());
+        assert!(path.exists());
+
+        let error = verify("https://example.com/a", &path, ABC_SHA256, &"0".repeat(64)).unwrap_err();
+        assert_eq!(error.kind(), "checksum_mismatch");
+        assert!(!path.exists());
+
+        assert!(normalize_digest("abc").is_err());
+    }
+
+    #[test]
+    fn test_download_without_a_digest_is_refused() {
+        let error = expected_digest("https://example.com/installer.sh", None).unwrap_err();
+        assert_eq!(error.kind(), "needs_manual_action");
+        assert_eq!(expected_digest("https://example.com/installer.sh", Some(ABC_SHA256)).unwrap(), ABC_SHA256);
+
+        let (url, digest) = KNOWN_DIGESTS[0];
+        assert_eq!(expected_digest(url, None).unwrap(), digest);
+        // Every pinned URL is versioned and every digest well formed
+        for (url, digest) in KNOWN_DIGESTS {
+            assert!(!url.contains("-latest-"), "{}", url);
+            assert_eq!(normalize_digest(digest).unwrap(), *digest);
+        }
+    }
+
+    #[test]
+    fn test_every_installer_is_pinned() {
+        // Published digests not yet copied in from the vendor's release page;
+        // until then these installs need `sha256`
+        const AWAITING_DIGEST: &[&str] = &[
+            "https://repo.anaconda.com/miniconda/Miniconda3-py311_23.11.0-2-Windows-x86_64.exe",
+            "https://github.com/git-for-windows/git/releases/download/v2.43.0.windows.1/Git-2.43.0-32-bit.exe",
+        ];
+        for url in crate::dispatcher::installer_urls() {
+            let awaiting = AWAITING_DIGEST.contains(&url.as_str());
+            assert_eq!(known_digest(&url).is_some(), !awaiting, "{}", url);
+        }
+    }
+}
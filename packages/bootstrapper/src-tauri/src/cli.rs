@@ -0,0 +1,617 @@
+//! Headless entry point: `braindrive-bootstrap <command>`
+//!
+//! Runs the same dispatcher functions the backend calls over the socket, for
+//! provisioning VMs and CI images where there is no window or backend. Results
+//! print to stdout (human-readable, or JSON with `--json`); progress goes to
+//! stderr. Ctrl-C cancels the running step the way a `cancel` message would.
+//!
+//! Build it without the window, and so without GTK/WebKit, using
+//! `cargo build --no-default-features --bin braindrive-bootstrap`.
+
+use crate::bandwidth;
+use crate::dispatcher::{self, CloneOptions};
+use crate::download_cache;
+use crate::error::DispatchError;
+use crate::git_remote::GitToken;
+use crate::install_plan;
+use crate::install_state;
+use crate::logging;
+use crate::mirrors::{self, MirrorUpdate};
+use crate::network::{self, NetworkSettings};
+use crate::offline_bundle::{self, BundleOptions};
+use crate::process_manager::{self, BrainDriveState, ProcessState, ServiceInfo};
+use crate::uninstall::{self, UninstallOptions};
+use crate::update;
+use crate::verify;
+use crate::protocol::{default_backend_port, default_frontend_port, Outbound, OutgoingMessage};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::{json, Value};
+use std::io::{IsTerminal, Write};
+use std::process::ExitCode;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+/// Request id attached to progress of CLI runs; only shown in `--json` output
+const CLI_REQUEST_ID: &str = "cli";
+
+/// Exit status for a run stopped with Ctrl-C, as shells report SIGINT
+const EXIT_CANCELLED: u8 = 130;
+
+/// Environment variable `clone` and `build-bundle` read a token for private repositories from
+const GIT_TOKEN_VAR: &str = "BRAINDRIVE_GIT_TOKEN";
+
+#[derive(Debug, Parser)]
+#[command(name = "braindrive-bootstrap", version, about = "Install and run BrainDrive without the installer window")]
+struct Cli {
+    /// Print results and progress as JSON
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Report OS, hardware and which components are installed
+    Detect,
+    /// Run the standard install from Miniconda to a running BrainDrive, skipping what is done
+    Install {
+        /// Recheck every step instead of trusting the install state journal
+        #[arg(long)]
+        no_resume: bool,
+    },
+    /// Show how each install step last ended, from the install state journal
+    State,
+    /// Install the isolated Miniconda under ~/BrainDrive
+    InstallConda {
+        /// SHA-256 the installer must have, if none is pinned for it
+        #[arg(long)]
+        sha256: Option<String>,
+    },
+    /// Install git into the BrainDrive conda environment
+    InstallGit {
+        /// SHA-256 the Git for Windows installer must have, if none is pinned for it
+        #[arg(long)]
+        sha256: Option<String>,
+    },
+    /// Clone the BrainDrive repository
+    ///
+    /// For a private repository, put an access token in BRAINDRIVE_GIT_TOKEN.
+    Clone {
+        #[arg(long)]
+        repo_url: Option<String>,
+        #[arg(long)]
+        target_path: Option<String>,
+        /// Branch, tag or full commit hash; the remote's default branch by default
+        #[arg(long = "ref")]
+        git_ref: Option<String>,
+        /// Commits of history to fetch; 0 for all of it
+        #[arg(long, default_value_t = 1)]
+        depth: u32,
+        /// Check out submodules too
+        #[arg(long)]
+        submodules: bool,
+    },
+    /// Create the BrainDrive conda environment
+    CreateEnv {
+        #[arg(long)]
+        env_name: Option<String>,
+        /// Remove and recreate the environment if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Install backend and/or frontend dependencies
+    InstallDeps {
+        #[arg(long)]
+        env_name: Option<String>,
+        #[arg(long)]
+        repo_path: Option<String>,
+        #[arg(long, value_enum, default_value_t = DepsTarget::All)]
+        target: DepsTarget,
+    },
+    /// Create the backend .env file from its template
+    SetupEnv {
+        #[arg(long)]
+        repo_path: Option<String>,
+    },
+    /// Start the BrainDrive backend and frontend
+    Start {
+        #[command(flatten)]
+        ports: Ports,
+    },
+    /// Stop the BrainDrive services listening on the given ports
+    Stop {
+        #[command(flatten)]
+        ports: Ports,
+    },
+    /// Show whether the BrainDrive services are running
+    Status {
+        #[command(flatten)]
+        ports: Ports,
+    },
+    /// Check every part of the installation and suggest repairs
+    Verify,
+    /// Repair what `verify` finds broken
+    Repair {
+        /// Only repair these checks (e.g. conda, frontend_deps); all failed checks by default
+        #[arg(long = "check")]
+        checks: Vec<String>,
+        /// SHA-256 the Miniconda installer must have, if none is pinned for it
+        #[arg(long)]
+        sha256: Option<String>,
+    },
+    /// Stop BrainDrive and remove everything the install created
+    Uninstall {
+        /// List what would be removed, with sizes, without removing it
+        #[arg(long)]
+        dry_run: bool,
+        /// Keep BrainDrive's settings, database and uploads
+        #[arg(long)]
+        keep_user_data: bool,
+        /// Keep the Ollama models the installer pulled
+        #[arg(long)]
+        keep_models: bool,
+        /// Checkout to remove, if it was cloned somewhere other than ~/BrainDrive
+        #[arg(long)]
+        repo_path: Option<String>,
+        #[command(flatten)]
+        ports: Ports,
+    },
+    /// Update the checkout, reinstall changed dependencies and restart BrainDrive
+    Update {
+        /// Check out this tag instead of the latest commit of the current branch
+        #[arg(long)]
+        tag: Option<String>,
+        #[arg(long)]
+        repo_path: Option<String>,
+        #[command(flatten)]
+        ports: Ports,
+    },
+    /// Collect everything the install downloads into a bundle for offline machines
+    ///
+    /// Run on a connected machine with the same OS and architecture as the
+    /// offline one. For a private repository, put an access token in
+    /// BRAINDRIVE_GIT_TOKEN.
+    BuildBundle {
+        /// Directory to write the bundle to; must not exist or be empty
+        output_path: String,
+        #[arg(long)]
+        repo_url: Option<String>,
+        /// Branch, tag or full commit hash; the remote's default branch by default
+        #[arg(long = "ref")]
+        git_ref: Option<String>,
+        /// Ollama model to include (repeatable); it must already be pulled here
+        #[arg(long = "model")]
+        models: Vec<String>,
+        /// Write OUTPUT_PATH.tar.gz instead of a directory
+        #[arg(long)]
+        tarball: bool,
+        /// SHA-256 the Miniconda installer must have, if none is pinned for it
+        #[arg(long)]
+        sha256: Option<String>,
+    },
+    /// Install BrainDrive from an offline bundle without network access
+    InstallBundle {
+        /// Bundle directory or .tar.gz file
+        bundle_path: String,
+    },
+    /// Delete cached installers and partial downloads
+    ClearDownloadCache,
+    /// Show or change the proxy and CA settings used for every download
+    ///
+    /// With no options, prints the settings in effect and where each came
+    /// from. An empty value removes a saved setting, so the environment
+    /// variable applies again.
+    Network {
+        #[arg(long)]
+        http_proxy: Option<String>,
+        #[arg(long)]
+        https_proxy: Option<String>,
+        /// Comma-separated hosts and domains to reach directly
+        #[arg(long)]
+        no_proxy: Option<String>,
+        /// PEM file with the CA certificates of a TLS-intercepting proxy
+        #[arg(long)]
+        ca_bundle: Option<String>,
+        /// Remove every saved setting before applying the options
+        #[arg(long)]
+        clear: bool,
+    },
+    /// Show or replace the mirrors tried before each public source
+    ///
+    /// Each option is repeatable, in the order the mirrors are tried, and
+    /// replaces that ecosystem's saved list; pass "" to remove it. With no
+    /// options, prints the saved mirrors.
+    Mirrors {
+        /// Directory holding the Miniconda3-* installers
+        #[arg(long)]
+        miniconda: Vec<String>,
+        /// Channel URL mirroring conda-forge
+        #[arg(long)]
+        conda: Vec<String>,
+        /// PyPI simple index URL
+        #[arg(long)]
+        pypi: Vec<String>,
+        /// npm registry URL
+        #[arg(long)]
+        npm: Vec<String>,
+        /// Remove every saved list before applying the options
+        #[arg(long)]
+        clear: bool,
+    },
+    /// Show or change the download bandwidth cap
+    ///
+    /// Downloads already running in the app follow a change within a second.
+    /// Ollama model pulls are not capped.
+    DownloadLimit {
+        /// Megabits per second; 0 removes the cap. Omit to show it
+        limit_mbps: Option<f64>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum DepsTarget {
+    All,
+    Backend,
+    Frontend,
+}
+
+#[derive(Debug, clap::Args)]
+struct Ports {
+    #[arg(long, default_value_t = default_frontend_port())]
+    frontend_port: u16,
+    #[arg(long, default_value_t = default_backend_port())]
+    backend_port: u16,
+}
+
+impl Ports {
+    /// Services on these ports, as if this process had started them
+    ///
+    /// Each CLI run is a new process, so `stop` and `status` can't know the PIDs
+    /// `start` spawned; the dispatcher falls back to the ports.
+    fn process_state(&self) -> ProcessState {
+        let service = |name: &str, port: u16| ServiceInfo {
+            name: name.to_string(),
+            pid: None,
+            port,
+            running: false,
+        };
+        Arc::new(Mutex::new(BrainDriveState {
+            backend: Some(service("backend", self.backend_port)),
+            frontend: Some(service("frontend", self.frontend_port)),
+        }))
+    }
+}
+
+/// Parse arguments, run one command and report how it went
+pub fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    if let Err(e) = logging::init_logging() {
+        eprintln!("Warning: Failed to initialize logging: {}", e);
+    }
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Error: Failed to start async runtime: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    runtime.block_on(run(cli))
+}
+
+async fn run(cli: Cli) -> ExitCode {
+    let cancel = CancellationToken::new();
+    let on_interrupt = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("\nCancelling...");
+            on_interrupt.cancel();
+        }
+    });
+
+    let (outbound, progress) = Outbound::channel();
+    let printer = tokio::spawn(print_progress(progress, cli.json));
+
+    let sender = Arc::new(Mutex::new(outbound));
+    tracing::info!(command = ?cli.command, "Running CLI command");
+    let result = execute(cli.command, sender, &cancel).await;
+    // All senders are gone now, so the printer drains what is left and stops
+    let _ = printer.await;
+
+    let result = match result {
+        Err(_) if cancel.is_cancelled() => Err(DispatchError::Cancelled),
+        result => result,
+    };
+    print_result(&result, cli.json);
+
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(DispatchError::Cancelled) => ExitCode::from(EXIT_CANCELLED),
+        Err(_) => ExitCode::FAILURE,
+    }
+}
+
+async fn execute(
+    command: Command,
+    sender: Arc<Mutex<Outbound>>,
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
+    let request_id = CLI_REQUEST_ID.to_string();
+    match command {
+        Command::Detect => dispatcher::detect_system().await,
+        Command::Install { no_resume } => {
+            let process_state = process_manager::new_process_state();
+            install_plan::run_install_plan(None, !no_resume, request_id, sender, cancel, &process_state).await
+        }
+        Command::State => {
+            serde_json::to_value(install_state::load()).map_err(|e| DispatchError::internal(e.to_string()))
+        }
+        Command::InstallConda { sha256 } => {
+            let run = dispatcher::install_conda(sha256, request_id.clone(), sender, cancel);
+            install_state::journaled("install_conda", &request_id, run).await
+        }
+        Command::InstallGit { sha256 } => {
+            let run = dispatcher::install_git(sha256, request_id.clone(), sender, cancel);
+            install_state::journaled("install_git", &request_id, run).await
+        }
+        Command::Clone {
+            repo_url,
+            target_path,
+            git_ref,
+            depth,
+            submodules,
+        } => {
+            // From the environment rather than an argument, which other users can see
+            let options = CloneOptions {
+                git_ref,
+                depth: Some(depth),
+                submodules,
+                token: std::env::var(GIT_TOKEN_VAR).ok().map(GitToken::new),
+            };
+            let run = dispatcher::clone_repo(repo_url, target_path, options, cancel);
+            install_state::journaled("clone_repo", &request_id, run).await
+        }
+        Command::CreateEnv { env_name, force } => {
+            let run = dispatcher::create_conda_env(env_name, Some(force), cancel);
+            install_state::journaled("create_conda_env", &request_id, run).await
+        }
+        Command::InstallDeps { env_name, repo_path, target } => match target {
+            DepsTarget::All => {
+                let run = dispatcher::install_all_deps(env_name, repo_path, cancel);
+                install_state::journaled("install_all_deps", &request_id, run).await
+            }
+            DepsTarget::Backend => {
+                let run = dispatcher::install_backend_deps(env_name, repo_path, cancel);
+                install_state::journaled("install_backend_deps", &request_id, run).await
+            }
+            DepsTarget::Frontend => {
+                let run = dispatcher::install_frontend_deps(env_name, repo_path, cancel);
+                install_state::journaled("install_frontend_deps", &request_id, run).await
+            }
+        },
+        Command::SetupEnv { repo_path } => {
+            install_state::journaled("setup_env_file", &request_id, dispatcher::setup_env_file(repo_path)).await
+        }
+        Command::Start { ports } => {
+            let process_state = ports.process_state();
+            let run = dispatcher::start_braindrive(ports.frontend_port, ports.backend_port, &process_state);
+            install_state::journaled("start_braindrive", &request_id, run).await
+        }
+        Command::Stop { ports } => dispatcher::stop_braindrive(&ports.process_state()).await,
+        Command::Status { ports } => dispatcher::get_braindrive_status(&ports.process_state()).await,
+        Command::Verify => {
+            let process_state = process_manager::new_process_state();
+            verify::verify_installation(&process_state, cancel).await
+        }
+        Command::Repair { checks, sha256 } => {
+            let process_state = process_manager::new_process_state();
+            let only = (!checks.is_empty()).then_some(checks);
+            verify::repair_installation(only, sha256, request_id, sender, &process_state, cancel).await
+        }
+        Command::Uninstall {
+            dry_run,
+            keep_user_data,
+            keep_models,
+            repo_path,
+            ports,
+        } => {
+            let options = UninstallOptions {
+                dry_run,
+                keep_user_data,
+                keep_models,
+                repo_path,
+            };
+            uninstall::uninstall_braindrive(options, &ports.process_state(), cancel).await
+        }
+        Command::Update { tag, repo_path, ports } => {
+            let process_state = ports.process_state();
+            let run = update::update_braindrive(
+                tag,
+                repo_path,
+                ports.frontend_port,
+                ports.backend_port,
+                request_id.clone(),
+                sender,
+                &process_state,
+                cancel,
+            );
+            install_state::journaled("update_braindrive", &request_id, run).await
+        }
+        Command::BuildBundle {
+            output_path,
+            repo_url,
+            git_ref,
+            models,
+            tarball,
+            sha256,
+        } => {
+            let options = BundleOptions {
+                output_path,
+                repo_url,
+                git_ref,
+                token: std::env::var(GIT_TOKEN_VAR).ok().map(GitToken::new),
+                models,
+                tarball,
+                sha256,
+            };
+            offline_bundle::build_offline_bundle(options, request_id, sender, cancel).await
+        }
+        Command::InstallBundle { bundle_path } => {
+            let run = offline_bundle::install_offline_bundle(bundle_path, request_id.clone(), sender, cancel);
+            install_state::journaled("install_offline_bundle", &request_id, run).await
+        }
+        Command::ClearDownloadCache => download_cache::clear_download_cache().await,
+        Command::Network {
+            http_proxy,
+            https_proxy,
+            no_proxy,
+            ca_bundle,
+            clear,
+        } => {
+            let settings = NetworkSettings { http_proxy, https_proxy, no_proxy, ca_bundle };
+            network::configure_network(settings, clear).await
+        }
+        Command::Mirrors {
+            miniconda,
+            conda,
+            pypi,
+            npm,
+            clear,
+        } => {
+            // An option left out keeps its list
+            let given = |urls: Vec<String>| (!urls.is_empty()).then_some(urls);
+            let update = MirrorUpdate {
+                miniconda: given(miniconda),
+                conda: given(conda),
+                pypi: given(pypi),
+                npm: given(npm),
+            };
+            mirrors::configure_mirrors(update, clear).await
+        }
+        Command::DownloadLimit { limit_mbps } => bandwidth::set_download_limit(limit_mbps).await,
+    }
+}
+
+/// Render progress on stderr until every sender is dropped
+///
+/// On a terminal each update overwrites the previous line; otherwise (CI logs)
+/// one line is printed per change.
+async fn print_progress(mut progress: mpsc::UnboundedReceiver<OutgoingMessage>, json: bool) {
+    let interactive = std::io::stderr().is_terminal();
+    let mut last_line: Option<String> = None;
+
+    while let Some(message) = progress.recv().await {
+        let OutgoingMessage::Progress { percent, message: text, .. } = &message else {
+            continue;
+        };
+        if json {
+            if let Ok(line) = serde_json::to_string(&message) {
+                eprintln!("{}", line);
+            }
+            continue;
+        }
+
+        let line = match percent {
+            Some(percent) => format!("[{:>3}%] {}", percent, text),
+            None => text.clone(),
+        };
+        if last_line.as_deref() == Some(line.as_str()) {
+            continue;
+        }
+        if interactive {
+            eprint!("\r\x1b[2K{}", line);
+            let _ = std::io::stderr().flush();
+        } else {
+            eprintln!("{}", line);
+        }
+        last_line = Some(line);
+    }
+
+    if interactive && last_line.is_some() {
+        eprintln!();
+    }
+}
+
+fn print_result(result: &Result<Value, DispatchError>, json: bool) {
+    let text = match (result, json) {
+        (Ok(value), true) => format!("{}\n", value),
+        (Ok(value), false) => format_fields(value, 0),
+        (Err(e), true) => format!("{}\n", json!({ "error": e.to_string(), "error_details": e })),
+        (Err(e), false) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    // Unlike println!, don't panic when piped into `head` and the pipe closes
+    let _ = std::io::stdout().lock().write_all(text.as_bytes());
+}
+
+/// Render a result as indented `key: value` lines
+fn format_fields(value: &Value, indent: usize) -> String {
+    let pad = "  ".repeat(indent);
+    match value {
+        Value::Object(fields) => fields
+            .iter()
+            .map(|(key, value)| match value {
+                Value::Object(_) => format!("{}{}:\n{}", pad, key, format_fields(value, indent + 1)),
+                _ => format!("{}{}: {}\n", pad, key, scalar_text(value)),
+            })
+            .collect(),
+        _ => format!("{}{}\n", pad, scalar_text(value)),
+    }
+}
+
+/// Strings without quotes; everything else as compact JSON
+fn scalar_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => "-".to_string(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_ports_default_to_the_tool_call_defaults() {
+        let cli = Cli::try_parse_from(["braindrive-bootstrap", "status", "--json"]).unwrap();
+        assert!(cli.json);
+        let Command::Status { ports } = cli.command else {
+            panic!("expected status");
+        };
+        assert_eq!(ports.frontend_port, default_frontend_port());
+        assert_eq!(ports.backend_port, default_backend_port());
+    }
+
+    #[test]
+    fn test_human_output_nests_objects() {
+        let value = json!({
+            "message": "BrainDrive services stopped",
+            "backend": { "port": 8005, "pid": null },
+            "gpus": [],
+        });
+        assert_eq!(
+            format_fields(&value, 0),
+            "backend:\n  pid: -\n  port: 8005\ngpus: []\nmessage: BrainDrive services stopped\n"
+        );
+    }
+
+    #[test]
+    fn test_install_deps_target() {
+        let cli = Cli::try_parse_from(["braindrive-bootstrap", "install-deps", "--target", "backend"]).unwrap();
+        assert!(matches!(cli.command, Command::InstallDeps { target: DepsTarget::Backend, .. }));
+        assert!(Cli::try_parse_from(["braindrive-bootstrap", "install-deps", "--target", "docs"]).is_err());
+    }
+}
@@ -1,37 +1,50 @@
+use crate::checksum;
+use crate::download;
+use crate::error::{ComponentFailure, DispatchError};
+use crate::git_remote::{self, CloneTarget, GitAuth, GitToken};
+use crate::mirrors::{self, Ecosystem, Mirror};
+use crate::network::NetworkConfig;
 use crate::process_manager::{
-    self, is_port_in_use, kill_process, kill_process_on_port,
+    self, is_port_in_use, kill_process, kill_process_on_port, kill_process_tree,
     spawn_detached, wait_for_port, wait_for_port_free, ProcessState, ServiceInfo,
 };
 use crate::system_info;
-use crate::websocket::{send_message, OutgoingMessage};
-use crate::WsSender;
+use crate::protocol::{send_message, Outbound, OutgoingMessage};
 use regex::Regex;
 use serde_json::{json, Value};
-use std::net::TcpListener;
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Command;
 use tokio::sync::Mutex;
 use tokio::time::{sleep, Duration};
+use tokio_util::sync::CancellationToken;
 
 #[cfg(target_os = "windows")]
 use std::os::windows::process::CommandExt;
 
+// Tools only the backend calls (Ollama pulls, port checks)
+#[cfg(feature = "gui")]
+use crate::{bandwidth, install_state};
+#[cfg(feature = "gui")]
+use std::net::TcpListener;
+#[cfg(feature = "gui")]
+use tokio::io::{AsyncBufReadExt, BufReader};
+
 #[cfg(target_os = "windows")]
 const CREATE_NO_WINDOW: u32 = 0x08000000;
 
 const DEFAULT_REPO_DIR: &str = "BrainDrive";
 const CONDA_ENV_NAME: &str = "BrainDriveDev";
+/// Packages the BrainDrive conda environment is created with, from conda-forge
+pub(crate) const CONDA_ENV_PACKAGES: &[&str] = &["python=3.11", "nodejs", "git"];
 const OLLAMA_DEFAULT_PORT: u16 = 11434;
 /// Isolated Miniconda is installed inside the BrainDrive directory
 /// This prevents conflicts with any existing user conda installation
 const ISOLATED_MINICONDA_DIR: &str = "miniconda3";
-const DOWNLOAD_MAX_RETRIES: u8 = 3;
-const DOWNLOAD_RETRY_DELAY_SECS: u64 = 2;
-/// Timeout for establishing HTTP connection (seconds)
-const DOWNLOAD_CONNECT_TIMEOUT_SECS: u64 = 30;
+/// Lines of `ollama pull` output kept for the error report
+#[cfg(feature = "gui")]
+const OLLAMA_RECENT_LINES: usize = 20;
 
 /// Known paths where Ollama might be installed
 /// GUI apps often have minimal PATH, so we check absolute paths directly
@@ -44,7 +57,7 @@ const OLLAMA_KNOWN_PATHS: &[&str] = &[
 
 /// Find Ollama binary in known paths
 /// Returns the full path if found, None otherwise
-fn find_ollama_binary() -> Option<PathBuf> {
+pub(crate) fn find_ollama_binary() -> Option<PathBuf> {
     for path in OLLAMA_KNOWN_PATHS {
         let path = PathBuf::from(path);
         if path.exists() {
@@ -70,14 +83,14 @@ fn find_ollama_binary() -> Option<PathBuf> {
 
 /// Get the path to the isolated Miniconda installation directory
 /// This is ~/BrainDrive/miniconda3 - completely separate from any system conda
-fn get_isolated_miniconda_dir() -> Option<PathBuf> {
+pub(crate) fn get_isolated_miniconda_dir() -> Option<PathBuf> {
     dirs::home_dir().map(|home| home.join(DEFAULT_REPO_DIR).join(ISOLATED_MINICONDA_DIR))
 }
 
 /// Get the path to the isolated conda binary
 /// Returns the full path to conda binary in ~/BrainDrive/miniconda3/bin/conda
 /// Only returns the path if the installation is valid (has conda binary and conda.sh on Unix)
-fn get_isolated_conda_binary() -> Option<PathBuf> {
+pub(crate) fn get_isolated_conda_binary() -> Option<PathBuf> {
     let miniconda_dir = get_isolated_miniconda_dir()?;
 
     #[cfg(target_os = "windows")]
@@ -102,13 +115,15 @@ fn get_isolated_conda_binary() -> Option<PathBuf> {
 }
 
 /// Detect system information and return it as JSON
-pub async fn detect_system() -> Result<Value, String> {
+pub async fn detect_system() -> Result<Value, DispatchError> {
     let info = system_info::detect().await?;
-    serde_json::to_value(info).map_err(|e| format!("Failed to encode system info: {}", e))
+    serde_json::to_value(info)
+        .map_err(|e| DispatchError::internal(format!("Failed to encode system info: {}", e)))
 }
 
 /// Check if a port is available
-pub async fn check_port(port: u16) -> Result<Value, String> {
+#[cfg(feature = "gui")]
+pub async fn check_port(port: u16) -> Result<Value, DispatchError> {
     let addr = format!("127.0.0.1:{}", port);
     let available = TcpListener::bind(&addr).is_ok();
 
@@ -124,10 +139,11 @@ pub async fn install_conda_env(
     env_name: &str,
     repo_path: Option<String>,
     environment_file: Option<String>,
-) -> Result<Value, String> {
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
     // Get the conda binary path (prefers isolated installation)
     let conda_path = find_conda_binary()
-        .ok_or("Conda is not installed. Please install it first using the install_conda tool.")?;
+        .ok_or_else(conda_not_installed)?;
 
     let sanitized_env = sanitize_env_name(env_name)?;
     let repo = resolve_repo_path(repo_path)?;
@@ -142,7 +158,7 @@ pub async fn install_conda_env(
         .arg("--file")
         .arg(&env_file);
 
-    let result = run_command(command).await?;
+    let result = check_output("conda env update", run_command(command, cancel).await?)?;
 
     Ok(json!({
         "success": result.success,
@@ -159,9 +175,11 @@ pub async fn install_conda_env(
 /// Downloads the installer for the user's platform and runs it in batch mode
 /// Installs to ~/BrainDrive/miniconda3 (isolated from any system conda)
 pub async fn install_conda(
+    sha256: Option<String>,
     request_id: String,
-    sender: Arc<Mutex<Option<WsSender>>>,
-) -> Result<Value, String> {
+    sender: Arc<Mutex<Outbound>>,
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
     // Check if isolated conda is already installed at ~/BrainDrive/miniconda3
     if let Some(conda_path) = get_isolated_conda_binary() {
         return Ok(json!({
@@ -203,30 +221,12 @@ pub async fn install_conda(
         }
     }
 
-    // Determine the correct installer URL based on OS and architecture
-    let os = std::env::consts::OS;
-    let arch = std::env::consts::ARCH;
-
-    let installer_url = match (os, arch) {
-        ("macos", "aarch64") => "https://repo.anaconda.com/miniconda/Miniconda3-latest-MacOSX-arm64.sh",
-        ("macos", "x86_64") => "https://repo.anaconda.com/miniconda/Miniconda3-latest-MacOSX-x86_64.sh",
-        ("linux", "x86_64") => "https://repo.anaconda.com/miniconda/Miniconda3-latest-Linux-x86_64.sh",
-        ("linux", "aarch64") => "https://repo.anaconda.com/miniconda/Miniconda3-latest-Linux-aarch64.sh",
-        ("windows", "x86_64") => "https://repo.anaconda.com/miniconda/Miniconda3-latest-Windows-x86_64.exe",
-        _ => return Err(format!("Unsupported platform: {} {}", os, arch)),
-    };
-
     // Create temp directory for installer
     let temp_dir = home_dir.join(".braindrive-installer").join("downloads");
     std::fs::create_dir_all(&temp_dir)
         .map_err(|e| format!("Failed to create download directory: {}", e))?;
 
-    let installer_filename = if os == "windows" {
-        "Miniconda3-installer.exe"
-    } else {
-        "Miniconda3-installer.sh"
-    };
-    let installer_path = temp_dir.join(installer_filename);
+    let installer_path = temp_dir.join(miniconda_installer_filename());
 
     // Send initial progress
     let _ = send_message(&sender, OutgoingMessage::Progress {
@@ -239,13 +239,16 @@ pub async fn install_conda(
     }).await;
 
     // Download the installer with progress
-    download_file_with_progress(
-        installer_url,
-        &installer_path,
-        request_id.clone(),
-        sender.clone(),
-        "install_conda",
-    ).await?;
+    let (installer_sha256, installer_url, mirror) =
+        download_miniconda_installer(
+            &installer_path,
+            sha256.as_deref(),
+            &request_id,
+            &sender,
+            "install_conda",
+            cancel,
+        )
+        .await?;
 
     // Send progress for installation phase
     let _ = send_message(&sender, OutgoingMessage::Progress {
@@ -258,26 +261,28 @@ pub async fn install_conda(
     }).await;
 
     // Run the installer
-    let install_result = if os == "windows" {
-        run_windows_miniconda_installer(&installer_path, &install_path).await
-    } else {
-        run_unix_miniconda_installer(&installer_path, &install_path).await
-    };
+    let install_result = run_miniconda_installer(&installer_path, &install_path, cancel).await;
 
     // Clean up installer file
     let _ = std::fs::remove_file(&installer_path);
 
+    // A half-written install would be mistaken for a broken one next time
+    if cancel.is_cancelled() {
+        let _ = std::fs::remove_dir_all(&install_path);
+        return Err(DispatchError::Cancelled);
+    }
+
     match install_result {
         Ok(()) => {
             // Verify installation
-            let conda_binary = if os == "windows" {
+            let conda_binary = if std::env::consts::OS == "windows" {
                 install_path.join("Scripts/conda.exe")
             } else {
                 install_path.join("bin/conda")
             };
 
             if !conda_binary.exists() {
-                return Err("Miniconda installation completed but conda binary not found".to_string());
+                return Err(DispatchError::internal("Miniconda installation completed but conda binary not found"));
             }
 
             // Configure conda to use conda-forge and avoid TOS issues
@@ -291,22 +296,7 @@ pub async fn install_conda(
                 bytes_total: None,
             }).await;
 
-            // Set conda-forge as default channel and remove defaults that require TOS
-            let config_commands = [
-                ["config", "--set", "auto_activate_base", "false"],
-                ["config", "--add", "channels", "conda-forge"],
-                ["config", "--set", "channel_priority", "strict"],
-                ["config", "--remove", "channels", "defaults"],
-            ];
-
-            for args in &config_commands {
-                let mut cmd = Command::new(&conda_binary);
-                cmd.args(*args);
-                #[cfg(target_os = "windows")]
-                cmd.creation_flags(CREATE_NO_WINDOW);
-                // Ignore errors - some configs may fail if already set
-                let _ = cmd.output().await;
-            }
+            configure_conda(&conda_binary).await;
 
             // Send completion progress
             let _ = send_message(&sender, OutgoingMessage::Progress {
@@ -324,10 +314,13 @@ pub async fn install_conda(
                 "conda_path": conda_binary.to_string_lossy(),
                 "install_path": install_path.to_string_lossy(),
                 "isolated": true,
+                "installer_sha256": installer_sha256,
+                "installer_url": installer_url,
+                "mirror": mirror.label(),
                 "message": "Miniconda installed successfully to BrainDrive directory"
             }))
         }
-        Err(e) => Err(format!("Failed to install Miniconda: {}", e)),
+        Err(e) => Err(e),
     }
 }
 
@@ -337,7 +330,7 @@ pub async fn install_conda(
 /// 2. User home directory installations (~/miniconda3, ~/anaconda3)
 /// 3. System-wide paths
 /// 4. PATH lookup via which/where
-fn find_conda_binary() -> Option<PathBuf> {
+pub(crate) fn find_conda_binary() -> Option<PathBuf> {
     // FIRST: Check the isolated BrainDrive installation (highest priority)
     if let Some(isolated_conda) = get_isolated_conda_binary() {
         return Some(isolated_conda);
@@ -404,232 +397,129 @@ fn find_conda_binary() -> Option<PathBuf> {
     None
 }
 
-/// Download a file with progress updates
-async fn download_file_with_progress(
-    url: &str,
-    dest: &PathBuf,
-    request_id: String,
-    sender: Arc<Mutex<Option<WsSender>>>,
-    operation: &str,
-) -> Result<(), String> {
-    let client = reqwest::Client::builder()
-        .user_agent("BrainDrive-Installer/1.0")
-        .connect_timeout(Duration::from_secs(DOWNLOAD_CONNECT_TIMEOUT_SECS))
-        // Note: No overall timeout set - large downloads (100MB+) need unlimited time
-        // The connect_timeout handles initial connection issues
-        // Streaming errors are handled per-chunk in download_file_with_progress_once
-        .build()
-        .map_err(|e| {
-            let err_msg = format!("Failed to build HTTP client: {}", e);
-            tracing::error!("{}", err_msg);
-            err_msg
-        })?;
-
-    let mut last_error: Option<String> = None;
-
-    for attempt in 1..=DOWNLOAD_MAX_RETRIES {
-        if attempt > 1 {
-            let _ = send_message(&sender, OutgoingMessage::Progress {
-                id: request_id.clone(),
-                operation: operation.to_string(),
-                percent: Some(0),
-                message: format!(
-                    "Retrying download (attempt {} of {})...",
-                    attempt, DOWNLOAD_MAX_RETRIES
-                ),
-                bytes_downloaded: None,
-                bytes_total: None,
-            }).await;
-        }
-
-        match download_file_with_progress_once(
-            &client,
-            url,
-            dest,
-            request_id.clone(),
-            sender.clone(),
-            operation,
-        ).await {
-            Ok(()) => return Ok(()),
-            Err(e) => {
-                tracing::warn!(
-                    "Download attempt {} failed for {}: {}",
-                    attempt, url, e
-                );
-                last_error = Some(e);
-                let _ = std::fs::remove_file(dest);
-                if attempt < DOWNLOAD_MAX_RETRIES {
-                    sleep(Duration::from_secs(DOWNLOAD_RETRY_DELAY_SECS * attempt as u64)).await;
-                }
-            }
-        }
-    }
-
-    #[cfg(not(target_os = "windows"))]
-    {
-        let _ = send_message(&sender, OutgoingMessage::Progress {
-            id: request_id.clone(),
-            operation: operation.to_string(),
-            percent: Some(0),
-            message: "Retrying download with system curl...".to_string(),
-            bytes_downloaded: None,
-            bytes_total: None,
-        }).await;
-
-        if let Err(curl_err) = download_file_with_curl(url, dest).await {
-            let combined_error = match last_error {
-                Some(err) => format!("{} | curl fallback failed: {}", err, curl_err),
-                None => format!("curl fallback failed: {}", curl_err),
-            };
-            let final_error = format!(
-                "Download failed after {} attempts: {}",
-                DOWNLOAD_MAX_RETRIES,
-                combined_error
-            );
-            tracing::error!("{}", final_error);
-            return Err(final_error);
-        }
-
-        tracing::info!("Download succeeded via curl fallback for {}", url);
-        return Ok(());
-    }
+/// Miniconda installers by OS and architecture, as named on repo.anaconda.com and its mirrors
+///
+/// A fixed release rather than `-latest-`, so each file matches its pinned digest.
+const MINICONDA_INSTALLERS: &[(&str, &str, &str)] = &[
+    ("macos", "aarch64", "Miniconda3-py311_23.11.0-2-MacOSX-arm64.sh"),
+    ("macos", "x86_64", "Miniconda3-py311_23.11.0-2-MacOSX-x86_64.sh"),
+    ("linux", "x86_64", "Miniconda3-py311_23.11.0-2-Linux-x86_64.sh"),
+    ("linux", "aarch64", "Miniconda3-py311_23.11.0-2-Linux-aarch64.sh"),
+    ("windows", "x86_64", "Miniconda3-py311_23.11.0-2-Windows-x86_64.exe"),
+];
 
-    // Windows-only fallback (no curl available by default)
-    #[cfg(target_os = "windows")]
-    {
-        let last_error = last_error.unwrap_or_else(|| "Unknown download error".to_string());
-        let final_error = format!(
-            "Download failed after {} attempts: {}",
-            DOWNLOAD_MAX_RETRIES, last_error
-        );
-        tracing::error!("{}", final_error);
-        return Err(final_error);
-    }
+/// Git for Windows installers; the 32-bit one serves every other architecture
+const GIT_WINDOWS_64_BIT: &str =
+    "https://github.com/git-for-windows/git/releases/download/v2.43.0.windows.1/Git-2.43.0-64-bit.exe";
+const GIT_WINDOWS_32_BIT: &str =
+    "https://github.com/git-for-windows/git/releases/download/v2.43.0.windows.1/Git-2.43.0-32-bit.exe";
+
+/// Miniconda installer for this OS and architecture
+fn miniconda_installer_name() -> Result<&'static str, DispatchError> {
+    MINICONDA_INSTALLERS
+        .iter()
+        .find(|(os, arch, _)| *os == std::env::consts::OS && *arch == std::env::consts::ARCH)
+        .map(|(_, _, name)| *name)
+        .ok_or_else(DispatchError::unsupported_platform)
+}
 
-    // This is unreachable on non-Windows (curl fallback always returns above)
-    // but needed for type checking when cfg doesn't match
-    #[cfg(not(target_os = "windows"))]
-    unreachable!()
+/// Every upstream installer URL an install can download, on any platform
+#[cfg(test)]
+pub(crate) fn installer_urls() -> Vec<String> {
+    MINICONDA_INSTALLERS
+        .iter()
+        .map(|(_, _, name)| mirrors::miniconda_url(&Mirror::Default, name))
+        .chain([GIT_WINDOWS_64_BIT.to_string(), GIT_WINDOWS_32_BIT.to_string()])
+        .collect()
 }
 
-async fn download_file_with_progress_once(
-    client: &reqwest::Client,
-    url: &str,
-    dest: &PathBuf,
-    request_id: String,
-    sender: Arc<Mutex<Option<WsSender>>>,
+/// Download the Miniconda installer to `dest` from the first mirror that serves it
+///
+/// Held to `sha256` when given. Returns the installer's SHA-256, the URL it
+/// came from and the mirror.
+pub(crate) async fn download_miniconda_installer(
+    dest: &Path,
+    sha256: Option<&str>,
+    request_id: &str,
+    sender: &Arc<Mutex<Outbound>>,
     operation: &str,
-) -> Result<(), String> {
-    use tokio::io::AsyncWriteExt;
-
-    tracing::info!("Starting download from {}", url);
+    cancel: &CancellationToken,
+) -> Result<(String, String, Mirror), DispatchError> {
+    let name = miniconda_installer_name()?;
+    // A mirror serves the upstream file, so it is held to the upstream digest
+    let upstream_sha256 = checksum::expected_digest(&mirrors::miniconda_url(&Mirror::Default, name), sha256)?;
+    let upstream_sha256 = upstream_sha256.as_str();
+    let ((sha256, url), mirror) = mirrors::with_fallback(Ecosystem::Miniconda, |mirror| {
+        let url = mirrors::miniconda_url(&mirror, name);
+        async move {
+            let sha256 = download::download_file_with_progress(
+                &url,
+                dest,
+                Some(upstream_sha256),
+                request_id.to_string(),
+                sender.clone(),
+                operation,
+                cancel,
+            )
+            .await?;
+            Ok((sha256, url))
+        }
+    })
+    .await?;
+    Ok((sha256, url, mirror))
+}
 
-    let response = client
-        .get(url)
-        .send()
-        .await
-        .map_err(|e| {
-            // Log detailed error info for debugging
-            let err_msg = format!("Failed to start download from {}: {} (is_timeout={}, is_connect={}, is_request={})",
-                url, e, e.is_timeout(), e.is_connect(), e.is_request());
-            tracing::error!("{}", err_msg);
-            format!("Failed to start download: {}", e)
-        })?;
-
-    if !response.status().is_success() {
-        let err_msg = format!(
-            "Download failed with status: {} ({})",
-            response.status(),
-            url
-        );
-        tracing::error!("{}", err_msg);
-        return Err(err_msg);
+/// File name the Miniconda installer is saved under
+pub(crate) fn miniconda_installer_filename() -> &'static str {
+    if std::env::consts::OS == "windows" {
+        "Miniconda3-installer.exe"
+    } else {
+        "Miniconda3-installer.sh"
     }
+}
 
-    let total_size = response.content_length();
-    let mut downloaded: u64 = 0;
-
-    let mut file = tokio::fs::File::create(dest)
-        .await
-        .map_err(|e| format!("Failed to create file: {}", e))?;
-
-    let mut stream = response.bytes_stream();
-    let mut last_percent: u8 = 0;
-
-    while let Some(chunk) = futures_util::StreamExt::next(&mut stream).await {
-        let chunk = chunk.map_err(|e| format!("Download error: {}", e))?;
-        file.write_all(&chunk)
-            .await
-            .map_err(|e| format!("Failed to write file: {}", e))?;
-
-        downloaded += chunk.len() as u64;
-
-        // Calculate and send progress (only if we know total size)
-        if let Some(total) = total_size {
-            let percent = ((downloaded as f64 / total as f64) * 50.0) as u8; // 0-50% for download
-            if percent > last_percent {
-                last_percent = percent;
-                let _ = send_message(&sender, OutgoingMessage::Progress {
-                    id: request_id.clone(),
-                    operation: operation.to_string(),
-                    percent: Some(percent),
-                    message: format!("Downloading... {:.1} MB / {:.1} MB",
-                        downloaded as f64 / 1_048_576.0,
-                        total as f64 / 1_048_576.0
-                    ),
-                    bytes_downloaded: Some(downloaded),
-                    bytes_total: Some(total),
-                }).await;
-            }
-        }
+/// Run the Miniconda installer for this OS, installing to `install_path`
+pub(crate) async fn run_miniconda_installer(
+    installer_path: &PathBuf,
+    install_path: &PathBuf,
+    cancel: &CancellationToken,
+) -> Result<(), DispatchError> {
+    if std::env::consts::OS == "windows" {
+        run_windows_miniconda_installer(installer_path, install_path, cancel).await
+    } else {
+        run_unix_miniconda_installer(installer_path, install_path, cancel).await
     }
-
-    file.flush().await.map_err(|e| format!("Failed to flush file: {}", e))?;
-
-    Ok(())
 }
 
-#[cfg(not(target_os = "windows"))]
-async fn download_file_with_curl(url: &str, dest: &PathBuf) -> Result<(), String> {
-    tracing::info!("Attempting curl fallback download from {}", url);
-
-    let output = Command::new("curl")
-        .arg("--fail")
-        .arg("--location")
-        .arg("--show-error")
-        .arg("--connect-timeout")
-        .arg("30")
-        .arg("--max-time")
-        .arg("600")  // 10 minute max for large downloads
-        .arg("--retry")
-        .arg("2")
-        .arg("--output")
-        .arg(dest)
-        .arg(url)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .await
-        .map_err(|e| {
-            let err_msg = format!("Failed to run curl: {}", e);
-            tracing::error!("{}", err_msg);
-            err_msg
-        })?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let err_msg = format!("curl download failed (exit code {:?}): {}",
-            output.status.code(), stderr.trim());
-        tracing::error!("{}", err_msg);
-        return Err(err_msg);
+/// Set conda-forge (behind any conda mirrors) as default channel and remove
+/// defaults that require TOS
+pub(crate) async fn configure_conda(conda_binary: &Path) {
+    let channels = mirrors::conda_channels();
+    let mut config_commands = vec![vec!["config", "--set", "auto_activate_base", "false"]];
+    // `--add` puts a channel on top, so the first mirror goes in last
+    for channel in channels.iter().rev() {
+        config_commands.push(vec!["config", "--add", "channels", channel]);
+    }
+    config_commands.push(vec!["config", "--set", "channel_priority", "strict"]);
+    config_commands.push(vec!["config", "--remove", "channels", "defaults"]);
+
+    for args in &config_commands {
+        let mut cmd = Command::new(conda_binary);
+        cmd.args(args);
+        NetworkConfig::current().apply(&mut cmd);
+        #[cfg(target_os = "windows")]
+        cmd.creation_flags(CREATE_NO_WINDOW);
+        // Ignore errors - some configs may fail if already set
+        let _ = cmd.output().await;
     }
-
-    tracing::info!("curl fallback download completed successfully");
-    Ok(())
 }
 
 /// Run the Miniconda installer on Unix (macOS/Linux)
-async fn run_unix_miniconda_installer(installer_path: &PathBuf, install_path: &PathBuf) -> Result<(), String> {
+async fn run_unix_miniconda_installer(
+    installer_path: &PathBuf,
+    install_path: &PathBuf,
+    cancel: &CancellationToken,
+) -> Result<(), DispatchError> {
     // Make installer executable
     #[cfg(unix)]
     {
@@ -642,52 +532,44 @@ async fn run_unix_miniconda_installer(installer_path: &PathBuf, install_path: &P
     // -b = batch mode (no prompts)
     // -p = prefix (install location)
     // -u = update existing installation
-    let output = Command::new("bash")
+    let mut command = Command::new("bash");
+    command
         .arg(installer_path)
         .arg("-b")
         .arg("-p")
         .arg(install_path)
-        .arg("-u")
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .await
-        .map_err(|e| format!("Failed to run installer: {}", e))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Installer failed: {}", stderr));
-    }
+        .arg("-u");
+    check_output("Miniconda installer", run_command(command, cancel).await?)?;
 
     Ok(())
 }
 
 /// Run the Miniconda installer on Windows
 #[cfg(target_os = "windows")]
-async fn run_windows_miniconda_installer(installer_path: &PathBuf, install_path: &PathBuf) -> Result<(), String> {
+async fn run_windows_miniconda_installer(
+    installer_path: &PathBuf,
+    install_path: &PathBuf,
+    cancel: &CancellationToken,
+) -> Result<(), DispatchError> {
     // Run installer silently
     // /S = silent
     // /D= = destination (no space after =)
-    let output = Command::new(installer_path)
+    let mut command = Command::new(installer_path);
+    command
         .arg("/S")
-        .arg(format!("/D={}", install_path.display()))
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .await
-        .map_err(|e| format!("Failed to run installer: {}", e))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Installer failed: {}", stderr));
-    }
+        .arg(format!("/D={}", install_path.display()));
+    check_output("Miniconda installer", run_command(command, cancel).await?)?;
 
     Ok(())
 }
 
 #[cfg(not(target_os = "windows"))]
-async fn run_windows_miniconda_installer(_installer_path: &PathBuf, _install_path: &PathBuf) -> Result<(), String> {
-    Err("Windows installer not supported on this platform".to_string())
+async fn run_windows_miniconda_installer(
+    _installer_path: &PathBuf,
+    _install_path: &PathBuf,
+    _cancel: &CancellationToken,
+) -> Result<(), DispatchError> {
+    Err(DispatchError::unsupported_platform())
 }
 
 /// Install Git automatically
@@ -695,9 +577,11 @@ async fn run_windows_miniconda_installer(_installer_path: &PathBuf, _install_pat
 /// - Windows: Downloads and runs Git installer silently
 /// - Linux: Returns instructions (requires sudo)
 pub async fn install_git(
+    sha256: Option<String>,
     request_id: String,
-    sender: Arc<Mutex<Option<WsSender>>>,
-) -> Result<Value, String> {
+    sender: Arc<Mutex<Outbound>>,
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
     // Check if git is already installed
     if let Some(git_path) = find_git_binary() {
         return Ok(json!({
@@ -711,27 +595,29 @@ pub async fn install_git(
     let os = std::env::consts::OS;
 
     match os {
-        "macos" => install_git_macos(request_id, sender).await,
-        "windows" => install_git_windows(request_id, sender).await,
+        "macos" => install_git_macos(request_id, sender, cancel).await,
+        "windows" => install_git_windows(sha256, request_id, sender, cancel).await,
         "linux" => {
             // Linux typically requires sudo for package manager
-            Ok(json!({
-                "success": false,
-                "needs_manual_install": true,
-                "instructions": "Please install Git using your package manager:\n\
+            Err(DispatchError::needs_manual_action(
+                "Git installation on Linux requires sudo. Please install manually.",
+                Some(
+                    "Please install Git using your package manager:\n\
                     - Ubuntu/Debian: sudo apt install git\n\
                     - Fedora: sudo dnf install git\n\
                     - Arch: sudo pacman -S git\n\n\
-                    After installing, come back and I'll detect it automatically.",
-                "message": "Git installation on Linux requires sudo. Please install manually."
-            }))
+                    After installing, come back and I'll detect it automatically."
+                        .to_string(),
+                ),
+                None,
+            ))
         }
-        _ => Err(format!("Unsupported platform: {}", os)),
+        _ => Err(DispatchError::unsupported_platform()),
     }
 }
 
 /// Find git binary in known paths
-fn find_git_binary() -> Option<PathBuf> {
+pub(crate) fn find_git_binary() -> Option<PathBuf> {
     // Check common paths
     let known_paths = [
         "/usr/bin/git",
@@ -779,12 +665,28 @@ fn find_git_binary() -> Option<PathBuf> {
     None
 }
 
+/// Git from the BrainDrive conda environment, or else one installed on the system
+pub(crate) fn find_braindrive_git() -> Option<PathBuf> {
+    if let Some(miniconda) = get_isolated_miniconda_dir() {
+        let env = miniconda.join("envs").join(CONDA_ENV_NAME);
+        #[cfg(target_os = "windows")]
+        let env_git = env.join("Library").join("bin").join("git.exe");
+        #[cfg(not(target_os = "windows"))]
+        let env_git = env.join("bin").join("git");
+        if env_git.exists() {
+            return Some(env_git);
+        }
+    }
+    find_git_binary()
+}
+
 /// Install Git on macOS via Xcode Command Line Tools
 /// This triggers a native macOS GUI dialog - no terminal needed
 async fn install_git_macos(
     request_id: String,
-    sender: Arc<Mutex<Option<WsSender>>>,
-) -> Result<Value, String> {
+    sender: Arc<Mutex<Outbound>>,
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
     // Send initial progress
     let _ = send_message(&sender, OutgoingMessage::Progress {
         id: request_id.clone(),
@@ -842,7 +744,8 @@ async fn install_git_macos(
                 let mut waited = 0;
 
                 while waited < max_wait_secs {
-                    sleep(Duration::from_secs(poll_interval_secs)).await;
+                    // The Xcode dialog is owned by the OS; cancelling only stops waiting for it
+                    sleep_or_cancel(Duration::from_secs(poll_interval_secs), cancel).await?;
                     waited += poll_interval_secs;
 
                     // Check if git is now available
@@ -877,12 +780,11 @@ async fn install_git_macos(
                 }
 
                 // Timed out waiting for installation
-                Ok(json!({
-                    "success": false,
-                    "pending_install": true,
-                    "message": "Installation dialog was opened. Please complete the installation and try again.",
-                    "instructions": "Click 'Install' in the Xcode Command Line Tools dialog, wait for it to complete, then continue."
-                }))
+                Err(DispatchError::needs_manual_action(
+                    "Installation dialog was opened. Please complete the installation and try again.",
+                    Some("Click 'Install' in the Xcode Command Line Tools dialog, wait for it to complete, then continue.".to_string()),
+                    None,
+                ))
             } else {
                 let stderr = String::from_utf8_lossy(&output.stderr);
                 // Check if it says already installed
@@ -896,18 +798,24 @@ async fn install_git_macos(
                         }));
                     }
                 }
-                Err(format!("Failed to trigger Xcode CLI tools installation: {}", stderr))
+                Err(DispatchError::process_failed(
+                    "xcode-select --install",
+                    output.status.code().unwrap_or(-1),
+                    &stderr,
+                ))
             }
         }
-        Err(e) => Err(format!("Failed to run xcode-select: {}", e)),
+        Err(e) => Err(DispatchError::internal(format!("Failed to run xcode-select: {}", e))),
     }
 }
 
 /// Install Git on Windows by downloading and running the installer silently
 async fn install_git_windows(
+    sha256: Option<String>,
     request_id: String,
-    sender: Arc<Mutex<Option<WsSender>>>,
-) -> Result<Value, String> {
+    sender: Arc<Mutex<Outbound>>,
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
     let home_dir = dirs::home_dir().ok_or("Could not determine home directory")?;
 
     // Send initial progress
@@ -920,14 +828,11 @@ async fn install_git_windows(
         bytes_total: None,
     }).await;
 
-    // Get the latest Git for Windows release URL
-    // We'll use a known stable version to avoid API calls
-    let arch = std::env::consts::ARCH;
-    let installer_url = if arch == "x86_64" {
-        // Use a recent stable version - Git for Windows 2.43.0
-        "https://github.com/git-for-windows/git/releases/download/v2.43.0.windows.1/Git-2.43.0-64-bit.exe"
+    // A pinned release, so the installer matches its digest and no API call is needed
+    let installer_url = if std::env::consts::ARCH == "x86_64" {
+        GIT_WINDOWS_64_BIT
     } else {
-        "https://github.com/git-for-windows/git/releases/download/v2.43.0.windows.1/Git-2.43.0-32-bit.exe"
+        GIT_WINDOWS_32_BIT
     };
 
     // Create temp directory for installer
@@ -947,12 +852,14 @@ async fn install_git_windows(
         bytes_total: None,
     }).await;
 
-    download_file_with_progress(
+    let installer_sha256 = download::download_file_with_progress(
         installer_url,
         &installer_path,
+        sha256.as_deref(),
         request_id.clone(),
         sender.clone(),
         "install_git",
+        cancel,
     ).await?;
 
     // Run the installer silently
@@ -970,21 +877,14 @@ async fn install_git_windows(
     // /NORESTART = don't restart
     // /NOCANCEL = prevent user from cancelling
     // /SP- = skip "This will install..." prompt
-    let output = Command::new(&installer_path)
-        .args(["/VERYSILENT", "/NORESTART", "/NOCANCEL", "/SP-"])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .await
-        .map_err(|e| format!("Failed to run Git installer: {}", e))?;
+    let mut command = Command::new(&installer_path);
+    command.args(["/VERYSILENT", "/NORESTART", "/NOCANCEL", "/SP-"]);
+    let output = run_command(command, cancel).await;
 
     // Clean up installer
     let _ = std::fs::remove_file(&installer_path);
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Git installer failed: {}", stderr));
-    }
+    check_output("Git installer", output?)?;
 
     // Verify installation
     // Give it a moment to finish
@@ -1004,17 +904,22 @@ async fn install_git_windows(
             "success": true,
             "already_installed": false,
             "git_path": git_path.to_string_lossy(),
+            "installer_sha256": installer_sha256,
             "message": "Git installed successfully"
         }))
     } else {
-        Err("Git installation completed but git binary not found. You may need to restart the bootstrapper.".to_string())
+        Err(DispatchError::needs_manual_action(
+            "Git installation completed but git binary not found. You may need to restart the bootstrapper.",
+            None,
+            None,
+        ))
     }
 }
 
 /// Ensure Ollama is installed and running
 /// If installed: starts service if needed
 /// If not installed: returns instructions for manual installation
-pub async fn install_ollama() -> Result<Value, String> {
+pub async fn install_ollama() -> Result<Value, DispatchError> {
     // Check if Ollama binary exists using absolute paths
     if let Some(ollama_path) = find_ollama_binary() {
         let version = get_ollama_version();
@@ -1032,21 +937,14 @@ pub async fn install_ollama() -> Result<Value, String> {
         }
 
         // Installed but not running - start the service
-        let start_result = start_ollama_service().await;
-        let service_ok = start_result.is_ok();
-        let start_error = start_result.err();
+        start_ollama_service().await?;
         return Ok(json!({
-            "success": service_ok,
+            "success": true,
             "installed": true,
             "ollama_path": ollama_path.to_string_lossy(),
             "version": version,
-            "service_running": service_ok,
-            "service_start_error": start_error,
-            "message": if service_ok {
-                "Ollama service started successfully"
-            } else {
-                "Ollama is installed but failed to start service"
-            }
+            "service_running": true,
+            "message": "Ollama service started successfully"
         }));
     }
 
@@ -1084,14 +982,11 @@ pub async fn install_ollama() -> Result<Value, String> {
         _ => format!("Please visit {} to download and install Ollama for your system.", download_url),
     };
 
-    Ok(json!({
-        "success": false,
-        "installed": false,
-        "needs_manual_install": true,
-        "download_url": download_url,
-        "instructions": install_instructions,
-        "message": "Ollama is not installed. Please install it manually and I'll detect it automatically."
-    }))
+    Err(DispatchError::needs_manual_action(
+        "Ollama is not installed. Please install it manually and I'll detect it automatically.",
+        Some(install_instructions),
+        Some(download_url),
+    ))
 }
 
 /// Get Ollama version string using absolute path
@@ -1123,16 +1018,15 @@ fn get_ollama_version() -> Option<String> {
 }
 
 /// Start the Ollama service (public API)
-pub async fn start_ollama() -> Result<Value, String> {
+pub async fn start_ollama() -> Result<Value, DispatchError> {
     // Use absolute path detection
     let ollama_path = match find_ollama_binary() {
         Some(path) => path,
         None => {
-            return Ok(json!({
-                "success": false,
-                "installed": false,
-                "message": "Ollama is not installed. Please install it first from https://ollama.com/download"
-            }));
+            return Err(DispatchError::not_installed(
+                "ollama",
+                "Ollama is not installed. Please install it first from https://ollama.com/download",
+            ));
         }
     };
 
@@ -1157,28 +1051,27 @@ pub async fn start_ollama() -> Result<Value, String> {
             "version": version,
             "message": "Ollama service started successfully"
         })),
-        Err(e) => Ok(json!({
-            "success": false,
-            "error": e,
-            "message": "Failed to start Ollama service"
-        })),
+        Err(e) => Err(e),
     }
 }
 
 /// Start the Ollama service and wait for it to be ready (internal helper)
-async fn start_ollama_service() -> Result<(), String> {
+async fn start_ollama_service() -> Result<(), DispatchError> {
     // Check if already running
     if is_port_in_use(OLLAMA_DEFAULT_PORT) {
         return Ok(());
     }
 
     // Find the ollama binary - must exist to start service
-    let ollama_path = find_ollama_binary()
-        .ok_or("Ollama binary not found. Please install Ollama first.")?;
+    let ollama_path = find_ollama_binary().ok_or_else(|| {
+        DispatchError::not_installed("ollama", "Ollama binary not found. Please install Ollama first.")
+    })?;
     let ollama_path_str = ollama_path.to_string_lossy().to_string();
 
     let home_dir = dirs::home_dir().ok_or("Could not determine home directory")?;
-    let empty_env: &[(&str, &str)] = &[];
+    // Ollama does the model downloads, so it needs the proxy and CAs
+    let network_env = NetworkConfig::current().env_vars();
+    let serve_env: Vec<(&str, &str)> = network_env.iter().map(|(name, value)| (*name, value.as_str())).collect();
 
     #[cfg(target_os = "macos")]
     {
@@ -1197,8 +1090,8 @@ async fn start_ollama_service() -> Result<(), String> {
         }
 
         // Fall back to spawning ollama serve directly using absolute path
-        spawn_detached(&ollama_path_str, &["serve"], &home_dir, empty_env).await
-            .map_err(|e| format!("Failed to start Ollama service: {}", e))?;
+        spawn_detached(&ollama_path_str, &["serve"], &home_dir, &serve_env).await
+            .map_err(|e| DispatchError::service_failed("ollama", format!("Failed to start Ollama service: {}", e)))?;
     }
 
     #[cfg(target_os = "linux")]
@@ -1230,36 +1123,44 @@ async fn start_ollama_service() -> Result<(), String> {
         }
 
         // Fall back to spawning ollama serve directly using absolute path
-        spawn_detached(&ollama_path_str, &["serve"], &home_dir, empty_env).await
-            .map_err(|e| format!("Failed to start Ollama service: {}", e))?;
+        spawn_detached(&ollama_path_str, &["serve"], &home_dir, &serve_env).await
+            .map_err(|e| DispatchError::service_failed("ollama", format!("Failed to start Ollama service: {}", e)))?;
     }
 
     #[cfg(target_os = "windows")]
     {
         // On Windows, just spawn ollama serve using absolute path
-        spawn_detached(&ollama_path_str, &["serve"], &home_dir, empty_env).await
-            .map_err(|e| format!("Failed to start Ollama service: {}", e))?;
+        spawn_detached(&ollama_path_str, &["serve"], &home_dir, &serve_env).await
+            .map_err(|e| DispatchError::service_failed("ollama", format!("Failed to start Ollama service: {}", e)))?;
     }
 
     // Wait for service to be ready
     if wait_for_port(OLLAMA_DEFAULT_PORT, 30).await {
         Ok(())
     } else {
-        Err("Ollama service started but not responding on port 11434 after 30 seconds".to_string())
+        Err(DispatchError::timeout(
+            "Ollama service started but not responding on port 11434 after 30 seconds",
+        ))
     }
 }
 
 /// Pull a vetted Ollama model with progress streaming
+#[cfg(feature = "gui")]
 pub async fn pull_ollama_model_with_progress(
     model: &str,
     registry: Option<String>,
     force: bool,
     request_id: String,
-    sender: Arc<Mutex<Option<WsSender>>>,
-) -> Result<Value, String> {
+    sender: Arc<Mutex<Outbound>>,
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
     // Find Ollama binary using absolute path
-    let ollama_path = find_ollama_binary()
-        .ok_or("Ollama is not installed. Please install it first from https://ollama.com/download")?;
+    let ollama_path = find_ollama_binary().ok_or_else(|| {
+        DispatchError::not_installed(
+            "ollama",
+            "Ollama is not installed. Please install it first from https://ollama.com/download",
+        )
+    })?;
 
     let sanitized_model = sanitize_model_name(model)?;
 
@@ -1271,11 +1172,15 @@ pub async fn pull_ollama_model_with_progress(
     };
 
     let mut command = Command::new(&ollama_path);
+    NetworkConfig::current().apply(&mut command);
     command
         .arg("pull")
         .arg(&model_arg)
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    #[cfg(unix)]
+    command.process_group(0);
 
     if force {
         command.arg("--force");
@@ -1290,9 +1195,36 @@ pub async fn pull_ollama_model_with_progress(
 
     let mut reader = BufReader::new(stderr).lines();
     let mut last_progress_message = String::new();
+    // Kept for the error report; the progress bar lines are mixed in with the errors
+    let mut recent_lines: Vec<String> = Vec::new();
 
     // Read stderr line by line (ollama outputs progress to stderr)
-    while let Ok(Some(line)) = reader.next_line().await {
+    loop {
+        let line = tokio::select! {
+            line = reader.next_line() => line,
+            _ = cancel.cancelled() => {
+                if let Some(pid) = child.id() {
+                    terminate_process_tree(pid).await;
+                }
+                let _ = send_message(&sender, OutgoingMessage::Progress {
+                    id: request_id.clone(),
+                    operation: "pull_ollama_model".to_string(),
+                    percent: None,
+                    message: "Download cancelled".to_string(),
+                    bytes_downloaded: None,
+                    bytes_total: None,
+                }).await;
+                return Err(DispatchError::Cancelled);
+            }
+        };
+        let Ok(Some(line)) = line else {
+            break;
+        };
+        if recent_lines.len() == OLLAMA_RECENT_LINES {
+            recent_lines.remove(0);
+        }
+        recent_lines.push(line.clone());
+
         if let Some(progress) = parse_ollama_progress(&line) {
             // Only send if message changed (avoid spamming identical updates)
             if line != last_progress_message {
@@ -1334,14 +1266,30 @@ pub async fn pull_ollama_model_with_progress(
     };
     let _ = send_message(&sender, final_msg).await;
 
-    Ok(json!({
-        "success": success,
+    if !success {
+        return Err(DispatchError::process_failed(
+            "ollama pull",
+            status.code().unwrap_or(-1),
+            &recent_lines.join("\n"),
+        ));
+    }
+
+    install_state::record_pulled_model(&model_arg);
+
+    let mut result = json!({
+        "success": true,
         "exit_code": status.code().unwrap_or(-1),
         "model": sanitized_model
-    }))
+    });
+    // Ollama has no bandwidth setting to pass the cap on to
+    if bandwidth::limit().is_some() {
+        result["download_limit_applied"] = json!(false);
+    }
+    Ok(result)
 }
 
 /// Parsed progress information from Ollama output
+#[cfg(feature = "gui")]
 struct OllamaProgress {
     percent: Option<u8>,
     message: String,
@@ -1356,6 +1304,7 @@ struct OllamaProgress {
 /// "verifying sha256 digest"
 /// "writing manifest"
 /// "success"
+#[cfg(feature = "gui")]
 fn parse_ollama_progress(line: &str) -> Option<OllamaProgress> {
     let line = line.trim();
     if line.is_empty() {
@@ -1410,7 +1359,7 @@ fn parse_ollama_progress(line: &str) -> Option<OllamaProgress> {
 }
 
 /// Convert size string to bytes
-fn parse_size_to_bytes(value: &str, unit: &str) -> Option<u64> {
+pub(crate) fn parse_size_to_bytes(value: &str, unit: &str) -> Option<u64> {
     let num: f64 = value.parse().ok()?;
     let multiplier = match unit.to_uppercase().as_str() {
         "KB" => 1024.0,
@@ -1421,12 +1370,30 @@ fn parse_size_to_bytes(value: &str, unit: &str) -> Option<u64> {
     Some((num * multiplier) as u64)
 }
 
+/// Branch, history depth, submodules and credentials for `clone_repo`
+#[derive(Debug, Clone, Default)]
+pub struct CloneOptions {
+    /// Branch, tag or full commit hash; the remote's default branch when unset
+    pub git_ref: Option<String>,
+    /// Commits of history to fetch, 1 when unset; 0 fetches all of it
+    pub depth: Option<u32>,
+    /// Check out submodules too
+    pub submodules: bool,
+    /// Token for a private HTTPS repository; never stored or logged
+    pub token: Option<GitToken>,
+}
+
 /// Clone the BrainDrive repository
 /// Handles the case where ~/BrainDrive already exists with miniconda3 (from install_conda)
-pub async fn clone_repo(repo_url: Option<String>, target_path: Option<String>) -> Result<Value, String> {
+pub async fn clone_repo(
+    repo_url: Option<String>,
+    target_path: Option<String>,
+    options: CloneOptions,
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
     // Use find_git_binary to get absolute path (GUI apps have limited PATH)
     let git_path = find_git_binary()
-        .ok_or("Git is not installed. Please install Git first.")?;
+        .ok_or_else(|| DispatchError::not_installed("git", "Git is not installed. Please install Git first."))?;
 
     let home = dirs::home_dir().ok_or("Could not determine home directory")?;
     let target = match target_path {
@@ -1434,7 +1401,10 @@ pub async fn clone_repo(repo_url: Option<String>, target_path: Option<String>) -
             let path = PathBuf::from(&p);
             // Ensure target is inside home directory
             if !path.starts_with(&home) && !p.starts_with("~/") {
-                return Err("Target path must be inside your home directory".to_string());
+                return Err(DispatchError::path_rejected(
+                    Some(p),
+                    "Target path must be inside your home directory",
+                ));
             }
             if p.starts_with("~/") {
                 home.join(&p[2..])
@@ -1450,12 +1420,28 @@ pub async fn clone_repo(repo_url: Option<String>, target_path: Option<String>) -
 
     // Validate URL format (basic check)
     if !url.starts_with("https://") && !url.starts_with("git@") {
-        return Err("Repository URL must start with https:// or git@".to_string());
+        return Err(DispatchError::invalid_argument("Repository URL must start with https:// or git@"));
     }
+    let git_ref = options.git_ref.as_deref().map(git_remote::sanitize_git_ref).transpose()?;
+    let auth = git_remote::git_auth(&url, options.token.as_ref())?;
 
     // Check if already exists
     if target.exists() {
         if target.join(".git").exists() {
+            // Leaving another version in place would look like success
+            if let Some(wanted) = git_ref.as_deref() {
+                if let Some(current) = git_remote::checkout_off_ref(&git_path, &target, wanted, cancel).await? {
+                    return Err(DispatchError::needs_manual_action(
+                        format!("{} is already cloned at {}, not {}", target.display(), current, wanted),
+                        Some(
+                            "Move the existing checkout (update_braindrive takes a release tag) \
+                             or clone into another target_path"
+                                .to_string(),
+                        ),
+                        None,
+                    ));
+                }
+            }
             return Ok(json!({
                 "success": true,
                 "message": "BrainDrive repository already exists",
@@ -1466,29 +1452,69 @@ pub async fn clone_repo(repo_url: Option<String>, target_path: Option<String>) -
 
         // Check if directory only contains installer artifacts (miniconda3, .braindrive-installer)
         // If so, we can clone into it using git init + fetch approach
-        let has_only_installer_artifacts = check_only_installer_artifacts(&target);
-
-        if has_only_installer_artifacts {
-            // Use git init + fetch + checkout approach for existing directory
-            return clone_into_existing_dir(&target, &url, &git_path).await;
-        } else {
-            return Err(format!(
-                "Directory {} exists but is not a git repository and contains non-installer files",
-                target.display()
+        if !check_only_installer_artifacts(&target) {
+            return Err(DispatchError::path_rejected(
+                Some(target.display().to_string()),
+                format!(
+                    "Directory {} exists but is not a git repository and contains non-installer files",
+                    target.display()
+                ),
             ));
         }
     }
 
+    let clone_target =
+        git_remote::resolve_clone_target(&git_path, &url, git_ref.as_deref(), auth.as_ref(), cancel).await?;
+    let depth = options.depth.unwrap_or(1);
+
+    // A commit can't be cloned by name, so it takes the same route as an existing directory
+    if target.exists() || matches!(clone_target, CloneTarget::Commit(_)) {
+        let created = !target.exists();
+        std::fs::create_dir_all(&target)
+            .map_err(|e| format!("Failed to create {}: {}", target.display(), e))?;
+        let checkout = CheckoutSpec {
+            target: &clone_target,
+            depth,
+            submodules: options.submodules,
+        };
+        let result = clone_into_existing_dir(&target, &url, &git_path, checkout, auth.as_ref(), cancel).await;
+        if result.is_err() {
+            // Don't leave a half-made repository that later looks like a finished clone
+            let _ = if created {
+                std::fs::remove_dir_all(&target)
+            } else {
+                std::fs::remove_dir_all(target.join(".git"))
+            };
+        }
+        return result;
+    }
+
     // Standard clone for non-existing directory
-    let mut command = Command::new(&git_path);
-    command
-        .arg("clone")
-        .arg("--depth")
-        .arg("1")  // Shallow clone for faster download
-        .arg(&url)
-        .arg(&target);
+    let mut command = git_remote::git_command(&git_path, auth.as_ref());
+    command.arg("clone");
+    if depth > 0 {
+        command.arg("--depth").arg(depth.to_string()); // Shallow clone for faster download
+    }
+    command.arg("--branch").arg(clone_target.name());
+    if options.submodules {
+        command.arg("--recurse-submodules");
+        if depth > 0 {
+            command.arg("--shallow-submodules");
+        }
+    }
+    command.arg(&url).arg(&target);
 
-    let result = run_command(command).await?;
+    let result = match run_command(command, cancel).await {
+        Ok(result) => result,
+        Err(e) => {
+            // Don't leave a partial checkout that later looks like a finished clone
+            if cancel.is_cancelled() {
+                let _ = std::fs::remove_dir_all(&target);
+            }
+            return Err(e);
+        }
+    };
+    let result = git_remote::check_git_output("git clone", result, auth.as_ref())?;
 
     Ok(json!({
         "success": result.success,
@@ -1496,12 +1522,16 @@ pub async fn clone_repo(repo_url: Option<String>, target_path: Option<String>) -
         "stdout": result.stdout,
         "stderr": result.stderr,
         "path": target.to_string_lossy(),
-        "url": url
+        "url": url,
+        "ref": clone_target.name(),
+        "ref_type": clone_target.kind(),
+        "depth": depth,
+        "submodules": options.submodules
     }))
 }
 
 /// Check if a directory only contains installer artifacts (miniconda3, .braindrive-installer)
-fn check_only_installer_artifacts(dir: &PathBuf) -> bool {
+pub(crate) fn check_only_installer_artifacts(dir: &PathBuf) -> bool {
     let allowed_names = ["miniconda3", ".braindrive-installer"];
 
     match std::fs::read_dir(dir) {
@@ -1519,62 +1549,72 @@ fn check_only_installer_artifacts(dir: &PathBuf) -> bool {
     }
 }
 
+/// What to check out in `clone_into_existing_dir`
+struct CheckoutSpec<'a> {
+    target: &'a CloneTarget,
+    /// 0 for the full history
+    depth: u32,
+    submodules: bool,
+}
+
 /// Clone into an existing directory that contains only installer artifacts
 /// Uses git init + fetch + checkout approach
-async fn clone_into_existing_dir(target: &PathBuf, url: &str, git_path: &PathBuf) -> Result<Value, String> {
+async fn clone_into_existing_dir(
+    target: &Path,
+    url: &str,
+    git_path: &Path,
+    checkout: CheckoutSpec<'_>,
+    auth: Option<&GitAuth>,
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
+    let git = |args: &[&str]| {
+        let mut command = git_remote::git_command(git_path, auth);
+        command.args(args).current_dir(target);
+        command
+    };
+
     // Initialize git repo
-    let mut init_cmd = Command::new(git_path);
-    init_cmd.arg("init").current_dir(target);
-    let init_result = run_command(init_cmd).await?;
-    if !init_result.success {
-        return Err(format!("Failed to initialize git repository: {}", init_result.stderr));
-    }
+    check_output("git init", run_command(git(&["init"]), cancel).await?)?;
 
     // Add remote origin
-    let mut remote_cmd = Command::new(git_path);
-    remote_cmd
-        .args(["remote", "add", "origin", url])
-        .current_dir(target);
-    let remote_result = run_command(remote_cmd).await?;
-    if !remote_result.success {
-        return Err(format!("Failed to add remote: {}", remote_result.stderr));
-    }
-
-    // Fetch with depth 1
-    let mut fetch_cmd = Command::new(git_path);
-    fetch_cmd
-        .args(["fetch", "--depth", "1", "origin", "main"])
-        .current_dir(target);
-    let fetch_result = run_command(fetch_cmd).await?;
-    if !fetch_result.success {
-        // Try 'master' branch if 'main' doesn't exist
-        let mut fetch_master = Command::new(git_path);
-        fetch_master
-            .args(["fetch", "--depth", "1", "origin", "master"])
-            .current_dir(target);
-        let fetch_master_result = run_command(fetch_master).await?;
-        if !fetch_master_result.success {
-            return Err(format!("Failed to fetch repository: {}", fetch_result.stderr));
-        }
-        // Checkout master
-        let mut checkout_cmd = Command::new(git_path);
-        checkout_cmd
-            .args(["checkout", "-b", "master", "origin/master"])
-            .current_dir(target);
-        let checkout_result = run_command(checkout_cmd).await?;
-        if !checkout_result.success {
-            return Err(format!("Failed to checkout: {}", checkout_result.stderr));
-        }
-    } else {
-        // Checkout main
-        let mut checkout_cmd = Command::new(git_path);
-        checkout_cmd
-            .args(["checkout", "-b", "main", "origin/main"])
-            .current_dir(target);
-        let checkout_result = run_command(checkout_cmd).await?;
-        if !checkout_result.success {
-            return Err(format!("Failed to checkout: {}", checkout_result.stderr));
+    check_output("git remote add", run_command(git(&["remote", "add", "origin", url]), cancel).await?)?;
+
+    let depth_arg = format!("--depth={}", checkout.depth);
+    let mut fetch_args = vec!["fetch"];
+    if checkout.depth > 0 {
+        fetch_args.push(&depth_arg);
+    }
+    fetch_args.push("origin");
+    let (refspec, checkout_args) = match checkout.target {
+        CloneTarget::Branch(branch) => (
+            format!("+refs/heads/{0}:refs/remotes/origin/{0}", branch),
+            vec!["checkout".to_string(), "-b".to_string(), branch.clone(), format!("origin/{}", branch)],
+        ),
+        CloneTarget::Tag(tag) => (
+            format!("+refs/tags/{0}:refs/tags/{0}", tag),
+            vec!["checkout".to_string(), "--detach".to_string(), format!("refs/tags/{}", tag)],
+        ),
+        CloneTarget::Commit(commit) => (
+            commit.clone(),
+            vec!["checkout".to_string(), "--detach".to_string(), "FETCH_HEAD".to_string()],
+        ),
+    };
+    fetch_args.push(&refspec);
+    git_remote::check_git_output("git fetch", run_command(git(&fetch_args), cancel).await?, auth)?;
+
+    let checkout_args: Vec<&str> = checkout_args.iter().map(String::as_str).collect();
+    check_output("git checkout", run_command(git(&checkout_args), cancel).await?)?;
+
+    if checkout.submodules {
+        let mut submodule_args = vec!["submodule", "update", "--init", "--recursive"];
+        if checkout.depth > 0 {
+            submodule_args.push(&depth_arg);
         }
+        git_remote::check_git_output(
+            "git submodule update",
+            run_command(git(&submodule_args), cancel).await?,
+            auth,
+        )?;
     }
 
     Ok(json!({
@@ -1582,6 +1622,10 @@ async fn clone_into_existing_dir(target: &PathBuf, url: &str, git_path: &PathBuf
         "message": "BrainDrive repository cloned into existing directory",
         "path": target.to_string_lossy(),
         "url": url,
+        "ref": checkout.target.name(),
+        "ref_type": checkout.target.kind(),
+        "depth": checkout.depth,
+        "submodules": checkout.submodules,
         "method": "init_fetch_checkout"
     }))
 }
@@ -1591,10 +1635,11 @@ async fn clone_into_existing_dir(target: &PathBuf, url: &str, git_path: &PathBuf
 pub async fn install_backend_deps(
     env_name: Option<String>,
     repo_path: Option<String>,
-) -> Result<Value, String> {
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
     // Get the conda binary path (prefers isolated installation)
     let conda_path = find_conda_binary()
-        .ok_or("Conda is not installed. Please install it first using the install_conda tool.")?;
+        .ok_or_else(conda_not_installed)?;
 
     let env = sanitize_env_name(&env_name.unwrap_or_else(|| CONDA_ENV_NAME.to_string()))?;
     let repo = resolve_repo_path_or_default(repo_path)?;
@@ -1602,46 +1647,51 @@ pub async fn install_backend_deps(
     let requirements_file = backend_path.join("requirements.txt");
 
     if !backend_path.exists() {
-        return Err(format!(
-            "Backend directory not found at {}",
-            backend_path.display()
+        return Err(DispatchError::not_installed(
+            "braindrive_repo",
+            format!("Backend directory not found at {}", backend_path.display()),
         ));
     }
 
     if !requirements_file.exists() {
-        return Err(format!(
-            "requirements.txt not found at {}",
-            requirements_file.display()
+        return Err(DispatchError::not_installed(
+            "braindrive_repo",
+            format!("requirements.txt not found at {}", requirements_file.display()),
         ));
     }
 
-    #[cfg(target_os = "windows")]
-    let result = {
-        let mut command = Command::new(&conda_path);
-        command
-            .arg("run")
-            .arg("-n")
-            .arg(&env)
-            .arg("python")
-            .arg("-m")
-            .arg("pip")
-            .arg("install")
-            .arg("-r")
-            .arg(&requirements_file);
-        command.creation_flags(CREATE_NO_WINDOW);
-        run_command(command).await?
-    };
+    let (result, mirror) = mirrors::with_fallback(Ecosystem::Pypi, |mirror| {
+        #[cfg(target_os = "windows")]
+        let mut command = {
+            let mut command = Command::new(&conda_path);
+            command
+                .arg("run")
+                .arg("-n")
+                .arg(&env)
+                .arg("python")
+                .arg("-m")
+                .arg("pip")
+                .arg("install")
+                .arg("-r")
+                .arg(&requirements_file);
+            command.creation_flags(CREATE_NO_WINDOW);
+            command
+        };
 
-    #[cfg(not(target_os = "windows"))]
-    let result = {
-        // Build the pip install command to run in conda environment using the isolated conda
-        let pip_cmd = format!(
-            "pip install -r \"{}\"",
-            requirements_file.display()
-        );
-        let full_cmd = process_manager::conda_run_command_with_path(&conda_path, &env, &pip_cmd);
-        run_shell_script(&full_cmd).await?
-    };
+        #[cfg(not(target_os = "windows"))]
+        let mut command = {
+            // Build the pip install command to run in conda environment using the isolated conda
+            let pip_cmd = format!(
+                "pip install -r \"{}\"",
+                requirements_file.display()
+            );
+            shell_command(&process_manager::conda_run_command_with_path(&conda_path, &env, &pip_cmd))
+        };
+
+        mirror.apply(Ecosystem::Pypi, &mut command);
+        async move { check_output("pip install", run_command(command, cancel).await?) }
+    })
+    .await?;
 
     Ok(json!({
         "success": result.success,
@@ -1650,7 +1700,8 @@ pub async fn install_backend_deps(
         "stderr": result.stderr,
         "env_name": env,
         "requirements_file": requirements_file.to_string_lossy(),
-        "conda_path": conda_path.to_string_lossy()
+        "conda_path": conda_path.to_string_lossy(),
+        "mirror": mirror.label()
     }))
 }
 
@@ -1658,50 +1709,56 @@ pub async fn install_backend_deps(
 pub async fn install_frontend_deps(
     env_name: Option<String>,
     repo_path: Option<String>,
-) -> Result<Value, String> {
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
     // Get the conda binary path (prefers isolated installation)
     let conda_path = find_conda_binary()
-        .ok_or("Conda is not installed. Please install it first using the install_conda tool.")?;
+        .ok_or_else(conda_not_installed)?;
 
     let env = sanitize_env_name(&env_name.unwrap_or_else(|| CONDA_ENV_NAME.to_string()))?;
     let repo = resolve_repo_path_or_default(repo_path)?;
     let frontend_path = repo.join("frontend");
 
     if !frontend_path.exists() {
-        return Err(format!(
-            "Frontend directory not found at {}",
-            frontend_path.display()
+        return Err(DispatchError::not_installed(
+            "braindrive_repo",
+            format!("Frontend directory not found at {}", frontend_path.display()),
         ));
     }
 
     let package_json = frontend_path.join("package.json");
     if !package_json.exists() {
-        return Err(format!(
-            "package.json not found at {}",
-            package_json.display()
+        return Err(DispatchError::not_installed(
+            "braindrive_repo",
+            format!("package.json not found at {}", package_json.display()),
         ));
     }
 
-    #[cfg(target_os = "windows")]
-    let result = {
-        let mut command = Command::new(&conda_path);
-        command
-            .arg("run")
-            .arg("-n")
-            .arg(&env)
-            .arg("npm")
-            .arg("install")
-            .current_dir(&frontend_path);
-        command.creation_flags(CREATE_NO_WINDOW);
-        run_command(command).await?
-    };
+    let (result, mirror) = mirrors::with_fallback(Ecosystem::Npm, |mirror| {
+        #[cfg(target_os = "windows")]
+        let mut command = {
+            let mut command = Command::new(&conda_path);
+            command
+                .arg("run")
+                .arg("-n")
+                .arg(&env)
+                .arg("npm")
+                .arg("install")
+                .current_dir(&frontend_path);
+            command.creation_flags(CREATE_NO_WINDOW);
+            command
+        };
 
-    #[cfg(not(target_os = "windows"))]
-    let result = {
-        let npm_cmd = format!("cd \"{}\" && npm install", frontend_path.display());
-        let full_cmd = process_manager::conda_run_command_with_path(&conda_path, &env, &npm_cmd);
-        run_shell_script(&full_cmd).await?
-    };
+        #[cfg(not(target_os = "windows"))]
+        let mut command = {
+            let npm_cmd = format!("cd \"{}\" && npm install", frontend_path.display());
+            shell_command(&process_manager::conda_run_command_with_path(&conda_path, &env, &npm_cmd))
+        };
+
+        mirror.apply(Ecosystem::Npm, &mut command);
+        async move { check_output("npm install", run_command(command, cancel).await?) }
+    })
+    .await?;
 
     Ok(json!({
         "success": result.success,
@@ -1710,7 +1767,8 @@ pub async fn install_frontend_deps(
         "stderr": result.stderr,
         "frontend_path": frontend_path.to_string_lossy(),
         "env_name": env,
-        "conda_path": conda_path.to_string_lossy()
+        "conda_path": conda_path.to_string_lossy(),
+        "mirror": mirror.label()
     }))
 }
 
@@ -1719,7 +1777,8 @@ pub async fn install_frontend_deps(
 pub async fn install_all_deps(
     env_name: Option<String>,
     repo_path: Option<String>,
-) -> Result<Value, String> {
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
     // Clone the values for the parallel tasks
     let env_name_backend = env_name.clone();
     let env_name_frontend = env_name.clone();
@@ -1728,73 +1787,57 @@ pub async fn install_all_deps(
 
     // Run both installations in parallel
     let (backend_result, frontend_result) = tokio::join!(
-        install_backend_deps(env_name_backend, repo_path_backend),
-        install_frontend_deps(env_name_frontend, repo_path_frontend)
+        install_backend_deps(env_name_backend, repo_path_backend, cancel),
+        install_frontend_deps(env_name_frontend, repo_path_frontend, cancel)
     );
 
-    // Process results
-    let backend_success = backend_result.as_ref().map(|v| {
-        v.get("success").and_then(|s| s.as_bool()).unwrap_or(false)
-    }).unwrap_or(false);
-
-    let frontend_success = frontend_result.as_ref().map(|v| {
-        v.get("success").and_then(|s| s.as_bool()).unwrap_or(false)
-    }).unwrap_or(false);
-
-    let overall_success = backend_success && frontend_success;
-
-    // Build detailed response
-    let backend_data = match backend_result {
-        Ok(data) => data,
-        Err(e) => json!({ "success": false, "error": e }),
-    };
-
-    let frontend_data = match frontend_result {
-        Ok(data) => data,
-        Err(e) => json!({ "success": false, "error": e }),
-    };
+    if cancel.is_cancelled() {
+        return Err(DispatchError::Cancelled);
+    }
 
-    let message = if overall_success {
-        "Both backend and frontend dependencies installed successfully"
-    } else if backend_success {
-        "Backend dependencies installed, but frontend failed"
-    } else if frontend_success {
-        "Frontend dependencies installed, but backend failed"
-    } else {
-        "Both backend and frontend dependency installations failed"
+    let (backend_data, frontend_data) = match (backend_result, frontend_result) {
+        (Ok(backend), Ok(frontend)) => (backend, frontend),
+        (backend_result, frontend_result) => {
+            let message = match (&backend_result, &frontend_result) {
+                (Ok(_), Err(_)) => "Backend dependencies installed, but frontend failed",
+                (Err(_), Ok(_)) => "Frontend dependencies installed, but backend failed",
+                _ => "Both backend and frontend dependency installations failed",
+            };
+            let mut completed = Vec::new();
+            let mut failed = Vec::new();
+            for (component, result) in [("backend", backend_result), ("frontend", frontend_result)] {
+                match result {
+                    Ok(_) => completed.push(component.to_string()),
+                    Err(e) => failed.push(ComponentFailure::new(component, e)),
+                }
+            }
+            return Err(DispatchError::partial_failure(message, completed, failed));
+        }
     };
 
-    // Return Err if overall operation failed, so ToolResult.success reflects actual status
-    if overall_success {
-        Ok(json!({
-            "success": true,
-            "message": message,
-            "parallel": true,
-            "backend": backend_data,
-            "frontend": frontend_data
-        }))
-    } else {
-        // Include detailed results in the error for debugging
-        Err(format!(
-            "{}\n\nBackend: {}\nFrontend: {}",
-            message,
-            serde_json::to_string_pretty(&backend_data).unwrap_or_default(),
-            serde_json::to_string_pretty(&frontend_data).unwrap_or_default()
-        ))
-    }
+    Ok(json!({
+        "success": true,
+        "message": "Both backend and frontend dependencies installed successfully",
+        "parallel": true,
+        "backend": backend_data,
+        "frontend": frontend_data
+    }))
 }
 
 /// Setup the environment file by copying .env-dev to .env
-pub async fn setup_env_file(repo_path: Option<String>) -> Result<Value, String> {
+pub async fn setup_env_file(repo_path: Option<String>) -> Result<Value, DispatchError> {
     let repo = resolve_repo_path_or_default(repo_path)?;
     let backend_path = repo.join("backend");
     let env_dev = backend_path.join(".env-dev");
     let env_file = backend_path.join(".env");
 
     if !env_dev.exists() {
-        return Err(format!(
-            ".env-dev not found at {}. The repository may not be properly cloned.",
-            env_dev.display()
+        return Err(DispatchError::not_installed(
+            "braindrive_repo",
+            format!(
+                ".env-dev not found at {}. The repository may not be properly cloned.",
+                env_dev.display()
+            ),
         ));
     }
 
@@ -1823,10 +1866,14 @@ pub async fn setup_env_file(repo_path: Option<String>) -> Result<Value, String>
 /// Create a new conda environment for BrainDrive
 /// Uses the isolated conda installation at ~/BrainDrive/miniconda3
 /// If force_recreate is true, removes existing env and creates fresh one
-pub async fn create_conda_env(env_name: Option<String>, force_recreate: Option<bool>) -> Result<Value, String> {
+pub async fn create_conda_env(
+    env_name: Option<String>,
+    force_recreate: Option<bool>,
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
     // Get the conda binary path (prefers isolated installation)
     let conda_path = find_conda_binary()
-        .ok_or("Conda is not installed. Please install it first using the install_conda tool.")?;
+        .ok_or_else(conda_not_installed)?;
 
     let env = sanitize_env_name(&env_name.unwrap_or_else(|| CONDA_ENV_NAME.to_string()))?;
     let force = force_recreate.unwrap_or(false);
@@ -1856,28 +1903,26 @@ pub async fn create_conda_env(env_name: Option<String>, force_recreate: Option<b
     if env_exists && force {
         let mut remove_cmd = Command::new(&conda_path);
         remove_cmd.args(["env", "remove", "-n", &env, "-y"]);
-        let remove_result = run_command(remove_cmd).await?;
-        if !remove_result.success {
-            return Err(format!("Failed to remove existing environment: {}", remove_result.stderr));
-        }
+        check_output("conda env remove", run_command(remove_cmd, cancel).await?)?;
     }
 
     // Create the environment with Python 3.11, nodejs, and git from conda-forge
+    // (or a mirror of it)
     // Use --override-channels to bypass Anaconda channel TOS requirements
-    let mut command = Command::new(&conda_path);
-    command
-        .args([
-            "create",
-            "-n", &env,
-            "--override-channels",
-            "-c", "conda-forge",
-            "python=3.11",
-            "nodejs",
-            "git",
-            "-y"
-        ]);
-
-    let result = run_command(command).await?;
+    let (result, mirror) = mirrors::with_fallback(Ecosystem::Conda, |mirror| {
+        let mut command = Command::new(&conda_path);
+        command
+            .args([
+                "create",
+                "-n", &env,
+                "--override-channels",
+                "-c", mirrors::conda_channel(&mirror),
+            ])
+            .args(CONDA_ENV_PACKAGES)
+            .arg("-y");
+        async move { check_output("conda create", run_command(command, cancel).await?) }
+    })
+    .await?;
 
     Ok(json!({
         "success": result.success,
@@ -1886,7 +1931,8 @@ pub async fn create_conda_env(env_name: Option<String>, force_recreate: Option<b
         "stderr": result.stderr,
         "env_name": env,
         "conda_path": conda_path.to_string_lossy(),
-        "recreated": force && env_exists
+        "recreated": force && env_exists,
+        "mirror": mirror.label()
     }))
 }
 
@@ -1894,6 +1940,13 @@ pub async fn create_conda_env(env_name: Option<String>, force_recreate: Option<b
 const BACKEND_PORTS: [u16; 3] = [8005, 8006, 8007];
 const FRONTEND_PORTS: [u16; 3] = [5173, 5174, 5175];
 
+/// Ports `find_available_port` tries, for error reports
+fn tried_ports(preferred: u16, fallbacks: &[u16]) -> Vec<u16> {
+    let mut ports = vec![preferred];
+    ports.extend(fallbacks.iter().filter(|&&port| port != preferred));
+    ports
+}
+
 /// Find an available port from a list of options
 fn find_available_port(preferred: u16, fallbacks: &[u16]) -> Option<u16> {
     // Try preferred first
@@ -1915,10 +1968,13 @@ pub async fn start_braindrive(
     frontend_port: u16,
     backend_port: u16,
     process_state: &ProcessState,
-) -> Result<Value, String> {
+) -> Result<Value, DispatchError> {
     let repo_path = resolve_repo_path(None)?;
     if !repo_path.exists() {
-        return Err("BrainDrive is not installed. Please install it first.".to_string());
+        return Err(DispatchError::not_installed(
+            "braindrive_repo",
+            "BrainDrive is not installed. Please install it first.",
+        ));
     }
 
     let backend_path = repo_path.join("backend");
@@ -1926,15 +1982,15 @@ pub async fn start_braindrive(
 
     // Verify paths exist
     if !backend_path.exists() {
-        return Err(format!(
-            "Backend directory not found at {}",
-            backend_path.display()
+        return Err(DispatchError::not_installed(
+            "braindrive_repo",
+            format!("Backend directory not found at {}", backend_path.display()),
         ));
     }
     if !frontend_path.exists() {
-        return Err(format!(
-            "Frontend directory not found at {}",
-            frontend_path.display()
+        return Err(DispatchError::not_installed(
+            "braindrive_repo",
+            format!("Frontend directory not found at {}", frontend_path.display()),
         ));
     }
 
@@ -1986,9 +2042,9 @@ pub async fn start_braindrive(
     if !backend_already_running {
         // Find available port (try preferred, then fallbacks)
         actual_backend_port = find_available_port(backend_port, &BACKEND_PORTS)
-            .ok_or_else(|| format!(
-                "No available backend ports. Tried: {}, {:?}",
-                backend_port, BACKEND_PORTS
+            .ok_or_else(|| DispatchError::port_unavailable(
+                tried_ports(backend_port, &BACKEND_PORTS),
+                format!("No available backend ports. Tried: {}, {:?}", backend_port, BACKEND_PORTS),
             ))?;
 
         backend_pid = start_backend_service(&backend_path, actual_backend_port).await?;
@@ -1998,7 +2054,10 @@ pub async fn start_braindrive(
             if let Some(pid) = backend_pid {
                 kill_process(pid);
             }
-            return Err("Backend failed to start within 45 seconds. Check ~/.braindrive-installer/logs/ for details.".to_string());
+            return Err(DispatchError::service_failed(
+                "backend",
+                "Backend failed to start within 45 seconds. Check ~/.braindrive-installer/logs/ for details.",
+            ));
         }
     }
 
@@ -2006,9 +2065,9 @@ pub async fn start_braindrive(
     if !frontend_already_running {
         // Find available port (try preferred, then fallbacks)
         actual_frontend_port = find_available_port(frontend_port, &FRONTEND_PORTS)
-            .ok_or_else(|| format!(
-                "No available frontend ports. Tried: {}, {:?}",
-                frontend_port, FRONTEND_PORTS
+            .ok_or_else(|| DispatchError::port_unavailable(
+                tried_ports(frontend_port, &FRONTEND_PORTS),
+                format!("No available frontend ports. Tried: {}, {:?}", frontend_port, FRONTEND_PORTS),
             ))?;
 
         frontend_pid = start_frontend_service(&frontend_path, actual_frontend_port).await?;
@@ -2020,16 +2079,20 @@ pub async fn start_braindrive(
                 kill_process(pid);
             }
             // Backend is still running, report partial success
-            return Ok(json!({
-                "success": false,
-                "partial": true,
-                "message": "Backend started but frontend failed to start within 45 seconds",
-                "backend_port": actual_backend_port,
-                "backend_url": format!("http://localhost:{}", actual_backend_port),
-                "backend_running": true,
-                "frontend_running": false,
-                "error": "Frontend startup timed out. Check ~/.braindrive-installer/logs/ for details."
-            }));
+            return Err(DispatchError::partial_failure(
+                format!(
+                    "Backend started on http://localhost:{} but frontend failed to start within 45 seconds",
+                    actual_backend_port
+                ),
+                vec!["backend".to_string()],
+                vec![ComponentFailure::new(
+                    "frontend",
+                    DispatchError::service_failed(
+                        "frontend",
+                        "Frontend startup timed out. Check ~/.braindrive-installer/logs/ for details.",
+                    ),
+                )],
+            ));
         }
     }
 
@@ -2078,7 +2141,7 @@ pub async fn start_braindrive(
 
 /// Start the backend service
 #[cfg(not(target_os = "windows"))]
-async fn start_backend_service(backend_path: &PathBuf, port: u16) -> Result<Option<u32>, String> {
+async fn start_backend_service(backend_path: &PathBuf, port: u16) -> Result<Option<u32>, DispatchError> {
     // Create a shell script to run the backend with conda
     let script_content = format!(
         r#"#!/bin/bash
@@ -2126,9 +2189,9 @@ exec uvicorn main:app --host 0.0.0.0 --port {}
 
 /// Start the backend service on Windows
 #[cfg(target_os = "windows")]
-async fn start_backend_service(backend_path: &PathBuf, port: u16) -> Result<Option<u32>, String> {
+async fn start_backend_service(backend_path: &PathBuf, port: u16) -> Result<Option<u32>, DispatchError> {
     let conda_path = find_conda_binary()
-        .ok_or("Conda is not installed. Please install it first using the install_conda tool.")?;
+        .ok_or_else(conda_not_installed)?;
 
     // Create a batch script to run the backend with conda
     let script_content = format!(
@@ -2169,7 +2232,7 @@ cd /d "{}"
 
 /// Start the frontend service
 #[cfg(not(target_os = "windows"))]
-async fn start_frontend_service(frontend_path: &PathBuf, port: u16) -> Result<Option<u32>, String> {
+async fn start_frontend_service(frontend_path: &PathBuf, port: u16) -> Result<Option<u32>, DispatchError> {
     // Create a shell script to run the frontend
     let conda_activate = process_manager::conda_run_command(CONDA_ENV_NAME, "true")
         .replace(" true", "");
@@ -2216,9 +2279,9 @@ exec npm run dev -- --host localhost --port {}
 
 /// Start the frontend service on Windows
 #[cfg(target_os = "windows")]
-async fn start_frontend_service(frontend_path: &PathBuf, port: u16) -> Result<Option<u32>, String> {
+async fn start_frontend_service(frontend_path: &PathBuf, port: u16) -> Result<Option<u32>, DispatchError> {
     let conda_path = find_conda_binary()
-        .ok_or("Conda is not installed. Please install it first using the install_conda tool.")?;
+        .ok_or_else(conda_not_installed)?;
 
     // Create a batch script to run the frontend
     let script_content = format!(
@@ -2257,7 +2320,7 @@ cd /d "{}"
 }
 
 /// Stop BrainDrive services
-pub async fn stop_braindrive(process_state: &ProcessState) -> Result<Value, String> {
+pub async fn stop_braindrive(process_state: &ProcessState) -> Result<Value, DispatchError> {
     let mut stopped_backend = false;
     let mut stopped_frontend = false;
     let mut backend_port = 8005u16;
@@ -2325,21 +2388,31 @@ pub async fn stop_braindrive(process_state: &ProcessState) -> Result<Value, Stri
 
     let success = (stopped_backend || !is_port_in_use(backend_port))
         && (stopped_frontend || !is_port_in_use(frontend_port));
+    if !success {
+        return Err(DispatchError::service_failed(
+            "braindrive",
+            format!(
+                "Some services may still be running (backend port {}, frontend port {})",
+                backend_port, frontend_port
+            ),
+        ));
+    }
 
     Ok(json!({
-        "success": success,
-        "message": if success { "BrainDrive services stopped" } else { "Some services may still be running" },
+        "success": true,
+        "message": "BrainDrive services stopped",
         "backend_stopped": stopped_backend || backend_freed,
         "frontend_stopped": stopped_frontend || frontend_freed
     }))
 }
 
 /// Restart BrainDrive services
+#[cfg(feature = "gui")]
 pub async fn restart_braindrive(
     frontend_port: u16,
     backend_port: u16,
     process_state: &ProcessState,
-) -> Result<Value, String> {
+) -> Result<Value, DispatchError> {
     // Stop existing services
     let stop_result = stop_braindrive(process_state).await?;
 
@@ -2358,7 +2431,7 @@ pub async fn restart_braindrive(
 }
 
 /// Get the current status of BrainDrive services
-pub async fn get_braindrive_status(process_state: &ProcessState) -> Result<Value, String> {
+pub async fn get_braindrive_status(process_state: &ProcessState) -> Result<Value, DispatchError> {
     let state = process_state.lock().await;
 
     // Check actual port status
@@ -2384,13 +2457,38 @@ pub async fn get_braindrive_status(process_state: &ProcessState) -> Result<Value
 }
 
 /// Run an arbitrary command and capture stdout/stderr
-async fn run_command(mut command: Command) -> Result<CommandOutput, String> {
-    let output = command
+/// If `cancel` fires first, the command and everything it spawned (pip, npm,
+/// installers) is killed and the call fails with `DispatchError::Cancelled`
+///
+/// A command that runs but exits unsuccessfully is not an error here; callers
+/// that need it to succeed pass the output through `check_output`.
+pub(crate) async fn run_command(mut command: Command, cancel: &CancellationToken) -> Result<CommandOutput, DispatchError> {
+    // Proxy and custom CAs for conda, pip, npm and git
+    NetworkConfig::current().apply(&mut command);
+    command
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .output()
-        .await
-        .map_err(|e| format!("Failed to execute command: {}", e))?;
+        .kill_on_drop(true);
+    // Lead a new process group so cancellation reaches grandchildren too
+    #[cfg(unix)]
+    command.process_group(0);
+
+    let child = command
+        .spawn()
+        .map_err(|e| DispatchError::internal(format!("Failed to execute command: {}", e)))?;
+    let pid = child.id();
+
+    let output = tokio::select! {
+        output = child.wait_with_output() => {
+            output.map_err(|e| DispatchError::internal(format!("Failed to execute command: {}", e)))?
+        }
+        _ = cancel.cancelled() => {
+            if let Some(pid) = pid {
+                terminate_process_tree(pid).await;
+            }
+            return Err(DispatchError::Cancelled);
+        }
+    };
 
     let stdout = String::from_utf8_lossy(&output.stdout).to_string();
     let stderr = String::from_utf8_lossy(&output.stderr).to_string();
@@ -2404,47 +2502,75 @@ async fn run_command(mut command: Command) -> Result<CommandOutput, String> {
     })
 }
 
+/// Fail with `ProcessFailed` (or `Network`) unless the command exited successfully
+pub(crate) fn check_output(what: &str, output: CommandOutput) -> Result<CommandOutput, DispatchError> {
+    if output.success {
+        Ok(output)
+    } else {
+        Err(DispatchError::process_failed(what, output.exit_code, &output.stderr))
+    }
+}
+
+/// Kill a cancelled command and its descendants without blocking the runtime
+async fn terminate_process_tree(pid: u32) {
+    let _ = tokio::task::spawn_blocking(move || kill_process_tree(pid)).await;
+}
+
+/// Sleep for `duration`, failing with `DispatchError::Cancelled` if `cancel` fires first
+pub(crate) async fn sleep_or_cancel(duration: Duration, cancel: &CancellationToken) -> Result<(), DispatchError> {
+    tokio::select! {
+        _ = sleep(duration) => Ok(()),
+        _ = cancel.cancelled() => Err(DispatchError::Cancelled),
+    }
+}
+
+/// Command running `script` in the platform shell, for `run_command`
 #[cfg(not(target_os = "windows"))]
-async fn run_shell_script(script: &str) -> Result<CommandOutput, String> {
+fn shell_command(script: &str) -> Command {
     let mut command = Command::new("sh");
     command.arg("-c").arg(script);
-    run_command(command).await
+    command
 }
 
 #[cfg(target_os = "windows")]
-async fn run_shell_script(script: &str) -> Result<CommandOutput, String> {
+fn shell_command(script: &str) -> Command {
     let mut command = Command::new("cmd.exe");
     // Use /S /C with the command wrapped in quotes to handle nested quotes properly
     // Without /S, cmd.exe has special parsing when the command starts with a quote
     // that can break commands with multiple quoted paths
     command.arg("/S").arg("/C").arg(format!("\"{}\"", script));
     command.creation_flags(CREATE_NO_WINDOW);
-    run_command(command).await
+    command
 }
 
-fn sanitize_env_name(name: &str) -> Result<String, String> {
+fn sanitize_env_name(name: &str) -> Result<String, DispatchError> {
     let trimmed = name.trim();
     let re = Regex::new(r"^[A-Za-z0-9_-]+$").unwrap();
     if trimmed.is_empty() || !re.is_match(trimmed) {
-        return Err("Environment name may only contain letters, numbers, underscores, and dashes.".to_string());
+        return Err(DispatchError::invalid_argument(
+            "Environment name may only contain letters, numbers, underscores, and dashes.",
+        ));
     }
     Ok(trimmed.to_string())
 }
 
-fn sanitize_model_name(model: &str) -> Result<String, String> {
+pub(crate) fn sanitize_model_name(model: &str) -> Result<String, DispatchError> {
     let trimmed = model.trim();
     let re = Regex::new(r"^[A-Za-z0-9._:+/-]+$").unwrap();
     if trimmed.is_empty() || !re.is_match(trimmed) {
-        return Err("Model names may only contain letters, numbers, dots, underscores, dashes, slashes, and colons.".to_string());
+        return Err(DispatchError::invalid_argument(
+            "Model names may only contain letters, numbers, dots, underscores, dashes, slashes, and colons.",
+        ));
     }
     Ok(trimmed.to_string())
 }
 
-fn sanitize_registry(registry: &str) -> Result<String, String> {
+#[cfg(feature = "gui")]
+fn sanitize_registry(registry: &str) -> Result<String, DispatchError> {
     let trimmed = registry.trim();
     let re = Regex::new(r"^[A-Za-z0-9._:/-]+$").unwrap();
     if trimmed.is_empty() || !re.is_match(trimmed) {
-        return Err("Registry must be a valid hostname or URL fragment.".to_string());
+        return Err(DispatchError::invalid_argument("Registry must be a valid hostname or URL fragment."));
     }
 
     let mut normalized = trimmed.to_string();
@@ -2489,7 +2615,23 @@ fn command_exists(cmd: &str) -> bool {
     }
 }
 
-fn resolve_repo_path(input: Option<String>) -> Result<PathBuf, String> {
+/// Error for a missing conda, which `install_conda` can fix
+fn conda_not_installed() -> DispatchError {
+    DispatchError::not_installed(
+        "conda",
+        "Conda is not installed. Please install it first using the install_conda tool.",
+    )
+}
+
+/// Error for a repository path outside the home directory
+fn outside_home(path: &Path) -> DispatchError {
+    DispatchError::path_rejected(
+        Some(path.display().to_string()),
+        "Repository path must be inside your home directory",
+    )
+}
+
+fn resolve_repo_path(input: Option<String>) -> Result<PathBuf, DispatchError> {
     let home = dirs::home_dir().ok_or("Could not determine home directory")?;
     let base = match input {
         Some(path) => PathBuf::from(path),
@@ -2501,9 +2643,9 @@ fn resolve_repo_path(input: Option<String>) -> Result<PathBuf, String> {
         .unwrap_or_else(|_| base.clone());
 
     if !canonical.exists() {
-        return Err(format!(
-            "Repository path '{}' does not exist",
-            canonical.display()
+        return Err(DispatchError::not_installed(
+            "braindrive_repo",
+            format!("Repository path '{}' does not exist", canonical.display()),
         ));
     }
 
@@ -2518,7 +2660,7 @@ fn resolve_repo_path(input: Option<String>) -> Result<PathBuf, String> {
         let home_str = home.to_string_lossy().to_lowercase();
         let clean_lower = clean_path.to_lowercase();
         if !clean_lower.starts_with(&home_str) {
-            return Err("Repository path must be inside your home directory".to_string());
+            return Err(outside_home(&clean_pathbuf));
         }
         clean_pathbuf
     };
@@ -2526,7 +2668,7 @@ fn resolve_repo_path(input: Option<String>) -> Result<PathBuf, String> {
     #[cfg(not(target_os = "windows"))]
     let result = {
         if !canonical.starts_with(&home) {
-            return Err("Repository path must be inside your home directory".to_string());
+            return Err(outside_home(&canonical));
         }
         canonical
     };
@@ -2536,7 +2678,7 @@ fn resolve_repo_path(input: Option<String>) -> Result<PathBuf, String> {
 
 /// Resolve repo path, returning default if not specified.
 /// Unlike resolve_repo_path, this expects the path to exist and validates it.
-fn resolve_repo_path_or_default(input: Option<String>) -> Result<PathBuf, String> {
+pub(crate) fn resolve_repo_path_or_default(input: Option<String>) -> Result<PathBuf, DispatchError> {
     let home = dirs::home_dir().ok_or("Could not determine home directory")?;
     let base = match input {
         Some(path) => {
@@ -2554,9 +2696,12 @@ fn resolve_repo_path_or_default(input: Option<String>) -> Result<PathBuf, String
     let resolved = if base.exists() {
         base.canonicalize().unwrap_or(base)
     } else {
-        return Err(format!(
-            "Repository path '{}' does not exist. Please clone the repository first.",
-            base.display()
+        return Err(DispatchError::not_installed(
+            "braindrive_repo",
+            format!(
+                "Repository path '{}' does not exist. Please clone the repository first.",
+                base.display()
+            ),
         ));
     };
 
@@ -2571,7 +2716,7 @@ fn resolve_repo_path_or_default(input: Option<String>) -> Result<PathBuf, String
         let home_str = home.to_string_lossy().to_lowercase();
         let clean_lower = clean_path.to_lowercase();
         if !clean_lower.starts_with(&home_str) {
-            return Err("Repository path must be inside your home directory".to_string());
+            return Err(outside_home(&clean_pathbuf));
         }
         clean_pathbuf
     };
@@ -2579,7 +2724,7 @@ fn resolve_repo_path_or_default(input: Option<String>) -> Result<PathBuf, String
     #[cfg(not(target_os = "windows"))]
     let result = {
         if !resolved.starts_with(&home) {
-            return Err("Repository path must be inside your home directory".to_string());
+            return Err(outside_home(&resolved));
         }
         resolved
     };
@@ -2587,23 +2732,29 @@ fn resolve_repo_path_or_default(input: Option<String>) -> Result<PathBuf, String
     Ok(result)
 }
 
-fn resolve_environment_file(repo: &Path, environment_file: Option<String>) -> Result<PathBuf, String> {
+fn resolve_environment_file(repo: &Path, environment_file: Option<String>) -> Result<PathBuf, DispatchError> {
     let relative = environment_file.unwrap_or_else(|| "environment.yml".to_string());
     let candidate = repo.join(relative);
-    let canonical = candidate
-        .canonicalize()
-        .map_err(|_| "Environment file could not be found".to_string())?;
+    let canonical = candidate.canonicalize().map_err(|_| {
+        DispatchError::path_rejected(
+            Some(candidate.display().to_string()),
+            "Environment file could not be found",
+        )
+    })?;
 
     if !canonical.starts_with(repo) {
-        return Err("Environment file must live inside the BrainDrive repository".to_string());
+        return Err(DispatchError::path_rejected(
+            Some(canonical.display().to_string()),
+            "Environment file must live inside the BrainDrive repository",
+        ));
     }
 
     Ok(canonical)
 }
 
-struct CommandOutput {
-    success: bool,
-    stdout: String,
-    stderr: String,
-    exit_code: i32,
+pub(crate) struct CommandOutput {
+    pub(crate) success: bool,
+    pub(crate) stdout: String,
+    pub(crate) stderr: String,
+    pub(crate) exit_code: i32,
 }
@@ -0,0 +1,811 @@
+//! Installer downloads: retries, resuming and the curl fallback
+//!
+//! A download is written to `<dest>.part` and renamed to `dest` once it is
+//! complete. When the server advertises `Accept-Ranges: bytes` and gives a
+//! validator (a strong ETag, or Last-Modified), the partial file is kept when
+//! an attempt fails, along with what the server said about it in
+//! `<dest>.part.json`, and the next attempt (or the next run) asks for the rest
+//! with `Range` and `If-Range`. A server whose copy changed in between answers
+//! with the whole file and the download starts over, as it does when the
+//! server doesn't support ranges at all.
+//!
+//! A large file from a server that takes ranges is fetched over several
+//! connections at once, each writing its own range of the partial file. If
+//! that fails, the partial file is cut back to the part downloaded without
+//! gaps and the next attempt resumes it over one connection. Every download
+//! is held to the bandwidth cap (see `bandwidth`).
+//!
+//! Finished downloads go into the download cache, and a verified copy from
+//! there is used instead of downloading again.
+
+use crate::bandwidth;
+use crate::checksum::{self, DownloadHasher};
+use crate::dispatcher::{run_command, sleep_or_cancel};
+use crate::download_cache;
+use crate::error::{DispatchError, CANCELLED_MESSAGE};
+use crate::network::NetworkConfig;
+use crate::protocol::{send_message, Outbound, OutgoingMessage};
+use reqwest::header::{HeaderMap, ACCEPT_RANGES, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::process::Command;
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+const DOWNLOAD_MAX_RETRIES: u8 = 3;
+const DOWNLOAD_RETRY_DELAY_SECS: u64 = 2;
+/// Timeout for establishing HTTP connection (seconds)
+const DOWNLOAD_CONNECT_TIMEOUT_SECS: u64 = 30;
+/// Files at least this large are fetched over several connections
+const PARALLEL_MIN_BYTES: u64 = 32 * 1024 * 1024;
+const PARALLEL_CONNECTIONS: u64 = 4;
+
+/// What the server said about the file a partial download came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct PartialMeta {
+    url: String,
+    /// Strong ETag; weak ones can't be used in `If-Range`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+    /// Size of the whole file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    total: Option<u64>,
+}
+
+impl PartialMeta {
+    /// `None` unless the server takes ranges and gave a validator to resume against
+    fn from_headers(url: &str, headers: &HeaderMap, total: Option<u64>) -> Option<Self> {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok()).map(str::trim);
+        if !header(ACCEPT_RANGES).is_some_and(|ranges| ranges.eq_ignore_ascii_case("bytes")) {
+            return None;
+        }
+        let meta = Self {
+            url: url.to_string(),
+            etag: header(ETAG).filter(|etag| !etag.starts_with("W/")).map(str::to_string),
+            last_modified: header(LAST_MODIFIED).map(str::to_string),
+            total,
+        };
+        meta.validator().is_some().then_some(meta)
+    }
+
+    /// `If-Range` value
+    fn validator(&self) -> Option<&str> {
+        self.etag.as_deref().or(self.last_modified.as_deref())
+    }
+}
+
+/// `<dest>.part` and the metadata beside it
+struct Partial {
+    part: PathBuf,
+    meta: PathBuf,
+}
+
+impl Partial {
+    fn new(dest: &Path) -> Self {
+        let name = dest.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        Self {
+            part: dest.with_file_name(format!("{}.part", name)),
+            meta: dest.with_file_name(format!("{}.part.json", name)),
+        }
+    }
+
+    /// Bytes already downloaded from `url` that the server said can be resumed
+    fn resumable(&self, url: &str) -> Option<(u64, PartialMeta)> {
+        let meta: PartialMeta = serde_json::from_str(&std::fs::read_to_string(&self.meta).ok()?).ok()?;
+        let downloaded = std::fs::metadata(&self.part).ok()?.len();
+        let usable = meta.url == url
+            && meta.validator().is_some()
+            && downloaded > 0
+            && meta.total.is_none_or(|total| downloaded < total);
+        usable.then_some((downloaded, meta))
+    }
+
+    /// Remember how to resume; without `meta` the partial file can't be resumed
+    fn describe(&self, meta: Option<&PartialMeta>) {
+        let result = match meta {
+            Some(meta) => serde_json::to_string(meta)
+                .map_err(|e| e.to_string())
+                .and_then(|json| std::fs::write(&self.meta, json).map_err(|e| e.to_string())),
+            None => match std::fs::remove_file(&self.meta) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+                _ => Ok(()),
+            },
+        };
+        if let Err(e) = result {
+            tracing::warn!(path = %self.meta.display(), error = %e, "Failed to record partial download");
+        }
+    }
+
+    fn discard(&self) {
+        let _ = std::fs::remove_file(&self.part);
+        let _ = std::fs::remove_file(&self.meta);
+    }
+
+    /// Drop the partial file unless a later attempt can resume it
+    fn discard_unless_resumable(&self, url: &str) {
+        if self.resumable(url).is_none() {
+            self.discard();
+        }
+    }
+
+    fn finish(&self, dest: &Path) -> Result<(), DispatchError> {
+        let _ = std::fs::remove_file(dest);
+        std::fs::rename(&self.part, dest)
+            .map_err(|e| DispatchError::internal(format!("Failed to move download into place: {}", e)))?;
+        let _ = std::fs::remove_file(&self.meta);
+        Ok(())
+    }
+}
+
+/// Download a file with progress updates, returning its SHA-256
+/// Fails with `ChecksumMismatch`, and deletes the file, if the digest isn't
+/// `expected_sha256` (or the manifest's digest for `url` when that is unset);
+/// with neither it fails before downloading
+/// A verified copy in the download cache is used instead when there is one
+pub(crate) async fn download_file_with_progress(
+    url: &str,
+    dest: &Path,
+    expected_sha256: Option<&str>,
+    request_id: String,
+    sender: Arc<Mutex<Outbound>>,
+    operation: &str,
+    cancel: &CancellationToken,
+) -> Result<String, DispatchError> {
+    let expected = checksum::expected_digest(url, expected_sha256)?;
+
+    if let Some(digest) = download_cache::restore(url, Some(&expected), dest).await {
+        let size = std::fs::metadata(dest).map(|metadata| metadata.len()).ok();
+        let _ = send_message(&sender, OutgoingMessage::Progress {
+            id: request_id,
+            operation: operation.to_string(),
+            percent: Some(100),
+            message: "Using the previously downloaded file".to_string(),
+            bytes_downloaded: size,
+            bytes_total: size,
+        }).await;
+        return Ok(digest);
+    }
+
+    let digest = download_uncached(url, dest, &expected, request_id, sender, operation, cancel).await?;
+    download_cache::store(url, dest, &digest).await;
+    Ok(digest)
+}
+
+async fn download_uncached(
+    url: &str,
+    dest: &Path,
+    expected: &str,
+    request_id: String,
+    sender: Arc<Mutex<Outbound>>,
+    operation: &str,
+    cancel: &CancellationToken,
+) -> Result<String, DispatchError> {
+    // Proxy and custom CAs; a broken setting fails here, before any attempt
+    let client = NetworkConfig::current()
+        .http_client_builder()?
+        .user_agent("BrainDrive-Installer/1.0")
+        .connect_timeout(Duration::from_secs(DOWNLOAD_CONNECT_TIMEOUT_SECS))
+        // Note: No overall timeout set - large downloads (100MB+) need unlimited time
+        // The connect_timeout handles initial connection issues
+        // Streaming errors are handled per-chunk in download_file_with_progress_once
+        .build()
+        .map_err(|e| {
+            let err_msg = format!("Failed to build HTTP client: {}", e);
+            tracing::error!("{}", err_msg);
+            DispatchError::internal(err_msg)
+        })?;
+
+    let partial = Partial::new(dest);
+    let mut last_error: Option<String> = None;
+
+    for attempt in 1..=DOWNLOAD_MAX_RETRIES {
+        if attempt > 1 {
+            let _ = send_message(&sender, OutgoingMessage::Progress {
+                id: request_id.clone(),
+                operation: operation.to_string(),
+                percent: Some(0),
+                message: format!(
+                    "Retrying download (attempt {} of {})...",
+                    attempt, DOWNLOAD_MAX_RETRIES
+                ),
+                bytes_downloaded: None,
+                bytes_total: None,
+            }).await;
+        }
+
+        // Retries use one connection, in case the server limits them
+        match download_file_with_progress_once(
+            &client,
+            url,
+            &partial,
+            attempt == 1,
+            request_id.clone(),
+            sender.clone(),
+            operation,
+            cancel,
+        ).await {
+            Ok(digest) => {
+                partial.finish(dest)?;
+                checksum::verify(url, dest, &digest, expected)?;
+                return Ok(digest);
+            }
+            Err(_) if cancel.is_cancelled() => {
+                tracing::info!("Download of {} cancelled", url);
+                partial.discard_unless_resumable(url);
+                return Err(DispatchError::Cancelled);
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Download attempt {} failed for {}: {}",
+                    attempt, url, e
+                );
+                last_error = Some(e);
+                partial.discard_unless_resumable(url);
+                if attempt < DOWNLOAD_MAX_RETRIES {
+                    sleep_or_cancel(Duration::from_secs(DOWNLOAD_RETRY_DELAY_SECS * attempt as u64), cancel).await?;
+                }
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = send_message(&sender, OutgoingMessage::Progress {
+            id: request_id.clone(),
+            operation: operation.to_string(),
+            percent: Some(0),
+            message: "Retrying download with system curl...".to_string(),
+            bytes_downloaded: None,
+            bytes_total: None,
+        }).await;
+
+        let resume = partial.resumable(url).map(|(_, meta)| meta);
+        let mut result = download_file_with_curl(url, &partial.part, resume.as_ref(), cancel).await;
+        if result.is_err() && resume.is_some() && !cancel.is_cancelled() {
+            // The file changed on the server or it stopped taking ranges
+            partial.discard();
+            result = download_file_with_curl(url, &partial.part, None, cancel).await;
+        }
+
+        if let Err(curl_err) = result {
+            if cancel.is_cancelled() {
+                partial.discard_unless_resumable(url);
+                return Err(DispatchError::Cancelled);
+            }
+            partial.discard();
+            let combined_error = match last_error {
+                Some(err) => format!("{} | curl fallback failed: {}", err, curl_err),
+                None => format!("curl fallback failed: {}", curl_err),
+            };
+            let final_error = format!(
+                "Download failed after {} attempts: {}",
+                DOWNLOAD_MAX_RETRIES,
+                combined_error
+            );
+            tracing::error!("{}", final_error);
+            return Err(DispatchError::network(final_error));
+        }
+
+        tracing::info!("Download succeeded via curl fallback for {}", url);
+        partial.finish(dest)?;
+        let path = dest.to_path_buf();
+        let digest = tokio::task::spawn_blocking(move || checksum::file_digest(&path))
+            .await
+            .map_err(|e| DispatchError::internal(e.to_string()))?
+            .map_err(DispatchError::internal)?;
+        checksum::verify(url, dest, &digest, expected)?;
+        return Ok(digest);
+    }
+
+    // Windows-only fallback (no curl available by default)
+    #[cfg(target_os = "windows")]
+    {
+        partial.discard_unless_resumable(url);
+        let last_error = last_error.unwrap_or_else(|| "Unknown download error".to_string());
+        let final_error = format!(
+            "Download failed after {} attempts: {}",
+            DOWNLOAD_MAX_RETRIES, last_error
+        );
+        tracing::error!("{}", final_error);
+        return Err(DispatchError::network(final_error));
+    }
+
+    // This is unreachable on non-Windows (curl fallback always returns above)
+    // but needed for type checking when cfg doesn't match
+    #[cfg(not(target_os = "windows"))]
+    unreachable!()
+}
+
+/// One attempt: resume the partial file if the server allows it, otherwise
+/// download from the start, over several connections if `parallel` and the
+/// file is large; returns the SHA-256 of the whole file
+#[allow(clippy::too_many_arguments)]
+async fn download_file_with_progress_once(
+    client: &reqwest::Client,
+    url: &str,
+    partial: &Partial,
+    parallel: bool,
+    request_id: String,
+    sender: Arc<Mutex<Outbound>>,
+    operation: &str,
+    cancel: &CancellationToken,
+) -> Result<String, String> {
+    use tokio::io::AsyncWriteExt;
+
+    let resume = partial.resumable(url);
+    let mut request = client.get(url);
+    match &resume {
+        Some((offset, meta)) => {
+            tracing::info!("Resuming download from {} at byte {}", url, offset);
+            request = request
+                .header(RANGE, format!("bytes={}-", offset))
+                .header(IF_RANGE, meta.validator().unwrap_or_default());
+        }
+        None => tracing::info!("Starting download from {}", url),
+    }
+
+    let response = tokio::select! {
+        response = request.send() => response,
+        _ = cancel.cancelled() => return Err(CANCELLED_MESSAGE.to_string()),
+    };
+    let response = response
+        .map_err(|e| {
+            // Log detailed error info for debugging
+            let err_msg = format!("Failed to start download from {}: {} (is_timeout={}, is_connect={}, is_request={})",
+                url, e, e.is_timeout(), e.is_connect(), e.is_request());
+            tracing::error!("{}", err_msg);
+            format!("Failed to start download: {}", e)
+        })?;
+
+    let status = response.status();
+    let (mut file, mut hasher, mut downloaded, total_size) = match resume {
+        Some((offset, meta)) if status == StatusCode::PARTIAL_CONTENT => {
+            let range = response
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_content_range);
+            let total = match range {
+                Some((start, total)) if start == offset && (meta.total.is_none() || total == meta.total) => {
+                    total.or(meta.total)
+                }
+                _ => {
+                    partial.discard();
+                    return Err(format!("Server resumed {} at the wrong offset; starting over", url));
+                }
+            };
+            let part = partial.part.clone();
+            let hasher = tokio::task::spawn_blocking(move || {
+                let mut hasher = DownloadHasher::default();
+                hasher.update_from_file(&part).map(|()| hasher)
+            })
+            .await
+            .map_err(|e| e.to_string())??;
+            let file = tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(&partial.part)
+                .await
+                .map_err(|e| format!("Failed to open partial download: {}", e))?;
+            (file, hasher, offset, total)
+        }
+        _ if status == StatusCode::RANGE_NOT_SATISFIABLE => {
+            partial.discard();
+            return Err(format!("Server can't resume {}; starting over", url));
+        }
+        _ if status.is_success() => {
+            // A fresh download, or the file changed and If-Range sent all of it
+            let total = response.content_length();
+            let meta = PartialMeta::from_headers(url, response.headers(), total);
+            if let (true, Some(meta), Some(total)) = (parallel, &meta, total) {
+                if status == StatusCode::OK && total >= PARALLEL_MIN_BYTES {
+                    drop(response);
+                    return download_in_parallel(client, meta, total, partial, request_id, sender, operation, cancel).await;
+                }
+            }
+            partial.describe(meta.as_ref());
+            let file = tokio::fs::File::create(&partial.part)
+                .await
+                .map_err(|e| format!("Failed to create file: {}", e))?;
+            (file, DownloadHasher::default(), 0, total)
+        }
+        _ => {
+            let err_msg = format!(
+                "Download failed with status: {} ({})",
+                status,
+                url
+            );
+            tracing::error!("{}", err_msg);
+            return Err(err_msg);
+        }
+    };
+
+    let mut stream = response.bytes_stream();
+    let mut last_percent: u8 = 0;
+
+    loop {
+        let chunk = tokio::select! {
+            chunk = futures_util::StreamExt::next(&mut stream) => chunk,
+            _ = cancel.cancelled() => return Err(CANCELLED_MESSAGE.to_string()),
+        };
+        let Some(chunk) = chunk else {
+            break;
+        };
+        let chunk = chunk.map_err(|e| format!("Download error: {}", e))?;
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| format!("Failed to write file: {}", e))?;
+        hasher.update(&chunk);
+        bandwidth::throttle(chunk.len(), cancel).await.map_err(|_| CANCELLED_MESSAGE.to_string())?;
+
+        downloaded += chunk.len() as u64;
+
+        // Calculate and send progress (only if we know total size)
+        if let Some(total) = total_size {
+            let percent = ((downloaded as f64 / total as f64) * 50.0) as u8; // 0-50% for download
+            if percent > last_percent {
+                last_percent = percent;
+                let _ = send_message(&sender, OutgoingMessage::Progress {
+                    id: request_id.clone(),
+                    operation: operation.to_string(),
+                    percent: Some(percent),
+                    message: format!("Downloading... {:.1} MB / {:.1} MB",
+                        downloaded as f64 / 1_048_576.0,
+                        total as f64 / 1_048_576.0
+                    ),
+                    bytes_downloaded: Some(downloaded),
+                    bytes_total: Some(total),
+                }).await;
+            }
+        }
+    }
+
+    file.flush().await.map_err(|e| format!("Failed to flush file: {}", e))?;
+
+    // A connection closed early can end the stream without an error
+    if let Some(total) = total_size {
+        if downloaded != total {
+            return Err(format!("Download ended after {} of {} bytes", downloaded, total));
+        }
+    }
+
+    Ok(hasher.finish())
+}
+
+/// Fetch the `total` bytes of `meta.url` into the partial file over
+/// `PARALLEL_CONNECTIONS` ranged requests, then hash it
+///
+/// On failure the partial file keeps the part downloaded without gaps, for
+/// the next attempt to resume.
+#[allow(clippy::too_many_arguments)]
+async fn download_in_parallel(
+    client: &reqwest::Client,
+    meta: &PartialMeta,
+    total: u64,
+    partial: &Partial,
+    request_id: String,
+    sender: Arc<Mutex<Outbound>>,
+    operation: &str,
+    cancel: &CancellationToken,
+) -> Result<String, String> {
+    tracing::info!("Downloading {} over {} connections", meta.url, PARALLEL_CONNECTIONS);
+    // Until it is cut back to a prefix, the file has gaps and can't be resumed
+    partial.describe(None);
+    let file = std::fs::File::create(&partial.part).map_err(|e| format!("Failed to create file: {}", e))?;
+    file.set_len(total).map_err(|e| format!("Failed to allocate {} bytes: {}", total, e))?;
+    drop(file);
+
+    let ranges = split_ranges(total, PARALLEL_CONNECTIONS);
+    let done: Vec<Arc<AtomicU64>> = ranges.iter().map(|_| Arc::new(AtomicU64::new(0))).collect();
+    let (received_tx, mut received) = mpsc::unbounded_channel::<u64>();
+    let fetches = ranges.iter().zip(&done).map(|(&range, done)| {
+        fetch_range(client, meta, range, &partial.part, done.clone(), received_tx.clone(), cancel)
+    });
+    let fetches = futures_util::future::try_join_all(fetches);
+    drop(received_tx);
+    tokio::pin!(fetches);
+
+    let mut downloaded = 0u64;
+    let mut last_percent: u8 = 0;
+    let result = loop {
+        tokio::select! {
+            result = &mut fetches => break result,
+            Some(bytes) = received.recv() => {
+                downloaded += bytes;
+                let percent = ((downloaded as f64 / total as f64) * 50.0) as u8; // 0-50% for download
+                if percent > last_percent {
+                    last_percent = percent;
+                    let _ = send_message(&sender, OutgoingMessage::Progress {
+                        id: request_id.clone(),
+                        operation: operation.to_string(),
+                        percent: Some(percent),
+                        message: format!("Downloading... {:.1} MB / {:.1} MB",
+                            downloaded as f64 / 1_048_576.0,
+                            total as f64 / 1_048_576.0
+                        ),
+                        bytes_downloaded: Some(downloaded),
+                        bytes_total: Some(total),
+                    }).await;
+                }
+            }
+        }
+    };
+
+    if let Err(e) = result {
+        let done: Vec<u64> = done.iter().map(|done| done.load(Ordering::SeqCst)).collect();
+        let prefix = contiguous_prefix(&ranges, &done);
+        let kept = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&partial.part)
+            .and_then(|file| file.set_len(prefix));
+        match kept {
+            Ok(()) if prefix > 0 => partial.describe(Some(meta)),
+            _ => partial.discard(),
+        }
+        return Err(e);
+    }
+
+    let part = partial.part.clone();
+    tokio::task::spawn_blocking(move || checksum::file_digest(&part))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Fetch the inclusive byte range `(start, end)` into `path` at the same offset,
+/// counting bytes written in `done`
+async fn fetch_range(
+    client: &reqwest::Client,
+    meta: &PartialMeta,
+    (start, end): (u64, u64),
+    path: &Path,
+    done: Arc<AtomicU64>,
+    received: mpsc::UnboundedSender<u64>,
+    cancel: &CancellationToken,
+) -> Result<(), String> {
+    use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+    let request = client
+        .get(&meta.url)
+        .header(RANGE, format!("bytes={}-{}", start, end))
+        .header(IF_RANGE, meta.validator().unwrap_or_default());
+    let response = tokio::select! {
+        response = request.send() => response,
+        _ = cancel.cancelled() => return Err(CANCELLED_MESSAGE.to_string()),
+    };
+    let response = response.map_err(|e| format!("Failed to start download: {}", e))?;
+    let range_start = response
+        .headers()
+        .get(CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_content_range)
+        .map(|(range_start, _)| range_start);
+    if response.status() != StatusCode::PARTIAL_CONTENT || range_start != Some(start) {
+        // The file changed, or the server only takes ranges from one connection
+        return Err(format!(
+            "Server answered a ranged request for {} with {}",
+            meta.url,
+            response.status()
+        ));
+    }
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .await
+        .map_err(|e| format!("Failed to open partial download: {}", e))?;
+    file.seek(std::io::SeekFrom::Start(start))
+        .await
+        .map_err(|e| format!("Failed to write file: {}", e))?;
+
+    let expected = end - start + 1;
+    let mut written = 0u64;
+    let mut stream = response.bytes_stream();
+    loop {
+        let chunk = tokio::select! {
+            chunk = futures_util::StreamExt::next(&mut stream) => chunk,
+            _ = cancel.cancelled() => return Err(CANCELLED_MESSAGE.to_string()),
+        };
+        let Some(chunk) = chunk else {
+            break;
+        };
+        let chunk = chunk.map_err(|e| format!("Download error: {}", e))?;
+        if written + chunk.len() as u64 > expected {
+            return Err(format!("Server sent more than the requested range of {}", meta.url));
+        }
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| format!("Failed to write file: {}", e))?;
+        written += chunk.len() as u64;
+        done.store(written, Ordering::SeqCst);
+        let _ = received.send(chunk.len() as u64);
+        bandwidth::throttle(chunk.len(), cancel).await.map_err(|_| CANCELLED_MESSAGE.to_string())?;
+    }
+    file.flush().await.map_err(|e| format!("Failed to flush file: {}", e))?;
+
+    if written != expected {
+        return Err(format!("Range of {} ended after {} of {} bytes", meta.url, written, expected));
+    }
+    Ok(())
+}
+
+/// `total` bytes split into `parts` inclusive ranges, the last taking the remainder
+fn split_ranges(total: u64, parts: u64) -> Vec<(u64, u64)> {
+    let size = total / parts;
+    (0..parts)
+        .map(|i| {
+            let start = i * size;
+            let end = if i == parts - 1 { total - 1 } else { start + size - 1 };
+            (start, end)
+        })
+        .collect()
+}
+
+/// Bytes from the start of the file written without gaps, given the bytes
+/// written at the start of each range
+fn contiguous_prefix(ranges: &[(u64, u64)], done: &[u64]) -> u64 {
+    let mut prefix = 0;
+    for (&(start, end), &done) in ranges.iter().zip(done) {
+        prefix = start + done;
+        if done < end - start + 1 {
+            break;
+        }
+    }
+    prefix
+}
+
+/// `start` and the total size from `Content-Range: bytes start-end/total`
+fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _) = range.split_once('-')?;
+    let total = match total {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    Some((start.parse().ok()?, total))
+}
+
+/// With `resume`, continue the partial file, failing if the server's copy changed
+#[cfg(not(target_os = "windows"))]
+async fn download_file_with_curl(
+    url: &str,
+    dest: &Path,
+    resume: Option<&PartialMeta>,
+    cancel: &CancellationToken,
+) -> Result<(), String> {
+    tracing::info!("Attempting curl fallback download from {}", url);
+
+    let mut command = Command::new("curl");
+    command
+        .arg("--fail")
+        .arg("--location")
+        .arg("--show-error")
+        .arg("--connect-timeout")
+        .arg("30")
+        .arg("--max-time")
+        .arg("600")  // 10 minute max for large downloads
+        .arg("--retry")
+        .arg("2")
+        .args(NetworkConfig::current().curl_args(url));
+    if let Some(limit) = bandwidth::limit() {
+        // Fixed for this run; curl can't pick up a change
+        command.arg("--limit-rate").arg(limit.to_string());
+    }
+    if let Some(validator) = resume.and_then(PartialMeta::validator) {
+        // A changed file comes back whole, which curl refuses to append
+        command
+            .arg("--continue-at")
+            .arg("-")
+            .arg("--header")
+            .arg(format!("If-Range: {}", validator));
+    }
+    command
+        .arg("--output")
+        .arg(dest)
+        .arg(url);
+
+    let output = run_command(command, cancel).await.map_err(|e| {
+        let err_msg = format!("Failed to run curl: {}", e);
+        tracing::error!("{}", err_msg);
+        err_msg
+    })?;
+
+    if !output.success {
+        let err_msg = format!("curl download failed (exit code {}): {}",
+            output.exit_code, output.stderr.trim());
+        tracing::error!("{}", err_msg);
+        return Err(err_msg);
+    }
+
+    tracing::info!("curl fallback download completed successfully");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    const URL: &str = "https://repo.anaconda.com/miniconda/Miniconda3-latest-Linux-x86_64.sh";
+
+    fn headers(pairs: &[(reqwest::header::HeaderName, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(name.clone(), HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_only_servers_with_ranges_and_a_validator_can_resume() {
+        let meta = PartialMeta::from_headers(
+            URL,
+            &headers(&[(ACCEPT_RANGES, "bytes"), (ETAG, "\"abc\""), (LAST_MODIFIED, "Tue, 01 Oct 2024 00:00:00 GMT")]),
+            Some(100),
+        )
+        .unwrap();
+        assert_eq!(meta.validator(), Some("\"abc\""));
+
+        let weak = PartialMeta::from_headers(
+            URL,
+            &headers(&[(ACCEPT_RANGES, "bytes"), (ETAG, "W/\"abc\""), (LAST_MODIFIED, "Tue, 01 Oct 2024 00:00:00 GMT")]),
+            None,
+        )
+        .unwrap();
+        assert_eq!(weak.validator(), Some("Tue, 01 Oct 2024 00:00:00 GMT"));
+
+        assert!(PartialMeta::from_headers(URL, &headers(&[(ETAG, "\"abc\"")]), None).is_none());
+        assert!(PartialMeta::from_headers(URL, &headers(&[(ACCEPT_RANGES, "none"), (ETAG, "\"abc\"")]), None).is_none());
+        assert!(PartialMeta::from_headers(URL, &headers(&[(ACCEPT_RANGES, "bytes"), (ETAG, "W/\"abc\"")]), None).is_none());
+    }
+
+    #[test]
+    fn test_content_range_is_parsed() {
+        assert_eq!(parse_content_range("bytes 100-999/1000"), Some((100, Some(1000))));
+        assert_eq!(parse_content_range("bytes 100-999/*"), Some((100, None)));
+        assert_eq!(parse_content_range("bytes */1000"), None);
+        assert_eq!(parse_content_range("items 1-2/3"), None);
+    }
+
+    #[test]
+    fn test_parallel_ranges_cover_the_file_and_keep_the_gapless_prefix() {
+        let ranges = split_ranges(10, 3);
+        assert_eq!(ranges, [(0, 2), (3, 5), (6, 9)]);
+
+        assert_eq!(contiguous_prefix(&ranges, &[3, 3, 4]), 10);
+        // The first range stopped early: what the others fetched is behind a gap
+        assert_eq!(contiguous_prefix(&ranges, &[1, 3, 4]), 1);
+        assert_eq!(contiguous_prefix(&ranges, &[3, 2, 4]), 5);
+        assert_eq!(contiguous_prefix(&ranges, &[0, 3, 4]), 0);
+    }
+
+    #[test]
+    fn test_partial_download_resumes_only_from_the_same_url() {
+        let dir = std::env::temp_dir().join(format!("braindrive-download-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dest = dir.join("Miniconda3-installer.sh");
+        let partial = Partial::new(&dest);
+        assert_eq!(partial.part, dir.join("Miniconda3-installer.sh.part"));
+
+        std::fs::write(&partial.part, b"abc").unwrap();
+        let meta = PartialMeta {
+            url: URL.to_string(),
+            etag: Some("\"abc\"".to_string()),
+            last_modified: None,
+            total: Some(10),
+        };
+        partial.describe(Some(&meta));
+        assert_eq!(partial.resumable(URL).map(|(offset, _)| offset), Some(3));
+        assert!(partial.resumable("https://example.com/other.sh").is_none());
+
+        partial.describe(None);
+        partial.discard_unless_resumable(URL);
+        assert!(!partial.part.exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
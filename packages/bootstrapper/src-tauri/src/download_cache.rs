@@ -0,0 +1,331 @@
+//! Cache of verified downloads under `~/.braindrive-installer/downloads/cache`
+//!
+//! Installers are deleted once they have run, so without this every retry or
+//! reinstall downloads 100MB+ again. Each verified download is kept in the
+//! cache under its SHA-256, and `index.json` records which URL it came from.
+//! `download_file_with_progress` hands out the cached file instead of
+//! downloading when the expected digest is cached, or, for URLs without a
+//! published digest, when the URL was downloaded within the last week:
+//! `-latest-` installers change with each release, so those are fetched again
+//! after that. A cached file is hashed again before it is handed out. The
+//! least recently used files are evicted to keep the cache under its size
+//! limit.
+//!
+//! Like the install state journal, the cache is advisory: failing to read or
+//! write it is logged and the download goes ahead.
+
+use crate::checksum;
+use crate::error::DispatchError;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+const CACHE_DIR: &str = "cache";
+const INDEX_FILE: &str = "index.json";
+
+/// Largest the cache grows before the least recently used files are evicted
+const MAX_CACHE_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// How long a download with no published digest is reused
+const UNPINNED_MAX_AGE_DAYS: i64 = 7;
+
+/// Serializes read-modify-write of the index between concurrent downloads
+static INDEX_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Cached downloads, by URL
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheIndex {
+    #[serde(default)]
+    entries: BTreeMap<String, CacheEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    sha256: String,
+    size: u64,
+    /// When the file was downloaded
+    stored_at: String,
+    /// When the file was last stored or handed out
+    last_used: String,
+}
+
+impl CacheEntry {
+    /// Whether the entry can stand in for a download of its URL
+    fn usable(&self, expected: Option<&str>, now: DateTime<Utc>) -> bool {
+        match expected {
+            Some(expected) => self.sha256 == expected,
+            None => DateTime::parse_from_rfc3339(&self.stored_at)
+                .is_ok_and(|stored| now - stored.with_timezone(&Utc) < Duration::days(UNPINNED_MAX_AGE_DAYS)),
+        }
+    }
+}
+
+/// `~/.braindrive-installer/downloads`, where installers and partial downloads go
+pub(crate) fn downloads_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".braindrive-installer").join("downloads"))
+}
+
+/// Put a cached copy of `url` at `dest` and return its digest, if there is one
+///
+/// With `expected`, any cached file with that digest will do, whatever URL it
+/// came from.
+pub(crate) async fn restore(url: &str, expected: Option<&str>, dest: &Path) -> Option<String> {
+    let root = downloads_dir()?.join(CACHE_DIR);
+    let (url, expected, dest) = (url.to_string(), expected.map(str::to_string), dest.to_path_buf());
+    tokio::task::spawn_blocking(move || restore_from(&root, &url, expected.as_deref(), &dest, Utc::now()))
+        .await
+        .ok()
+        .flatten()
+}
+
+/// Keep the verified download at `path` for next time
+pub(crate) async fn store(url: &str, path: &Path, sha256: &str) {
+    let Some(root) = downloads_dir().map(|dir| dir.join(CACHE_DIR)) else {
+        return;
+    };
+    let (url, path, sha256) = (url.to_string(), path.to_path_buf(), sha256.to_string());
+    let stored = tokio::task::spawn_blocking(move || store_in(&root, &url, &path, &sha256, MAX_CACHE_BYTES, Utc::now()))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|stored| stored);
+    if let Err(e) = stored {
+        tracing::warn!(error = %e, "Failed to cache download");
+    }
+}
+
+/// Delete every cached download and partial download
+pub async fn clear_download_cache() -> Result<Value, DispatchError> {
+    let downloads = downloads_dir().ok_or("Could not determine home directory")?;
+    tokio::task::spawn_blocking(move || {
+        let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let cache = downloads.join(CACHE_DIR);
+        let mut freed = crate::uninstall::dir_size(&cache, &[]);
+        let files = read_index(&cache).entries.len();
+        match std::fs::remove_dir_all(&cache) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(DispatchError::internal(format!("Failed to remove {}: {}", cache.display(), e)));
+            }
+            _ => {}
+        }
+
+        // Interrupted downloads kept for resuming
+        let mut partials = 0;
+        for entry in std::fs::read_dir(&downloads).into_iter().flatten().flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.ends_with(".part") || name.ends_with(".part.json") {
+                let size = entry.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+                if std::fs::remove_file(entry.path()).is_ok() {
+                    freed += size;
+                    partials += 1;
+                }
+            }
+        }
+
+        Ok(json!({
+            "success": true,
+            "message": format!("Cleared the download cache ({} freed)", crate::uninstall::format_size(freed)),
+            "freed_bytes": freed,
+            "cached_downloads_removed": files,
+            "partial_files_removed": partials,
+            "path": cache.to_string_lossy(),
+        }))
+    })
+    .await
+    .map_err(|e| DispatchError::internal(e.to_string()))?
+}
+
+fn restore_from(root: &Path, url: &str, expected: Option<&str>, dest: &Path, now: DateTime<Utc>) -> Option<String> {
+    let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut index = read_index(root);
+    let sha256 = match (expected, index.entries.get(url)) {
+        (Some(expected), _) => expected.to_string(),
+        (None, Some(entry)) if entry.usable(None, now) => entry.sha256.clone(),
+        (None, _) => return None,
+    };
+    let cached = root.join(&sha256);
+    if !cached.exists() {
+        return None;
+    }
+    if checksum::file_digest(&cached).ok()? != sha256 {
+        tracing::warn!(url, path = %cached.display(), "Cached download is corrupt; discarding it");
+        let _ = std::fs::remove_file(&cached);
+        index.entries.retain(|_, entry| entry.sha256 != sha256);
+        let _ = write_index(root, &index);
+        return None;
+    }
+
+    let _ = std::fs::remove_file(dest);
+    if let Err(e) = link_or_copy(&cached, dest) {
+        tracing::warn!(url, error = %e, "Failed to use cached download");
+        return None;
+    }
+    let stamp = now.to_rfc3339_opts(SecondsFormat::Secs, true);
+    for entry in index.entries.values_mut().filter(|entry| entry.sha256 == sha256) {
+        entry.last_used = stamp.clone();
+    }
+    let _ = write_index(root, &index);
+    tracing::info!(url, sha256 = %sha256, "Using cached download");
+    Some(sha256)
+}
+
+fn store_in(root: &Path, url: &str, path: &Path, sha256: &str, max_bytes: u64, now: DateTime<Utc>) -> Result<(), String> {
+    let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let size = std::fs::metadata(path).map_err(|e| e.to_string())?.len();
+    if size > max_bytes {
+        return Ok(());
+    }
+    std::fs::create_dir_all(root).map_err(|e| format!("Failed to create {}: {}", root.display(), e))?;
+    let cached = root.join(sha256);
+    if !cached.exists() {
+        link_or_copy(path, &cached)?;
+    }
+
+    let mut index = read_index(root);
+    let stamp = now.to_rfc3339_opts(SecondsFormat::Secs, true);
+    index.entries.insert(
+        url.to_string(),
+        CacheEntry {
+            sha256: sha256.to_string(),
+            size,
+            stored_at: stamp.clone(),
+            last_used: stamp,
+        },
+    );
+    for evicted in plan_eviction(&index.entries, max_bytes, sha256) {
+        index.entries.remove(&evicted);
+    }
+
+    // Files no entry refers to any more, including ones evicted just now
+    let kept: HashSet<&str> = index.entries.values().map(|entry| entry.sha256.as_str()).collect();
+    for file in std::fs::read_dir(root).into_iter().flatten().flatten() {
+        let name = file.file_name().to_string_lossy().to_string();
+        if name != INDEX_FILE && !kept.contains(name.as_str()) {
+            let _ = std::fs::remove_file(file.path());
+        }
+    }
+    write_index(root, &index)
+}
+
+/// URLs to drop, least recently used first, until the files left fit in
+/// `max_bytes`; `keep` is the digest just stored
+fn plan_eviction(entries: &BTreeMap<String, CacheEntry>, max_bytes: u64, keep: &str) -> Vec<String> {
+    // Several URLs can share one file, which is only gone once all of them are
+    let mut files: BTreeMap<&str, (u64, &str)> = BTreeMap::new();
+    for entry in entries.values() {
+        let file = files.entry(entry.sha256.as_str()).or_insert((entry.size, entry.last_used.as_str()));
+        file.1 = file.1.max(entry.last_used.as_str());
+    }
+    let mut total: u64 = files.values().map(|(size, _)| size).sum();
+    let mut by_age: Vec<(&str, u64, &str)> = files
+        .iter()
+        .filter(|(sha256, _)| **sha256 != keep)
+        .map(|(sha256, (size, last_used))| (*sha256, *size, *last_used))
+        .collect();
+    by_age.sort_by_key(|(_, _, last_used)| *last_used);
+
+    let mut evicted = HashSet::new();
+    for (sha256, size, _) in by_age {
+        if total <= max_bytes {
+            break;
+        }
+        total -= size;
+        evicted.insert(sha256);
+    }
+    entries
+        .iter()
+        .filter(|(_, entry)| evicted.contains(entry.sha256.as_str()))
+        .map(|(url, _)| url.clone())
+        .collect()
+}
+
+fn read_index(root: &Path) -> CacheIndex {
+    std::fs::read_to_string(root.join(INDEX_FILE))
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn write_index(root: &Path, index: &CacheIndex) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(index).map_err(|e| e.to_string())?;
+    let tmp = root.join(format!("{}.tmp", INDEX_FILE));
+    std::fs::write(&tmp, text).map_err(|e| format!("Failed to write download cache index: {}", e))?;
+    std::fs::rename(&tmp, root.join(INDEX_FILE)).map_err(|e| format!("Failed to write download cache index: {}", e))
+}
+
+/// Hard link where the filesystem allows it, so the cache costs no extra space
+fn link_or_copy(from: &Path, to: &Path) -> Result<(), String> {
+    if std::fs::hard_link(from, to).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(from, to)
+        .map(|_| ())
+        .map_err(|e| format!("Failed to copy {} to {}: {}", from.display(), to.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("braindrive-cache-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entry(sha256: &str, size: u64, last_used: &str) -> CacheEntry {
+        CacheEntry {
+            sha256: sha256.to_string(),
+            size,
+            stored_at: last_used.to_string(),
+            last_used: last_used.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_least_recently_used_files_are_evicted() {
+        let entries = BTreeMap::from([
+            ("https://a".to_string(), entry("aa", 60, "2026-01-01T00:00:00Z")),
+            ("https://a-mirror".to_string(), entry("aa", 60, "2026-01-04T00:00:00Z")),
+            ("https://b".to_string(), entry("bb", 30, "2026-01-02T00:00:00Z")),
+            ("https://c".to_string(), entry("cc", 30, "2026-01-03T00:00:00Z")),
+        ]);
+        // The shared file counts once and was used most recently through its mirror
+        assert!(plan_eviction(&entries, 120, "cc").is_empty());
+        assert_eq!(plan_eviction(&entries, 100, "cc"), vec!["https://b".to_string()]);
+        assert_eq!(plan_eviction(&entries, 40, "aa"), vec!["https://b".to_string(), "https://c".to_string()]);
+    }
+
+    #[test]
+    fn test_cached_download_is_reused_while_it_verifies() {
+        let dir = scratch_dir("reuse");
+        let root = dir.join(CACHE_DIR);
+        let download = dir.join("installer.sh");
+        std::fs::write(&download, b"abc").unwrap();
+        let now = Utc::now();
+        store_in(&root, "https://example.com/latest.sh", &download, ABC_SHA256, 1024, now).unwrap();
+        std::fs::remove_file(&download).unwrap();
+
+        let dest = dir.join("again.sh");
+        let url = "https://example.com/latest.sh";
+        assert_eq!(restore_from(&root, url, None, &dest, now).as_deref(), Some(ABC_SHA256));
+        assert_eq!(std::fs::read(&dest).unwrap(), b"abc");
+        // Any URL with the expected digest, but only that digest
+        assert!(restore_from(&root, "https://mirror.example.com/x.sh", Some(ABC_SHA256), &dest, now).is_some());
+        assert!(restore_from(&root, url, Some(&"0".repeat(64)), &dest, now).is_none());
+        // Unpinned downloads go stale
+        let later = now + Duration::days(UNPINNED_MAX_AGE_DAYS + 1);
+        assert!(restore_from(&root, url, None, &dest, later).is_none());
+
+        // A corrupted file is dropped rather than handed out
+        std::fs::remove_file(&dest).unwrap();
+        std::fs::write(root.join(ABC_SHA256), b"abd").unwrap();
+        assert!(restore_from(&root, url, None, &dest, now).is_none());
+        assert!(!root.join(ABC_SHA256).exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,527 @@
+//! Typed failures reported by tool calls
+//!
+//! Every tool fails with a `DispatchError`. Its `kind` tells the backend what to
+//! do next: retry (`network`, `timeout`), install a prerequisite first
+//! (`not_installed`), hand over to the user (`needs_manual_action`) or give up.
+//! The human-readable message travels in `tool_result.error`; the variant and its
+//! structured fields travel in `tool_result.error_details`. That encoding is part
+//! of the protocol: add variants and fields freely, but don't rename or remove them.
+
+use serde::Serialize;
+use std::fmt;
+
+/// Error message of a call aborted through its cancellation token
+pub const CANCELLED_MESSAGE: &str = "Operation cancelled";
+
+/// Reply to tool calls from a backend session we aren't paired with
+const NOT_PAIRED_MESSAGE: &str =
+    "Bootstrapper is not paired with this session. Enter the code shown in the bootstrapper window.";
+
+/// Lines of stderr kept when a command fails
+const STDERR_TAIL_LINES: usize = 20;
+/// Upper bound on the kept stderr, for tools that print very long lines
+const STDERR_TAIL_MAX_CHARS: usize = 4000;
+
+/// Fragments of stderr output that mean a command failed to reach the network
+const NETWORK_ERROR_MARKERS: &[&str] = &[
+    "could not resolve host",
+    "temporary failure in name resolution",
+    "name or service not known",
+    "network is unreachable",
+    "connection timed out",
+    "connection refused",
+    "connection reset",
+    "failed to connect",
+    "could not connect",
+    "tls handshake",
+    "condahttperror",
+    "connectionerror",
+    "readtimeouterror",
+    "etimedout",
+    "enotfound",
+    "econnreset",
+    "eai_again",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DispatchError {
+    /// A prerequisite isn't installed yet; installing it and retrying should work
+    NotInstalled {
+        /// `conda`, `git`, `ollama` or `braindrive_repo`
+        component: String,
+        #[serde(skip)]
+        message: String,
+    },
+
+    /// Only the user can take the next step, e.g. install with sudo or click
+    /// through an OS dialog; call the tool again once they're done
+    NeedsManualAction {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        instructions: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        download_url: Option<String>,
+        #[serde(skip)]
+        message: String,
+    },
+
+    /// A download or fetch failed; usually transient and worth retrying
+    Network {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        exit_code: Option<i32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        stderr_tail: Option<String>,
+        #[serde(skip)]
+        message: String,
+    },
+
+    /// A command ran and exited unsuccessfully
+    ProcessFailed {
+        exit_code: i32,
+        stderr_tail: String,
+        #[serde(skip)]
+        message: String,
+    },
+
+    /// A service (BrainDrive backend/frontend, Ollama) didn't start or stop
+    ServiceFailed {
+        service: String,
+        #[serde(skip)]
+        message: String,
+    },
+
+    /// Something didn't become ready in time; retrying may work
+    Timeout {
+        #[serde(skip)]
+        message: String,
+    },
+
+    /// None of the candidate ports is free
+    PortUnavailable {
+        tried: Vec<u16>,
+        #[serde(skip)]
+        message: String,
+    },
+
+    /// A path argument points outside the allowed directories
+    PathRejected {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+        #[serde(skip)]
+        message: String,
+    },
+
+    /// An argument failed validation, e.g. an env name with shell characters
+    InvalidArgument {
+        #[serde(skip)]
+        message: String,
+    },
+
+    /// The tool doesn't support this OS or architecture
+    UnsupportedPlatform {
+        os: String,
+        arch: String,
+    },
+
+    /// Parts of the operation succeeded and others failed
+    PartialFailure {
+        completed: Vec<String>,
+        failed: Vec<ComponentFailure>,
+        #[serde(skip)]
+        message: String,
+    },
+
+    /// A step of an install plan didn't run because a step it depends on failed
+    DependencyFailed { dependency: String },
+
+    /// A download's SHA-256 didn't match the expected digest; it was deleted, not run
+    ChecksumMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+        #[serde(skip)]
+        message: String,
+    },
+
+    /// An update failed partway and was undone; BrainDrive is back on the
+    /// commit it ran before
+    RolledBack {
+        restored_commit: String,
+        attempted_commit: String,
+        /// What made the update fail
+        cause: Box<DispatchError>,
+        #[serde(skip)]
+        message: String,
+    },
+
+    /// The request didn't match the shape this bootstrapper expects for its type
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    ParseError {
+        /// Offending field, when it could be pinned down
+        #[serde(skip_serializing_if = "Option::is_none")]
+        field: Option<String>,
+        /// Type serde expected for that field, e.g. `u16`
+        #[serde(skip_serializing_if = "Option::is_none")]
+        expected: Option<String>,
+        #[serde(skip)]
+        message: String,
+    },
+
+    /// The request's type isn't a tool this bootstrapper version knows
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    UnsupportedTool { tool: String },
+
+    /// The request came from a backend session we aren't paired with
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    NotPaired,
+
+    /// The call was cancelled before it finished
+    Cancelled,
+
+    /// Anything else: I/O failures, unexpected states
+    Internal {
+        #[serde(skip)]
+        message: String,
+    },
+}
+
+/// One failed part of a `PartialFailure`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComponentFailure {
+    pub component: String,
+    pub error: String,
+    pub details: DispatchError,
+}
+
+impl ComponentFailure {
+    pub fn new(component: &str, error: DispatchError) -> Self {
+        Self {
+            component: component.to_string(),
+            error: error.to_string(),
+            details: error,
+        }
+    }
+}
+
+impl DispatchError {
+    pub fn not_installed(component: &str, message: impl Into<String>) -> Self {
+        DispatchError::NotInstalled {
+            component: component.to_string(),
+            message: message.into(),
+        }
+    }
+
+    pub fn needs_manual_action(
+        message: impl Into<String>,
+        instructions: Option<String>,
+        download_url: Option<&str>,
+    ) -> Self {
+        DispatchError::NeedsManualAction {
+            instructions,
+            download_url: download_url.map(str::to_string),
+            message: message.into(),
+        }
+    }
+
+    pub fn network(message: impl Into<String>) -> Self {
+        DispatchError::Network {
+            exit_code: None,
+            stderr_tail: None,
+            message: message.into(),
+        }
+    }
+
+    /// A command exited unsuccessfully; reported as `network` when its stderr
+    /// shows it couldn't reach the network, since that is worth retrying
+    pub fn process_failed(what: &str, exit_code: i32, stderr: &str) -> Self {
+        let stderr_tail = stderr_tail(stderr);
+        let last_line = stderr_tail.lines().rev().find(|line| !line.trim().is_empty());
+        let message = match last_line {
+            Some(line) => format!("{} failed (exit code {}): {}", what, exit_code, line.trim()),
+            None => format!("{} failed (exit code {})", what, exit_code),
+        };
+
+        let lowercase = stderr_tail.to_lowercase();
+        if NETWORK_ERROR_MARKERS.iter().any(|marker| lowercase.contains(marker)) {
+            return DispatchError::Network {
+                exit_code: Some(exit_code),
+                stderr_tail: Some(stderr_tail),
+                message,
+            };
+        }
+        DispatchError::ProcessFailed {
+            exit_code,
+            stderr_tail,
+            message,
+        }
+    }
+
+    pub fn service_failed(service: &str, message: impl Into<String>) -> Self {
+        DispatchError::ServiceFailed {
+            service: service.to_string(),
+            message: message.into(),
+        }
+    }
+
+    pub fn timeout(message: impl Into<String>) -> Self {
+        DispatchError::Timeout { message: message.into() }
+    }
+
+    pub fn port_unavailable(tried: Vec<u16>, message: impl Into<String>) -> Self {
+        DispatchError::PortUnavailable {
+            tried,
+            message: message.into(),
+        }
+    }
+
+    pub fn path_rejected(path: Option<String>, message: impl Into<String>) -> Self {
+        DispatchError::PathRejected {
+            path,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_argument(message: impl Into<String>) -> Self {
+        DispatchError::InvalidArgument { message: message.into() }
+    }
+
+    pub fn unsupported_platform() -> Self {
+        DispatchError::UnsupportedPlatform {
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+        }
+    }
+
+    pub fn partial_failure(
+        message: impl Into<String>,
+        completed: Vec<String>,
+        failed: Vec<ComponentFailure>,
+    ) -> Self {
+        DispatchError::PartialFailure {
+            completed,
+            failed,
+            message: message.into(),
+        }
+    }
+
+    pub fn dependency_failed(dependency: &str) -> Self {
+        DispatchError::DependencyFailed { dependency: dependency.to_string() }
+    }
+
+    pub fn checksum_mismatch(url: &str, expected: &str, actual: &str) -> Self {
+        DispatchError::ChecksumMismatch {
+            message: format!(
+                "Refusing to run the download from {}: its SHA-256 is {} but {} was expected",
+                url, actual, expected
+            ),
+            url: url.to_string(),
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        }
+    }
+
+    pub fn rolled_back(restored_commit: &str, attempted_commit: &str, cause: DispatchError) -> Self {
+        DispatchError::RolledBack {
+            message: format!(
+                "Update to {} failed and was rolled back to {}: {}",
+                short_commit(attempted_commit),
+                short_commit(restored_commit),
+                cause
+            ),
+            restored_commit: restored_commit.to_string(),
+            attempted_commit: attempted_commit.to_string(),
+            cause: Box::new(cause),
+        }
+    }
+
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    pub fn parse_error(field: Option<String>, expected: Option<String>, message: impl Into<String>) -> Self {
+        DispatchError::ParseError {
+            field,
+            expected,
+            message: message.into(),
+        }
+    }
+
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    pub fn unsupported_tool(tool: &str) -> Self {
+        DispatchError::UnsupportedTool { tool: tool.to_string() }
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        DispatchError::Internal { message: message.into() }
+    }
+
+    /// Stable name of the variant, as serialized in `kind`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DispatchError::NotInstalled { .. } => "not_installed",
+            DispatchError::NeedsManualAction { .. } => "needs_manual_action",
+            DispatchError::Network { .. } => "network",
+            DispatchError::ProcessFailed { .. } => "process_failed",
+            DispatchError::ServiceFailed { .. } => "service_failed",
+            DispatchError::Timeout { .. } => "timeout",
+            DispatchError::PortUnavailable { .. } => "port_unavailable",
+            DispatchError::PathRejected { .. } => "path_rejected",
+            DispatchError::InvalidArgument { .. } => "invalid_argument",
+            DispatchError::UnsupportedPlatform { .. } => "unsupported_platform",
+            DispatchError::PartialFailure { .. } => "partial_failure",
+            DispatchError::DependencyFailed { .. } => "dependency_failed",
+            DispatchError::ChecksumMismatch { .. } => "checksum_mismatch",
+            DispatchError::RolledBack { .. } => "rolled_back",
+            DispatchError::ParseError { .. } => "parse_error",
+            DispatchError::UnsupportedTool { .. } => "unsupported_tool",
+            DispatchError::NotPaired => "not_paired",
+            DispatchError::Cancelled => "cancelled",
+            DispatchError::Internal { .. } => "internal",
+        }
+    }
+}
+
+impl fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DispatchError::NotInstalled { message, .. }
+            | DispatchError::NeedsManualAction { message, .. }
+            | DispatchError::Network { message, .. }
+            | DispatchError::ProcessFailed { message, .. }
+            | DispatchError::ServiceFailed { message, .. }
+            | DispatchError::Timeout { message }
+            | DispatchError::PortUnavailable { message, .. }
+            | DispatchError::PathRejected { message, .. }
+            | DispatchError::InvalidArgument { message }
+            | DispatchError::PartialFailure { message, .. }
+            | DispatchError::ChecksumMismatch { message, .. }
+            | DispatchError::RolledBack { message, .. }
+            | DispatchError::ParseError { message, .. }
+            | DispatchError::Internal { message } => f.write_str(message),
+            DispatchError::UnsupportedPlatform { os, arch } => {
+                write!(f, "Unsupported platform: {} {}", os, arch)
+            }
+            DispatchError::DependencyFailed { dependency } => {
+                write!(f, "Not run because step '{}' failed", dependency)
+            }
+            DispatchError::UnsupportedTool { tool } => write!(
+                f,
+                "Tool '{}' is not supported by this bootstrapper (version {}, protocol {}). Please update the BrainDrive bootstrapper.",
+                tool,
+                env!("CARGO_PKG_VERSION"),
+                crate::protocol::PROTOCOL_VERSION
+            ),
+            DispatchError::NotPaired => f.write_str(NOT_PAIRED_MESSAGE),
+            DispatchError::Cancelled => f.write_str(CANCELLED_MESSAGE),
+        }
+    }
+}
+
+/// Helpers that still fail with plain strings (system detection, process
+/// spawning) surface as `internal`
+impl From<String> for DispatchError {
+    fn from(message: String) -> Self {
+        DispatchError::internal(message)
+    }
+}
+
+impl From<&str> for DispatchError {
+    fn from(message: &str) -> Self {
+        DispatchError::internal(message)
+    }
+}
+
+/// Abbreviated commit hash, as git prints it
+pub(crate) fn short_commit(commit: &str) -> &str {
+    commit.get(..7).unwrap_or(commit)
+}
+
+/// Last lines of a command's stderr, which is where tools put the actual error
+fn stderr_tail(stderr: &str) -> String {
+    let lines: Vec<&str> = stderr.trim_end().lines().collect();
+    let tail = lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n");
+    if tail.len() <= STDERR_TAIL_MAX_CHARS {
+        return tail;
+    }
+    let mut start = tail.len() - STDERR_TAIL_MAX_CHARS;
+    while !tail.is_char_boundary(start) {
+        start += 1;
+    }
+    tail[start..].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoding_carries_kind_and_fields_but_not_message() {
+        let error = DispatchError::not_installed("conda", "Conda is not installed");
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json, serde_json::json!({ "kind": "not_installed", "component": "conda" }));
+        assert_eq!(error.to_string(), "Conda is not installed");
+
+        let json = serde_json::to_value(DispatchError::Cancelled).unwrap();
+        assert_eq!(json, serde_json::json!({ "kind": "cancelled" }));
+        assert_eq!(DispatchError::Cancelled.to_string(), CANCELLED_MESSAGE);
+    }
+
+    #[test]
+    fn test_kind_matches_serialized_tag() {
+        let errors = [
+            DispatchError::not_installed("git", "x"),
+            DispatchError::needs_manual_action("x", None, None),
+            DispatchError::network("x"),
+            DispatchError::process_failed("x", 1, ""),
+            DispatchError::service_failed("ollama", "x"),
+            DispatchError::timeout("x"),
+            DispatchError::port_unavailable(vec![8005], "x"),
+            DispatchError::path_rejected(None, "x"),
+            DispatchError::invalid_argument("x"),
+            DispatchError::unsupported_platform(),
+            DispatchError::partial_failure("x", vec![], vec![]),
+            DispatchError::checksum_mismatch("x", "a", "b"),
+            DispatchError::rolled_back("a", "b", DispatchError::timeout("x")),
+            DispatchError::parse_error(None, None, "x"),
+            DispatchError::unsupported_tool("x"),
+            DispatchError::NotPaired,
+            DispatchError::Cancelled,
+            DispatchError::internal("x"),
+        ];
+        for error in errors {
+            let json = serde_json::to_value(&error).unwrap();
+            assert_eq!(json["kind"], error.kind());
+        }
+    }
+
+    #[test]
+    fn test_process_failed_keeps_stderr_tail() {
+        let stderr: String = (1..=30).map(|i| format!("line {}\n", i)).collect();
+        let error = DispatchError::process_failed("pip install", 1, &stderr);
+        let DispatchError::ProcessFailed { exit_code, stderr_tail, .. } = &error else {
+            panic!("expected process_failed, got {:?}", error);
+        };
+        assert_eq!(*exit_code, 1);
+        assert_eq!(stderr_tail.lines().count(), STDERR_TAIL_LINES);
+        assert!(stderr_tail.starts_with("line 11"));
+        assert_eq!(error.to_string(), "pip install failed (exit code 1): line 30");
+    }
+
+    #[test]
+    fn test_network_failures_are_told_apart() {
+        let error = DispatchError::process_failed(
+            "git clone",
+            128,
+            "fatal: unable to access 'https://github.com/x.git/': Could not resolve host: github.com",
+        );
+        assert_eq!(error.kind(), "network");
+
+        let error = DispatchError::process_failed("git clone", 128, "fatal: repository not found");
+        assert_eq!(error.kind(), "process_failed");
+    }
+
+    #[test]
+    fn test_stderr_tail_is_bounded() {
+        let stderr = "é".repeat(STDERR_TAIL_MAX_CHARS);
+        let tail = stderr_tail(&stderr);
+        assert!(tail.len() <= STDERR_TAIL_MAX_CHARS);
+        assert!(tail.chars().all(|c| c == 'é'));
+    }
+}
@@ -0,0 +1,814 @@
+//! Install plans: a list of tool steps run in dependency order
+//!
+//! The usual install sequence otherwise lives only in the backend's prompt and
+//! costs one round trip per step. `run_install_plan` takes the whole sequence at
+//! once: each step names a tool and the steps it depends on, and steps run one
+//! at a time so that every step follows its dependencies (ties keep the order
+//! they were listed in). A step whose outcome is already in place according to
+//! `system_info::detect` is skipped, a step whose dependency failed is not run,
+//! and independent steps still run after a failure.
+//!
+//! Plans resume: a step whose outcome `detect` can't see (installed
+//! dependencies, Git, a named environment) is skipped when the install state
+//! journal records it as succeeded with the same arguments, unless one of its
+//! dependencies ran again in this plan. A plan rerun after the installer was
+//! closed therefore picks up at the first step that didn't finish. Starting
+//! services and forced steps always run.
+
+use crate::dispatcher::{self, CloneOptions};
+use crate::error::{ComponentFailure, DispatchError};
+use crate::git_remote::GitToken;
+use crate::install_state;
+use crate::process_manager::{is_port_in_use, ProcessState};
+use crate::system_info;
+use crate::protocol::{default_backend_port, default_frontend_port, send_message, Outbound, OutgoingMessage};
+use crate::SystemInfo;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+/// `operation` of the plan's own progress messages
+const PLAN_OPERATION: &str = "install_plan";
+
+/// Checkout location when a step doesn't name one, relative to the home directory
+const DEFAULT_REPO_DIR: &str = "BrainDrive";
+
+/// One step of a plan
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlanStep {
+    /// Name used in other steps' `depends_on`; defaults to the tool name
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(flatten)]
+    pub action: StepAction,
+    /// Ids of steps that must succeed (or be skipped as done) before this one runs
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+}
+
+impl PlanStep {
+    fn new(action: StepAction, depends_on: &[&str]) -> Self {
+        Self {
+            id: None,
+            action,
+            depends_on: depends_on.iter().map(|dep| dep.to_string()).collect(),
+        }
+    }
+
+    fn id(&self) -> String {
+        self.id.clone().unwrap_or_else(|| self.action.tool().to_string())
+    }
+}
+
+/// The tool a step runs, with the same arguments as the standalone tool call
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "tool", rename_all = "snake_case")]
+pub enum StepAction {
+    InstallConda {
+        #[serde(default)]
+        sha256: Option<String>,
+    },
+    InstallGit {
+        #[serde(default)]
+        sha256: Option<String>,
+    },
+    CreateCondaEnv {
+        #[serde(default)]
+        env_name: Option<String>,
+        #[serde(default)]
+        force_recreate: bool,
+    },
+    CloneRepo {
+        #[serde(default)]
+        repo_url: Option<String>,
+        #[serde(default)]
+        target_path: Option<String>,
+        #[serde(default, rename = "ref")]
+        git_ref: Option<String>,
+        #[serde(default)]
+        depth: Option<u32>,
+        #[serde(default)]
+        submodules: bool,
+        #[serde(default)]
+        token: Option<GitToken>,
+    },
+    SetupEnvFile {
+        #[serde(default)]
+        repo_path: Option<String>,
+    },
+    InstallAllDeps {
+        #[serde(default)]
+        env_name: Option<String>,
+        #[serde(default)]
+        repo_path: Option<String>,
+    },
+    InstallOllama,
+    StartOllama,
+    StartBraindrive {
+        #[serde(default = "default_frontend_port")]
+        frontend_port: u16,
+        #[serde(default = "default_backend_port")]
+        backend_port: u16,
+    },
+}
+
+impl StepAction {
+    /// Arguments that decide what the step produces, recorded in the journal
+    /// and compared on resume; the token is left out
+    fn args(&self) -> Value {
+        match self {
+            StepAction::CreateCondaEnv {
+                env_name,
+                force_recreate,
+            } => json!({ "env_name": env_name, "force_recreate": force_recreate }),
+            StepAction::CloneRepo {
+                repo_url,
+                target_path,
+                git_ref,
+                depth,
+                submodules,
+                ..
+            } => json!({
+                "repo_url": repo_url,
+                "target_path": target_path,
+                "ref": git_ref,
+                "depth": depth,
+                "submodules": submodules,
+            }),
+            StepAction::SetupEnvFile { repo_path } => json!({ "repo_path": repo_path }),
+            StepAction::InstallAllDeps { env_name, repo_path } => {
+                json!({ "env_name": env_name, "repo_path": repo_path })
+            }
+            StepAction::StartBraindrive {
+                frontend_port,
+                backend_port,
+            } => json!({ "frontend_port": frontend_port, "backend_port": backend_port }),
+            // A digest only checks the download; what gets installed is the same
+            StepAction::InstallConda { .. }
+            | StepAction::InstallGit { .. }
+            | StepAction::InstallOllama
+            | StepAction::StartOllama => json!({}),
+        }
+    }
+
+    /// Whether an earlier run's success may stand in for this step
+    ///
+    /// Only for steps `already_done` can't check. Starting a service is never
+    /// done for good, and a forced step is asked to run again.
+    fn resumable(&self) -> bool {
+        match self {
+            StepAction::InstallGit { .. } | StepAction::InstallAllDeps { .. } => true,
+            StepAction::CreateCondaEnv { force_recreate, .. } => !force_recreate,
+            _ => false,
+        }
+    }
+
+    fn tool(&self) -> &'static str {
+        match self {
+            StepAction::InstallConda { .. } => "install_conda",
+            StepAction::InstallGit { .. } => "install_git",
+            StepAction::CreateCondaEnv { .. } => "create_conda_env",
+            StepAction::CloneRepo { .. } => "clone_repo",
+            StepAction::SetupEnvFile { .. } => "setup_env_file",
+            StepAction::InstallAllDeps { .. } => "install_all_deps",
+            StepAction::InstallOllama => "install_ollama",
+            StepAction::StartOllama => "start_ollama",
+            StepAction::StartBraindrive { .. } => "start_braindrive",
+        }
+    }
+}
+
+/// What happened to one step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum StepStatus {
+    Completed,
+    /// Already done before the plan ran, or by an earlier run of it
+    Skipped,
+    Failed,
+    /// Not run because a dependency failed
+    Blocked,
+}
+
+#[derive(Debug, Serialize)]
+struct StepReport {
+    id: String,
+    tool: &'static str,
+    status: StepStatus,
+    /// Why a skipped step was considered done
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<DispatchError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_ms: Option<u64>,
+}
+
+impl StepReport {
+    fn new(step: &PlanStep, status: StepStatus) -> Self {
+        Self {
+            id: step.id(),
+            tool: step.action.tool(),
+            status,
+            reason: None,
+            result: None,
+            error: None,
+            duration_ms: None,
+        }
+    }
+}
+
+/// The standard install, used when the request has no `steps`
+pub fn default_plan() -> Vec<PlanStep> {
+    vec![
+        PlanStep::new(StepAction::InstallConda { sha256: None }, &[]),
+        PlanStep::new(
+            StepAction::CreateCondaEnv {
+                env_name: None,
+                force_recreate: false,
+            },
+            &["install_conda"],
+        ),
+        // Git comes from the conda environment
+        PlanStep::new(
+            StepAction::CloneRepo {
+                repo_url: None,
+                target_path: None,
+                git_ref: None,
+                depth: None,
+                submodules: false,
+                token: None,
+            },
+            &["create_conda_env"],
+        ),
+        PlanStep::new(StepAction::SetupEnvFile { repo_path: None }, &["clone_repo"]),
+        PlanStep::new(
+            StepAction::InstallAllDeps {
+                env_name: None,
+                repo_path: None,
+            },
+            &["create_conda_env", "clone_repo"],
+        ),
+        PlanStep::new(
+            StepAction::StartBraindrive {
+                frontend_port: default_frontend_port(),
+                backend_port: default_backend_port(),
+            },
+            &["setup_env_file", "install_all_deps"],
+        ),
+    ]
+}
+
+/// Run a plan and report on every step
+///
+/// Succeeds when every step completed or was skipped, with a `steps` report in
+/// execution order. Otherwise fails with `partial_failure`, listing failed steps
+/// and the steps they blocked (as `dependency_failed`) under `failed`. With
+/// `resume` false the journal is ignored and only `detect` decides what to skip.
+pub async fn run_install_plan(
+    steps: Option<Vec<PlanStep>>,
+    resume: bool,
+    request_id: String,
+    sender: Arc<Mutex<Outbound>>,
+    cancel: &CancellationToken,
+    process_state: &ProcessState,
+) -> Result<Value, DispatchError> {
+    let steps = steps.unwrap_or_else(default_plan);
+    let order = execution_order(&steps)?;
+    let total = order.len();
+
+    // One snapshot is enough: a step that changes what detect reports is itself
+    // in the plan, ahead of the steps that depend on it
+    let system = match system_info::detect().await {
+        Ok(system) => Some(system),
+        Err(e) => {
+            tracing::warn!(error = %e, "System detection failed, running every plan step");
+            None
+        }
+    };
+
+    let journal = resume.then(install_state::load);
+
+    let mut reports = Vec::with_capacity(total);
+    let mut unsuccessful: HashSet<String> = HashSet::new();
+    // Steps that ran in this plan; what depends on them runs again too
+    let mut ran: HashSet<String> = HashSet::new();
+
+    for (position, &index) in order.iter().enumerate() {
+        if cancel.is_cancelled() {
+            return Err(DispatchError::Cancelled);
+        }
+        let step = &steps[index];
+        let id = step.id();
+
+        if let Some(dependency) = step.depends_on.iter().find(|dep| unsuccessful.contains(*dep)) {
+            tracing::info!(step = %id, dependency = %dependency, "Not running install plan step");
+            let mut report = StepReport::new(step, StepStatus::Blocked);
+            report.error = Some(DispatchError::dependency_failed(dependency));
+            reports.push(report);
+            unsuccessful.insert(id);
+            continue;
+        }
+
+        if let Some(reason) = system.as_ref().and_then(|system| already_done(&step.action, system)) {
+            tracing::info!(step = %id, reason, "Skipping install plan step");
+            let mut report = StepReport::new(step, StepStatus::Skipped);
+            report.reason = Some(reason.to_string());
+            reports.push(report);
+            continue;
+        }
+
+        if let Some(finished_at) = journal.as_ref().and_then(|journal| finished_earlier(step, journal, &ran)) {
+            tracing::info!(step = %id, finished_at, "Skipping install plan step done in an earlier run");
+            let mut report = StepReport::new(step, StepStatus::Skipped);
+            report.reason = Some(format!("Completed by an earlier run at {}", finished_at));
+            reports.push(report);
+            continue;
+        }
+
+        tracing::info!(step = %id, tool = step.action.tool(), "Running install plan step");
+        ran.insert(id.clone());
+        let _ = send_message(
+            &sender,
+            plan_progress(&request_id, position, total, Some(0), format!("Step {}/{}: {}", position + 1, total, id)),
+        )
+        .await;
+
+        // The step reports into its own channel; its progress is re-sent scaled
+        // to its share of the whole plan
+        let started = Instant::now();
+        let (outbound, step_progress) = Outbound::channel();
+        let run = run_step(&step.action, &request_id, Arc::new(Mutex::new(outbound)), cancel, process_state);
+        let (result, ()) = tokio::join!(
+            install_state::journaled_with_args(step.action.tool(), Some(step.action.args()), &request_id, run),
+            forward_progress(step_progress, &sender, &request_id, &id, position, total),
+        );
+
+        let mut report = match result {
+            Ok(result) => {
+                let mut report = StepReport::new(step, StepStatus::Completed);
+                report.result = Some(result);
+                report
+            }
+            Err(_) if cancel.is_cancelled() => return Err(DispatchError::Cancelled),
+            Err(e) => {
+                tracing::warn!(step = %id, kind = e.kind(), error = %e, "Install plan step failed");
+                unsuccessful.insert(id);
+                let mut report = StepReport::new(step, StepStatus::Failed);
+                report.error = Some(e);
+                report
+            }
+        };
+        report.duration_ms = Some(started.elapsed().as_millis() as u64);
+        reports.push(report);
+    }
+
+    let _ = send_message(
+        &sender,
+        plan_progress(&request_id, total, total, None, "Install plan finished".to_string()),
+    )
+    .await;
+
+    summarize(reports)
+}
+
+fn summarize(reports: Vec<StepReport>) -> Result<Value, DispatchError> {
+    let count = |status| reports.iter().filter(|r| r.status == status).count();
+    let (completed, skipped) = (count(StepStatus::Completed), count(StepStatus::Skipped));
+
+    let failed: Vec<ComponentFailure> = reports
+        .iter()
+        .filter_map(|r| r.error.clone().map(|e| ComponentFailure::new(&r.id, e)))
+        .collect();
+    if failed.is_empty() {
+        return Ok(json!({
+            "success": true,
+            "message": format!("Install plan finished: {} steps completed, {} already done", completed, skipped),
+            "steps": reports,
+        }));
+    }
+
+    let message = format!(
+        "Install plan failed: {} failed, {} not run, {} completed, {} already done",
+        count(StepStatus::Failed),
+        count(StepStatus::Blocked),
+        completed,
+        skipped
+    );
+    let done = reports
+        .iter()
+        .filter(|r| matches!(r.status, StepStatus::Completed | StepStatus::Skipped))
+        .map(|r| r.id.clone())
+        .collect();
+    Err(DispatchError::partial_failure(message, done, failed))
+}
+
+/// Indices of `steps` in the order they run
+///
+/// Each step comes after its dependencies; among steps that are ready, the one
+/// listed first goes first. Duplicate ids, unknown dependencies and cycles are
+/// rejected before anything runs.
+fn execution_order(steps: &[PlanStep]) -> Result<Vec<usize>, DispatchError> {
+    if steps.is_empty() {
+        return Err(DispatchError::invalid_argument("An install plan needs at least one step"));
+    }
+
+    let ids: Vec<String> = steps.iter().map(PlanStep::id).collect();
+    let mut index_of = HashMap::new();
+    for (index, id) in ids.iter().enumerate() {
+        if index_of.insert(id.as_str(), index).is_some() {
+            return Err(DispatchError::invalid_argument(format!(
+                "Step id '{}' is used more than once; give repeated tools distinct ids",
+                id
+            )));
+        }
+    }
+
+    let mut dependencies = Vec::with_capacity(steps.len());
+    for (step, id) in steps.iter().zip(&ids) {
+        let mut indices = Vec::with_capacity(step.depends_on.len());
+        for dependency in &step.depends_on {
+            let Some(&index) = index_of.get(dependency.as_str()) else {
+                return Err(DispatchError::invalid_argument(format!(
+                    "Step '{}' depends on unknown step '{}'",
+                    id, dependency
+                )));
+            };
+            indices.push(index);
+        }
+        dependencies.push(indices);
+    }
+
+    let mut scheduled = vec![false; steps.len()];
+    let mut order = Vec::with_capacity(steps.len());
+    while order.len() < steps.len() {
+        let ready = (0..steps.len())
+            .find(|&i| !scheduled[i] && dependencies[i].iter().all(|&dep| scheduled[dep]));
+        let Some(next) = ready else {
+            let stuck: Vec<&str> = (0..steps.len())
+                .filter(|&i| !scheduled[i])
+                .map(|i| ids[i].as_str())
+                .collect();
+            return Err(DispatchError::invalid_argument(format!(
+                "Steps {} depend on each other in a cycle",
+                stuck.join(", ")
+            )));
+        };
+        scheduled[next] = true;
+        order.push(next);
+    }
+    Ok(order)
+}
+
+/// Why a step needs no work, if it doesn't
+fn already_done(action: &StepAction, system: &SystemInfo) -> Option<&'static str> {
+    match action {
+        StepAction::InstallConda { .. } if system.conda_installed => Some("Miniconda is already installed"),
+        // detect only looks at the default environment
+        StepAction::CreateCondaEnv {
+            env_name: None,
+            force_recreate: false,
+        } if system.braindrive_env_ready => Some("The BrainDrive conda environment already exists"),
+        // With a ref, clone_repo checks the existing checkout is on it
+        StepAction::CloneRepo {
+            target_path,
+            git_ref: None,
+            ..
+        } if checkout_dir(target_path.as_deref()).is_some_and(|dir| dir.join(".git").exists()) =>
+        {
+            Some("The repository is already cloned")
+        }
+        StepAction::SetupEnvFile { repo_path }
+            if checkout_dir(repo_path.as_deref()).is_some_and(|dir| dir.join("backend").join(".env").exists()) =>
+        {
+            Some("The .env file already exists")
+        }
+        StepAction::InstallOllama if system.ollama_installed => Some("Ollama is already installed"),
+        StepAction::StartOllama if system.ollama_running => Some("Ollama is already running"),
+        StepAction::StartBraindrive {
+            frontend_port,
+            backend_port,
+        } if is_port_in_use(*frontend_port) && is_port_in_use(*backend_port) => {
+            Some("BrainDrive is already running")
+        }
+        _ => None,
+    }
+}
+
+/// When an earlier run finished `step`, if that result still stands
+///
+/// It doesn't once a dependency ran again: a recreated environment needs its
+/// dependencies installed again, for example.
+fn finished_earlier<'a>(
+    step: &PlanStep,
+    journal: &'a install_state::InstallState,
+    ran: &HashSet<String>,
+) -> Option<&'a str> {
+    if !step.action.resumable() || step.depends_on.iter().any(|dep| ran.contains(dep)) {
+        return None;
+    }
+    journal.succeeded_with(step.action.tool(), &step.action.args())
+}
+
+/// Where a step's checkout lives, for the skip checks; the tools validate the path themselves
+fn checkout_dir(path: Option<&str>) -> Option<PathBuf> {
+    let home = dirs::home_dir()?;
+    Some(match path {
+        None => home.join(DEFAULT_REPO_DIR),
+        Some(path) => match path.strip_prefix("~/") {
+            Some(relative) => home.join(relative),
+            None => PathBuf::from(path),
+        },
+    })
+}
+
+async fn run_step(
+    action: &StepAction,
+    request_id: &str,
+    sender: Arc<Mutex<Outbound>>,
+    cancel: &CancellationToken,
+    process_state: &ProcessState,
+) -> Result<Value, DispatchError> {
+    match action.clone() {
+        StepAction::InstallConda { sha256 } => {
+            dispatcher::install_conda(sha256, request_id.to_string(), sender, cancel).await
+        }
+        StepAction::InstallGit { sha256 } => dispatcher::install_git(sha256, request_id.to_string(), sender, cancel).await,
+        StepAction::CreateCondaEnv {
+            env_name,
+            force_recreate,
+        } => dispatcher::create_conda_env(env_name, Some(force_recreate), cancel).await,
+        StepAction::CloneRepo {
+            repo_url,
+            target_path,
+            git_ref,
+            depth,
+            submodules,
+            token,
+        } => {
+            let options = CloneOptions {
+                git_ref,
+                depth,
+                submodules,
+                token,
+            };
+            dispatcher::clone_repo(repo_url, target_path, options, cancel).await
+        }
+        StepAction::SetupEnvFile { repo_path } => dispatcher::setup_env_file(repo_path).await,
+        StepAction::InstallAllDeps { env_name, repo_path } => {
+            dispatcher::install_all_deps(env_name, repo_path, cancel).await
+        }
+        StepAction::InstallOllama => dispatcher::install_ollama().await,
+        StepAction::StartOllama => dispatcher::start_ollama().await,
+        StepAction::StartBraindrive {
+            frontend_port,
+            backend_port,
+        } => dispatcher::start_braindrive(frontend_port, backend_port, process_state).await,
+    }
+}
+
+/// Re-send a step's progress as progress of the whole plan
+async fn forward_progress(
+    mut step_progress: mpsc::UnboundedReceiver<OutgoingMessage>,
+    sender: &Arc<Mutex<Outbound>>,
+    request_id: &str,
+    step_id: &str,
+    position: usize,
+    total: usize,
+) {
+    while let Some(message) = step_progress.recv().await {
+        let OutgoingMessage::Progress {
+            percent,
+            message,
+            bytes_downloaded,
+            bytes_total,
+            ..
+        } = message
+        else {
+            continue;
+        };
+        let mut progress = plan_progress(request_id, position, total, percent, format!("{}: {}", step_id, message));
+        if let OutgoingMessage::Progress {
+            bytes_downloaded: downloaded,
+            bytes_total: expected,
+            ..
+        } = &mut progress
+        {
+            *downloaded = bytes_downloaded;
+            *expected = bytes_total;
+        }
+        let _ = send_message(sender, progress).await;
+    }
+}
+
+fn plan_progress(
+    request_id: &str,
+    position: usize,
+    total: usize,
+    step_percent: Option<u8>,
+    message: String,
+) -> OutgoingMessage {
+    OutgoingMessage::Progress {
+        id: request_id.to_string(),
+        operation: PLAN_OPERATION.to_string(),
+        percent: Some(overall_percent(position, total, step_percent)),
+        message,
+        bytes_downloaded: None,
+        bytes_total: None,
+    }
+}
+
+/// Share of the plan done when step `position` (0-based) is `step_percent` through
+fn overall_percent(position: usize, total: usize, step_percent: Option<u8>) -> u8 {
+    let step_percent = step_percent.unwrap_or(0).min(100) as usize;
+    ((position * 100 + step_percent) / total.max(1)).min(100) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps(value: Value) -> Vec<PlanStep> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn ordered_ids(steps: &[PlanStep]) -> Vec<String> {
+        execution_order(steps)
+            .unwrap()
+            .into_iter()
+            .map(|i| steps[i].id())
+            .collect()
+    }
+
+    #[test]
+    fn test_default_plan_runs_in_listed_order() {
+        let plan = default_plan();
+        assert_eq!(
+            ordered_ids(&plan),
+            [
+                "install_conda",
+                "create_conda_env",
+                "clone_repo",
+                "setup_env_file",
+                "install_all_deps",
+                "start_braindrive"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dependencies_run_first() {
+        let plan = steps(json!([
+            { "tool": "start_braindrive", "depends_on": ["deps"] },
+            { "id": "deps", "tool": "install_all_deps", "repo_path": "~/BrainDrive", "depends_on": ["clone_repo"] },
+            { "tool": "clone_repo" },
+            { "tool": "start_ollama" },
+        ]));
+        assert_eq!(ordered_ids(&plan), ["clone_repo", "deps", "start_braindrive", "start_ollama"]);
+        assert!(matches!(
+            &plan[1].action,
+            StepAction::InstallAllDeps { repo_path: Some(path), .. } if path == "~/BrainDrive"
+        ));
+    }
+
+    #[test]
+    fn test_invalid_plans_are_rejected() {
+        let cycle = steps(json!([
+            { "tool": "clone_repo", "depends_on": ["setup_env_file"] },
+            { "tool": "setup_env_file", "depends_on": ["clone_repo"] },
+        ]));
+        let unknown = steps(json!([{ "tool": "clone_repo", "depends_on": ["nope"] }]));
+        let duplicate = steps(json!([{ "tool": "install_conda" }, { "tool": "install_conda" }]));
+
+        for plan in [cycle, unknown, duplicate, Vec::new()] {
+            let error = execution_order(&plan).unwrap_err();
+            assert_eq!(error.kind(), "invalid_argument", "{}", error);
+        }
+        assert!(serde_json::from_value::<Vec<PlanStep>>(json!([{ "tool": "format_disk" }])).is_err());
+    }
+
+    #[test]
+    fn test_failure_blocks_dependents_only() {
+        let plan = steps(json!([
+            { "tool": "clone_repo" },
+            { "tool": "setup_env_file", "depends_on": ["clone_repo"] },
+            { "tool": "install_ollama" },
+        ]));
+        let mut clone = StepReport::new(&plan[0], StepStatus::Failed);
+        clone.error = Some(DispatchError::network("Could not resolve host github.com"));
+        let mut env_file = StepReport::new(&plan[1], StepStatus::Blocked);
+        env_file.error = Some(DispatchError::dependency_failed("clone_repo"));
+        let ollama = StepReport::new(&plan[2], StepStatus::Completed);
+
+        let error = summarize(vec![clone, env_file, ollama]).unwrap_err();
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["kind"], "partial_failure");
+        assert_eq!(json["completed"], json!(["install_ollama"]));
+        assert_eq!(json["failed"][0]["details"]["kind"], "network");
+        assert_eq!(json["failed"][1]["component"], "setup_env_file");
+        assert_eq!(json["failed"][1]["details"]["kind"], "dependency_failed");
+        assert_eq!(json["failed"][1]["details"]["dependency"], "clone_repo");
+    }
+
+    fn journal_of(steps: Value) -> install_state::InstallState {
+        let steps = steps
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(tool, args)| {
+                let record = json!({
+                    "status": "succeeded",
+                    "request_id": "tool_1",
+                    "started_at": "2026-01-01T00:00:00Z",
+                    "finished_at": "2026-01-01T00:05:00Z",
+                    "bootstrapper_version": "0.0.0",
+                    "args": args,
+                });
+                (tool.clone(), record)
+            })
+            .collect::<serde_json::Map<_, _>>();
+        serde_json::from_value(json!({ "schema_version": 1, "steps": steps })).unwrap()
+    }
+
+    fn args_of(plan: &[PlanStep]) -> Value {
+        plan.iter().map(|step| (step.action.tool().to_string(), step.action.args())).collect()
+    }
+
+    #[test]
+    fn test_resume_skips_steps_an_earlier_run_finished() {
+        let plan = steps(json!([
+            { "tool": "install_git" },
+            { "tool": "create_conda_env", "env_name": "BrainDriveDev" },
+            { "tool": "install_all_deps", "env_name": "BrainDriveDev", "depends_on": ["create_conda_env"] },
+        ]));
+        let journal = journal_of(args_of(&plan));
+
+        let mut ran = HashSet::new();
+        assert_eq!(finished_earlier(&plan[0], &journal, &ran), Some("2026-01-01T00:05:00Z"));
+        assert!(finished_earlier(&plan[1], &journal, &ran).is_some());
+        assert!(finished_earlier(&plan[2], &journal, &ran).is_some());
+
+        // The environment was created again, so its dependencies are installed again
+        ran.insert("create_conda_env".to_string());
+        assert!(finished_earlier(&plan[2], &journal, &ran).is_none());
+
+        // A standalone install_git call records no arguments, and takes none
+        let standalone = journal_of(json!({ "install_git": null }));
+        assert!(finished_earlier(&plan[0], &standalone, &HashSet::new()).is_some());
+    }
+
+    #[test]
+    fn test_resume_always_starts_services() {
+        let plan = default_plan();
+        let mut with_ollama = plan.clone();
+        with_ollama.push(PlanStep::new(StepAction::StartOllama, &[]));
+        let journal = journal_of(args_of(&with_ollama));
+        let ran = HashSet::new();
+
+        let start_braindrive = &with_ollama[5];
+        assert!(matches!(start_braindrive.action, StepAction::StartBraindrive { .. }));
+        assert!(finished_earlier(start_braindrive, &journal, &ran).is_none());
+        assert!(finished_earlier(&with_ollama[6], &journal, &ran).is_none());
+    }
+
+    #[test]
+    fn test_resume_reruns_forced_steps() {
+        let plan = steps(json!([{ "tool": "create_conda_env", "force_recreate": true }]));
+        let journal = journal_of(args_of(&plan));
+        assert!(finished_earlier(&plan[0], &journal, &HashSet::new()).is_none());
+    }
+
+    #[test]
+    fn test_resume_compares_step_arguments() {
+        let earlier = steps(json!([
+            { "tool": "clone_repo", "target_path": "~/BrainDrive", "ref": "v1.0.0" },
+            { "tool": "install_all_deps", "repo_path": "~/BrainDrive" },
+        ]));
+        let journal = journal_of(args_of(&earlier));
+        let ran = HashSet::new();
+
+        // A checkout is only ever judged by what is on disk
+        assert!(finished_earlier(&earlier[0], &journal, &ran).is_none());
+        let other_ref = steps(json!([{ "tool": "clone_repo", "target_path": "~/BrainDrive", "ref": "v2.0.0" }]));
+        assert!(finished_earlier(&other_ref[0], &journal, &ran).is_none());
+
+        assert!(finished_earlier(&earlier[1], &journal, &ran).is_some());
+        let other_repo = steps(json!([{ "tool": "install_all_deps", "repo_path": "~/BrainDrive-2" }]));
+        assert!(finished_earlier(&other_repo[0], &journal, &ran).is_none());
+    }
+
+    #[test]
+    fn test_overall_percent() {
+        assert_eq!(overall_percent(0, 4, Some(0)), 0);
+        assert_eq!(overall_percent(1, 4, Some(50)), 37);
+        assert_eq!(overall_percent(3, 4, Some(100)), 100);
+        assert_eq!(overall_percent(4, 4, None), 100);
+    }
+}
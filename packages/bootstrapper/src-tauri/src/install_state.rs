@@ -0,0 +1,350 @@
+//! Install state journal at `~/.braindrive-installer/state.json`
+//!
+//! Records when each install step last started and finished, how it ended and
+//! which versions it reported, so a session that starts after the installer
+//! was closed mid-install knows what finished instead of guessing from the
+//! files left behind. `install_plan` uses it to resume after the last step
+//! that succeeded, recording each step's arguments so a rerun with other ones
+//! isn't taken as done. Steps are keyed by tool name and only the latest run of
+//! each is kept. It also lists the Ollama models the bootstrapper pulled, which
+//! `uninstall_braindrive` removes.
+//!
+//! The journal is advisory: failing to read or write it is logged and never
+//! fails the step itself.
+
+use crate::error::DispatchError;
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+
+/// Bumped when the file layout changes incompatibly; other versions are ignored
+const SCHEMA_VERSION: u32 = 1;
+
+/// Serializes read-modify-write of the file between concurrent steps
+static JOURNAL_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepState {
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+    /// Was running when the installer exited
+    Interrupted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepRecord {
+    pub status: StepState,
+    pub request_id: String,
+    pub started_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+    /// Bootstrapper version that ran the step
+    pub bootstrapper_version: String,
+    /// Arguments the step ran with, when recorded by `journaled_with_args`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub args: Option<Value>,
+    /// Versions the step reported, e.g. `version` for Ollama
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub versions: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallState {
+    pub schema_version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+    #[serde(default)]
+    pub steps: BTreeMap<String, StepRecord>,
+    /// Models pulled through `pull_ollama_model`, as passed to `ollama pull`
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub pulled_models: BTreeSet<String>,
+}
+
+impl Default for InstallState {
+    fn default() -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            updated_at: None,
+            steps: BTreeMap::new(),
+            pulled_models: BTreeSet::new(),
+        }
+    }
+}
+
+impl InstallState {
+    /// Whether the last run of `step` succeeded, with when it finished
+    pub fn succeeded_at(&self, step: &str) -> Option<&str> {
+        self.steps
+            .get(step)
+            .filter(|record| record.status == StepState::Succeeded)
+            .and_then(|record| record.finished_at.as_deref())
+    }
+
+    /// Like `succeeded_at`, but only for a run with the same `args`
+    ///
+    /// A record without arguments comes from a standalone tool call and only
+    /// stands for a step that takes none.
+    pub fn succeeded_with(&self, step: &str, args: &Value) -> Option<&str> {
+        let record = self.steps.get(step)?;
+        let same_args = match &record.args {
+            Some(recorded) => recorded == args,
+            None => args.as_object().is_some_and(|args| args.is_empty()),
+        };
+        if !same_args {
+            return None;
+        }
+        self.succeeded_at(step)
+    }
+}
+
+/// Location of the journal
+pub fn state_path() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(".braindrive-installer")
+        .join("state.json")
+}
+
+/// Read the journal; a missing or unreadable file is an empty journal
+pub fn load() -> InstallState {
+    let _guard = JOURNAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    read_state(&state_path())
+}
+
+/// Run a step, recording its start and outcome in the journal
+pub async fn journaled<F>(step: &str, request_id: &str, run: F) -> Result<Value, DispatchError>
+where
+    F: Future<Output = Result<Value, DispatchError>>,
+{
+    journaled_with_args(step, None, request_id, run).await
+}
+
+/// `journaled`, also recording the arguments the step runs with
+pub async fn journaled_with_args<F>(
+    step: &str,
+    args: Option<Value>,
+    request_id: &str,
+    run: F,
+) -> Result<Value, DispatchError>
+where
+    F: Future<Output = Result<Value, DispatchError>>,
+{
+    update(|state| {
+        state.steps.insert(
+            step.to_string(),
+            StepRecord {
+                status: StepState::Running,
+                request_id: request_id.to_string(),
+                started_at: now(),
+                finished_at: None,
+                bootstrapper_version: env!("CARGO_PKG_VERSION").to_string(),
+                args,
+                versions: BTreeMap::new(),
+                error_kind: None,
+                error: None,
+            },
+        );
+    });
+
+    let result = run.await;
+
+    update(|state| {
+        if let Some(record) = state.steps.get_mut(step) {
+            finish(record, &result);
+        }
+    });
+    result
+}
+
+/// Mark steps left running by a previous session as interrupted
+///
+/// Called once at startup, before any step can run.
+#[cfg(feature = "gui")]
+pub fn mark_interrupted() {
+    update(|state| {
+        for (step, record) in state.steps.iter_mut() {
+            if record.status == StepState::Running {
+                tracing::info!(step = %step, started_at = %record.started_at, "Step was interrupted");
+                record.status = StepState::Interrupted;
+            }
+        }
+    });
+}
+
+/// Remember a model the bootstrapper pulled
+#[cfg(feature = "gui")]
+pub fn record_pulled_model(model: &str) {
+    update(|state| {
+        state.pulled_models.insert(model.to_string());
+    });
+}
+
+/// Start over after an uninstall, remembering only `pulled_models`
+pub fn reset(pulled_models: BTreeSet<String>) {
+    let _guard = JOURNAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let path = state_path();
+    let result = if pulled_models.is_empty() {
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+            _ => Ok(()),
+        }
+    } else {
+        let state = InstallState {
+            updated_at: Some(now()),
+            pulled_models,
+            ..InstallState::default()
+        };
+        write_state(&path, &state)
+    };
+    if let Err(e) = result {
+        tracing::warn!(path = %path.display(), error = %e, "Failed to reset install state");
+    }
+}
+
+fn finish(record: &mut StepRecord, result: &Result<Value, DispatchError>) {
+    record.finished_at = Some(now());
+    match result {
+        Ok(value) => {
+            record.status = StepState::Succeeded;
+            record.versions = reported_versions(value);
+        }
+        Err(DispatchError::Cancelled) => record.status = StepState::Cancelled,
+        Err(e) => {
+            record.status = StepState::Failed;
+            record.error_kind = Some(e.kind().to_string());
+            record.error = Some(e.to_string());
+        }
+    }
+}
+
+/// Top-level string fields named `version` or `*_version` in a step's result
+fn reported_versions(result: &Value) -> BTreeMap<String, String> {
+    let Some(fields) = result.as_object() else {
+        return BTreeMap::new();
+    };
+    fields
+        .iter()
+        .filter(|(key, _)| *key == "version" || key.ends_with("_version"))
+        .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+        .collect()
+}
+
+fn update(change: impl FnOnce(&mut InstallState)) {
+    let _guard = JOURNAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let path = state_path();
+    let mut state = read_state(&path);
+    change(&mut state);
+    state.updated_at = Some(now());
+    if let Err(e) = write_state(&path, &state) {
+        tracing::warn!(path = %path.display(), error = %e, "Failed to write install state");
+    }
+}
+
+fn read_state(path: &Path) -> InstallState {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return InstallState::default(),
+        Err(e) => {
+            tracing::warn!(path = %path.display(), error = %e, "Failed to read install state");
+            return InstallState::default();
+        }
+    };
+    match serde_json::from_str::<InstallState>(&contents) {
+        Ok(state) if state.schema_version == SCHEMA_VERSION => state,
+        Ok(state) => {
+            tracing::warn!(schema_version = state.schema_version, "Ignoring install state from another version");
+            InstallState::default()
+        }
+        Err(e) => {
+            tracing::warn!(path = %path.display(), error = %e, "Ignoring unreadable install state");
+            InstallState::default()
+        }
+    }
+}
+
+/// Write through a temporary file so a crash mid-write leaves the old journal
+fn write_state(path: &Path, state: &InstallState) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let json = serde_json::to_string_pretty(state).map_err(|e| e.to_string())?;
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, json).map_err(|e| format!("Failed to write {}: {}", temp.display(), e))?;
+    std::fs::rename(&temp, path).map_err(|e| format!("Failed to replace {}: {}", path.display(), e))
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn running() -> StepRecord {
+        StepRecord {
+            status: StepState::Running,
+            request_id: "tool_1".to_string(),
+            started_at: now(),
+            finished_at: None,
+            bootstrapper_version: "0.0.0".to_string(),
+            args: None,
+            versions: BTreeMap::new(),
+            error_kind: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_outcomes_are_recorded() {
+        let mut ollama = running();
+        finish(&mut ollama, &Ok(json!({ "success": true, "version": "0.5.7", "path": "/usr/bin/ollama" })));
+        assert_eq!(ollama.status, StepState::Succeeded);
+        assert_eq!(ollama.versions.get("version").map(String::as_str), Some("0.5.7"));
+        assert_eq!(ollama.versions.len(), 1);
+
+        let mut clone = running();
+        finish(&mut clone, &Err(DispatchError::network("Could not resolve host github.com")));
+        assert_eq!(clone.status, StepState::Failed);
+        assert_eq!(clone.error_kind.as_deref(), Some("network"));
+
+        let mut conda = running();
+        finish(&mut conda, &Err(DispatchError::Cancelled));
+        assert_eq!(conda.status, StepState::Cancelled);
+        assert!(conda.finished_at.is_some());
+    }
+
+    #[test]
+    fn test_state_round_trips_and_survives_bad_files() {
+        let dir = std::env::temp_dir().join(format!("braindrive-state-test-{}", std::process::id()));
+        let path = dir.join("state.json");
+
+        let mut state = InstallState::default();
+        let mut record = running();
+        finish(&mut record, &Ok(json!({ "success": true })));
+        state.steps.insert("install_conda".to_string(), record);
+        write_state(&path, &state).unwrap();
+
+        let loaded = read_state(&path);
+        assert!(loaded.succeeded_at("install_conda").is_some());
+        assert!(loaded.succeeded_at("clone_repo").is_none());
+
+        std::fs::write(&path, "{ not json").unwrap();
+        assert!(read_state(&path).steps.is_empty());
+        std::fs::write(&path, r#"{ "schema_version": 99, "steps": {} }"#).unwrap();
+        assert_eq!(read_state(&path).schema_version, SCHEMA_VERSION);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,36 +1,85 @@
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "gui")]
 use std::sync::Arc;
+#[cfg(feature = "gui")]
 use tauri::State;
+#[cfg(feature = "gui")]
 use tokio::sync::Mutex;
-use futures_util::stream::SplitSink;
+use futures_util::stream::{SplitSink, SplitStream};
 use tokio_tungstenite::{tungstenite::Message, WebSocketStream, MaybeTlsStream};
 use tokio::net::TcpStream;
 
+// The connection to the backend and the window it reports to; the headless CLI
+// builds without them (`--no-default-features`)
+#[cfg(feature = "gui")]
 mod websocket;
+mod protocol;
 mod system_info;
 mod dispatcher;
+mod error;
+#[cfg(feature = "gui")]
+mod scheduler;
+#[cfg(feature = "gui")]
+mod pairing;
+#[cfg(feature = "gui")]
+mod replay;
+#[cfg(feature = "gui")]
+mod local_server;
+mod install_plan;
+mod install_state;
+mod uninstall;
+mod verify;
+mod update;
+mod git_remote;
+mod checksum;
+mod download;
+mod download_cache;
+mod bandwidth;
+mod mirrors;
+mod network;
+mod offline_bundle;
 pub mod process_manager;
 pub mod logging;
+pub mod cli;
 
 // Type alias for the WebSocket sender
 pub type WsSender = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+// Type alias for the WebSocket receiver
+pub type WsReader = SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>;
 
 // Application state
+#[cfg(feature = "gui")]
 pub struct AppState {
     ws_connected: Arc<Mutex<bool>>,
     backend_url: Arc<Mutex<String>>,
-    ws_sender: Arc<Mutex<Option<WsSender>>>,
+    ws_sender: Arc<Mutex<protocol::Outbound>>,
+    ws_supervisor: Arc<Mutex<Option<websocket::SupervisorHandle>>>,
+    /// Port to serve a local client on instead of dialing `backend_url`
+    local_port: Option<u16>,
+    heartbeat: websocket::Heartbeat,
+    pairing: pairing::SharedPairing,
+    replay: replay::SharedReplayCache,
     process_state: process_manager::ProcessState,
 }
 
+#[cfg(feature = "gui")]
 impl Default for AppState {
     fn default() -> Self {
-        let default_url = std::env::var("BRAINDRIVE_BACKEND_URL")
-            .unwrap_or_else(|_| "wss://backend-production-f57a.up.railway.app".to_string());
+        let local_port = local_server::local_port_from_env();
+        let default_url = match local_port {
+            Some(port) => local_server::local_url(port),
+            None => std::env::var("BRAINDRIVE_BACKEND_URL")
+                .unwrap_or_else(|_| "wss://backend-production-f57a.up.railway.app".to_string()),
+        };
         Self {
             ws_connected: Arc::new(Mutex::new(false)),
             backend_url: Arc::new(Mutex::new(default_url)),
-            ws_sender: Arc::new(Mutex::new(None)),
+            ws_sender: Arc::new(Mutex::new(protocol::Outbound::default())),
+            ws_supervisor: Arc::new(Mutex::new(None)),
+            local_port,
+            heartbeat: websocket::Heartbeat::new(websocket::HeartbeatConfig::from_env()),
+            pairing: Arc::new(Mutex::new(pairing::Pairing::new())),
+            replay: Arc::new(std::sync::Mutex::new(replay::ReplayCache::new())),
             process_state: process_manager::new_process_state(),
         }
     }
@@ -40,6 +89,9 @@ impl Default for AppState {
 pub struct ConnectionStatus {
     connected: bool,
     url: String,
+    /// Round-trip time of the last heartbeat ping, in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_ms: Option<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -79,47 +131,75 @@ pub struct SystemInfo {
 }
 
 // Tauri commands
+#[cfg(feature = "gui")]
 #[tauri::command]
 async fn get_connection_status(state: State<'_, AppState>) -> Result<ConnectionStatus, String> {
     let connected = *state.ws_connected.lock().await;
     let url = state.backend_url.lock().await.clone();
-    Ok(ConnectionStatus { connected, url })
+    let latency_ms = state.heartbeat.last_rtt_ms().await;
+    Ok(ConnectionStatus { connected, url, latency_ms })
 }
 
+#[cfg(feature = "gui")]
 #[tauri::command]
 async fn connect_to_backend(
     app: tauri::AppHandle,
     state: State<'_, AppState>,
     url: Option<String>,
 ) -> Result<(), String> {
-    let backend_url = match url {
-        Some(u) => {
+    let ctx = websocket::ConnectionContext {
+        ws_connected: state.ws_connected.clone(),
+        ws_sender: state.ws_sender.clone(),
+        heartbeat: state.heartbeat.clone(),
+        pairing: state.pairing.clone(),
+        replay: state.replay.clone(),
+        process_state: state.process_state.clone(),
+    };
+
+    let backend_url = match (url, state.local_port) {
+        (Some(u), _) => {
             *state.backend_url.lock().await = u.clone();
             u
         }
-        None => state.backend_url.lock().await.clone(),
+        // Local mode: the web UI connects to us
+        (None, Some(port)) => {
+            *state.backend_url.lock().await = local_server::local_url(port);
+            return local_server::serve(app, ctx, state.ws_supervisor.clone(), port).await;
+        }
+        (None, None) => state.backend_url.lock().await.clone(),
     };
+    websocket::connect(app, ctx, state.ws_supervisor.clone(), &backend_url).await
+}
 
-    websocket::connect(
-        app,
-        state.ws_connected.clone(),
-        state.ws_sender.clone(),
-        state.process_state.clone(),
-        &backend_url,
-    ).await
+/// Current pairing code and whether a backend session is paired
+#[cfg(feature = "gui")]
+#[tauri::command]
+async fn get_pairing_status(state: State<'_, AppState>) -> Result<pairing::PairingStatus, String> {
+    Ok(state.pairing.lock().await.status())
 }
 
+#[cfg(feature = "gui")]
 #[tauri::command]
-async fn disconnect_from_backend(state: State<'_, AppState>) -> Result<(), String> {
-    *state.ws_connected.lock().await = false;
+async fn disconnect_from_backend(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    websocket::disconnect(
+        &app,
+        &state.ws_connected,
+        &state.ws_sender,
+        &state.ws_supervisor,
+    ).await;
     Ok(())
 }
 
+#[cfg(feature = "gui")]
 #[tauri::command]
 async fn get_system_info() -> Result<SystemInfo, String> {
     system_info::detect().await
 }
 
+#[cfg(feature = "gui")]
 #[tauri::command]
 async fn start_braindrive(
     state: State<'_, AppState>,
@@ -128,14 +208,20 @@ async fn start_braindrive(
 ) -> Result<serde_json::Value, String> {
     let fp = frontend_port.unwrap_or(5173);
     let bp = backend_port.unwrap_or(8005);
-    dispatcher::start_braindrive(fp, bp, &state.process_state).await
+    dispatcher::start_braindrive(fp, bp, &state.process_state)
+        .await
+        .map_err(|e| e.to_string())
 }
 
+#[cfg(feature = "gui")]
 #[tauri::command]
 async fn stop_braindrive(state: State<'_, AppState>) -> Result<serde_json::Value, String> {
-    dispatcher::stop_braindrive(&state.process_state).await
+    dispatcher::stop_braindrive(&state.process_state)
+        .await
+        .map_err(|e| e.to_string())
 }
 
+#[cfg(feature = "gui")]
 #[tauri::command]
 async fn restart_braindrive(
     state: State<'_, AppState>,
@@ -144,14 +230,27 @@ async fn restart_braindrive(
 ) -> Result<serde_json::Value, String> {
     let fp = frontend_port.unwrap_or(5173);
     let bp = backend_port.unwrap_or(8005);
-    dispatcher::restart_braindrive(fp, bp, &state.process_state).await
+    dispatcher::restart_braindrive(fp, bp, &state.process_state)
+        .await
+        .map_err(|e| e.to_string())
 }
 
+#[cfg(feature = "gui")]
 #[tauri::command]
 async fn get_braindrive_status(state: State<'_, AppState>) -> Result<serde_json::Value, String> {
-    dispatcher::get_braindrive_status(&state.process_state).await
+    dispatcher::get_braindrive_status(&state.process_state)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// How each install step last ended, from `~/.braindrive-installer/state.json`
+#[cfg(feature = "gui")]
+#[tauri::command]
+async fn get_install_state() -> Result<install_state::InstallState, String> {
+    Ok(install_state::load())
 }
 
+#[cfg(feature = "gui")]
 #[tauri::command]
 async fn export_logs() -> Result<String, String> {
     tracing::info!("Exporting logs for sharing");
@@ -159,17 +258,20 @@ async fn export_logs() -> Result<String, String> {
     Ok(path.to_string_lossy().to_string())
 }
 
+#[cfg(feature = "gui")]
 #[tauri::command]
 async fn get_recent_logs(count: Option<usize>) -> Result<Vec<String>, String> {
     let num_lines = count.unwrap_or(50);
     logging::get_recent_events(num_lines)
 }
 
+#[cfg(feature = "gui")]
 #[tauri::command]
 async fn get_log_directory() -> Result<String, String> {
     Ok(logging::get_log_dir().to_string_lossy().to_string())
 }
 
+#[cfg(feature = "gui")]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // Initialize logging system
@@ -184,6 +286,9 @@ pub fn run() {
 
     tracing::info!("BrainDrive Installer starting");
 
+    // Steps still marked running were cut off when the installer last exited
+    install_state::mark_interrupted();
+
     // Create app state and keep a clone of process_state for the exit handler
     let app_state = AppState::default();
     let exit_process_state = app_state.process_state.clone();
@@ -195,11 +300,13 @@ pub fn run() {
             get_connection_status,
             connect_to_backend,
             disconnect_from_backend,
+            get_pairing_status,
             get_system_info,
             start_braindrive,
             stop_braindrive,
             restart_braindrive,
             get_braindrive_status,
+            get_install_state,
             export_logs,
             get_recent_logs,
             get_log_directory,
@@ -0,0 +1,261 @@
+//! Local mode: serve the web UI from a loopback port instead of dialing the relay
+//!
+//! Normally the bootstrapper connects out to the cloud backend. In local mode it
+//! listens on `127.0.0.1` and a locally served web UI or a test harness connects
+//! to it instead, speaking the same protocol: it receives `bootstrapper_connect`,
+//! pairs with the code shown in the window and sends tool calls. One client is
+//! served at a time.
+//!
+//! Any web page can open a WebSocket to localhost, so the handshake is refused
+//! unless the `Origin` is a local page (or one listed in
+//! `BRAINDRIVE_LOCAL_ORIGINS`) and the `Host` is a loopback name, which keeps a
+//! DNS-rebound page out too. Pairing still applies on top of that.
+
+use crate::scheduler::ToolScheduler;
+use crate::websocket::{self, ConnectionContext, SupervisorHandle};
+use futures_util::StreamExt;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use tauri::AppHandle;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio::time::{timeout, Duration};
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::{accept_hdr_async, MaybeTlsStream};
+
+/// Port used when `BRAINDRIVE_LOCAL_PORT` is set but empty or not a number
+pub const DEFAULT_LOCAL_PORT: u16 = 3100;
+
+/// How long a client gets to finish the WebSocket handshake before the next
+/// one is accepted; a silent connection would otherwise block the port
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Host names that resolve to this machine without asking DNS
+const LOOPBACK_HOSTS: &[&str] = &["localhost", "127.0.0.1", "[::1]"];
+
+/// Port to serve on when local mode is enabled through `BRAINDRIVE_LOCAL_PORT`
+pub fn local_port_from_env() -> Option<u16> {
+    let value = std::env::var("BRAINDRIVE_LOCAL_PORT").ok()?;
+    Some(value.trim().parse().unwrap_or(DEFAULT_LOCAL_PORT))
+}
+
+/// Address clients connect to in local mode
+pub fn local_url(port: u16) -> String {
+    format!("ws://127.0.0.1:{}", port)
+}
+
+/// Listen for a local client on `port` in place of the relay connection
+///
+/// Stops any relay connection first; the listener runs in the supervisor slot,
+/// so `websocket::disconnect` stops it the same way.
+pub async fn serve(
+    app: AppHandle,
+    ctx: ConnectionContext,
+    ws_supervisor: Arc<Mutex<Option<SupervisorHandle>>>,
+    port: u16,
+) -> Result<(), String> {
+    websocket::disconnect(&app, &ctx.ws_connected, &ctx.ws_sender, &ws_supervisor).await;
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .await
+        .map_err(|e| format!("Failed to listen on port {}: {}", port, e))?;
+    tracing::info!(url = %local_url(port), "Waiting for a local client");
+
+    let allowed_origins = allowed_origins_from_env();
+    let handle = tokio::spawn(accept_loop(app, ctx, listener, allowed_origins));
+    *ws_supervisor.lock().await = Some(handle);
+    Ok(())
+}
+
+// Handshake callbacks return tungstenite's `ErrorResponse`, which is an HTTP response
+#[allow(clippy::result_large_err)]
+async fn accept_loop(
+    app: AppHandle,
+    ctx: ConnectionContext,
+    listener: TcpListener,
+    allowed_origins: Vec<String>,
+) {
+    // Shared across clients like the relay's, so a call queued before a client
+    // reconnects still orders after one that was running
+    let mut scheduler = ToolScheduler::default();
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to accept local connection");
+                continue;
+            }
+        };
+
+        let origins = allowed_origins.clone();
+        let callback = move |request: &Request, response: Response| {
+            check_request(request, &origins).map(|()| response)
+        };
+        let handshake = accept_hdr_async(MaybeTlsStream::Plain(stream), callback);
+        let ws_stream = match timeout(HANDSHAKE_TIMEOUT, handshake).await {
+            Ok(Ok(ws_stream)) => ws_stream,
+            Ok(Err(e)) => {
+                tracing::warn!(peer = %peer, error = %e, "Rejected local connection");
+                continue;
+            }
+            Err(_) => {
+                tracing::warn!(peer = %peer, "Local connection did not finish its handshake in time");
+                continue;
+            }
+        };
+
+        let (write, read) = ws_stream.split();
+        if let Err(e) = websocket::start_session(&app, &ctx, write, &peer.to_string()).await {
+            tracing::warn!(peer = %peer, error = %e, "Local session failed to start");
+            websocket::end_session(&app, &ctx).await;
+            continue;
+        }
+
+        // Turn away further clients while this one is served
+        let session = websocket::run_read_loop(read, &app, &ctx, &mut scheduler);
+        tokio::pin!(session);
+        loop {
+            tokio::select! {
+                _ = &mut session => break,
+                accepted = listener.accept() => {
+                    if let Ok((stream, other)) = accepted {
+                        tokio::spawn(reject_busy(stream, other));
+                    }
+                }
+            }
+        }
+
+        websocket::end_session(&app, &ctx).await;
+        tracing::info!(peer = %peer, "Local client disconnected");
+    }
+}
+
+/// Answer a second client's handshake with 409 so it doesn't hang
+#[allow(clippy::result_large_err)]
+async fn reject_busy(stream: TcpStream, peer: SocketAddr) {
+    tracing::warn!(peer = %peer, "Rejecting local connection, another client is connected");
+    let callback = |_: &Request, _: Response| {
+        Err(error_response(StatusCode::CONFLICT, "Another client is already connected"))
+    };
+    let _ = timeout(HANDSHAKE_TIMEOUT, accept_hdr_async(stream, callback)).await;
+}
+
+/// Origins allowed besides local pages, from comma-separated `BRAINDRIVE_LOCAL_ORIGINS`
+fn allowed_origins_from_env() -> Vec<String> {
+    std::env::var("BRAINDRIVE_LOCAL_ORIGINS")
+        .map(|value| {
+            value
+                .split(',')
+                .map(|origin| origin.trim().trim_end_matches('/').to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Accept the handshake only from a loopback `Host` and a trusted `Origin`
+///
+/// Clients that aren't browsers (test harnesses, scripts) send no `Origin` and
+/// are allowed; browsers always send one.
+#[allow(clippy::result_large_err)]
+fn check_request(request: &Request, allowed_origins: &[String]) -> Result<(), ErrorResponse> {
+    let header = |name: &str| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+
+    if !header("host").is_some_and(is_loopback_host) {
+        return Err(error_response(StatusCode::FORBIDDEN, "Host must be a loopback address"));
+    }
+
+    match header("origin") {
+        None => Ok(()),
+        Some(origin) if origin_allowed(origin, allowed_origins) => Ok(()),
+        Some(origin) => {
+            tracing::warn!(origin = %origin, "Rejecting local connection from untrusted origin");
+            Err(error_response(StatusCode::FORBIDDEN, "Origin not allowed"))
+        }
+    }
+}
+
+/// Whether a `Host` header value (with optional port) names this machine
+fn is_loopback_host(host: &str) -> bool {
+    let name = match host.rfind(':') {
+        // Strip the port, but not the colons inside an IPv6 literal
+        Some(colon) if !host[colon..].contains(']') => &host[..colon],
+        _ => host,
+    };
+    LOOPBACK_HOSTS.iter().any(|loopback| name.eq_ignore_ascii_case(loopback))
+}
+
+fn origin_allowed(origin: &str, allowed_origins: &[String]) -> bool {
+    if allowed_origins.iter().any(|allowed| allowed == origin) {
+        return true;
+    }
+    match url::Url::parse(origin) {
+        Ok(url) => {
+            matches!(url.scheme(), "http" | "https")
+                && url
+                    .host_str()
+                    .is_some_and(|host| LOOPBACK_HOSTS.iter().any(|loopback| host.eq_ignore_ascii_case(loopback)))
+        }
+        Err(_) => false,
+    }
+}
+
+fn error_response(status: StatusCode, message: &str) -> ErrorResponse {
+    let mut response = ErrorResponse::new(Some(message.to_string()));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(host: &str, origin: Option<&str>) -> Request {
+        let mut builder = Request::builder().uri("/").header("host", host);
+        if let Some(origin) = origin {
+            builder = builder.header("origin", origin);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn test_local_origins_are_accepted() {
+        for origin in ["http://localhost:5173", "http://127.0.0.1:3000", "https://[::1]:8443"] {
+            assert!(check_request(&request("127.0.0.1:3100", Some(origin)), &[]).is_ok(), "{}", origin);
+        }
+        // Scripts and test harnesses don't send an origin
+        assert!(check_request(&request("localhost:3100", None), &[]).is_ok());
+    }
+
+    #[test]
+    fn test_foreign_origins_are_rejected_unless_allowed() {
+        let foreign = request("127.0.0.1:3100", Some("https://evil.example"));
+        let rejected = check_request(&foreign, &[]).unwrap_err();
+        assert_eq!(rejected.status(), StatusCode::FORBIDDEN);
+
+        let allowed = vec!["https://evil.example".to_string()];
+        assert!(check_request(&foreign, &allowed).is_ok());
+
+        // Looks local, but isn't
+        let lookalike = request("127.0.0.1:3100", Some("http://localhost.evil.example"));
+        assert!(check_request(&lookalike, &[]).is_err());
+    }
+
+    #[test]
+    fn test_non_loopback_host_is_rejected() {
+        // A DNS-rebound page reaches us under its own host name
+        let rebound = request("evil.example:3100", Some("http://evil.example:3100"));
+        assert!(check_request(&rebound, &["http://evil.example:3100".to_string()]).is_err());
+
+        assert!(is_loopback_host("[::1]:3100"));
+        assert!(is_loopback_host("LOCALHOST"));
+        assert!(!is_loopback_host("127.0.0.1.evil.example"));
+    }
+}
@@ -0,0 +1,911 @@
+//! Offline installs from a prepared artifact bundle
+//!
+//! Air-gapped machines can't reach repo.anaconda.com, conda-forge, GitHub,
+//! PyPI, npm or ollama.com, which every install step otherwise downloads from.
+//! `build_offline_bundle` runs on a connected machine with the same OS and
+//! architecture and collects all of it into one directory, or a `.tar.gz` of
+//! it:
+//!
+//! ```text
+//! manifest.json            what was bundled, with SHA-256 digests
+//! Miniconda3-installer.*   the Miniconda installer
+//! conda-pkgs/              conda package cache for the BrainDrive environment
+//! repo.bundle              git bundle of the BrainDrive checkout
+//! wheels/                  wheels for backend/requirements.txt
+//! npm-cache/               npm cache holding the frontend dependencies
+//! ollama/                  Ollama manifests and blobs, if models were bundled
+//! ```
+//!
+//! `install_offline_bundle` installs from it with no network access: conda
+//! creates the environment `--offline` from the bundled package cache, the
+//! checkout is fetched from the git bundle, pip installs with `--no-index` and
+//! npm with `--offline`. Steps whose result is already in place are skipped.
+//! Ollama itself is not bundled; bundled models are copied into its model
+//! directory for an Ollama installed separately.
+
+use crate::checksum;
+use crate::dispatcher::{self, CloneOptions};
+use crate::error::DispatchError;
+use crate::git_remote::GitToken;
+use crate::mirrors::{self, Ecosystem};
+use crate::uninstall;
+use crate::verify;
+use crate::protocol::{send_message, Outbound, OutgoingMessage};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+/// Bumped when the layout changes incompatibly; other versions are refused
+const FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const REPO_BUNDLE: &str = "repo.bundle";
+const CONDA_PKGS_DIR: &str = "conda-pkgs";
+const WHEELS_DIR: &str = "wheels";
+const NPM_CACHE_DIR: &str = "npm-cache";
+const OLLAMA_DIR: &str = "ollama";
+
+/// Where the checkout goes, under the home directory
+const DEFAULT_REPO_DIR: &str = "BrainDrive";
+/// Environment the services run in
+const CONDA_ENV_NAME: &str = "BrainDriveDev";
+
+/// Registry Ollama stores models from when the name doesn't give one
+const OLLAMA_REGISTRY: &str = "registry.ollama.ai";
+
+const BUILD_OPERATION: &str = "build_offline_bundle";
+const INSTALL_OPERATION: &str = "install_offline_bundle";
+
+/// What a bundle holds, written to `manifest.json`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format_version: u32,
+    pub created_at: String,
+    /// Bootstrapper version that built the bundle
+    pub bootstrapper_version: String,
+    /// `std::env::consts::OS` and `ARCH` of the machine it was built on
+    pub os: String,
+    pub arch: String,
+    /// Where the bundled Miniconda installer came from, and its digest
+    pub miniconda_url: String,
+    pub miniconda_sha256: String,
+    /// Specs the conda environment is created with
+    pub conda_packages: Vec<String>,
+    pub repo: BundledRepo,
+    /// Ollama models bundled, as passed to `ollama pull`
+    #[serde(default)]
+    pub models: Vec<String>,
+}
+
+/// The checkout in `repo.bundle`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundledRepo {
+    /// Remote the checkout is set to track, for updates once online
+    pub url: String,
+    /// Branch checked out, or `None` for a tag or commit
+    #[serde(default)]
+    pub branch: Option<String>,
+    pub commit: String,
+    pub sha256: String,
+}
+
+impl BundleManifest {
+    /// Refuse bundles this bootstrapper can't read or this machine can't run
+    fn validate(&self) -> Result<(), DispatchError> {
+        if self.format_version != FORMAT_VERSION {
+            return Err(DispatchError::invalid_argument(format!(
+                "Offline bundle format {} is not supported (expected {}); rebuild it with this installer",
+                self.format_version, FORMAT_VERSION
+            )));
+        }
+        if self.os != std::env::consts::OS || self.arch != std::env::consts::ARCH {
+            return Err(DispatchError::invalid_argument(format!(
+                "Offline bundle was built for {}/{} but this machine is {}/{}",
+                self.os,
+                self.arch,
+                std::env::consts::OS,
+                std::env::consts::ARCH
+            )));
+        }
+        for model in &self.models {
+            model_manifest_path(model)?;
+        }
+        Ok(())
+    }
+}
+
+/// Inputs to `build_offline_bundle`
+#[derive(Debug, Clone, Default)]
+pub struct BundleOptions {
+    /// Directory to create; must not exist yet or be empty
+    pub output_path: String,
+    /// Repository to bundle; the BrainDrive repository when unset
+    pub repo_url: Option<String>,
+    /// Branch, tag or full commit hash; the remote's default branch when unset
+    pub git_ref: Option<String>,
+    /// Token for a private HTTPS repository; never stored or logged
+    pub token: Option<GitToken>,
+    /// Ollama models already pulled on this machine to include
+    pub models: Vec<String>,
+    /// Pack the directory into `<output_path>.tar.gz` and remove it
+    pub tarball: bool,
+    /// SHA-256 the Miniconda installer must have, if none is pinned for it
+    pub sha256: Option<String>,
+}
+
+/// Build an offline bundle on a connected machine
+///
+/// Needs conda, git and network access; the models have to be pulled already.
+pub async fn build_offline_bundle(
+    options: BundleOptions,
+    request_id: String,
+    sender: Arc<Mutex<Outbound>>,
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
+    let conda = dispatcher::find_conda_binary().ok_or_else(|| {
+        DispatchError::not_installed("conda", "Conda is not installed. Run install_conda on this machine first.")
+    })?;
+    let output = absolute_path(&options.output_path)?;
+    let tarball = options.tarball.then(|| tarball_path(&output));
+    if output.exists() && std::fs::read_dir(&output).map_or(true, |mut entries| entries.next().is_some()) {
+        return Err(DispatchError::path_rejected(
+            Some(output.display().to_string()),
+            "The bundle directory already exists and is not empty",
+        ));
+    }
+    if let Some(tarball) = tarball.as_ref().filter(|tarball| tarball.exists()) {
+        return Err(DispatchError::path_rejected(
+            Some(tarball.display().to_string()),
+            "The bundle archive already exists",
+        ));
+    }
+
+    // Fail before downloading anything if a model isn't here to copy
+    let models = options
+        .models
+        .iter()
+        .map(|model| dispatcher::sanitize_model_name(model))
+        .collect::<Result<Vec<_>, _>>()?;
+    let models_dir = ollama_models_dir().ok_or("Could not determine home directory")?;
+    for model in &models {
+        let manifest = models_dir.join(model_manifest_path(model)?);
+        if !manifest.exists() {
+            return Err(DispatchError::not_installed(
+                "ollama_model",
+                format!("Model {} has not been pulled on this machine; pull it before bundling", model),
+            ));
+        }
+    }
+
+    let work = dirs::home_dir()
+        .ok_or("Could not determine home directory")?
+        .join(".braindrive-installer")
+        .join("bundle-build");
+    let _ = std::fs::remove_dir_all(&work);
+    std::fs::create_dir_all(&output).map_err(|e| format!("Failed to create {}: {}", output.display(), e))?;
+
+    let built = build(&options, &models, &conda, &output, &work, &request_id, &sender, cancel).await;
+    let _ = std::fs::remove_dir_all(&work);
+    let manifest = match built {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            // A partial bundle would fail later on the offline machine, where it's harder to fix
+            let _ = std::fs::remove_dir_all(&output);
+            return Err(e);
+        }
+    };
+
+    let location = match tarball {
+        Some(tarball) => {
+            progress(&sender, &request_id, BUILD_OPERATION, 95, "Packing the bundle...").await;
+            let packed = pack(&output, &tarball, cancel).await;
+            let _ = std::fs::remove_dir_all(&output);
+            if packed.is_err() {
+                let _ = std::fs::remove_file(&tarball);
+            }
+            packed?;
+            tarball
+        }
+        None => output,
+    };
+
+    let size = uninstall::dir_size(&location, &[]);
+    progress(&sender, &request_id, BUILD_OPERATION, 100, "Offline bundle ready").await;
+    Ok(json!({
+        "success": true,
+        "message": format!(
+            "Offline bundle written to {} ({})",
+            location.display(),
+            uninstall::format_size(size)
+        ),
+        "path": location.to_string_lossy(),
+        "size_bytes": size,
+        "manifest": manifest,
+    }))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn build(
+    options: &BundleOptions,
+    models: &[String],
+    conda: &Path,
+    output: &Path,
+    work: &Path,
+    request_id: &str,
+    sender: &Arc<Mutex<Outbound>>,
+    cancel: &CancellationToken,
+) -> Result<BundleManifest, DispatchError> {
+    progress(sender, request_id, BUILD_OPERATION, 0, "Downloading the Miniconda installer...").await;
+    let (miniconda_sha256, miniconda_url, _) = dispatcher::download_miniconda_installer(
+        &output.join(dispatcher::miniconda_installer_filename()),
+        options.sha256.as_deref(),
+        request_id,
+        sender,
+        BUILD_OPERATION,
+        cancel,
+    )
+    .await?;
+
+    // Creating a scratch environment fills the bundle's package cache, and
+    // gives the pip and npm that fetch the dependencies below. Conda and npm
+    // stay on their public sources: the offline install looks the cached
+    // packages up by where they came from.
+    progress(sender, request_id, BUILD_OPERATION, 20, "Downloading conda packages...").await;
+    let env = work.join("env");
+    let mut command = Command::new(conda);
+    command
+        .env("CONDA_PKGS_DIRS", output.join(CONDA_PKGS_DIR))
+        .arg("create")
+        .arg("--prefix")
+        .arg(&env)
+        .args(["--override-channels", "-c", "conda-forge"])
+        .args(dispatcher::CONDA_ENV_PACKAGES)
+        .arg("-y");
+    dispatcher::check_output("conda create", dispatcher::run_command(command, cancel).await?)?;
+
+    progress(sender, request_id, BUILD_OPERATION, 40, "Bundling the BrainDrive repository...").await;
+    let checkout = work.join("repo");
+    let clone_options = CloneOptions {
+        git_ref: options.git_ref.clone(),
+        depth: Some(0),
+        submodules: false,
+        token: options.token.clone(),
+    };
+    let cloned = dispatcher::clone_repo(
+        options.repo_url.clone(),
+        Some(checkout.to_string_lossy().to_string()),
+        clone_options,
+        cancel,
+    )
+    .await?;
+    let repo = bundle_repo(&checkout, cloned["url"].as_str().unwrap_or_default(), output, cancel).await?;
+
+    progress(sender, request_id, BUILD_OPERATION, 55, "Downloading backend wheels...").await;
+    mirrors::with_fallback(Ecosystem::Pypi, |mirror| {
+        let mut command = env_command(&env, "python");
+        command
+            .args(["-m", "pip", "wheel", "-r"])
+            .arg(checkout.join("backend").join("requirements.txt"))
+            .arg("--wheel-dir")
+            .arg(output.join(WHEELS_DIR));
+        mirror.apply(Ecosystem::Pypi, &mut command);
+        async move { dispatcher::check_output("pip wheel", dispatcher::run_command(command, cancel).await?) }
+    })
+    .await?;
+
+    // npm only fills the cache; installing into a copy of the manifests keeps
+    // the checkout out of the bundle's way
+    progress(sender, request_id, BUILD_OPERATION, 70, "Downloading frontend packages...").await;
+    let npm_dir = work.join("npm");
+    std::fs::create_dir_all(&npm_dir).map_err(|e| format!("Failed to create {}: {}", npm_dir.display(), e))?;
+    for manifest in ["package.json", "package-lock.json"] {
+        let source = checkout.join("frontend").join(manifest);
+        if source.exists() {
+            std::fs::copy(&source, npm_dir.join(manifest))
+                .map_err(|e| format!("Failed to copy {}: {}", source.display(), e))?;
+        }
+    }
+    let mut command = env_command(&env, "npm");
+    command
+        .args(["install", "--ignore-scripts", "--no-audit", "--no-fund", "--cache"])
+        .arg(output.join(NPM_CACHE_DIR))
+        .current_dir(&npm_dir);
+    dispatcher::check_output("npm install", dispatcher::run_command(command, cancel).await?)?;
+
+    if !models.is_empty() {
+        progress(sender, request_id, BUILD_OPERATION, 85, "Copying Ollama models...").await;
+        let models_dir = ollama_models_dir().ok_or("Could not determine home directory")?;
+        copy_models(&models_dir, &output.join(OLLAMA_DIR), models.to_vec()).await?;
+    }
+
+    let manifest = BundleManifest {
+        format_version: FORMAT_VERSION,
+        created_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        bootstrapper_version: env!("CARGO_PKG_VERSION").to_string(),
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        miniconda_url,
+        miniconda_sha256,
+        conda_packages: dispatcher::CONDA_ENV_PACKAGES.iter().map(|spec| spec.to_string()).collect(),
+        repo,
+        models: models.to_vec(),
+    };
+    let text = serde_json::to_string_pretty(&manifest).map_err(|e| DispatchError::internal(e.to_string()))?;
+    std::fs::write(output.join(MANIFEST_FILE), text).map_err(|e| format!("Failed to write bundle manifest: {}", e))?;
+    Ok(manifest)
+}
+
+/// Write `repo.bundle` with HEAD and the branch checked out, if any
+async fn bundle_repo(
+    checkout: &Path,
+    url: &str,
+    output: &Path,
+    cancel: &CancellationToken,
+) -> Result<BundledRepo, DispatchError> {
+    let git = dispatcher::find_git_binary()
+        .ok_or_else(|| DispatchError::not_installed("git", "Git is not installed. Please install Git first."))?;
+    let run = |args: &[&str]| {
+        let mut command = Command::new(&git);
+        command.arg("-C").arg(checkout).args(args);
+        command
+    };
+
+    let branch_output = dispatcher::run_command(run(&["symbolic-ref", "--quiet", "--short", "HEAD"]), cancel).await?;
+    let branch = branch_output.success.then(|| branch_output.stdout.trim().to_string());
+    let commit = dispatcher::check_output("git rev-parse", dispatcher::run_command(run(&["rev-parse", "HEAD"]), cancel).await?)?
+        .stdout
+        .trim()
+        .to_string();
+
+    let path = output.join(REPO_BUNDLE);
+    let mut command = run(&["bundle", "create"]);
+    command.arg(&path).arg("HEAD");
+    if let Some(branch) = &branch {
+        command.arg(format!("refs/heads/{}", branch));
+    }
+    dispatcher::check_output("git bundle create", dispatcher::run_command(command, cancel).await?)?;
+
+    Ok(BundledRepo {
+        url: url.to_string(),
+        branch,
+        commit,
+        sha256: checksum::file_digest(&path)?,
+    })
+}
+
+/// Install BrainDrive from an offline bundle without network access
+pub async fn install_offline_bundle(
+    bundle_path: String,
+    request_id: String,
+    sender: Arc<Mutex<Outbound>>,
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
+    let bundle = open_bundle(&bundle_path, cancel).await?;
+    let manifest = read_manifest(&bundle)?;
+    manifest.validate()?;
+    let mut steps = Map::new();
+
+    // Conda
+    let miniconda = dispatcher::get_isolated_miniconda_dir().ok_or("Could not determine home directory")?;
+    match dispatcher::get_isolated_conda_binary() {
+        Some(_) => {
+            steps.insert("install_conda".to_string(), json!("already_installed"));
+        }
+        None => {
+            progress(&sender, &request_id, INSTALL_OPERATION, 5, "Installing Miniconda...").await;
+            install_miniconda(&bundle, &manifest, &miniconda, cancel).await?;
+            steps.insert("install_conda".to_string(), json!("installed"));
+        }
+    }
+    let conda = dispatcher::get_isolated_conda_binary()
+        .ok_or_else(|| DispatchError::internal("Miniconda installation completed but conda binary not found"))?;
+
+    // Environment
+    let env = miniconda.join("envs").join(CONDA_ENV_NAME);
+    if verify::env_tool(&env, "python").exists() {
+        steps.insert("create_conda_env".to_string(), json!("already_exists"));
+    } else {
+        progress(&sender, &request_id, INSTALL_OPERATION, 25, "Creating the conda environment...").await;
+        copy_tree(bundle.join(CONDA_PKGS_DIR), miniconda.join("pkgs")).await?;
+        let mut command = Command::new(&conda);
+        offline(&mut command)
+            .args(["create", "-n", CONDA_ENV_NAME, "--offline", "--override-channels", "-c", "conda-forge"])
+            .args(&manifest.conda_packages)
+            .arg("-y");
+        dispatcher::check_output("conda create", dispatcher::run_command(command, cancel).await?)?;
+        steps.insert("create_conda_env".to_string(), json!("created"));
+    }
+
+    // Repository
+    let repo = dirs::home_dir().ok_or("Could not determine home directory")?.join(DEFAULT_REPO_DIR);
+    if repo.join(".git").exists() {
+        steps.insert("clone_repo".to_string(), json!("already_exists"));
+    } else {
+        progress(&sender, &request_id, INSTALL_OPERATION, 45, "Checking out BrainDrive...").await;
+        checkout_repo(&bundle, &manifest.repo, &repo, cancel).await?;
+        steps.insert("clone_repo".to_string(), json!("checked_out"));
+    }
+    let repo_path = Some(repo.to_string_lossy().to_string());
+
+    progress(&sender, &request_id, INSTALL_OPERATION, 55, "Creating the environment file...").await;
+    dispatcher::setup_env_file(repo_path).await?;
+    steps.insert("setup_env_file".to_string(), json!("done"));
+
+    progress(&sender, &request_id, INSTALL_OPERATION, 60, "Installing backend dependencies...").await;
+    let mut command = env_command(&env, "python");
+    offline(&mut command)
+        .args(["-m", "pip", "install", "--no-index", "--find-links"])
+        .arg(bundle.join(WHEELS_DIR))
+        .arg("-r")
+        .arg(repo.join("backend").join("requirements.txt"));
+    dispatcher::check_output("pip install", dispatcher::run_command(command, cancel).await?)?;
+    steps.insert("install_backend_deps".to_string(), json!("done"));
+
+    progress(&sender, &request_id, INSTALL_OPERATION, 75, "Installing frontend dependencies...").await;
+    let mut command = env_command(&env, "npm");
+    offline(&mut command)
+        .args(["install", "--offline", "--no-audit", "--no-fund", "--cache"])
+        .arg(bundle.join(NPM_CACHE_DIR))
+        .current_dir(repo.join("frontend"));
+    dispatcher::check_output("npm install", dispatcher::run_command(command, cancel).await?)?;
+    steps.insert("install_frontend_deps".to_string(), json!("done"));
+
+    if !manifest.models.is_empty() {
+        progress(&sender, &request_id, INSTALL_OPERATION, 90, "Copying Ollama models...").await;
+        let models_dir = ollama_models_dir().ok_or("Could not determine home directory")?;
+        copy_models(&bundle.join(OLLAMA_DIR), &models_dir, manifest.models.clone()).await?;
+        steps.insert("ollama_models".to_string(), json!("copied"));
+    }
+
+    progress(&sender, &request_id, INSTALL_OPERATION, 100, "BrainDrive installed from the offline bundle").await;
+    Ok(json!({
+        "success": true,
+        "message": "BrainDrive installed from the offline bundle",
+        "bundle": bundle.to_string_lossy(),
+        "repo_path": repo.to_string_lossy(),
+        "commit": manifest.repo.commit,
+        "models": manifest.models,
+        "steps": steps,
+    }))
+}
+
+/// The bundle directory, unpacking a `.tar.gz` under the installer directory first
+async fn open_bundle(bundle_path: &str, cancel: &CancellationToken) -> Result<PathBuf, DispatchError> {
+    let path = absolute_path(bundle_path)?;
+    if path.is_dir() {
+        return Ok(path);
+    }
+    let Some(stem) = archive_stem(&path) else {
+        return Err(DispatchError::path_rejected(
+            Some(path.display().to_string()),
+            "Offline bundle not found; pass its directory or .tar.gz file",
+        ));
+    };
+    if !path.is_file() {
+        return Err(DispatchError::path_rejected(
+            Some(path.display().to_string()),
+            "Offline bundle not found",
+        ));
+    }
+
+    let dest = dirs::home_dir()
+        .ok_or("Could not determine home directory")?
+        .join(".braindrive-installer")
+        .join("bundles")
+        .join(stem);
+    let _ = std::fs::remove_dir_all(&dest);
+    std::fs::create_dir_all(&dest).map_err(|e| format!("Failed to create {}: {}", dest.display(), e))?;
+    let mut command = Command::new("tar");
+    command.arg("-xzf").arg(&path).arg("-C").arg(&dest);
+    let unpacked = dispatcher::run_command(command, cancel)
+        .await
+        .and_then(|output| dispatcher::check_output("tar", output));
+    if let Err(e) = unpacked {
+        let _ = std::fs::remove_dir_all(&dest);
+        return Err(e);
+    }
+
+    // The archive holds the bundle directory itself
+    if dest.join(MANIFEST_FILE).exists() {
+        return Ok(dest);
+    }
+    let inner: Vec<PathBuf> = std::fs::read_dir(&dest)
+        .map_err(|e| format!("Failed to read {}: {}", dest.display(), e))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|inner| inner.join(MANIFEST_FILE).exists())
+        .collect();
+    match inner.as_slice() {
+        [bundle] => Ok(bundle.clone()),
+        _ => Err(DispatchError::path_rejected(
+            Some(path.display().to_string()),
+            "The archive is not an offline bundle (no manifest.json)",
+        )),
+    }
+}
+
+fn read_manifest(bundle: &Path) -> Result<BundleManifest, DispatchError> {
+    let path = bundle.join(MANIFEST_FILE);
+    let text = std::fs::read_to_string(&path).map_err(|_| {
+        DispatchError::path_rejected(
+            Some(bundle.display().to_string()),
+            "Not an offline bundle: manifest.json is missing",
+        )
+    })?;
+    serde_json::from_str(&text)
+        .map_err(|e| DispatchError::invalid_argument(format!("Offline bundle manifest is invalid: {}", e)))
+}
+
+/// Check a bundled file against the digest the manifest recorded
+///
+/// Unlike a download, the file isn't deleted on a mismatch: it belongs to the
+/// bundle, which may be on read-only media.
+fn verify_bundled(path: &Path, expected: &str) -> Result<(), DispatchError> {
+    let actual = checksum::file_digest(path)?;
+    let expected = checksum::normalize_digest(expected)?;
+    if actual == expected {
+        return Ok(());
+    }
+    Err(DispatchError::checksum_mismatch(&path.to_string_lossy(), &expected, &actual))
+}
+
+async fn install_miniconda(
+    bundle: &Path,
+    manifest: &BundleManifest,
+    miniconda: &PathBuf,
+    cancel: &CancellationToken,
+) -> Result<(), DispatchError> {
+    let installer = bundle.join(dispatcher::miniconda_installer_filename());
+    verify_bundled(&installer, &manifest.miniconda_sha256)?;
+
+    // The installer has to be executable, which a copy on read-only media can't be made
+    let downloads = dirs::home_dir()
+        .ok_or("Could not determine home directory")?
+        .join(".braindrive-installer")
+        .join("downloads");
+    std::fs::create_dir_all(&downloads).map_err(|e| format!("Failed to create download directory: {}", e))?;
+    let local = downloads.join(dispatcher::miniconda_installer_filename());
+    std::fs::copy(&installer, &local).map_err(|e| format!("Failed to copy the Miniconda installer: {}", e))?;
+    if let Some(parent) = miniconda.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create BrainDrive directory: {}", e))?;
+    }
+
+    let installed = dispatcher::run_miniconda_installer(&local, miniconda, cancel).await;
+    let _ = std::fs::remove_file(&local);
+    if cancel.is_cancelled() {
+        let _ = std::fs::remove_dir_all(miniconda);
+        return Err(DispatchError::Cancelled);
+    }
+    installed?;
+
+    let conda = dispatcher::get_isolated_conda_binary()
+        .ok_or_else(|| DispatchError::internal("Miniconda installation completed but conda binary not found"))?;
+    dispatcher::configure_conda(&conda).await;
+    Ok(())
+}
+
+/// Check out the bundled commit into `repo`, tracking the original remote
+async fn checkout_repo(
+    bundle: &Path,
+    bundled: &BundledRepo,
+    repo: &PathBuf,
+    cancel: &CancellationToken,
+) -> Result<(), DispatchError> {
+    if repo.exists() && !dispatcher::check_only_installer_artifacts(repo) {
+        return Err(DispatchError::path_rejected(
+            Some(repo.display().to_string()),
+            format!(
+                "Directory {} exists but is not a git repository and contains non-installer files",
+                repo.display()
+            ),
+        ));
+    }
+    let git = dispatcher::find_braindrive_git()
+        .ok_or_else(|| DispatchError::not_installed("git", "Git is not installed. Please install Git first."))?;
+    let repo_bundle = bundle.join(REPO_BUNDLE);
+    verify_bundled(&repo_bundle, &bundled.sha256)?;
+
+    let created = !repo.exists();
+    std::fs::create_dir_all(repo).map_err(|e| format!("Failed to create {}: {}", repo.display(), e))?;
+    let result = fetch_bundle(&git, &repo_bundle, bundled, repo, cancel).await;
+    if result.is_err() {
+        // Don't leave a half-made repository that later looks like a finished clone
+        let _ = if created {
+            std::fs::remove_dir_all(repo)
+        } else {
+            std::fs::remove_dir_all(repo.join(".git"))
+        };
+    }
+    result
+}
+
+async fn fetch_bundle(
+    git: &Path,
+    repo_bundle: &Path,
+    bundled: &BundledRepo,
+    repo: &Path,
+    cancel: &CancellationToken,
+) -> Result<(), DispatchError> {
+    let git = |args: &[&str]| {
+        let mut command = Command::new(git);
+        command.env("GIT_TERMINAL_PROMPT", "0").arg("-C").arg(repo).args(args);
+        command
+    };
+    let bundle_arg = repo_bundle.to_string_lossy();
+
+    dispatcher::check_output("git init", dispatcher::run_command(git(&["init"]), cancel).await?)?;
+    // Added first so the branch tracks it, as after an online clone
+    let remote = git(&["remote", "add", "origin", &bundled.url]);
+    dispatcher::check_output("git remote add", dispatcher::run_command(remote, cancel).await?)?;
+    let (refspec, checkout_args) = match &bundled.branch {
+        Some(branch) => (
+            format!("+HEAD:refs/remotes/origin/{}", branch),
+            vec!["checkout".to_string(), "-b".to_string(), branch.clone(), format!("origin/{}", branch)],
+        ),
+        None => (
+            "HEAD".to_string(),
+            vec!["checkout".to_string(), "--detach".to_string(), "FETCH_HEAD".to_string()],
+        ),
+    };
+    let fetch = git(&["fetch", "--no-tags", &bundle_arg, &refspec]);
+    dispatcher::check_output("git fetch", dispatcher::run_command(fetch, cancel).await?)?;
+    let checkout_args: Vec<&str> = checkout_args.iter().map(String::as_str).collect();
+    dispatcher::check_output("git checkout", dispatcher::run_command(git(&checkout_args), cancel).await?)?;
+
+    let head = dispatcher::check_output("git rev-parse", dispatcher::run_command(git(&["rev-parse", "HEAD"]), cancel).await?)?;
+    if head.stdout.trim() != bundled.commit {
+        return Err(DispatchError::internal(format!(
+            "The repository bundle checked out {} instead of {}",
+            head.stdout.trim(),
+            bundled.commit
+        )));
+    }
+    Ok(())
+}
+
+/// Make a command fail rather than reach for the network
+fn offline(command: &mut Command) -> &mut Command {
+    command
+        .env("CONDA_OFFLINE", "true")
+        .env("PIP_NO_INDEX", "1")
+        .env("npm_config_offline", "true")
+}
+
+/// A tool from a conda environment, with the environment first on PATH
+///
+/// npm is a script run by the environment's node, so it has to be found there.
+fn env_command(env: &Path, tool: &str) -> Command {
+    let program = verify::env_tool(env, tool);
+    let mut command = Command::new(&program);
+    if let Some(bin) = program.parent() {
+        let inherited = std::env::var_os("PATH").unwrap_or_default();
+        let paths = std::iter::once(bin.to_path_buf()).chain(std::env::split_paths(&inherited));
+        if let Ok(path) = std::env::join_paths(paths) {
+            command.env("PATH", path);
+        }
+    }
+    command
+}
+
+/// `~/`-relative or absolute path given by the caller
+fn absolute_path(path: &str) -> Result<PathBuf, DispatchError> {
+    let path = path.trim();
+    let resolved = match path.strip_prefix("~/") {
+        Some(relative) => dirs::home_dir().ok_or("Could not determine home directory")?.join(relative),
+        None => PathBuf::from(path),
+    };
+    if path.is_empty() || !resolved.is_absolute() {
+        return Err(DispatchError::path_rejected(
+            Some(path.to_string()),
+            "Bundle paths must be absolute or start with ~/",
+        ));
+    }
+    Ok(resolved)
+}
+
+fn tarball_path(dir: &Path) -> PathBuf {
+    let mut name = dir.file_name().unwrap_or_default().to_os_string();
+    name.push(".tar.gz");
+    dir.with_file_name(name)
+}
+
+/// File name of a bundle archive without its extension
+fn archive_stem(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    name.strip_suffix(".tar.gz")
+        .or_else(|| name.strip_suffix(".tgz"))
+        .filter(|stem| !stem.is_empty())
+        .map(str::to_string)
+}
+
+async fn pack(dir: &Path, tarball: &Path, cancel: &CancellationToken) -> Result<(), DispatchError> {
+    let (Some(parent), Some(name)) = (dir.parent(), dir.file_name()) else {
+        return Err(DispatchError::internal("Bundle directory has no parent"));
+    };
+    let mut command = Command::new("tar");
+    command.arg("-czf").arg(tarball).arg("-C").arg(parent).arg(name);
+    dispatcher::check_output("tar", dispatcher::run_command(command, cancel).await?)?;
+    Ok(())
+}
+
+/// Where Ollama keeps models: `OLLAMA_MODELS`, or `~/.ollama/models`
+fn ollama_models_dir() -> Option<PathBuf> {
+    std::env::var_os("OLLAMA_MODELS")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".ollama").join("models")))
+}
+
+/// A model's manifest relative to the models directory, e.g.
+/// `manifests/registry.ollama.ai/library/llama3.2/1b` for `llama3.2:1b`
+fn model_manifest_path(model: &str) -> Result<PathBuf, DispatchError> {
+    let invalid = || DispatchError::invalid_argument(format!("Invalid model name: {}", model));
+    let (name, tag) = match model.rsplit_once(':') {
+        Some((name, tag)) if !tag.contains('/') => (name, tag),
+        _ => (model, "latest"),
+    };
+    let parts = match name.split('/').collect::<Vec<_>>().as_slice() {
+        [model] => vec![OLLAMA_REGISTRY, "library", model, tag],
+        [namespace, model] => vec![OLLAMA_REGISTRY, namespace, model, tag],
+        [registry, namespace, model] => vec![registry, namespace, model, tag],
+        _ => return Err(invalid()),
+    };
+    if parts.iter().any(|part| part.is_empty() || *part == "." || *part == "..") {
+        return Err(invalid());
+    }
+    Ok(parts.iter().fold(PathBuf::from("manifests"), |path, part| path.join(part)))
+}
+
+/// Copy models' manifests and the blobs they list between models directories
+async fn copy_models(from: &Path, to: &Path, models: Vec<String>) -> Result<(), DispatchError> {
+    let (from, to) = (from.to_path_buf(), to.to_path_buf());
+    tokio::task::spawn_blocking(move || {
+        for model in &models {
+            let manifest_path = model_manifest_path(model)?;
+            let text = std::fs::read_to_string(from.join(&manifest_path))
+                .map_err(|e| format!("Failed to read the manifest of {}: {}", model, e))?;
+            let manifest: Value = serde_json::from_str(&text)
+                .map_err(|e| DispatchError::invalid_argument(format!("Invalid manifest for {}: {}", model, e)))?;
+            let digests = std::iter::once(&manifest["config"])
+                .chain(manifest["layers"].as_array().into_iter().flatten())
+                .filter_map(|layer| layer["digest"].as_str());
+            for digest in digests {
+                let blob = format!("sha256-{}", checksum::normalize_digest(digest)?);
+                copy_file(&from.join("blobs").join(&blob), &to.join("blobs").join(&blob))?;
+            }
+            // The manifest goes last so a model never shows up with blobs missing
+            copy_file(&from.join(&manifest_path), &to.join(&manifest_path))?;
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|e| DispatchError::internal(e.to_string()))?
+}
+
+/// Copy `from` to `to`, creating parents; a file already there is kept
+fn copy_file(from: &Path, to: &Path) -> Result<(), DispatchError> {
+    if to.exists() {
+        return Ok(());
+    }
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    std::fs::copy(from, to).map_err(|e| format!("Failed to copy {}: {}", from.display(), e))?;
+    Ok(())
+}
+
+/// Copy a directory tree, keeping symlinks (conda packages have them) and
+/// anything already at the destination
+async fn copy_tree(from: PathBuf, to: PathBuf) -> Result<(), DispatchError> {
+    tokio::task::spawn_blocking(move || copy_tree_blocking(&from, &to))
+        .await
+        .map_err(|e| DispatchError::internal(e.to_string()))?
+}
+
+fn copy_tree_blocking(from: &Path, to: &Path) -> Result<(), DispatchError> {
+    std::fs::create_dir_all(to).map_err(|e| format!("Failed to create {}: {}", to.display(), e))?;
+    let entries = std::fs::read_dir(from).map_err(|e| format!("Failed to read {}: {}", from.display(), e))?;
+    for entry in entries.flatten() {
+        let (source, dest) = (entry.path(), to.join(entry.file_name()));
+        let metadata = std::fs::symlink_metadata(&source)
+            .map_err(|e| format!("Failed to read {}: {}", source.display(), e))?;
+        if metadata.is_dir() {
+            copy_tree_blocking(&source, &dest)?;
+        } else if std::fs::symlink_metadata(&dest).is_ok() {
+            continue;
+        } else if metadata.file_type().is_symlink() {
+            copy_symlink(&source, &dest)?;
+        } else {
+            std::fs::copy(&source, &dest).map_err(|e| format!("Failed to copy {}: {}", source.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(source: &Path, dest: &Path) -> Result<(), DispatchError> {
+    let target = std::fs::read_link(source).map_err(|e| format!("Failed to read {}: {}", source.display(), e))?;
+    std::os::unix::fs::symlink(target, dest).map_err(|e| format!("Failed to link {}: {}", dest.display(), e))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(source: &Path, dest: &Path) -> Result<(), DispatchError> {
+    std::fs::copy(source, dest).map_err(|e| format!("Failed to copy {}: {}", source.display(), e))?;
+    Ok(())
+}
+
+async fn progress(sender: &Arc<Mutex<Outbound>>, request_id: &str, operation: &str, percent: u8, message: &str) {
+    let _ = send_message(sender, OutgoingMessage::Progress {
+        id: request_id.to_string(),
+        operation: operation.to_string(),
+        percent: Some(percent),
+        message: message.to_string(),
+        bytes_downloaded: None,
+        bytes_total: None,
+    }).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> BundleManifest {
+        BundleManifest {
+            format_version: FORMAT_VERSION,
+            created_at: "2026-01-01T00:00:00Z".to_string(),
+            bootstrapper_version: "0.0.0".to_string(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            miniconda_url: "https://repo.anaconda.com/miniconda/Miniconda3-latest-Linux-x86_64.sh".to_string(),
+            miniconda_sha256: "0".repeat(64),
+            conda_packages: vec!["python=3.11".to_string()],
+            repo: BundledRepo {
+                url: "https://github.com/BrainDriveAI/BrainDrive.git".to_string(),
+                branch: Some("main".to_string()),
+                commit: "1".repeat(40),
+                sha256: "2".repeat(64),
+            },
+            models: vec!["llama3.2:1b".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_manifest_round_trips_and_is_checked() {
+        let manifest = manifest();
+        let text = serde_json::to_string(&manifest).unwrap();
+        let parsed: BundleManifest = serde_json::from_str(&text).unwrap();
+        assert_eq!(parsed, manifest);
+        assert!(parsed.validate().is_ok());
+
+        let other_platform = BundleManifest { arch: "sparc".to_string(), ..manifest.clone() };
+        assert!(other_platform.validate().is_err());
+        let newer = BundleManifest { format_version: FORMAT_VERSION + 1, ..manifest.clone() };
+        assert!(newer.validate().is_err());
+        let escaping = BundleManifest { models: vec!["../../etc:passwd".to_string()], ..manifest };
+        assert!(escaping.validate().is_err());
+    }
+
+    #[test]
+    fn test_model_names_map_to_ollama_manifests() {
+        let path = |model| model_manifest_path(model).unwrap();
+        assert_eq!(path("llama3.2:1b"), Path::new("manifests/registry.ollama.ai/library/llama3.2/1b"));
+        assert_eq!(path("llama3.2"), Path::new("manifests/registry.ollama.ai/library/llama3.2/latest"));
+        assert_eq!(path("user/model:q4"), Path::new("manifests/registry.ollama.ai/user/model/q4"));
+        assert_eq!(path("example.com/ns/model"), Path::new("manifests/example.com/ns/model/latest"));
+        assert!(model_manifest_path("a/b/c/d").is_err());
+        assert!(model_manifest_path("../model").is_err());
+    }
+
+    #[test]
+    fn test_bundle_archive_names() {
+        assert_eq!(tarball_path(Path::new("/media/usb/bundle")), Path::new("/media/usb/bundle.tar.gz"));
+        assert_eq!(archive_stem(Path::new("/media/usb/bundle.tar.gz")).as_deref(), Some("bundle"));
+        assert_eq!(archive_stem(Path::new("/media/usb/bundle.tgz")).as_deref(), Some("bundle"));
+        assert_eq!(archive_stem(Path::new("/media/usb/bundle.zip")), None);
+    }
+}
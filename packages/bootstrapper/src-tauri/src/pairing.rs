@@ -0,0 +1,215 @@
+//! Pairing between this bootstrapper and one backend session
+//!
+//! The bootstrapper window shows a short one-time code. The user types it into
+//! the web UI, and the backend combines it with the nonce we announced in
+//! `bootstrapper_connect` to send back a proof. If the proof checks out, both
+//! sides derive the same session token from nonce and code: the backend attaches
+//! it to every tool call, and we present it when reconnecting. The code itself
+//! never travels over our socket, so a backend the user didn't type it into
+//! can't pair with us.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Number of digits in a pairing code
+const PAIRING_CODE_DIGITS: u32 = 6;
+
+/// Wrong proofs accepted for one code before it is replaced
+const MAX_PAIRING_ATTEMPTS: u32 = 5;
+
+pub type SharedPairing = Arc<Mutex<Pairing>>;
+
+/// Payload for the `pairing-updated` event and `get_pairing_status` command
+#[derive(Debug, Clone, Serialize)]
+pub struct PairingStatus {
+    pub code: String,
+    pub paired: bool,
+}
+
+pub struct Pairing {
+    code: String,
+    nonce: String,
+    session_token: Option<String>,
+    failed_attempts: u32,
+}
+
+impl Default for Pairing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pairing {
+    pub fn new() -> Self {
+        Self {
+            code: generate_code(),
+            nonce: generate_nonce(),
+            session_token: None,
+            failed_attempts: 0,
+        }
+    }
+
+    /// Nonce the current code's proof and token are bound to
+    pub fn nonce(&self) -> &str {
+        &self.nonce
+    }
+
+    pub fn session_token(&self) -> Option<&str> {
+        self.session_token.as_deref()
+    }
+
+    pub fn status(&self) -> PairingStatus {
+        PairingStatus {
+            code: self.code.clone(),
+            paired: self.session_token.is_some(),
+        }
+    }
+
+    /// Check a proof sent by the backend
+    ///
+    /// On success the session token is set and the code is replaced, so it can
+    /// only be used once. After too many wrong proofs the code is replaced too,
+    /// which keeps a backend from guessing it.
+    pub fn complete(&mut self, proof: &str) -> Result<(), String> {
+        let expected = pairing_proof(&self.nonce, &self.code);
+        if constant_time_eq(expected.as_bytes(), proof.as_bytes()) {
+            self.session_token = Some(session_token(&self.nonce, &self.code));
+            self.rotate_code();
+            return Ok(());
+        }
+
+        self.failed_attempts += 1;
+        if self.failed_attempts >= MAX_PAIRING_ATTEMPTS {
+            self.rotate_code();
+            return Err("Too many incorrect pairing codes. A new code is shown in the bootstrapper window.".to_string());
+        }
+        Err("Incorrect pairing code".to_string())
+    }
+
+    /// Drop the session token, e.g. when the backend no longer recognizes it
+    pub fn forget_session(&mut self) {
+        self.session_token = None;
+    }
+
+    /// Whether `token` is the token of the current session
+    pub fn authorize(&self, token: Option<&str>) -> bool {
+        match (self.session_token.as_deref(), token) {
+            (Some(expected), Some(token)) => constant_time_eq(expected.as_bytes(), token.as_bytes()),
+            _ => false,
+        }
+    }
+
+    fn rotate_code(&mut self) {
+        self.code = generate_code();
+        self.nonce = generate_nonce();
+        self.failed_attempts = 0;
+    }
+}
+
+/// Proof that the sender knows `code`; must match the backend's derivation
+pub fn pairing_proof(nonce: &str, code: &str) -> String {
+    derive("braindrive-pairing-proof", nonce, code)
+}
+
+/// Session token both sides derive once pairing succeeds
+pub fn session_token(nonce: &str, code: &str) -> String {
+    derive("braindrive-session-token", nonce, code)
+}
+
+fn derive(label: &str, nonce: &str, code: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(label.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(nonce.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(code.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+fn generate_code() -> String {
+    let code = rand::random::<u32>() % 10u32.pow(PAIRING_CODE_DIGITS);
+    format!("{:0width$}", code, width = PAIRING_CODE_DIGITS as usize)
+}
+
+fn generate_nonce() -> String {
+    let bytes: [u8; 16] = rand::random();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_is_six_digits() {
+        let pairing = Pairing::new();
+        let code = pairing.status().code;
+        assert_eq!(code.len(), 6);
+        assert!(code.chars().all(|c| c.is_ascii_digit()));
+        assert_eq!(pairing.nonce().len(), 32);
+    }
+
+    #[test]
+    fn test_correct_proof_pairs_and_rotates_code() {
+        let mut pairing = Pairing::new();
+        let code = pairing.status().code;
+        let nonce = pairing.nonce().to_string();
+
+        pairing.complete(&pairing_proof(&nonce, &code)).unwrap();
+
+        let token = session_token(&nonce, &code);
+        assert!(pairing.status().paired);
+        assert!(pairing.authorize(Some(&token)));
+        assert!(!pairing.authorize(Some("not-the-token")));
+        assert!(!pairing.authorize(None));
+        // One-time: the same proof doesn't work twice
+        assert_ne!(pairing.nonce(), nonce);
+        assert!(pairing.complete(&pairing_proof(&nonce, &code)).is_err());
+    }
+
+    #[test]
+    fn test_repeated_wrong_proofs_replace_code() {
+        let mut pairing = Pairing::new();
+        let nonce = pairing.nonce().to_string();
+
+        for _ in 0..MAX_PAIRING_ATTEMPTS {
+            assert!(pairing.complete("wrong").is_err());
+        }
+        assert_ne!(pairing.nonce(), nonce);
+        assert!(!pairing.status().paired);
+    }
+
+    #[test]
+    fn test_forget_session_revokes_token() {
+        let mut pairing = Pairing::new();
+        let code = pairing.status().code;
+        let nonce = pairing.nonce().to_string();
+        pairing.complete(&pairing_proof(&nonce, &code)).unwrap();
+
+        pairing.forget_session();
+        assert!(!pairing.authorize(Some(&session_token(&nonce, &code))));
+    }
+
+    #[test]
+    fn test_derivation_matches_backend() {
+        // Computed with the backend's Node implementation; the two must not drift apart
+        let nonce = "00112233445566778899aabbccddeeff";
+        assert_eq!(
+            pairing_proof(nonce, "123456"),
+            "78293c4e552e05c382c74d6520011071946067618989e5edcc6dd9a2af8f270e"
+        );
+        assert_eq!(
+            session_token(nonce, "123456"),
+            "9a2f4a859b4061f49d48c08537985b0c43a075fdfb35c291ab88d216cbbfd697"
+        );
+    }
+}
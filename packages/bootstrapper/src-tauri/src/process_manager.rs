@@ -170,6 +170,26 @@ pub fn kill_process(pid: u32) -> bool {
         .unwrap_or(false)
 }
 
+/// Kill a process and every process it spawned
+/// On Unix the process must lead its own process group (see `Command::process_group`)
+#[cfg(unix)]
+pub fn kill_process_tree(pid: u32) -> bool {
+    kill_process(pid)
+}
+
+#[cfg(windows)]
+pub fn kill_process_tree(pid: u32) -> bool {
+    use std::process::Command as StdCommand;
+
+    StdCommand::new("taskkill")
+        .args(["/PID", &pid.to_string(), "/T", "/F"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
 /// Kill any process listening on a port
 /// Retries up to 3 times to handle parent/child process hierarchies
 pub fn kill_process_on_port(port: u16) -> bool {
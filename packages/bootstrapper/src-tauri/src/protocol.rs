@@ -0,0 +1,428 @@
+//! Messages sent to the backend, and the queue they wait in while disconnected
+//!
+//! Kept apart from `websocket` because the headless CLI reports progress with
+//! the same messages and builds without the installer window; see the `gui`
+//! feature.
+
+use crate::error::DispatchError;
+use crate::WsSender;
+use futures_util::SinkExt;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tokio_tungstenite::tungstenite::Message;
+
+/// Version of the message protocol spoken with the backend; bump on breaking changes
+pub const PROTOCOL_VERSION: u32 = 1;
+
+pub(crate) fn default_frontend_port() -> u16 {
+    5173
+}
+pub(crate) fn default_backend_port() -> u16 {
+    8005
+}
+
+/// Outcome of a tool call, reported alongside `success` so the backend can tell
+/// a cancelled call from a failed one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+pub enum ToolStatus {
+    Ok,
+    Error,
+    Cancelled,
+    /// The request's type isn't a tool this bootstrapper version knows
+    UnsupportedTool,
+}
+
+/// Outgoing messages to the backend server
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum OutgoingMessage {
+    /// Announces the nonce the current pairing code is bound to, plus the session
+    /// token if we are already paired
+    #[serde(rename = "bootstrapper_connect")]
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    BootstrapperConnect {
+        protocol_version: u32,
+        bootstrapper_version: &'static str,
+        os: &'static str,
+        arch: &'static str,
+        /// Tool call types this bootstrapper handles
+        capabilities: &'static [&'static str],
+        pairing_nonce: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session_token: Option<String>,
+    },
+
+    #[serde(rename = "pair_result")]
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    PairResult {
+        success: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+        /// Nonce for the next attempt; changes when the code is replaced
+        pairing_nonce: String,
+    },
+
+    #[serde(rename = "tool_result")]
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    ToolResult {
+        id: String,
+        success: bool,
+        status: ToolStatus,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        data: Option<serde_json::Value>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+        /// Typed form of `error`; see `DispatchError` for the encoding
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error_details: Option<DispatchError>,
+    },
+
+    #[serde(rename = "progress")]
+    Progress {
+        id: String,
+        operation: String,
+        percent: Option<u8>,
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bytes_downloaded: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bytes_total: Option<u64>,
+    },
+}
+
+/// Send a message to the backend via the WebSocket
+///
+/// Tool results and progress are queued instead while the backend can't take
+/// them, and go out once the session is back; see `Outbound`.
+pub async fn send_message(sender: &Arc<Mutex<Outbound>>, message: OutgoingMessage) -> Result<(), String> {
+    sender.lock().await.send(message).await
+}
+
+/// Tool messages kept while disconnected; beyond this progress goes first,
+/// then the oldest results
+const OUTBOUND_QUEUE_CAPACITY: usize = 256;
+
+/// The socket to the backend, plus what is waiting to be sent on it
+///
+/// A tool call keeps running when the connection drops, so its progress and
+/// result would otherwise be lost. Until the next connection is accepted by the
+/// paired session (resumed with our token, or paired again), those messages are
+/// queued and then flushed in the order they were produced.
+#[derive(Default)]
+pub struct Outbound {
+    socket: Option<WsSender>,
+    /// Whether the backend on `socket` knows our session and takes tool messages
+    session_ready: bool,
+    queue: OutboundQueue,
+    /// Set when there is no backend at all and messages go to a local consumer
+    channel: Option<mpsc::UnboundedSender<OutgoingMessage>>,
+}
+
+impl Outbound {
+    /// Deliver every message to the returned receiver instead of a socket, for
+    /// running tools without a backend (the headless CLI)
+    pub fn channel() -> (Self, mpsc::UnboundedReceiver<OutgoingMessage>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let outbound = Self {
+            channel: Some(tx),
+            ..Self::default()
+        };
+        (outbound, rx)
+    }
+
+    pub(crate) async fn send(&mut self, message: OutgoingMessage) -> Result<(), String> {
+        if let Some(channel) = &self.channel {
+            return channel
+                .send(message)
+                .map_err(|_| "Message receiver is gone".to_string());
+        }
+
+        let queueable = OutboundQueue::accepts(&message);
+        if queueable && !self.session_ready {
+            self.queue.push(message);
+            return Ok(());
+        }
+
+        let Some(ws) = self.socket.as_mut() else {
+            return Err("WebSocket not connected".to_string());
+        };
+        let result = write_message(ws, &message).await;
+        if result.is_err() && queueable {
+            // The connection is going down; retry on the next one
+            self.queue.push(message);
+            return Ok(());
+        }
+        result
+    }
+
+    /// Send on the current connection only, never queued
+    ///
+    /// For answers meant for whoever is on this socket, e.g. rejecting a call
+    /// from a backend that isn't paired; a later session must not get them.
+    #[cfg(feature = "gui")]
+    pub(crate) async fn send_now(&mut self, message: OutgoingMessage) -> Result<(), String> {
+        let Some(ws) = self.socket.as_mut() else {
+            return Err("WebSocket not connected".to_string());
+        };
+        write_message(ws, &message).await
+    }
+
+    /// Use a freshly opened socket; tool messages stay queued until `resume`
+    #[cfg(feature = "gui")]
+    pub(crate) fn attach(&mut self, socket: WsSender) {
+        self.socket = Some(socket);
+        self.session_ready = false;
+    }
+
+    /// Forget the socket, e.g. after it dropped
+    #[cfg(feature = "gui")]
+    pub(crate) fn detach(&mut self) -> Option<WsSender> {
+        self.session_ready = false;
+        self.socket.take()
+    }
+
+    /// Send a heartbeat ping; pings belong to one connection and are never queued
+    #[cfg(feature = "gui")]
+    pub(crate) async fn ping(&mut self, payload: Vec<u8>) -> Result<(), String> {
+        let Some(ws) = self.socket.as_mut() else {
+            return Err("WebSocket not connected".to_string());
+        };
+        ws.send(Message::Ping(payload))
+            .await
+            .map_err(|e| format!("Failed to send ping: {}", e))
+    }
+
+    /// The backend accepted our session: send everything queued, oldest first
+    #[cfg(feature = "gui")]
+    pub(crate) async fn resume(&mut self) -> Result<(), String> {
+        let Some(ws) = self.socket.as_mut() else {
+            return Err("WebSocket not connected".to_string());
+        };
+        if !self.queue.is_empty() {
+            tracing::info!(queued = self.queue.len(), "Sending messages queued while disconnected");
+        }
+        while let Some(message) = self.queue.front() {
+            write_message(ws, message).await?;
+            self.queue.pop_front();
+        }
+        self.session_ready = true;
+        Ok(())
+    }
+
+    /// Drop queued messages for a session the backend no longer knows
+    #[cfg(feature = "gui")]
+    pub(crate) fn discard_queue(&mut self) {
+        if !self.queue.is_empty() {
+            tracing::info!(dropped = self.queue.len(), "Dropping messages queued for a forgotten session");
+        }
+        self.queue = OutboundQueue::default();
+        self.session_ready = false;
+    }
+}
+
+async fn write_message(ws: &mut WsSender, message: &OutgoingMessage) -> Result<(), String> {
+    let json = serde_json::to_string(message)
+        .map_err(|e| format!("Failed to serialize message: {}", e))?;
+    ws.send(Message::Text(json))
+        .await
+        .map_err(|e| format!("Failed to send message: {}", e))
+}
+
+/// Tool messages waiting for the backend, in the order they were produced
+///
+/// Only the latest progress of each call is worth sending late, so a new one
+/// replaces the previous, and a call's result replaces its progress. When full,
+/// progress is dropped before any result.
+#[derive(Default)]
+struct OutboundQueue {
+    messages: VecDeque<OutgoingMessage>,
+}
+
+impl OutboundQueue {
+    /// Messages belonging to a tool call rather than to one connection
+    fn accepts(message: &OutgoingMessage) -> bool {
+        matches!(message, OutgoingMessage::ToolResult { .. } | OutgoingMessage::Progress { .. })
+    }
+
+    fn push(&mut self, message: OutgoingMessage) {
+        let id = match &message {
+            OutgoingMessage::ToolResult { id, .. } | OutgoingMessage::Progress { id, .. } => id,
+            _ => return,
+        };
+        self.messages.retain(|queued| {
+            !matches!(queued, OutgoingMessage::Progress { id: queued_id, .. } if queued_id == id)
+        });
+        self.messages.push_back(message);
+
+        while self.messages.len() > OUTBOUND_QUEUE_CAPACITY {
+            let oldest_progress = self
+                .messages
+                .iter()
+                .position(|queued| matches!(queued, OutgoingMessage::Progress { .. }));
+            match oldest_progress {
+                Some(index) => {
+                    self.messages.remove(index);
+                }
+                None => {
+                    if let Some(OutgoingMessage::ToolResult { id, .. }) = self.messages.pop_front() {
+                        tracing::warn!(id = %id, "Outbound queue full of results, dropping the oldest");
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "gui")]
+    fn front(&self) -> Option<&OutgoingMessage> {
+        self.messages.front()
+    }
+
+    #[cfg(feature = "gui")]
+    fn pop_front(&mut self) -> Option<OutgoingMessage> {
+        self.messages.pop_front()
+    }
+
+    #[cfg(feature = "gui")]
+    fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    #[cfg(feature = "gui")]
+    fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(id: &str, percent: u8) -> OutgoingMessage {
+        OutgoingMessage::Progress {
+            id: id.to_string(),
+            operation: "download".to_string(),
+            percent: Some(percent),
+            message: format!("{}%", percent),
+            bytes_downloaded: None,
+            bytes_total: None,
+        }
+    }
+
+    fn result(id: &str) -> OutgoingMessage {
+        OutgoingMessage::ToolResult {
+            id: id.to_string(),
+            success: true,
+            status: ToolStatus::Ok,
+            data: Some(serde_json::json!({})),
+            error: None,
+            error_details: None,
+        }
+    }
+
+    fn queued(queue: &OutboundQueue) -> Vec<serde_json::Value> {
+        queue
+            .messages
+            .iter()
+            .map(|m| serde_json::to_value(m).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_outbound_queue_keeps_latest_progress_per_call() {
+        let mut queue = OutboundQueue::default();
+        queue.push(progress("call_1", 10));
+        queue.push(progress("call_2", 10));
+        queue.push(progress("call_1", 50));
+
+        let messages = queued(&queue);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["id"], "call_2");
+        assert_eq!(messages[1]["id"], "call_1");
+        assert_eq!(messages[1]["percent"], 50);
+    }
+
+    #[test]
+    fn test_outbound_queue_result_replaces_progress() {
+        let mut queue = OutboundQueue::default();
+        queue.push(progress("call_1", 10));
+        queue.push(result("call_2"));
+        queue.push(result("call_1"));
+        // Messages tied to one connection are never queued
+        queue.push(OutgoingMessage::PairResult {
+            success: true,
+            error: None,
+            pairing_nonce: String::new(),
+        });
+
+        let messages = queued(&queue);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["type"], "tool_result");
+        assert_eq!(messages[0]["id"], "call_2");
+        assert_eq!(messages[1]["type"], "tool_result");
+        assert_eq!(messages[1]["id"], "call_1");
+    }
+
+    #[test]
+    fn test_outbound_queue_drops_progress_before_results() {
+        let mut queue = OutboundQueue::default();
+        queue.push(result("first"));
+        for call in 1..OUTBOUND_QUEUE_CAPACITY {
+            queue.push(progress(&format!("call_{}", call), 10));
+        }
+        queue.push(result("last"));
+
+        let messages = queued(&queue);
+        assert_eq!(messages.len(), OUTBOUND_QUEUE_CAPACITY);
+        assert_eq!(messages[0]["id"], "first");
+        assert_eq!(messages[1]["id"], "call_2");
+        assert_eq!(messages[OUTBOUND_QUEUE_CAPACITY - 1]["id"], "last");
+
+        // With nothing but results left, the oldest result goes
+        let mut queue = OutboundQueue::default();
+        for call in 0..=OUTBOUND_QUEUE_CAPACITY {
+            queue.push(result(&format!("call_{}", call)));
+        }
+        let messages = queued(&queue);
+        assert_eq!(messages.len(), OUTBOUND_QUEUE_CAPACITY);
+        assert_eq!(messages[0]["id"], "call_1");
+    }
+
+    #[cfg(feature = "gui")]
+    #[tokio::test]
+    async fn test_rejection_reaches_unpaired_socket_without_queueing() {
+        use futures_util::StreamExt;
+        use tokio_tungstenite::tungstenite::protocol::Role;
+        use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = tokio::net::TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        let (sink, _) = WebSocketStream::from_raw_socket(MaybeTlsStream::Plain(client), Role::Client, None)
+            .await
+            .split();
+        let mut backend = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+
+        let mut outbound = Outbound::default();
+        outbound.attach(sink);
+        let rejection = OutgoingMessage::ToolResult {
+            id: "call_1".to_string(),
+            success: false,
+            status: ToolStatus::Error,
+            data: None,
+            error: Some("Not paired".to_string()),
+            error_details: Some(DispatchError::NotPaired),
+        };
+        outbound.send_now(rejection).await.unwrap();
+
+        let Some(Ok(Message::Text(text))) = backend.next().await else {
+            panic!("no reply on the socket");
+        };
+        let reply: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(reply["id"], "call_1");
+        assert!(outbound.queue.is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+//! Replay of tool calls the backend sends more than once
+//!
+//! When the socket drops before a result arrives, the backend sends the call
+//! again with the same `id`. Running it again could start a second Miniconda
+//! install on top of the first, so recent ids are remembered: a duplicate of a
+//! call still in flight attaches to it (its progress and result carry the same
+//! id), and a duplicate of a finished call gets the original result again.
+
+use crate::protocol::OutgoingMessage;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Finished results kept for replay; in-flight calls are never evicted
+const REPLAY_CACHE_CAPACITY: usize = 256;
+
+pub type SharedReplayCache = Arc<Mutex<ReplayCache>>;
+
+/// What to do with an incoming tool call
+#[derive(Debug)]
+pub enum Replay {
+    /// First time this id is seen: run the call
+    Start,
+    /// The call is already running; the caller will get its progress and result
+    Attach,
+    /// The call already finished: send this result again
+    Finished(OutgoingMessage),
+}
+
+enum Entry {
+    Running,
+    Finished(OutgoingMessage),
+}
+
+pub struct ReplayCache {
+    entries: HashMap<String, Entry>,
+    /// Ids of finished calls, oldest first
+    finished: VecDeque<String>,
+    capacity: usize,
+}
+
+impl Default for ReplayCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReplayCache {
+    pub fn new() -> Self {
+        Self::with_capacity(REPLAY_CACHE_CAPACITY)
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            finished: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Look up `id`, marking it as running if it is new
+    pub fn begin(&mut self, id: &str) -> Replay {
+        match self.entries.get(id) {
+            Some(Entry::Running) => Replay::Attach,
+            Some(Entry::Finished(result)) => Replay::Finished(result.clone()),
+            None => {
+                self.entries.insert(id.to_string(), Entry::Running);
+                Replay::Start
+            }
+        }
+    }
+
+    /// Remember the result of `id` so a duplicate can be answered without rerunning it
+    pub fn finish(&mut self, id: &str, result: OutgoingMessage) {
+        let previous = self.entries.insert(id.to_string(), Entry::Finished(result));
+        if matches!(previous, Some(Entry::Finished(_))) {
+            return;
+        }
+
+        self.finished.push_back(id.to_string());
+        while self.finished.len() > self.capacity {
+            if let Some(oldest) = self.finished.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ToolStatus;
+
+    fn result(id: &str) -> OutgoingMessage {
+        OutgoingMessage::ToolResult {
+            id: id.to_string(),
+            success: true,
+            status: ToolStatus::Ok,
+            data: Some(serde_json::json!({ "id": id })),
+            error: None,
+            error_details: None,
+        }
+    }
+
+    #[test]
+    fn test_duplicate_of_running_call_attaches() {
+        let mut cache = ReplayCache::new();
+        assert!(matches!(cache.begin("call_1"), Replay::Start));
+        assert!(matches!(cache.begin("call_1"), Replay::Attach));
+        assert!(matches!(cache.begin("call_2"), Replay::Start));
+    }
+
+    #[test]
+    fn test_duplicate_of_finished_call_replays_result() {
+        let mut cache = ReplayCache::new();
+        cache.begin("call_1");
+        cache.finish("call_1", result("call_1"));
+
+        let Replay::Finished(replayed) = cache.begin("call_1") else {
+            panic!("expected the finished result");
+        };
+        let json = serde_json::to_value(&replayed).unwrap();
+        assert_eq!(json["id"], "call_1");
+        assert_eq!(json["data"]["id"], "call_1");
+    }
+
+    #[test]
+    fn test_oldest_finished_results_are_evicted() {
+        let mut cache = ReplayCache::with_capacity(2);
+        cache.begin("running");
+        for id in ["call_1", "call_2", "call_3"] {
+            cache.begin(id);
+            cache.finish(id, result(id));
+        }
+
+        assert!(matches!(cache.begin("call_1"), Replay::Start));
+        assert!(matches!(cache.begin("call_3"), Replay::Finished(_)));
+        // Eviction only counts finished calls
+        assert!(matches!(cache.begin("running"), Replay::Attach));
+    }
+}
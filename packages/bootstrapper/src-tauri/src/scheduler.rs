@@ -0,0 +1,255 @@
+//! Concurrent execution of tool calls
+//!
+//! Each tool call runs on its own task so a 10-minute model pull doesn't block a
+//! quick `check_port`. Calls that mutate the same resource (the conda install,
+//! the repo checkout, ...) are chained: a call starts only after every earlier
+//! call on any of its resources has finished, so calls sharing a resource run
+//! one at a time in the order the backend sent them. A global semaphore bounds
+//! how many calls run at once.
+//!
+//! Every call gets a cancellation token, registered under its request id until
+//! the call finishes, so a `cancel` message can abort it whether it is running
+//! or still waiting for its turn.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{watch, Semaphore};
+use tokio_util::sync::CancellationToken;
+
+/// Maximum number of tool calls running at the same time
+pub const MAX_CONCURRENT_TOOL_CALLS: usize = 8;
+
+/// Something on the user's machine that tool calls mutate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Resource {
+    /// The isolated Miniconda install and its environments
+    Conda,
+    /// The BrainDrive checkout (source, node_modules, .env)
+    Repo,
+    /// The running BrainDrive backend/frontend processes
+    Services,
+    /// The Ollama service and its model store
+    Ollama,
+    /// The system-wide Git installation
+    SystemGit,
+}
+
+/// Cancellation tokens of calls that have not finished yet, keyed by request id.
+/// The sequence number tells apart two calls that reused the same id.
+type InFlightCalls = Arc<Mutex<HashMap<String, (u64, CancellationToken)>>>;
+
+/// Schedules tool calls onto tasks, serializing calls that share a resource
+pub struct ToolScheduler {
+    permits: Arc<Semaphore>,
+    /// Completion signal of the most recently scheduled call per resource
+    tails: HashMap<Resource, watch::Receiver<bool>>,
+    in_flight: InFlightCalls,
+    next_seq: u64,
+}
+
+impl ToolScheduler {
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_concurrent.max(1))),
+            tails: HashMap::new(),
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            next_seq: 0,
+        }
+    }
+
+    /// Spawn `job` once every earlier job on one of `resources` has finished
+    ///
+    /// Must be called in the order calls arrive; that order is what callers on
+    /// the same resource observe. `job` receives the call's cancellation token.
+    /// If the call is cancelled while still queued, `job` runs immediately with
+    /// an already-cancelled token so it can reply, but later calls on the same
+    /// resources keep waiting for the calls queued ahead of it.
+    pub fn spawn<F, Fut>(&mut self, id: String, resources: &[Resource], job: F)
+    where
+        F: FnOnce(CancellationToken) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let predecessors: Vec<watch::Receiver<bool>> = resources
+            .iter()
+            .filter_map(|resource| self.tails.get(resource).cloned())
+            .collect();
+
+        let (done_tx, done_rx) = watch::channel(false);
+        for resource in resources {
+            self.tails.insert(*resource, done_rx.clone());
+        }
+
+        let token = CancellationToken::new();
+        self.next_seq += 1;
+        let seq = self.next_seq;
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            in_flight.insert(id.clone(), (seq, token.clone()));
+        }
+
+        let permits = self.permits.clone();
+        let in_flight = self.in_flight.clone();
+        tokio::spawn(async move {
+            // A predecessor that panicked drops its sender, which also counts as done
+            let wait_for_predecessors = async move {
+                for mut predecessor in predecessors {
+                    let _ = predecessor.wait_for(|done| *done).await;
+                }
+            };
+            tokio::pin!(wait_for_predecessors);
+
+            let cancelled_while_queued = tokio::select! {
+                _ = &mut wait_for_predecessors => false,
+                _ = token.cancelled() => true,
+            };
+
+            if cancelled_while_queued {
+                job(token).await;
+                wait_for_predecessors.await;
+            } else {
+                let _permit = permits.acquire_owned().await;
+                job(token).await;
+            }
+
+            if let Ok(mut in_flight) = in_flight.lock() {
+                if in_flight.get(&id).map(|(s, _)| *s) == Some(seq) {
+                    in_flight.remove(&id);
+                }
+            }
+            let _ = done_tx.send(true);
+        });
+    }
+
+    /// Cancel the call with request id `id`; returns false if no such call is in flight
+    pub fn cancel(&self, id: &str) -> bool {
+        let token = match self.in_flight.lock() {
+            Ok(in_flight) => in_flight.get(id).map(|(_, token)| token.clone()),
+            Err(_) => None,
+        };
+        match token {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Default for ToolScheduler {
+    fn default() -> Self {
+        Self::new(MAX_CONCURRENT_TOOL_CALLS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::{mpsc, oneshot};
+    use tokio::time::{timeout, Duration};
+
+    #[tokio::test]
+    async fn test_same_resource_runs_in_order() {
+        let mut scheduler = ToolScheduler::default();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        for i in 0..5u32 {
+            let tx = tx.clone();
+            scheduler.spawn(format!("call_{}", i), &[Resource::Conda], move |_| async move {
+                // Later jobs finish faster, so only chaining keeps them ordered
+                tokio::time::sleep(Duration::from_millis(u64::from(5 - i) * 5)).await;
+                tx.send(i).unwrap();
+            });
+        }
+        drop(tx);
+
+        let mut order = Vec::new();
+        while let Some(i) = rx.recv().await {
+            order.push(i);
+        }
+        assert_eq!(order, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_independent_resources_run_concurrently() {
+        let mut scheduler = ToolScheduler::default();
+        let (release_tx, release_rx) = oneshot::channel::<()>();
+        let (done_tx, done_rx) = oneshot::channel();
+
+        // Long-running job holding the Ollama resource
+        scheduler.spawn("pull".to_string(), &[Resource::Ollama], move |_| async move {
+            let _ = release_rx.await;
+        });
+        // Quick job with no resources must not wait for it
+        scheduler.spawn("probe".to_string(), &[], move |_| async move {
+            let _ = done_tx.send(());
+        });
+
+        assert!(timeout(Duration::from_secs(1), done_rx).await.is_ok());
+        let _ = release_tx.send(());
+    }
+
+    #[tokio::test]
+    async fn test_multi_resource_job_waits_for_each_resource() {
+        let mut scheduler = ToolScheduler::default();
+        let (release_tx, release_rx) = oneshot::channel::<()>();
+        let (done_tx, mut done_rx) = oneshot::channel();
+
+        scheduler.spawn("clone".to_string(), &[Resource::Repo], move |_| async move {
+            let _ = release_rx.await;
+        });
+        scheduler.spawn("deps".to_string(), &[Resource::Conda, Resource::Repo], move |_| async move {
+            let _ = done_tx.send(());
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(done_rx.try_recv().is_err());
+
+        let _ = release_tx.send(());
+        assert!(timeout(Duration::from_secs(1), done_rx).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_running_call() {
+        let mut scheduler = ToolScheduler::default();
+        let (done_tx, done_rx) = oneshot::channel();
+
+        scheduler.spawn("pull".to_string(), &[Resource::Ollama], move |cancel| async move {
+            cancel.cancelled().await;
+            let _ = done_tx.send(());
+        });
+
+        assert!(scheduler.cancel("pull"));
+        assert!(timeout(Duration::from_secs(1), done_rx).await.is_ok());
+        assert!(!scheduler.cancel("unknown"));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_queued_call_replies_without_breaking_order() {
+        let mut scheduler = ToolScheduler::default();
+        let (release_tx, release_rx) = oneshot::channel::<()>();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let first_tx = tx.clone();
+        scheduler.spawn("first".to_string(), &[Resource::Conda], move |_| async move {
+            let _ = release_rx.await;
+            first_tx.send("first").unwrap();
+        });
+        let queued_tx = tx.clone();
+        scheduler.spawn("queued".to_string(), &[Resource::Conda], move |cancel| async move {
+            queued_tx.send(if cancel.is_cancelled() { "queued-cancelled" } else { "queued" }).unwrap();
+        });
+        let last_tx = tx.clone();
+        scheduler.spawn("last".to_string(), &[Resource::Conda], move |_| async move {
+            last_tx.send("last").unwrap();
+        });
+        drop(tx);
+
+        assert!(scheduler.cancel("queued"));
+        assert_eq!(rx.recv().await, Some("queued-cancelled"));
+
+        let _ = release_tx.send(());
+        assert_eq!(rx.recv().await, Some("first"));
+        assert_eq!(rx.recv().await, Some("last"));
+    }
+}
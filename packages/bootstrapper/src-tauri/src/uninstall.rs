@@ -0,0 +1,421 @@
+//! `uninstall_braindrive`: remove what the install created
+//!
+//! That is the checkout (`~/BrainDrive`, or `repo_path` when it was cloned
+//! elsewhere), the isolated Miniconda in `~/BrainDrive` (with the BrainDriveDev
+//! environment), the installer's scripts, downloads and logs
+//! under `~/.braindrive-installer`, the install state journal and the Ollama
+//! models the bootstrapper pulled. Git and Ollama themselves may have been
+//! installed system-wide and are left alone, as are models the user pulled.
+//!
+//! A dry run lists the same removals with their sizes and changes nothing.
+//! Otherwise the BrainDrive services are stopped first and every removal is
+//! attempted even if one fails. The log file of the running installer is
+//! still open, so it is kept and reported under `kept`.
+
+use crate::dispatcher;
+use crate::error::{ComponentFailure, DispatchError};
+use crate::install_state;
+use crate::logging;
+use crate::process_manager::ProcessState;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+/// Checkout and Miniconda location, relative to the home directory
+const DEFAULT_REPO_DIR: &str = "BrainDrive";
+
+/// Subdirectories of `~/.braindrive-installer` the bootstrapper writes, with their components
+const INSTALLER_DIRS: &[(&str, &str)] = &[
+    ("scripts", "installer_scripts"),
+    ("downloads", "installer_downloads"),
+    ("logs", "installer_logs"),
+];
+
+/// Paths inside the checkout kept with `keep_user_data`: settings, the database
+/// and uploaded files
+const USER_DATA_PATHS: &[&str] = &["backend/.env", "backend/braindrive.db", "backend/uploads"];
+
+#[derive(Debug, Clone, Default)]
+pub struct UninstallOptions {
+    /// List what would be removed without removing anything
+    pub dry_run: bool,
+    /// Leave BrainDrive's settings, database and uploads in the checkout
+    pub keep_user_data: bool,
+    /// Leave the Ollama models the bootstrapper pulled
+    pub keep_models: bool,
+    /// Checkout to remove, for one cloned outside `~/BrainDrive`
+    pub repo_path: Option<String>,
+}
+
+/// One thing to remove
+#[derive(Debug, Serialize)]
+struct Removal {
+    component: &'static str,
+    /// Path, or model name for `ollama_model`
+    target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note: Option<&'static str>,
+}
+
+/// Remove BrainDrive, or with `dry_run` list what would be removed
+pub async fn uninstall_braindrive(
+    options: UninstallOptions,
+    process_state: &ProcessState,
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
+    let home = dirs::home_dir().ok_or("Could not determine home directory")?;
+    let repo = match options.repo_path {
+        Some(path) => checkout_to_remove(path)?,
+        None => home.join(DEFAULT_REPO_DIR),
+    };
+    let kept: Vec<PathBuf> = if options.keep_user_data {
+        USER_DATA_PATHS
+            .iter()
+            .map(|path| repo.join(path))
+            .filter(|path| path.exists())
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    // Open for as long as this process runs; Windows can't delete it
+    let active_log: Vec<PathBuf> = logging::active_log_path()
+        .filter(|path| path.exists())
+        .into_iter()
+        .collect();
+
+    let models = if options.keep_models {
+        Vec::new()
+    } else {
+        install_state::load().pulled_models.into_iter().collect()
+    };
+    let removals = plan_removals(&home, &repo, &kept, &active_log, &models).await;
+    let total_bytes: u64 = removals.iter().filter_map(|r| r.size_bytes).sum();
+    let kept_paths: Vec<String> = kept
+        .iter()
+        .chain(&active_log)
+        .map(|path| path.display().to_string())
+        .collect();
+
+    if options.dry_run {
+        return Ok(json!({
+            "success": true,
+            "dry_run": true,
+            "message": format!("Would remove {} items ({})", removals.len(), format_size(total_bytes)),
+            "removals": removals,
+            "total_bytes": total_bytes,
+            "kept": kept_paths,
+        }));
+    }
+
+    // Running services hold files in the checkout and the environment open
+    dispatcher::stop_braindrive(process_state).await?;
+
+    let mut removed = Vec::new();
+    let mut failed = Vec::new();
+    for removal in &removals {
+        if cancel.is_cancelled() {
+            return Err(DispatchError::Cancelled);
+        }
+        let label = format!("{} {}", removal.component, removal.target);
+        let result = match removal.component {
+            "ollama_model" => remove_model(&removal.target, cancel).await,
+            "braindrive_repo" => remove_path(PathBuf::from(&removal.target), kept.clone()).await,
+            "installer_logs" => remove_path(PathBuf::from(&removal.target), active_log.clone()).await,
+            _ => remove_path(PathBuf::from(&removal.target), Vec::new()).await,
+        };
+        match result {
+            Ok(()) => {
+                tracing::info!(component = removal.component, target = %removal.target, "Removed");
+                removed.push(label);
+            }
+            Err(DispatchError::Cancelled) => return Err(DispatchError::Cancelled),
+            Err(e) => {
+                tracing::warn!(component = removal.component, target = %removal.target, error = %e, "Failed to remove");
+                failed.push(ComponentFailure::new(&label, e));
+            }
+        }
+    }
+
+    // Models that weren't removed stay recorded, so a later uninstall finds them
+    let remaining_models = if options.keep_models {
+        install_state::load().pulled_models
+    } else {
+        failed
+            .iter()
+            .filter_map(|failure| failure.component.strip_prefix("ollama_model "))
+            .map(str::to_string)
+            .collect()
+    };
+    install_state::reset(remaining_models);
+
+    if !failed.is_empty() {
+        return Err(DispatchError::partial_failure(
+            format!("Removed {} of {} items", removed.len(), removals.len()),
+            removed,
+            failed,
+        ));
+    }
+
+    Ok(json!({
+        "success": true,
+        "dry_run": false,
+        "message": format!("BrainDrive uninstalled, freed {}", format_size(total_bytes)),
+        "removed": removed,
+        "total_bytes": total_bytes,
+        "kept": kept_paths,
+    }))
+}
+
+/// Everything there is to remove, with sizes
+async fn plan_removals(
+    home: &Path,
+    repo: &Path,
+    kept: &[PathBuf],
+    active_log: &[PathBuf],
+    models: &[String],
+) -> Vec<Removal> {
+    let miniconda = dispatcher::get_isolated_miniconda_dir();
+
+    let mut paths: Vec<(&'static str, PathBuf, Option<&'static str>)> = Vec::new();
+    if let Some(miniconda) = miniconda.clone().filter(|dir| dir.exists()) {
+        paths.push(("conda", miniconda, Some("Includes the BrainDriveDev environment")));
+    }
+    if repo.exists() {
+        let note = (!kept.is_empty()).then_some("User data in the checkout is kept");
+        paths.push(("braindrive_repo", repo.to_path_buf(), note));
+    }
+    let installer_dir = home.join(".braindrive-installer");
+    for (dir, component) in INSTALLER_DIRS {
+        let path = installer_dir.join(dir);
+        if path.exists() {
+            let note = (*component == "installer_logs" && !active_log.is_empty())
+                .then_some("The running installer's log file is kept");
+            paths.push((*component, path, note));
+        }
+    }
+
+    let mut removals = Vec::new();
+    for (component, path, note) in paths {
+        // The checkout's size leaves out Miniconda, listed separately, and the kept files
+        let mut skip = Vec::new();
+        if component == "braindrive_repo" {
+            skip.extend(kept.iter().cloned());
+            skip.extend(miniconda.clone());
+        }
+        if component == "installer_logs" {
+            skip.extend(active_log.iter().cloned());
+        }
+        let size = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || dir_size(&path, &skip)
+        })
+        .await
+        .ok();
+        removals.push(Removal {
+            component,
+            target: path.display().to_string(),
+            size_bytes: size,
+            note,
+        });
+    }
+
+    let sizes = model_sizes().await;
+    for model in models {
+        removals.push(Removal {
+            component: "ollama_model",
+            target: model.clone(),
+            size_bytes: sizes.iter().find(|(name, _)| name_matches(name, model)).map(|(_, size)| *size),
+            note: None,
+        });
+    }
+    removals
+}
+
+/// A `repo_path` checkout, refused unless it is a git checkout inside the home directory
+fn checkout_to_remove(path: String) -> Result<PathBuf, DispatchError> {
+    let repo = dispatcher::resolve_repo_path_or_default(Some(path))?;
+    let home = dirs::home_dir().ok_or("Could not determine home directory")?;
+    if repo == home || !repo.join(".git").exists() {
+        return Err(DispatchError::path_rejected(
+            Some(repo.display().to_string()),
+            "Not a BrainDrive checkout, so it won't be removed",
+        ));
+    }
+    Ok(repo)
+}
+
+/// Bytes taken by `path`, without following symlinks or counting `skip`
+pub(crate) fn dir_size(path: &Path, skip: &[PathBuf]) -> u64 {
+    if skip.iter().any(|skipped| skipped == path) {
+        return 0;
+    }
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|entry| dir_size(&entry.path(), skip)).sum())
+        .unwrap_or(0)
+}
+
+/// Delete `path`, except `keep` and the directories leading to it
+async fn remove_path(path: PathBuf, keep: Vec<PathBuf>) -> Result<(), DispatchError> {
+    tokio::task::spawn_blocking(move || remove_except(&path, &keep))
+        .await
+        .map_err(|e| DispatchError::internal(e.to_string()))?
+        .map_err(DispatchError::internal)
+}
+
+fn remove_except(path: &Path, keep: &[PathBuf]) -> Result<(), String> {
+    if keep.iter().any(|kept| kept == path) {
+        return Ok(());
+    }
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    if !metadata.is_dir() {
+        return std::fs::remove_file(path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e));
+    }
+    if !keep.iter().any(|kept| kept.starts_with(path)) {
+        return std::fs::remove_dir_all(path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e));
+    }
+
+    let entries = std::fs::read_dir(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    for entry in entries.flatten() {
+        remove_except(&entry.path(), keep)?;
+    }
+    Ok(())
+}
+
+/// Delete a model through Ollama, which has to be running for it
+async fn remove_model(model: &str, cancel: &CancellationToken) -> Result<(), DispatchError> {
+    let ollama = dispatcher::find_ollama_binary()
+        .ok_or_else(|| DispatchError::not_installed("ollama", "Ollama is not installed, so its models can't be removed"))?;
+    let mut command = Command::new(ollama);
+    command.arg("rm").arg(model);
+    let output = dispatcher::run_command(command, cancel).await?;
+    dispatcher::check_output("ollama rm", output).map(|_| ())
+}
+
+/// Sizes from `ollama list`; empty when Ollama isn't available
+async fn model_sizes() -> Vec<(String, u64)> {
+    let Some(ollama) = dispatcher::find_ollama_binary() else {
+        return Vec::new();
+    };
+    let mut command = Command::new(ollama);
+    command.arg("list");
+    match dispatcher::run_command(command, &CancellationToken::new()).await {
+        Ok(output) if output.success => parse_model_list(&output.stdout),
+        _ => Vec::new(),
+    }
+}
+
+/// Parse `NAME  ID  SIZE  MODIFIED` rows, e.g. `qwen2.5:1.5b  65ec06548149  986 MB  2 days ago`
+fn parse_model_list(output: &str) -> Vec<(String, u64)> {
+    output
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            let name = columns.next()?;
+            let _id = columns.next()?;
+            let size = dispatcher::parse_size_to_bytes(columns.next()?, columns.next()?)?;
+            Some((name.to_string(), size))
+        })
+        .collect()
+}
+
+/// `ollama list` shows untagged models with `:latest`
+fn name_matches(listed: &str, model: &str) -> bool {
+    listed == model || (!model.contains(':') && listed == format!("{}:latest", model))
+}
+
+pub(crate) fn format_size(bytes: u64) -> String {
+    const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+    const MB: f64 = 1024.0 * 1024.0;
+    let bytes = bytes as f64;
+    if bytes >= GB {
+        format!("{:.1} GB", bytes / GB)
+    } else {
+        format!("{:.0} MB", bytes / MB)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("braindrive-uninstall-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_removal_keeps_user_data() {
+        let repo = scratch_dir("keep");
+        std::fs::create_dir_all(repo.join("backend/uploads")).unwrap();
+        std::fs::create_dir_all(repo.join("frontend/node_modules")).unwrap();
+        std::fs::write(repo.join("backend/.env"), "SECRET=1").unwrap();
+        std::fs::write(repo.join("backend/main.py"), "app = None").unwrap();
+        std::fs::write(repo.join("backend/uploads/photo.png"), [0u8; 16]).unwrap();
+        std::fs::write(repo.join("README.md"), "# BrainDrive").unwrap();
+
+        let keep = vec![repo.join("backend/.env"), repo.join("backend/uploads")];
+        assert_eq!(dir_size(&repo, &keep), "app = None".len() as u64 + "# BrainDrive".len() as u64);
+
+        remove_except(&repo, &keep).unwrap();
+        assert!(repo.join("backend/.env").exists());
+        assert!(repo.join("backend/uploads/photo.png").exists());
+        assert!(!repo.join("backend/main.py").exists());
+        assert!(!repo.join("frontend").exists());
+        assert!(!repo.join("README.md").exists());
+
+        remove_except(&repo, &[]).unwrap();
+        assert!(!repo.exists());
+    }
+
+    #[test]
+    fn test_log_removal_keeps_the_open_log() {
+        let logs = scratch_dir("logs");
+        std::fs::write(logs.join("installer.log.2026-01-01"), "old").unwrap();
+        std::fs::write(logs.join("installer.log.2026-01-02"), "today").unwrap();
+
+        let active = vec![logs.join("installer.log.2026-01-02")];
+        assert_eq!(dir_size(&logs, &active), "old".len() as u64);
+        remove_except(&logs, &active).unwrap();
+        assert!(!logs.join("installer.log.2026-01-01").exists());
+        assert!(active[0].exists());
+
+        let _ = std::fs::remove_dir_all(&logs);
+    }
+
+    #[test]
+    fn test_repo_path_must_be_a_checkout() {
+        let home = dirs::home_dir().unwrap();
+        let result = checkout_to_remove(home.display().to_string());
+        assert!(matches!(result, Err(DispatchError::PathRejected { .. })));
+    }
+
+    #[test]
+    fn test_model_sizes_are_parsed_from_ollama_list() {
+        let output = "NAME            ID              SIZE      MODIFIED\n\
+                      qwen2.5:1.5b    65ec06548149    986 MB    2 days ago\n\
+                      llama3.2:latest a80c4f17acd5    2.0 GB    3 weeks ago\n";
+        let sizes = parse_model_list(output);
+        assert_eq!(sizes.len(), 2);
+        assert_eq!(sizes[0], ("qwen2.5:1.5b".to_string(), 986 * 1024 * 1024));
+        assert!(name_matches(&sizes[1].0, "llama3.2"));
+        assert!(!name_matches(&sizes[1].0, "llama3.2:1b"));
+    }
+}
@@ -0,0 +1,362 @@
+//! `update_braindrive`: move an existing checkout to newer code
+//!
+//! `clone_repo` leaves an existing checkout alone, so updating is its own
+//! step. The new commit is fetched first and nothing is stopped when there is
+//! nothing new. Otherwise the services are stopped, the branch is
+//! fast-forwarded (or HEAD detached at the requested tag), only the dependencies
+//! whose manifests changed are reinstalled and BrainDrive is started again.
+//! If any of that fails the checkout goes back to the branch and commit it was
+//! on, the same dependencies are reinstalled to match it and BrainDrive is
+//! restarted, so a bad update leaves the previous version running.
+
+use crate::dispatcher::{self, CommandOutput};
+use crate::error::{short_commit, DispatchError};
+use crate::git_remote;
+use crate::process_manager::ProcessState;
+use crate::protocol::{send_message, Outbound, OutgoingMessage};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+const UPDATE_OPERATION: &str = "update_braindrive";
+
+/// Environment the services run in
+const CONDA_ENV_NAME: &str = "BrainDriveDev";
+
+/// Manifests whose changes call for a reinstall, relative to the checkout
+const ENVIRONMENT_FILE: &str = "environment.yml";
+const BACKEND_REQUIREMENTS: &str = "backend/requirements.txt";
+const FRONTEND_MANIFESTS: &[&str] = &["frontend/package.json", "frontend/package-lock.json"];
+
+/// Which dependencies have to be reinstalled after moving the checkout
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+struct Reinstall {
+    environment: bool,
+    backend: bool,
+    frontend: bool,
+}
+
+impl Reinstall {
+    /// From the paths `git diff --name-only` reported between the two commits
+    fn from_changed(paths: &[&str]) -> Self {
+        Self {
+            environment: paths.contains(&ENVIRONMENT_FILE),
+            backend: paths.contains(&BACKEND_REQUIREMENTS),
+            frontend: paths.iter().any(|path| FRONTEND_MANIFESTS.contains(path)),
+        }
+    }
+}
+
+/// How [`Checkout::move_to`] puts HEAD on a commit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Move {
+    /// Fast-forward the branch checked out, or move HEAD if it is detached
+    Forward,
+    /// Detach HEAD at a requested tag, leaving the branch where it was
+    Detach,
+    /// Back to the commit the update started from, on the branch it was on
+    Restore,
+}
+
+/// Git commands against one checkout
+struct Checkout {
+    git: PathBuf,
+    repo: PathBuf,
+    /// Branch checked out, or `None` when HEAD is detached
+    branch: Option<String>,
+}
+
+impl Checkout {
+    async fn run(&self, args: &[&str], cancel: &CancellationToken) -> Result<CommandOutput, DispatchError> {
+        let mut command = Command::new(&self.git);
+        command.arg("-C").arg(&self.repo).args(args);
+        dispatcher::run_command(command, cancel).await
+    }
+
+    /// Run a command that has to succeed and return its trimmed stdout
+    async fn output(&self, what: &str, args: &[&str], cancel: &CancellationToken) -> Result<String, DispatchError> {
+        let output = dispatcher::check_output(what, self.run(args, cancel).await?)?;
+        Ok(output.stdout.trim().to_string())
+    }
+
+    /// Put HEAD on `commit` as `how` says
+    ///
+    /// Untracked files such as `.env` and `node_modules` are left alone.
+    async fn move_to(&self, commit: &str, how: Move, cancel: &CancellationToken) -> Result<(), DispatchError> {
+        match (how, &self.branch) {
+            (Move::Forward, Some(_)) => {
+                self.output("git merge", &["merge", "--ff-only", commit], cancel).await?;
+            }
+            (Move::Detach, _) => {
+                self.output("git checkout", &["checkout", "--detach", commit], cancel).await?;
+            }
+            (Move::Restore, Some(branch)) => {
+                // Forced because a failed reinstall may have rewritten tracked
+                // files such as package-lock.json
+                self.output("git checkout", &["checkout", "--force", branch], cancel).await?;
+                self.output("git reset", &["reset", "--hard", commit], cancel).await?;
+            }
+            (Move::Forward | Move::Restore, None) => {
+                self.output("git checkout", &["checkout", "--force", "--detach", commit], cancel).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Update the checkout to the latest commit of its branch, or to `tag`
+#[allow(clippy::too_many_arguments)]
+pub async fn update_braindrive(
+    tag: Option<String>,
+    repo_path: Option<String>,
+    frontend_port: u16,
+    backend_port: u16,
+    request_id: String,
+    sender: Arc<Mutex<Outbound>>,
+    process_state: &ProcessState,
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
+    let tag = tag.as_deref().map(git_remote::sanitize_git_ref).transpose()?;
+    let repo = dispatcher::resolve_repo_path_or_default(repo_path)?;
+    if !repo.join(".git").exists() {
+        return Err(DispatchError::not_installed(
+            "braindrive_repo",
+            "BrainDrive is not installed. Please install it first.",
+        ));
+    }
+    let git = dispatcher::find_braindrive_git()
+        .ok_or_else(|| DispatchError::not_installed("git", "Git is not installed. Please install Git first."))?;
+
+    let branch_output = Checkout { git: git.clone(), repo: repo.clone(), branch: None }
+        .run(&["symbolic-ref", "--quiet", "--short", "HEAD"], cancel)
+        .await?;
+    let branch = branch_output.success.then(|| branch_output.stdout.trim().to_string());
+    let checkout = Checkout { git, repo, branch };
+
+    let local_changes = checkout
+        .output("git status", &["status", "--porcelain", "--untracked-files=no"], cancel)
+        .await?;
+    if !local_changes.is_empty() {
+        return Err(DispatchError::needs_manual_action(
+            "The BrainDrive checkout has local changes that an update would overwrite",
+            Some(format!(
+                "Commit, stash or discard the changes in {} first:\n{}",
+                checkout.repo.display(),
+                local_changes
+            )),
+            None,
+        ));
+    }
+
+    progress(&sender, &request_id, 5, "Fetching updates...").await;
+    let previous = checkout.output("git rev-parse", &["rev-parse", "HEAD"], cancel).await?;
+    let refspec = match (&tag, &checkout.branch) {
+        (Some(tag), _) => format!("refs/tags/{tag}"),
+        (None, Some(branch)) => branch.clone(),
+        (None, None) => "HEAD".to_string(),
+    };
+    checkout.output("git fetch", &["fetch", "origin", &refspec], cancel).await?;
+    let target = checkout
+        .output("git rev-parse", &["rev-parse", "FETCH_HEAD^{commit}"], cancel)
+        .await?;
+
+    if target == previous {
+        progress(&sender, &request_id, 100, "BrainDrive is already up to date").await;
+        return Ok(json!({
+            "success": true,
+            "message": "BrainDrive is already up to date",
+            "already_up_to_date": true,
+            "commit": previous,
+            "tag": tag,
+        }));
+    }
+
+    let mut diff_args = vec![
+        "diff",
+        "--name-only",
+        previous.as_str(),
+        target.as_str(),
+        "--",
+        ENVIRONMENT_FILE,
+        BACKEND_REQUIREMENTS,
+    ];
+    diff_args.extend_from_slice(FRONTEND_MANIFESTS);
+    let diff = checkout.output("git diff", &diff_args, cancel).await?;
+    let changed: Vec<&str> = diff.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    let reinstall = Reinstall::from_changed(&changed);
+
+    progress(&sender, &request_id, 20, "Stopping BrainDrive...").await;
+    dispatcher::stop_braindrive(process_state).await?;
+
+    let forward = if tag.is_some() { Move::Detach } else { Move::Forward };
+    let applied = apply(
+        &checkout,
+        &target,
+        forward,
+        reinstall,
+        frontend_port,
+        backend_port,
+        &sender,
+        &request_id,
+        process_state,
+        cancel,
+    )
+    .await;
+
+    let start_result = match applied {
+        Ok(start_result) => start_result,
+        Err(cause) => {
+            tracing::warn!(commit = %target, error = %cause, "Update failed, rolling back");
+            progress(&sender, &request_id, 90, "Update failed, restoring the previous version...").await;
+            // The update's token may be what failed it; the rollback has to finish regardless
+            let rollback_cancel = CancellationToken::new();
+            let _ = dispatcher::stop_braindrive(process_state).await;
+            let restored = apply(
+                &checkout,
+                &previous,
+                Move::Restore,
+                reinstall,
+                frontend_port,
+                backend_port,
+                &sender,
+                &request_id,
+                process_state,
+                &rollback_cancel,
+            )
+            .await;
+            return Err(match (restored, cause) {
+                (Err(rollback_error), cause) => DispatchError::needs_manual_action(
+                    format!(
+                        "Update failed ({}) and restoring the previous version also failed ({})",
+                        cause, rollback_error
+                    ),
+                    Some("Run repair_installation to fix the installation".to_string()),
+                    None,
+                ),
+                (Ok(_), DispatchError::Cancelled) => DispatchError::Cancelled,
+                (Ok(_), cause) => DispatchError::rolled_back(&previous, &target, cause),
+            });
+        }
+    };
+
+    progress(&sender, &request_id, 100, "BrainDrive updated").await;
+    Ok(json!({
+        "success": true,
+        "message": format!(
+            "BrainDrive updated from {} to {}",
+            short_commit(&previous),
+            short_commit(&target)
+        ),
+        "already_up_to_date": false,
+        "previous_commit": previous,
+        "commit": target,
+        "tag": tag,
+        "changed_files": changed,
+        "reinstalled": reinstall,
+        "start_result": start_result,
+    }))
+}
+
+/// Move the checkout to `commit`, reinstall what changed and start BrainDrive
+#[allow(clippy::too_many_arguments)]
+async fn apply(
+    checkout: &Checkout,
+    commit: &str,
+    how: Move,
+    reinstall: Reinstall,
+    frontend_port: u16,
+    backend_port: u16,
+    sender: &Arc<Mutex<Outbound>>,
+    request_id: &str,
+    process_state: &ProcessState,
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
+    progress(sender, request_id, 30, &format!("Checking out {}...", short_commit(commit))).await;
+    checkout.move_to(commit, how, cancel).await?;
+
+    let repo = Some(checkout.repo.to_string_lossy().to_string());
+    if reinstall.environment {
+        progress(sender, request_id, 40, "Updating the conda environment...").await;
+        dispatcher::install_conda_env(CONDA_ENV_NAME, repo.clone(), None, cancel).await?;
+    }
+    if reinstall.backend {
+        progress(sender, request_id, 55, "Reinstalling backend dependencies...").await;
+        dispatcher::install_backend_deps(None, repo.clone(), cancel).await?;
+    }
+    if reinstall.frontend {
+        progress(sender, request_id, 70, "Reinstalling frontend dependencies...").await;
+        dispatcher::install_frontend_deps(None, repo, cancel).await?;
+    }
+
+    progress(sender, request_id, 85, "Starting BrainDrive...").await;
+    dispatcher::start_braindrive(frontend_port, backend_port, process_state).await
+}
+
+async fn progress(sender: &Arc<Mutex<Outbound>>, request_id: &str, percent: u8, message: &str) {
+    let _ = send_message(sender, OutgoingMessage::Progress {
+        id: request_id.to_string(),
+        operation: UPDATE_OPERATION.to_string(),
+        percent: Some(percent),
+        message: message.to_string(),
+        bytes_downloaded: None,
+        bytes_total: None,
+    }).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_changed_manifests_are_reinstalled() {
+        assert_eq!(Reinstall::from_changed(&[]), Reinstall::default());
+        assert_eq!(
+            Reinstall::from_changed(&["backend/requirements.txt", "frontend/package-lock.json"]),
+            Reinstall { environment: false, backend: true, frontend: true }
+        );
+        assert_eq!(
+            Reinstall::from_changed(&["environment.yml"]),
+            Reinstall { environment: true, backend: false, frontend: false }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tag_detaches_and_rollback_restores_branch() {
+        let repo = std::env::temp_dir().join(format!("braindrive-update-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&repo);
+        std::fs::create_dir_all(&repo).unwrap();
+        let cancel = CancellationToken::new();
+        let mut checkout = Checkout { git: PathBuf::from("git"), repo: repo.clone(), branch: None };
+        let identity = ["-c", "user.name=t", "-c", "user.email=t@example.com"];
+        checkout.output("git init", &["init", "-q", "-b", "main"], &cancel).await.unwrap();
+        for version in ["1", "2"] {
+            std::fs::write(repo.join("VERSION"), version).unwrap();
+            checkout.output("git add", &["add", "VERSION"], &cancel).await.unwrap();
+            let commit = [&identity[..], &["commit", "-q", "-m", version]].concat();
+            checkout.output("git commit", &commit, &cancel).await.unwrap();
+        }
+        checkout.output("git tag", &["tag", "v2"], &cancel).await.unwrap();
+        checkout.output("git reset", &["reset", "-q", "--hard", "HEAD~1"], &cancel).await.unwrap();
+        let previous = checkout.output("git rev-parse", &["rev-parse", "HEAD"], &cancel).await.unwrap();
+        let tagged = checkout.output("git rev-parse", &["rev-parse", "v2"], &cancel).await.unwrap();
+        checkout.branch = Some("main".to_string());
+
+        checkout.move_to(&tagged, Move::Detach, &cancel).await.unwrap();
+        assert!(!checkout.run(&["symbolic-ref", "-q", "HEAD"], &cancel).await.unwrap().success);
+        assert_eq!(checkout.output("git rev-parse", &["rev-parse", "HEAD"], &cancel).await.unwrap(), tagged);
+        assert_eq!(checkout.output("git rev-parse", &["rev-parse", "main"], &cancel).await.unwrap(), previous);
+
+        std::fs::write(repo.join("VERSION"), "edited by a failed reinstall").unwrap();
+        checkout.move_to(&previous, Move::Restore, &cancel).await.unwrap();
+        let branch = checkout.output("git symbolic-ref", &["symbolic-ref", "--short", "HEAD"], &cancel).await.unwrap();
+        assert_eq!(branch, "main");
+        assert_eq!(checkout.output("git rev-parse", &["rev-parse", "HEAD"], &cancel).await.unwrap(), previous);
+        assert_eq!(std::fs::read_to_string(repo.join("VERSION")).unwrap(), "1");
+
+        let _ = std::fs::remove_dir_all(&repo);
+    }
+}
@@ -0,0 +1,813 @@
+//! `verify_installation` and `repair_installation`
+//!
+//! An install can be left half done: a Miniconda directory without a working
+//! `conda`, an environment missing node, a checkout with a corrupt object
+//! store, no `.env`, a frontend without `node_modules`. Verification runs a
+//! fixed battery of checks and reports each one with the repair that should
+//! fix it; checks that can't run until an earlier one passes are skipped.
+//! Repair runs those actions, verifies again and keeps going while new fixes
+//! become possible (a reinstalled Miniconda then needs its environment).
+
+use crate::dispatcher;
+use crate::error::{ComponentFailure, DispatchError};
+use crate::install_state;
+use crate::process_manager::{is_port_in_use, ProcessState};
+use crate::protocol::{default_backend_port, default_frontend_port, Outbound};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+/// Checkout location, relative to the home directory
+const DEFAULT_REPO_DIR: &str = "BrainDrive";
+
+/// Environment the services run in
+const CONDA_ENV_NAME: &str = "BrainDriveDev";
+
+/// Verify, repair, verify again: a repair can only unblock checks so many levels deep
+const MAX_REPAIR_ROUNDS: usize = 3;
+
+/// Missing names listed in a message before it says "and N more"
+const MAX_LISTED: usize = 5;
+
+/// Paths per `git checkout` when restoring missing files
+const RESTORE_BATCH: usize = 100;
+
+/// Modules the backend's launch script imports first
+const BACKEND_ENTRY_MODULES: &[&str] = &["fastapi", "uvicorn"];
+
+/// Reports requirements that aren't installed and entry modules that don't
+/// import, as JSON; written to a file so no shell has to quote it
+const BACKEND_CHECK_SCRIPT: &str = r##"import importlib, importlib.metadata, json, re, sys
+missing, broken = [], []
+with open(sys.argv[1]) as requirements:
+    for line in requirements:
+        line = line.split("#", 1)[0].strip()
+        if not line or line.startswith("-"):
+            continue
+        name = re.split(r"[<>=!~;\[ @]", line, 1)[0]
+        try:
+            importlib.metadata.version(name)
+        except importlib.metadata.PackageNotFoundError:
+            missing.append(name)
+for module in sys.argv[2:]:
+    try:
+        importlib.import_module(module)
+    except Exception as e:
+        broken.append("%s: %s" % (module, e))
+print(json.dumps({"missing": missing, "broken": broken}))
+"##;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum CheckStatus {
+    Passed,
+    /// Works, but worth telling the user about; nothing to repair
+    Warning,
+    Failed,
+    /// Not checked because an earlier check failed
+    Skipped,
+}
+
+/// What `repair_installation` does about a failed check
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum RepairAction {
+    InstallConda,
+    /// Remove the incomplete Miniconda directory, then install again
+    ReinstallConda,
+    CreateCondaEnv { force_recreate: bool },
+    CloneRepo,
+    /// Check out the tracked files missing from the checkout again at the
+    /// current commit; local edits to other files are kept
+    RestoreFiles,
+    /// Fetch every object of the current commit again to mend a corrupt object
+    /// store; the checked out commit and the working tree stay as they are
+    RefetchRepo,
+    SetupEnvFile,
+    /// Copy these keys with their defaults from `.env-dev` into `.env`
+    AddEnvKeys { keys: Vec<String> },
+    InstallBackendDeps,
+    InstallFrontendDeps,
+}
+
+impl RepairAction {
+    fn name(&self) -> &'static str {
+        match self {
+            RepairAction::InstallConda => "install_conda",
+            RepairAction::ReinstallConda => "reinstall_conda",
+            RepairAction::CreateCondaEnv { .. } => "create_conda_env",
+            RepairAction::CloneRepo => "clone_repo",
+            RepairAction::RestoreFiles => "restore_files",
+            RepairAction::RefetchRepo => "refetch_repo",
+            RepairAction::SetupEnvFile => "setup_env_file",
+            RepairAction::AddEnvKeys { .. } => "add_env_keys",
+            RepairAction::InstallBackendDeps => "install_backend_deps",
+            RepairAction::InstallFrontendDeps => "install_frontend_deps",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Check {
+    id: &'static str,
+    status: CheckStatus,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    repair: Option<RepairAction>,
+}
+
+impl Check {
+    fn passed(id: &'static str, message: impl Into<String>) -> Self {
+        Self { id, status: CheckStatus::Passed, message: message.into(), repair: None }
+    }
+
+    fn warning(id: &'static str, message: impl Into<String>) -> Self {
+        Self { id, status: CheckStatus::Warning, message: message.into(), repair: None }
+    }
+
+    fn failed(id: &'static str, message: impl Into<String>, repair: RepairAction) -> Self {
+        Self { id, status: CheckStatus::Failed, message: message.into(), repair: Some(repair) }
+    }
+
+    fn skipped(id: &'static str, blocked_by: &str) -> Self {
+        Self {
+            id,
+            status: CheckStatus::Skipped,
+            message: format!("Not checked because the {} check failed", blocked_by),
+            repair: None,
+        }
+    }
+
+    fn ok(&self) -> bool {
+        matches!(self.status, CheckStatus::Passed | CheckStatus::Warning)
+    }
+}
+
+/// Where things are expected to be
+struct Layout {
+    miniconda: PathBuf,
+    env: PathBuf,
+    repo: PathBuf,
+}
+
+impl Layout {
+    fn new() -> Result<Self, DispatchError> {
+        let home = dirs::home_dir().ok_or("Could not determine home directory")?;
+        let miniconda = dispatcher::get_isolated_miniconda_dir().ok_or("Could not determine home directory")?;
+        Ok(Self {
+            env: miniconda.join("envs").join(CONDA_ENV_NAME),
+            miniconda,
+            repo: home.join(DEFAULT_REPO_DIR),
+        })
+    }
+}
+
+/// Check every part of the installation
+pub async fn verify_installation(process_state: &ProcessState, cancel: &CancellationToken) -> Result<Value, DispatchError> {
+    let checks = run_checks(&Layout::new()?, process_state, cancel).await?;
+    Ok(report(&checks))
+}
+
+/// Run the repairs `verify_installation` suggests, for all failed checks or only `only`
+///
+/// `sha256` is what the Miniconda installer must have if a conda repair
+/// downloads it and no digest is pinned for it. Succeeds with the final verification when every selected check passes.
+/// Otherwise fails with `partial_failure`, listing repairs that failed and
+/// checks that still fail under `failed`.
+pub async fn repair_installation(
+    only: Option<Vec<String>>,
+    sha256: Option<String>,
+    request_id: String,
+    sender: Arc<Mutex<Outbound>>,
+    process_state: &ProcessState,
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
+    let layout = Layout::new()?;
+    let selected = |check: &Check| only.as_ref().is_none_or(|only| only.iter().any(|id| id == check.id));
+
+    let mut attempted: HashSet<RepairAction> = HashSet::new();
+    let mut repaired = Vec::new();
+    let mut failed = Vec::new();
+    let mut checks = run_checks(&layout, process_state, cancel).await?;
+
+    for _ in 0..MAX_REPAIR_ROUNDS {
+        let actions: Vec<(&'static str, RepairAction)> = checks
+            .iter()
+            .filter(|check| selected(check))
+            .filter_map(|check| Some((check.id, check.repair.clone()?)))
+            .filter(|(_, action)| !attempted.contains(action))
+            .collect();
+        if actions.is_empty() {
+            break;
+        }
+
+        for (check, action) in actions {
+            attempted.insert(action.clone());
+            tracing::info!(check, action = action.name(), "Repairing installation");
+            let run = run_repair(&action, &layout, sha256.clone(), &request_id, sender.clone(), cancel);
+            match install_state::journaled(action.name(), &request_id, run).await {
+                Ok(_) => repaired.push(format!("{}: {}", check, action.name())),
+                Err(DispatchError::Cancelled) => return Err(DispatchError::Cancelled),
+                Err(e) => {
+                    tracing::warn!(check, action = action.name(), error = %e, "Repair failed");
+                    failed.push(ComponentFailure::new(check, e));
+                }
+            }
+        }
+        checks = run_checks(&layout, process_state, cancel).await?;
+    }
+
+    let mut result = report(&checks);
+    result["repaired"] = json!(repaired);
+    let still_failing: Vec<&Check> = checks
+        .iter()
+        .filter(|check| selected(check) && !check.ok())
+        .filter(|check| !failed.iter().any(|f| f.component == check.id))
+        .collect();
+    for check in still_failing {
+        failed.push(ComponentFailure::new(
+            check.id,
+            DispatchError::internal(format!("Still failing after repair: {}", check.message)),
+        ));
+    }
+    if failed.is_empty() {
+        let message = if checks.iter().all(Check::ok) {
+            format!("Repaired {} problems; the installation checks out", repaired.len())
+        } else {
+            format!("Repaired {} problems; checks not selected still fail", repaired.len())
+        };
+        result["message"] = json!(message);
+        return Ok(result);
+    }
+    Err(DispatchError::partial_failure(
+        format!("{} problems could not be repaired", failed.len()),
+        repaired,
+        failed,
+    ))
+}
+
+fn report(checks: &[Check]) -> Value {
+    let failed = checks.iter().filter(|check| check.status == CheckStatus::Failed).count();
+    let warnings = checks.iter().filter(|check| check.status == CheckStatus::Warning).count();
+    let message = match (failed, warnings) {
+        (0, 0) => "Installation verified, no problems found".to_string(),
+        (0, _) => format!("Installation verified with {} warnings", warnings),
+        _ => format!("{} checks failed; repair_installation can fix them", failed),
+    };
+    json!({
+        "success": true,
+        "healthy": failed == 0,
+        "message": message,
+        "checks": checks,
+    })
+}
+
+async fn run_checks(
+    layout: &Layout,
+    process_state: &ProcessState,
+    cancel: &CancellationToken,
+) -> Result<Vec<Check>, DispatchError> {
+    let mut checks = Vec::new();
+
+    let conda = check_conda(layout);
+    let env = if conda.ok() {
+        check_env(layout)
+    } else {
+        Check::skipped("conda_env", "conda")
+    };
+    let repo = check_repo(layout, cancel).await?;
+    let env_file = if repo.ok() {
+        check_env_file(&layout.repo)
+    } else {
+        Check::skipped("env_file", "repo")
+    };
+    let backend = match (env.ok(), repo.ok()) {
+        (true, true) => check_backend_deps(layout, cancel).await?,
+        (false, _) => Check::skipped("backend_deps", "conda_env"),
+        (_, false) => Check::skipped("backend_deps", "repo"),
+    };
+    let frontend = if repo.ok() {
+        check_frontend_deps(&layout.repo)
+    } else {
+        Check::skipped("frontend_deps", "repo")
+    };
+    let ports = check_ports(process_state).await;
+
+    checks.extend([conda, env, repo, env_file, backend, frontend, ports]);
+    Ok(checks)
+}
+
+fn check_conda(layout: &Layout) -> Check {
+    if let Some(conda) = dispatcher::get_isolated_conda_binary() {
+        return Check::passed("conda", format!("Miniconda is installed at {}", conda.display()));
+    }
+    if layout.miniconda.exists() {
+        return Check::failed(
+            "conda",
+            format!("Miniconda at {} is incomplete: conda is missing", layout.miniconda.display()),
+            RepairAction::ReinstallConda,
+        );
+    }
+    Check::failed("conda", "Miniconda is not installed", RepairAction::InstallConda)
+}
+
+fn check_env(layout: &Layout) -> Check {
+    if !env_tool(&layout.env, "python").exists() {
+        return Check::failed(
+            "conda_env",
+            format!("The {} environment does not exist", CONDA_ENV_NAME),
+            RepairAction::CreateCondaEnv { force_recreate: layout.env.exists() },
+        );
+    }
+    let missing: Vec<&str> = ["node", "npm", "git"]
+        .into_iter()
+        .filter(|tool| !env_tool(&layout.env, tool).exists())
+        .collect();
+    if !missing.is_empty() {
+        return Check::failed(
+            "conda_env",
+            format!("The {} environment is missing {}", CONDA_ENV_NAME, missing.join(", ")),
+            RepairAction::CreateCondaEnv { force_recreate: true },
+        );
+    }
+    Check::passed("conda_env", format!("The {} environment has python, node, npm and git", CONDA_ENV_NAME))
+}
+
+async fn check_repo(layout: &Layout, cancel: &CancellationToken) -> Result<Check, DispatchError> {
+    let repo = &layout.repo;
+    if !repo.join(".git").exists() {
+        return Ok(Check::failed(
+            "repo",
+            format!("No BrainDrive checkout at {}", repo.display()),
+            RepairAction::CloneRepo,
+        ));
+    }
+    let Some(git) = dispatcher::find_braindrive_git() else {
+        return Ok(Check::warning("repo", "Git is not available, so the checkout was not checked"));
+    };
+
+    let fsck = run_git(&git, repo, &["fsck", "--no-progress", "--connectivity-only"], cancel).await?;
+    if !fsck.success {
+        return Ok(Check::failed(
+            "repo",
+            format!("The checkout is corrupt: {}", first_line(&fsck.stderr)),
+            RepairAction::RefetchRepo,
+        ));
+    }
+    let status = run_git(&git, repo, &["status", "--porcelain"], cancel).await?;
+    if !status.success {
+        return Ok(Check::failed(
+            "repo",
+            format!("git status failed: {}", first_line(&status.stderr)),
+            RepairAction::RefetchRepo,
+        ));
+    }
+
+    let changes = tracked_changes(&status.stdout);
+    let deleted = changes.iter().filter(|(code, _)| code.contains('D')).count();
+    if deleted > 0 || !repo.join("backend").is_dir() || !repo.join("frontend").is_dir() {
+        return Ok(Check::failed(
+            "repo",
+            format!("{} files are missing from the checkout", deleted.max(1)),
+            RepairAction::RestoreFiles,
+        ));
+    }
+    if !changes.is_empty() {
+        return Ok(Check::warning("repo", format!("{} files in the checkout were changed locally", changes.len())));
+    }
+    Ok(Check::passed("repo", "The checkout is intact"))
+}
+
+fn check_env_file(repo: &Path) -> Check {
+    let backend = repo.join("backend");
+    let env_file = backend.join(".env");
+    let Ok(contents) = std::fs::read_to_string(&env_file) else {
+        return Check::failed("env_file", "backend/.env is missing", RepairAction::SetupEnvFile);
+    };
+    let template = std::fs::read_to_string(backend.join(".env-dev")).unwrap_or_default();
+    let present = env_keys(&contents);
+    let missing: Vec<String> = env_keys(&template)
+        .into_iter()
+        .filter(|key| !present.contains(key))
+        .collect();
+    if !missing.is_empty() {
+        return Check::failed(
+            "env_file",
+            format!("backend/.env is missing {}", list(&missing)),
+            RepairAction::AddEnvKeys { keys: missing },
+        );
+    }
+    Check::passed("env_file", "backend/.env has every key from .env-dev")
+}
+
+async fn check_backend_deps(layout: &Layout, cancel: &CancellationToken) -> Result<Check, DispatchError> {
+    let requirements = layout.repo.join("backend").join("requirements.txt");
+    if !requirements.exists() {
+        return Ok(Check::failed("backend_deps", "backend/requirements.txt is missing", RepairAction::RestoreFiles));
+    }
+    let script = write_check_script()?;
+
+    let mut command = Command::new(env_tool(&layout.env, "python"));
+    command.arg(&script).arg(&requirements).args(BACKEND_ENTRY_MODULES);
+    let output = dispatcher::run_command(command, cancel).await?;
+    let findings: Value = serde_json::from_str(output.stdout.trim()).unwrap_or(Value::Null);
+    let names = |key: &str| -> Vec<String> {
+        findings[key]
+            .as_array()
+            .map(|items| items.iter().filter_map(|item| item.as_str().map(str::to_string)).collect())
+            .unwrap_or_default()
+    };
+    let (missing, broken) = (names("missing"), names("broken"));
+
+    if !output.success || findings.is_null() {
+        return Ok(Check::failed(
+            "backend_deps",
+            format!("Could not check the backend packages: {}", first_line(&output.stderr)),
+            RepairAction::InstallBackendDeps,
+        ));
+    }
+    if !missing.is_empty() {
+        return Ok(Check::failed(
+            "backend_deps",
+            format!("Backend packages not installed: {}", list(&missing)),
+            RepairAction::InstallBackendDeps,
+        ));
+    }
+    if !broken.is_empty() {
+        return Ok(Check::failed(
+            "backend_deps",
+            format!("Backend modules fail to import: {}", list(&broken)),
+            RepairAction::InstallBackendDeps,
+        ));
+    }
+    Ok(Check::passed("backend_deps", "Backend requirements are installed and import"))
+}
+
+fn check_frontend_deps(repo: &Path) -> Check {
+    let frontend = repo.join("frontend");
+    let node_modules = frontend.join("node_modules");
+    let empty = std::fs::read_dir(&node_modules).map_or(true, |mut entries| entries.next().is_none());
+    if empty {
+        return Check::failed("frontend_deps", "frontend/node_modules is missing", RepairAction::InstallFrontendDeps);
+    }
+
+    let package_json: Value = std::fs::read_to_string(frontend.join("package.json"))
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or(Value::Null);
+    let missing = missing_packages(&package_json, &node_modules);
+    if !missing.is_empty() {
+        return Check::failed(
+            "frontend_deps",
+            format!("Frontend packages not installed: {}", list(&missing)),
+            RepairAction::InstallFrontendDeps,
+        );
+    }
+    Check::passed("frontend_deps", "Frontend packages are installed")
+}
+
+/// Default ports taken by something other than our services
+///
+/// Not a failure: `start_braindrive` falls back to other ports.
+async fn check_ports(process_state: &ProcessState) -> Check {
+    let ours: Vec<u16> = {
+        let state = process_state.lock().await;
+        [&state.backend, &state.frontend]
+            .into_iter()
+            .flatten()
+            .filter(|service| service.running)
+            .map(|service| service.port)
+            .collect()
+    };
+    let taken: Vec<String> = [default_backend_port(), default_frontend_port()]
+        .into_iter()
+        .filter(|port| !ours.contains(port) && is_port_in_use(*port))
+        .map(|port| port.to_string())
+        .collect();
+    if taken.is_empty() {
+        return Check::passed("ports", "The default ports are free or used by BrainDrive");
+    }
+    Check::warning(
+        "ports",
+        format!("Port {} is in use by another program; BrainDrive will start on a fallback port", taken.join(", ")),
+    )
+}
+
+async fn run_repair(
+    action: &RepairAction,
+    layout: &Layout,
+    sha256: Option<String>,
+    request_id: &str,
+    sender: Arc<Mutex<Outbound>>,
+    cancel: &CancellationToken,
+) -> Result<Value, DispatchError> {
+    match action {
+        RepairAction::InstallConda => dispatcher::install_conda(sha256, request_id.to_string(), sender, cancel).await,
+        RepairAction::ReinstallConda => {
+            let miniconda = layout.miniconda.clone();
+            tokio::task::spawn_blocking(move || std::fs::remove_dir_all(&miniconda))
+                .await
+                .map_err(|e| DispatchError::internal(e.to_string()))?
+                .map_err(|e| DispatchError::internal(format!("Failed to remove the incomplete Miniconda: {}", e)))?;
+            dispatcher::install_conda(sha256, request_id.to_string(), sender, cancel).await
+        }
+        RepairAction::CreateCondaEnv { force_recreate } => {
+            dispatcher::create_conda_env(None, Some(*force_recreate), cancel).await
+        }
+        RepairAction::CloneRepo => dispatcher::clone_repo(None, None, Default::default(), cancel).await,
+        RepairAction::RestoreFiles => restore_files(layout, cancel).await,
+        RepairAction::RefetchRepo => refetch_repo(layout, cancel).await,
+        RepairAction::SetupEnvFile => dispatcher::setup_env_file(None).await,
+        RepairAction::AddEnvKeys { keys } => add_env_keys(&layout.repo.join("backend"), keys),
+        RepairAction::InstallBackendDeps => dispatcher::install_backend_deps(None, None, cancel).await,
+        RepairAction::InstallFrontendDeps => dispatcher::install_frontend_deps(None, None, cancel).await,
+    }
+}
+
+async fn restore_files(layout: &Layout, cancel: &CancellationToken) -> Result<Value, DispatchError> {
+    let git = dispatcher::find_braindrive_git()
+        .ok_or_else(|| DispatchError::not_installed("git", "Git is not installed. Please install Git first."))?;
+    let restored = restore_missing(&git, &layout.repo, cancel).await?;
+    Ok(json!({
+        "success": true,
+        "message": format!("Restored {} missing files", restored.len()),
+        "path": layout.repo.to_string_lossy(),
+        "files": restored,
+    }))
+}
+
+/// Check out files deleted since `HEAD` (staged or not) from `HEAD`, leaving
+/// every other file alone; returns their paths
+async fn restore_missing(git: &Path, repo: &Path, cancel: &CancellationToken) -> Result<Vec<String>, DispatchError> {
+    let deleted = dispatcher::check_output(
+        "git diff",
+        run_git(git, repo, &["diff", "--name-only", "-z", "--diff-filter=D", "HEAD"], cancel).await?,
+    )?;
+    let paths: Vec<String> = nul_separated(&deleted.stdout);
+    // Batches keep the command line within Windows' length limit
+    for batch in paths.chunks(RESTORE_BATCH) {
+        let mut args = vec!["checkout", "HEAD", "--"];
+        args.extend(batch.iter().map(String::as_str));
+        dispatcher::check_output("git checkout", run_git(git, repo, &args, cancel).await?)?;
+    }
+    Ok(paths)
+}
+
+async fn refetch_repo(layout: &Layout, cancel: &CancellationToken) -> Result<Value, DispatchError> {
+    let git = dispatcher::find_braindrive_git()
+        .ok_or_else(|| DispatchError::not_installed("git", "Git is not installed. Please install Git first."))?;
+    let repo = &layout.repo;
+    let head = dispatcher::check_output("git rev-parse", run_git(&git, repo, &["rev-parse", "HEAD"], cancel).await?)?;
+    let head = head.stdout.trim().to_string();
+    let shallow = run_git(&git, repo, &["rev-parse", "--is-shallow-repository"], cancel).await?;
+    let shallow = shallow.success && shallow.stdout.trim() == "true";
+
+    let mut fetched = run_git(&git, repo, &refetch_args(shallow, &head), cancel).await?;
+    if !fetched.success {
+        // Servers that don't serve commits by id still serve the branch holding it
+        let branch = run_git(&git, repo, &["symbolic-ref", "-q", "--short", "HEAD"], cancel).await?;
+        let branch = branch.stdout.trim();
+        if branch.is_empty() {
+            return Err(DispatchError::process_failed("git fetch", fetched.exit_code, &fetched.stderr));
+        }
+        fetched = run_git(&git, repo, &refetch_args(shallow, branch), cancel).await?;
+    }
+    dispatcher::check_output("git fetch", fetched)?;
+
+    Ok(json!({
+        "success": true,
+        "message": "Fetched the checked out commit again",
+        "path": repo.to_string_lossy(),
+        "commit": head,
+    }))
+}
+
+/// Fetch `target` from origin including objects we are supposed to have
+///
+/// A plain fetch skips objects the refs claim are present, which are the ones
+/// missing; `--refetch` (Git 2.36+) downloads them regardless. A full clone is
+/// fetched without `--depth` so it stays full.
+fn refetch_args(shallow: bool, target: &str) -> Vec<&str> {
+    let mut args = vec!["fetch", "--refetch", "--no-tags"];
+    if shallow {
+        args.extend(["--depth", "1"]);
+    }
+    args.extend(["origin", target]);
+    args
+}
+
+fn add_env_keys(backend: &Path, keys: &[String]) -> Result<Value, DispatchError> {
+    let template = std::fs::read_to_string(backend.join(".env-dev"))
+        .map_err(|e| format!("Failed to read .env-dev: {}", e))?;
+    let lines: Vec<&str> = template
+        .lines()
+        .filter(|line| env_key(line).is_some_and(|key| keys.iter().any(|k| k == key)))
+        .collect();
+
+    let env_file = backend.join(".env");
+    let mut contents = std::fs::read_to_string(&env_file).map_err(|e| format!("Failed to read .env: {}", e))?;
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str("\n# Added from .env-dev by the BrainDrive installer\n");
+    for line in &lines {
+        contents.push_str(line);
+        contents.push('\n');
+    }
+    std::fs::write(&env_file, contents).map_err(|e| format!("Failed to write .env: {}", e))?;
+
+    Ok(json!({
+        "success": true,
+        "message": format!("Added {} keys to .env", lines.len()),
+        "keys": keys,
+    }))
+}
+
+/// A tool inside the conda environment
+pub(crate) fn env_tool(env: &Path, tool: &str) -> PathBuf {
+    #[cfg(target_os = "windows")]
+    {
+        match tool {
+            "python" => env.join("python.exe"),
+            "npm" => env.join("npm.cmd"),
+            "git" => env.join("Library").join("bin").join("git.exe"),
+            _ => env.join(format!("{}.exe", tool)),
+        }
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        env.join("bin").join(tool)
+    }
+}
+
+async fn run_git(
+    git: &Path,
+    repo: &Path,
+    args: &[&str],
+    cancel: &CancellationToken,
+) -> Result<dispatcher::CommandOutput, DispatchError> {
+    let mut command = Command::new(git);
+    command.arg("-C").arg(repo).args(args);
+    dispatcher::run_command(command, cancel).await
+}
+
+fn write_check_script() -> Result<PathBuf, DispatchError> {
+    let dir = dirs::home_dir()
+        .ok_or("Could not determine home directory")?
+        .join(".braindrive-installer")
+        .join("scripts");
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create scripts directory: {}", e))?;
+    let script = dir.join("check_backend_deps.py");
+    std::fs::write(&script, BACKEND_CHECK_SCRIPT).map_err(|e| format!("Failed to write check script: {}", e))?;
+    Ok(script)
+}
+
+/// Paths in `-z` output
+fn nul_separated(output: &str) -> Vec<String> {
+    output.split('\0').filter(|path| !path.is_empty()).map(str::to_string).collect()
+}
+
+/// `(status code, path)` of tracked files in `git status --porcelain` output
+fn tracked_changes(porcelain: &str) -> Vec<(&str, &str)> {
+    porcelain
+        .lines()
+        .filter(|line| line.len() > 3 && !line.starts_with("??"))
+        .map(|line| (&line[..2], &line[3..]))
+        .collect()
+}
+
+/// Key set on a `.env` line, if it sets one
+fn env_key(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return None;
+    }
+    let line = line.strip_prefix("export ").unwrap_or(line);
+    let (key, _) = line.split_once('=')?;
+    let key = key.trim();
+    (!key.is_empty()).then_some(key)
+}
+
+fn env_keys(contents: &str) -> Vec<String> {
+    contents.lines().filter_map(env_key).map(str::to_string).collect()
+}
+
+/// Dependencies in `package.json` with no directory under `node_modules`
+fn missing_packages(package_json: &Value, node_modules: &Path) -> Vec<String> {
+    ["dependencies", "devDependencies"]
+        .iter()
+        .filter_map(|section| package_json[section].as_object())
+        .flat_map(|deps| deps.keys())
+        .filter(|name| !node_modules.join(name).join("package.json").exists())
+        .cloned()
+        .collect()
+}
+
+fn list(names: &[String]) -> String {
+    if names.len() <= MAX_LISTED {
+        return names.join(", ");
+    }
+    format!("{} and {} more", names[..MAX_LISTED].join(", "), names.len() - MAX_LISTED)
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().find(|line| !line.trim().is_empty()).unwrap_or("no output").trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_keys_ignore_comments_and_export() {
+        let contents = "# Settings\nAPP_NAME=BrainDrive\nexport SECRET_KEY = abc\n\nnot a setting\n#DEBUG=1\n";
+        assert_eq!(env_keys(contents), vec!["APP_NAME", "SECRET_KEY"]);
+    }
+
+    #[test]
+    fn test_tracked_changes_skip_untracked_files() {
+        let porcelain = " M backend/main.py\n D frontend/package.json\n?? backend/.env\n";
+        let changes = tracked_changes(porcelain);
+        assert_eq!(changes, vec![(" M", "backend/main.py"), (" D", "frontend/package.json")]);
+    }
+
+    #[tokio::test]
+    async fn test_restore_keeps_local_edits() {
+        let repo = std::env::temp_dir().join(format!("braindrive-restore-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&repo);
+        std::fs::create_dir_all(repo.join("backend")).unwrap();
+        std::fs::write(repo.join("backend/main.py"), "app = None\n").unwrap();
+        std::fs::write(repo.join("backend/requirements.txt"), "fastapi\n").unwrap();
+        std::fs::write(repo.join("README.md"), "# BrainDrive\n").unwrap();
+        let git = PathBuf::from("git");
+        let cancel = CancellationToken::new();
+        for args in [
+            &["init", "-q"][..],
+            &["add", "."],
+            &["-c", "user.name=t", "-c", "user.email=t@example.com", "commit", "-q", "-m", "init"],
+        ] {
+            assert!(run_git(&git, &repo, args, &cancel).await.unwrap().success);
+        }
+
+        std::fs::write(repo.join("backend/main.py"), "app = create_app()\n").unwrap();
+        std::fs::remove_file(repo.join("backend/requirements.txt")).unwrap();
+        assert!(run_git(&git, &repo, &["rm", "-q", "README.md"], &cancel).await.unwrap().success);
+
+        let mut restored = restore_missing(&git, &repo, &cancel).await.unwrap();
+        restored.sort();
+        assert_eq!(restored, ["README.md", "backend/requirements.txt"]);
+        assert!(repo.join("README.md").exists());
+        assert!(repo.join("backend/requirements.txt").exists());
+        assert_eq!(std::fs::read_to_string(repo.join("backend/main.py")).unwrap(), "app = create_app()\n");
+
+        let _ = std::fs::remove_dir_all(&repo);
+    }
+
+    #[test]
+    fn test_refetch_keeps_a_full_clone_full() {
+        assert_eq!(refetch_args(false, "abc123"), ["fetch", "--refetch", "--no-tags", "origin", "abc123"]);
+        assert_eq!(
+            refetch_args(true, "main"),
+            ["fetch", "--refetch", "--no-tags", "--depth", "1", "origin", "main"]
+        );
+    }
+
+    #[test]
+    fn test_missing_frontend_packages() {
+        let dir = std::env::temp_dir().join(format!("braindrive-verify-{}", std::process::id()));
+        let node_modules = dir.join("node_modules");
+        std::fs::create_dir_all(node_modules.join("react")).unwrap();
+        std::fs::write(node_modules.join("react/package.json"), "{}").unwrap();
+        std::fs::create_dir_all(node_modules.join("@vitejs/plugin-react")).unwrap();
+        std::fs::write(node_modules.join("@vitejs/plugin-react/package.json"), "{}").unwrap();
+
+        let package_json = json!({
+            "dependencies": { "react": "^18.2.0", "react-dom": "^18.2.0" },
+            "devDependencies": { "@vitejs/plugin-react": "^4.0.0" },
+        });
+        assert_eq!(missing_packages(&package_json, &node_modules), vec!["react-dom"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_failed_checks_carry_a_repair() {
+        let checks = vec![
+            Check::failed("conda", "Miniconda is not installed", RepairAction::InstallConda),
+            Check::skipped("conda_env", "conda"),
+            Check::failed("env_file", "backend/.env is missing A", RepairAction::AddEnvKeys { keys: vec!["A".into()] }),
+            Check::warning("ports", "Port 8005 is in use by another program"),
+        ];
+        let value = report(&checks);
+        assert_eq!(value["healthy"], false);
+        assert_eq!(value["checks"][0]["repair"], json!({ "action": "install_conda" }));
+        assert_eq!(value["checks"][1]["status"], "skipped");
+        assert!(value["checks"][1].get("repair").is_none());
+        assert_eq!(value["checks"][2]["repair"], json!({ "action": "add_env_keys", "keys": ["A"] }));
+        assert!(checks[3].ok());
+    }
+}
@@ -1,12 +1,66 @@
-use crate::dispatcher;
+use crate::dispatcher::{self, CloneOptions};
+use crate::download_cache;
+use crate::bandwidth;
+use crate::error::DispatchError;
+use crate::git_remote::GitToken;
+use crate::install_plan::{self, PlanStep};
+use crate::install_state;
+use crate::mirrors::{self, MirrorUpdate};
+use crate::network::{self, NetworkSettings};
+use crate::offline_bundle::{self, BundleOptions};
+use crate::pairing::SharedPairing;
+use crate::protocol::{send_message, Outbound, OutgoingMessage, ToolStatus, PROTOCOL_VERSION};
+use crate::protocol::{default_backend_port, default_frontend_port};
 use crate::process_manager::ProcessState;
-use crate::WsSender;
+use crate::replay::{Replay, SharedReplayCache};
+use crate::scheduler::{Resource, ToolScheduler};
+use crate::uninstall::{self, UninstallOptions};
+use crate::update;
+use crate::verify;
+use crate::{WsReader, WsSender};
 use futures_util::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tauri::{AppHandle, Emitter};
-use tokio::sync::Mutex;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio::sync::{oneshot, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::{interval_at, sleep, sleep_until, Duration, Instant, MissedTickBehavior};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_util::sync::CancellationToken;
+
+/// Tool call types this build handles, announced in `bootstrapper_connect` so the
+/// backend can avoid calling tools an older bootstrapper doesn't have
+const SUPPORTED_TOOLS: &[&str] = &[
+    "detect_system",
+    "install_conda",
+    "install_git",
+    "install_conda_env",
+    "install_ollama",
+    "start_ollama",
+    "pull_ollama_model",
+    "check_port",
+    "clone_repo",
+    "create_conda_env",
+    "install_backend_deps",
+    "install_frontend_deps",
+    "install_all_deps",
+    "setup_env_file",
+    "start_braindrive",
+    "stop_braindrive",
+    "restart_braindrive",
+    "run_install_plan",
+    "get_install_state",
+    "uninstall_braindrive",
+    "verify_installation",
+    "repair_installation",
+    "update_braindrive",
+    "build_offline_bundle",
+    "install_offline_bundle",
+    "clear_download_cache",
+    "configure_network",
+    "configure_mirrors",
+    "set_download_limit",
+];
 
 /// Incoming messages from the backend server
 #[derive(Debug, Deserialize)]
@@ -17,10 +71,18 @@ pub enum IncomingMessage {
     DetectSystem { id: String },
 
     #[serde(rename = "install_conda")]
-    InstallConda { id: String },
+    InstallConda {
+        id: String,
+        #[serde(default)]
+        sha256: Option<String>,
+    },
 
     #[serde(rename = "install_git")]
-    InstallGit { id: String },
+    InstallGit {
+        id: String,
+        #[serde(default)]
+        sha256: Option<String>,
+    },
 
     #[serde(rename = "install_conda_env")]
     InstallCondaEnv {
@@ -58,6 +120,17 @@ pub enum IncomingMessage {
         repo_url: Option<String>,
         #[serde(default)]
         target_path: Option<String>,
+        /// Branch, tag or full commit hash; the remote's default branch when unset
+        #[serde(default, rename = "ref")]
+        git_ref: Option<String>,
+        /// Commits of history to fetch (default 1); 0 for all of it
+        #[serde(default)]
+        depth: Option<u32>,
+        #[serde(default)]
+        submodules: Option<bool>,
+        /// For private HTTPS repositories; never logged
+        #[serde(default)]
+        token: Option<GitToken>,
     },
 
     #[serde(rename = "create_conda_env")]
@@ -78,14 +151,14 @@ pub enum IncomingMessage {
         repo_path: Option<String>,
     },
 
-    #[serde(rename = "install_frontend_deps")]
-    InstallFrontendDeps {
-        id: String,
-        #[serde(default)]
-        env_name: Option<String>,
-        #[serde(default)]
-        repo_path: Option<String>,
-    },
+    #[serde(rename = "install_frontend_deps")]
+    InstallFrontendDeps {
+        id: String,
+        #[serde(default)]
+        env_name: Option<String>,
+        #[serde(default)]
+        repo_path: Option<String>,
+    },
 
     #[serde(rename = "install_all_deps")]
     InstallAllDeps {
@@ -118,6 +191,151 @@ pub enum IncomingMessage {
     #[serde(rename = "restart_braindrive")]
     RestartBraindrive { id: String },
 
+    /// Run several tools in dependency order; without `steps`, the standard install
+    #[serde(rename = "run_install_plan")]
+    RunInstallPlan {
+        id: String,
+        #[serde(default)]
+        steps: Option<Vec<PlanStep>>,
+        /// Skip steps the install state journal records as succeeded (default true)
+        #[serde(default)]
+        resume: Option<bool>,
+    },
+
+    /// Read the install state journal: how each install step last ended
+    #[serde(rename = "get_install_state")]
+    GetInstallState { id: String },
+
+    /// Remove everything the install created, or with `dry_run` list it
+    #[serde(rename = "uninstall_braindrive")]
+    UninstallBraindrive {
+        id: String,
+        #[serde(default)]
+        dry_run: Option<bool>,
+        #[serde(default)]
+        keep_user_data: Option<bool>,
+        #[serde(default)]
+        keep_models: Option<bool>,
+        #[serde(default)]
+        repo_path: Option<String>,
+    },
+
+    /// Check every part of the installation and suggest a repair for each failure
+    #[serde(rename = "verify_installation")]
+    VerifyInstallation { id: String },
+
+    /// Run the repairs verify_installation suggests, for all failed checks or only `checks`
+    #[serde(rename = "repair_installation")]
+    RepairInstallation {
+        id: String,
+        #[serde(default)]
+        checks: Option<Vec<String>>,
+        /// Digest for a Miniconda installer that has none pinned
+        #[serde(default)]
+        sha256: Option<String>,
+    },
+
+    /// Move the checkout to the latest commit of its branch or to `tag`, then
+    /// reinstall what changed and restart; rolls back if that fails
+    #[serde(rename = "update_braindrive")]
+    UpdateBraindrive {
+        id: String,
+        #[serde(default)]
+        tag: Option<String>,
+        #[serde(default)]
+        repo_path: Option<String>,
+    },
+
+    /// Collect everything the install downloads into a bundle for offline machines
+    #[serde(rename = "build_offline_bundle")]
+    BuildOfflineBundle {
+        id: String,
+        output_path: String,
+        #[serde(default)]
+        repo_url: Option<String>,
+        /// Branch, tag or full commit hash; the remote's default branch when unset
+        #[serde(default, rename = "ref")]
+        git_ref: Option<String>,
+        /// For private HTTPS repositories; never logged
+        #[serde(default)]
+        token: Option<GitToken>,
+        /// Ollama models already pulled here to include
+        #[serde(default)]
+        models: Option<Vec<String>>,
+        /// Write `<output_path>.tar.gz` instead of a directory
+        #[serde(default)]
+        tarball: Option<bool>,
+        /// Digest for a Miniconda installer that has none pinned
+        #[serde(default)]
+        sha256: Option<String>,
+    },
+
+    /// Install from a bundle directory or `.tar.gz` without network access
+    #[serde(rename = "install_offline_bundle")]
+    InstallOfflineBundle { id: String, bundle_path: String },
+
+    /// Delete cached installers and partial downloads
+    #[serde(rename = "clear_download_cache")]
+    ClearDownloadCache { id: String },
+
+    /// Show or change the proxy and CA settings; omitted fields are left as they are
+    #[serde(rename = "configure_network")]
+    ConfigureNetwork {
+        id: String,
+        /// Empty string removes the saved value
+        #[serde(default)]
+        http_proxy: Option<String>,
+        #[serde(default)]
+        https_proxy: Option<String>,
+        #[serde(default)]
+        no_proxy: Option<String>,
+        /// PEM file with the proxy's CA certificates
+        #[serde(default)]
+        ca_bundle: Option<String>,
+        /// Drop every saved setting before applying the others
+        #[serde(default)]
+        clear: Option<bool>,
+    },
+
+    /// Show or replace the mirrors tried before each public source
+    #[serde(rename = "configure_mirrors")]
+    ConfigureMirrors {
+        id: String,
+        /// Replaces the saved list; an empty list removes it
+        #[serde(default)]
+        miniconda: Option<Vec<String>>,
+        #[serde(default)]
+        conda: Option<Vec<String>>,
+        #[serde(default)]
+        pypi: Option<Vec<String>>,
+        #[serde(default)]
+        npm: Option<Vec<String>>,
+        /// Drop every saved list before applying the others
+        #[serde(default)]
+        clear: Option<bool>,
+    },
+
+    /// Show or change the download bandwidth cap, also for downloads already running
+    #[serde(rename = "set_download_limit")]
+    SetDownloadLimit {
+        id: String,
+        /// Megabits per second; 0 removes the cap
+        #[serde(default)]
+        limit_mbps: Option<f64>,
+    },
+
+    /// Abort the in-flight tool call with this id; it replies with a cancelled result
+    #[serde(rename = "cancel")]
+    Cancel { id: String },
+
+    /// Proof that the user entered our pairing code in the web UI
+    #[serde(rename = "pair")]
+    Pair { proof: String },
+
+    /// The backend doesn't recognize our session token (e.g. it restarted)
+    #[serde(rename = "pairing_required")]
+    PairingRequired,
+
     /// Status update from backend (field unused but kept for JSON deserialization)
     #[serde(rename = "status_update")]
     #[allow(dead_code)]
@@ -128,167 +346,794 @@ pub enum IncomingMessage {
     Unknown,
 }
 
-fn default_frontend_port() -> u16 {
-    5173
+impl IncomingMessage {
+    /// Resources this call mutates, which determine its ordering guarantees
+    ///
+    /// Calls sharing any resource run one at a time in the order they arrived;
+    /// calls with no resources in common may run concurrently.
+    fn resources(&self) -> &'static [Resource] {
+        match self {
+            // Read-only probes: run immediately, alongside anything
+            IncomingMessage::DetectSystem { .. }
+            | IncomingMessage::CheckPort { .. }
+            | IncomingMessage::GetInstallState { .. } => &[],
+
+            // Ordered with every other conda mutation (install, env create/update)
+            IncomingMessage::InstallConda { .. }
+            | IncomingMessage::InstallCondaEnv { .. }
+            | IncomingMessage::CreateCondaEnv { .. } => &[Resource::Conda],
+
+            // Ordered with other Git installs only
+            IncomingMessage::InstallGit { .. } => &[Resource::SystemGit],
+
+            // Ordered with each other: install/start before pull when sent in that order
+            IncomingMessage::InstallOllama { .. }
+            | IncomingMessage::StartOllama { .. }
+            | IncomingMessage::PullOllamaModel { .. } => &[Resource::Ollama],
+
+            // Ordered with other changes to the checkout
+            IncomingMessage::CloneRepo { .. } | IncomingMessage::SetupEnvFile { .. } => {
+                &[Resource::Repo]
+            }
+
+            // Install into the conda env from the checkout: wait for both a pending
+            // env create and a pending clone
+            IncomingMessage::InstallBackendDeps { .. }
+            | IncomingMessage::InstallFrontendDeps { .. }
+            | IncomingMessage::InstallAllDeps { .. } => &[Resource::Conda, Resource::Repo],
+
+            // Start/stop/restart never interleave
+            IncomingMessage::StartBraindrive { .. }
+            | IncomingMessage::StopBraindrive { .. }
+            | IncomingMessage::RestartBraindrive { .. } => &[Resource::Services],
+
+            // A plan may touch anything its steps do
+            IncomingMessage::RunInstallPlan { .. } => &[
+                Resource::Conda,
+                Resource::Repo,
+                Resource::Services,
+                Resource::Ollama,
+                Resource::SystemGit,
+            ],
+
+            // Checks wait for pending installs, and repairs are installs
+            IncomingMessage::VerifyInstallation { .. } | IncomingMessage::RepairInstallation { .. } => {
+                &[Resource::Conda, Resource::Repo]
+            }
+
+            // Stops the services, then removes the checkout, conda and models
+            IncomingMessage::UninstallBraindrive { .. } => &[
+                Resource::Conda,
+                Resource::Repo,
+                Resource::Services,
+                Resource::Ollama,
+            ],
+
+            // Stops the services, moves the checkout and may reinstall the environment
+            IncomingMessage::UpdateBraindrive { .. } => &[Resource::Conda, Resource::Repo, Resource::Services],
+
+            // Builds a scratch environment with conda; reads pulled models
+            IncomingMessage::BuildOfflineBundle { .. } => &[Resource::Conda, Resource::Ollama],
+
+            // The whole install, minus Ollama itself
+            IncomingMessage::InstallOfflineBundle { .. } => &[Resource::Conda, Resource::Repo, Resource::Ollama],
+
+            // Waits for the installs that download into the cache
+            IncomingMessage::ClearDownloadCache { .. } => &[Resource::Conda, Resource::SystemGit],
+
+            // Only write their own settings files, read afresh by every download and command
+            IncomingMessage::ConfigureNetwork { .. } | IncomingMessage::ConfigureMirrors { .. } => &[],
+
+            // Has to run alongside the downloads it slows down
+            IncomingMessage::SetDownloadLimit { .. } => &[],
+
+            IncomingMessage::Cancel { .. }
+            | IncomingMessage::Pair { .. }
+            | IncomingMessage::PairingRequired
+            | IncomingMessage::StatusUpdate { .. }
+            | IncomingMessage::Unknown => &[],
+        }
+    }
+
+    /// Request id of a tool call, `None` for messages that aren't tool calls
+    fn call_id(&self) -> Option<&str> {
+        match self {
+            IncomingMessage::DetectSystem { id }
+            | IncomingMessage::InstallConda { id, .. }
+            | IncomingMessage::InstallGit { id, .. }
+            | IncomingMessage::InstallCondaEnv { id, .. }
+            | IncomingMessage::InstallOllama { id }
+            | IncomingMessage::StartOllama { id }
+            | IncomingMessage::PullOllamaModel { id, .. }
+            | IncomingMessage::CheckPort { id, .. }
+            | IncomingMessage::CloneRepo { id, .. }
+            | IncomingMessage::CreateCondaEnv { id, .. }
+            | IncomingMessage::InstallBackendDeps { id, .. }
+            | IncomingMessage::InstallFrontendDeps { id, .. }
+            | IncomingMessage::InstallAllDeps { id, .. }
+            | IncomingMessage::SetupEnvFile { id, .. }
+            | IncomingMessage::StartBraindrive { id, .. }
+            | IncomingMessage::StopBraindrive { id }
+            | IncomingMessage::RestartBraindrive { id }
+            | IncomingMessage::RunInstallPlan { id, .. }
+            | IncomingMessage::GetInstallState { id }
+            | IncomingMessage::UninstallBraindrive { id, .. }
+            | IncomingMessage::VerifyInstallation { id }
+            | IncomingMessage::RepairInstallation { id, .. }
+            | IncomingMessage::UpdateBraindrive { id, .. }
+            | IncomingMessage::BuildOfflineBundle { id, .. }
+            | IncomingMessage::InstallOfflineBundle { id, .. }
+            | IncomingMessage::ClearDownloadCache { id }
+            | IncomingMessage::ConfigureNetwork { id, .. }
+            | IncomingMessage::ConfigureMirrors { id, .. }
+            | IncomingMessage::SetDownloadLimit { id, .. } => Some(id),
+            IncomingMessage::Cancel { .. }
+            | IncomingMessage::Pair { .. }
+            | IncomingMessage::PairingRequired
+            | IncomingMessage::StatusUpdate { .. }
+            | IncomingMessage::Unknown => None,
+        }
+    }
+}
+
+/// Fields common to every incoming message, read separately from `IncomingMessage`
+/// so they survive when the message itself is of an unknown type
+#[derive(Debug, Default, Deserialize)]
+struct Envelope {
+    #[serde(default, rename = "type")]
+    kind: Option<String>,
+    #[serde(default)]
+    id: Option<String>,
+    /// Session token the backend attaches to every message that drives this machine
+    #[serde(default)]
+    session_token: Option<String>,
 }
-fn default_backend_port() -> u16 {
-    8005
+
+/// Delay before the first reconnection attempt after a dropped connection
+const RECONNECT_BASE_DELAY_MS: u64 = 1_000;
+/// Upper bound for the reconnection backoff
+const RECONNECT_MAX_DELAY_MS: u64 = 30_000;
+
+/// Default interval between application-level pings
+const DEFAULT_PING_INTERVAL_SECS: u64 = 20;
+/// Default time to wait for a pong before declaring the connection dead
+const DEFAULT_PONG_TIMEOUT_SECS: u64 = 10;
+
+/// Handle to the background task that owns the backend connection
+pub type SupervisorHandle = JoinHandle<()>;
+
+/// Heartbeat settings for detecting half-open connections
+///
+/// NAT gateways and sleeping laptops can leave a TCP connection that looks open
+/// but delivers nothing. We ping the backend every `ping_interval` and tear the
+/// connection down (letting the supervisor reconnect) if no pong arrives within
+/// `pong_timeout`.
+#[derive(Debug, Clone, Copy)]
+pub struct HeartbeatConfig {
+    pub ping_interval: Duration,
+    pub pong_timeout: Duration,
 }
 
-/// Outgoing messages to the backend server
-#[derive(Debug, Serialize)]
-#[serde(tag = "type")]
-pub enum OutgoingMessage {
-    #[serde(rename = "bootstrapper_connect")]
-    BootstrapperConnect,
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(DEFAULT_PING_INTERVAL_SECS),
+            pong_timeout: Duration::from_secs(DEFAULT_PONG_TIMEOUT_SECS),
+        }
+    }
+}
 
-    #[serde(rename = "tool_result")]
-    ToolResult {
-        id: String,
-        success: bool,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        data: Option<serde_json::Value>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        error: Option<String>,
-    },
+impl HeartbeatConfig {
+    /// Build the config from `BRAINDRIVE_WS_PING_INTERVAL_SECS` and
+    /// `BRAINDRIVE_WS_PONG_TIMEOUT_SECS`, falling back to the defaults
+    pub fn from_env() -> Self {
+        let read_secs = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs)
+        };
 
-    #[serde(rename = "progress")]
-    Progress {
-        id: String,
-        operation: String,
-        percent: Option<u8>,
-        message: String,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        bytes_downloaded: Option<u64>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        bytes_total: Option<u64>,
-    },
+        let defaults = Self::default();
+        Self {
+            ping_interval: read_secs("BRAINDRIVE_WS_PING_INTERVAL_SECS")
+                .unwrap_or(defaults.ping_interval),
+            pong_timeout: read_secs("BRAINDRIVE_WS_PONG_TIMEOUT_SECS")
+                .unwrap_or(defaults.pong_timeout),
+        }
+    }
 }
 
-/// Send a message to the backend via the WebSocket
-pub async fn send_message(sender: &Arc<Mutex<Option<WsSender>>>, message: OutgoingMessage) -> Result<(), String> {
-    let json = serde_json::to_string(&message)
-        .map_err(|e| format!("Failed to serialize message: {}", e))?;
-
-    let mut guard = sender.lock().await;
-    if let Some(ref mut ws) = *guard {
-        ws.send(Message::Text(json))
-            .await
-            .map_err(|e| format!("Failed to send message: {}", e))?;
-        Ok(())
-    } else {
-        Err("WebSocket not connected".to_string())
+/// Heartbeat configuration plus the most recent round-trip measurement
+#[derive(Clone)]
+pub struct Heartbeat {
+    pub config: HeartbeatConfig,
+    last_rtt_ms: Arc<Mutex<Option<u64>>>,
+}
+
+impl Heartbeat {
+    pub fn new(config: HeartbeatConfig) -> Self {
+        Self {
+            config,
+            last_rtt_ms: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Round-trip time of the last answered ping, if the connection is up
+    pub async fn last_rtt_ms(&self) -> Option<u64> {
+        *self.last_rtt_ms.lock().await
     }
 }
 
+/// State shared between the app and the connection supervisor
+#[derive(Clone)]
+pub struct ConnectionContext {
+    pub ws_connected: Arc<Mutex<bool>>,
+    pub ws_sender: Arc<Mutex<Outbound>>,
+    pub heartbeat: Heartbeat,
+    pub pairing: SharedPairing,
+    pub replay: SharedReplayCache,
+    pub process_state: ProcessState,
+}
+
+/// Payload for the `ws-reconnecting` event
+#[derive(Debug, Clone, Serialize)]
+struct ReconnectingPayload {
+    attempt: u32,
+    delay_ms: u64,
+}
+
+/// Connect to the backend and keep the connection alive
+///
+/// Spawns a supervisor task that owns the socket: whenever the connection drops
+/// (laptop sleep, backend redeploy, network change) it reconnects with jittered
+/// exponential backoff and re-sends `bootstrapper_connect`. Returns the outcome of
+/// the first connection attempt; the supervisor keeps retrying in the background
+/// either way until `disconnect` is called.
 pub async fn connect(
     app: AppHandle,
-    ws_connected: Arc<Mutex<bool>>,
-    ws_sender: Arc<Mutex<Option<WsSender>>>,
-    process_state: ProcessState,
+    ctx: ConnectionContext,
+    ws_supervisor: Arc<Mutex<Option<SupervisorHandle>>>,
     url: &str,
 ) -> Result<(), String> {
     let url = url::Url::parse(url).map_err(|e| format!("Invalid URL: {}", e))?;
 
-    let (ws_stream, _) = connect_async(url)
+    // Never run two supervisors side by side
+    disconnect(&app, &ctx.ws_connected, &ctx.ws_sender, &ws_supervisor).await;
+
+    let (first_attempt_tx, first_attempt_rx) = oneshot::channel();
+    let handle = tokio::spawn(supervise_connection(app, ctx, url, first_attempt_tx));
+    *ws_supervisor.lock().await = Some(handle);
+
+    first_attempt_rx
         .await
-        .map_err(|e| format!("Failed to connect: {}", e))?;
+        .unwrap_or_else(|_| Err("Connection supervisor stopped unexpectedly".to_string()))
+}
 
-    let (write, mut read) = ws_stream.split();
+/// Stop the connection supervisor and close the socket
+pub async fn disconnect(
+    app: &AppHandle,
+    ws_connected: &Arc<Mutex<bool>>,
+    ws_sender: &Arc<Mutex<Outbound>>,
+    ws_supervisor: &Arc<Mutex<Option<SupervisorHandle>>>,
+) {
+    let Some(handle) = ws_supervisor.lock().await.take() else {
+        return;
+    };
+    handle.abort();
+
+    let socket = ws_sender.lock().await.detach();
+    if let Some(mut ws) = socket {
+        // Closing the sink sends a Close frame to the backend
+        let _ = ws.close().await;
+    }
+
+    *ws_connected.lock().await = false;
+    app.emit("ws-connected", false).ok();
+    tracing::info!("Disconnected from backend");
+}
+
+/// Supervisor loop: connect, serve until the socket drops, back off, repeat
+async fn supervise_connection(
+    app: AppHandle,
+    ctx: ConnectionContext,
+    url: url::Url,
+    first_attempt: oneshot::Sender<Result<(), String>>,
+) {
+    let mut first_attempt = Some(first_attempt);
+    let mut attempt: u32 = 0;
+    // Outlives individual connections so a call queued before a drop still
+    // orders after one that was running
+    let mut scheduler = ToolScheduler::default();
+
+    loop {
+        match open_connection(&app, &ctx, &url).await {
+            Ok(read) => {
+                attempt = 0;
+                if let Some(tx) = first_attempt.take() {
+                    let _ = tx.send(Ok(()));
+                }
+
+                run_read_loop(read, &app, &ctx, &mut scheduler).await;
+                end_session(&app, &ctx).await;
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, url = %url, "Backend connection attempt failed");
+                if let Some(tx) = first_attempt.take() {
+                    let _ = tx.send(Err(e));
+                }
+            }
+        }
+
+        attempt = attempt.saturating_add(1);
+        let delay = reconnect_delay(attempt, rand::random::<f64>());
+        tracing::info!(attempt, delay_ms = delay.as_millis() as u64, "Reconnecting to backend");
+        app.emit(
+            "ws-reconnecting",
+            ReconnectingPayload {
+                attempt,
+                delay_ms: delay.as_millis() as u64,
+            },
+        )
+        .ok();
+        sleep(delay).await;
+    }
+}
+
+/// Open the socket to the relay and announce ourselves to the backend
+async fn open_connection(
+    app: &AppHandle,
+    ctx: &ConnectionContext,
+    url: &url::Url,
+) -> Result<WsReader, String> {
+    let ws_stream = network::connect_websocket(url).await?;
+
+    let (write, read) = ws_stream.split();
+    start_session(app, ctx, write, url.as_str()).await?;
+    Ok(read)
+}
+
+/// Register the sender of a freshly opened socket and announce ourselves on it
+///
+/// `peer` names the other side in logs: the relay URL, or a local client's address.
+pub(crate) async fn start_session(
+    app: &AppHandle,
+    ctx: &ConnectionContext,
+    write: WsSender,
+    peer: &str,
+) -> Result<(), String> {
+    let ws_sender = &ctx.ws_sender;
 
     // Store the sender
-    {
-        let mut sender_guard = ws_sender.lock().await;
-        *sender_guard = Some(write);
+    ws_sender.lock().await.attach(write);
+
+    // Send bootstrapper_connect message
+    let connect_message = {
+        let pairing = ctx.pairing.lock().await;
+        OutgoingMessage::BootstrapperConnect {
+            protocol_version: PROTOCOL_VERSION,
+            bootstrapper_version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            capabilities: SUPPORTED_TOOLS,
+            pairing_nonce: pairing.nonce().to_string(),
+            session_token: pairing.session_token().map(str::to_string),
+        }
+    };
+    let resuming = matches!(
+        connect_message,
+        OutgoingMessage::BootstrapperConnect { session_token: Some(_), .. }
+    );
+    if let Err(e) = send_message(ws_sender, connect_message).await {
+        ws_sender.lock().await.detach();
+        return Err(e);
+    }
+
+    // The backend handles our messages in order, so by the time it reads these it
+    // has accepted the session token; if it didn't, it answers `pairing_required`
+    if resuming {
+        let mut outbound = ws_sender.lock().await;
+        if let Err(e) = outbound.resume().await {
+            outbound.detach();
+            return Err(e);
+        }
     }
 
     // Mark as connected
-    *ws_connected.lock().await = true;
+    *ctx.ws_connected.lock().await = true;
 
     // Emit connection event to frontend
     app.emit("ws-connected", true).ok();
+    tracing::info!(peer = %peer, "Connected to backend");
 
-    // Send bootstrapper_connect message
-    send_message(&ws_sender, OutgoingMessage::BootstrapperConnect).await?;
-
-    // Spawn task to handle incoming messages
-    let app_clone = app.clone();
-    let ws_connected_clone = ws_connected.clone();
-    let ws_sender_clone = ws_sender.clone();
-    let process_state_clone = process_state.clone();
-
-    tokio::spawn(async move {
-        while let Some(msg) = read.next().await {
-            match msg {
-                Ok(Message::Text(text)) => {
-                    // Emit raw message to frontend for logging
-                    app_clone.emit("ws-message", text.clone()).ok();
-
-                    // Parse and dispatch the message
-                    match serde_json::from_str::<IncomingMessage>(&text) {
-                        Ok(incoming) => {
-                            handle_incoming_message(
-                                incoming,
-                                &app_clone,
-                                &ws_sender_clone,
-                                &process_state_clone,
-                            )
-                            .await;
+    Ok(())
+}
+
+/// Handle incoming messages until the socket closes, errors or stops answering pings
+pub(crate) async fn run_read_loop(
+    mut read: WsReader,
+    app: &AppHandle,
+    ctx: &ConnectionContext,
+    scheduler: &mut ToolScheduler,
+) {
+    let ws_sender = &ctx.ws_sender;
+    let heartbeat = &ctx.heartbeat;
+    let mut ping_timer = interval_at(
+        Instant::now() + heartbeat.config.ping_interval,
+        heartbeat.config.ping_interval,
+    );
+    ping_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    // The ping we are waiting on: (payload, sent_at)
+    let mut outstanding_ping: Option<(Vec<u8>, Instant)> = None;
+    let mut ping_counter: u64 = 0;
+
+    loop {
+        let pong_deadline = outstanding_ping
+            .as_ref()
+            .map(|(_, sent_at)| *sent_at + heartbeat.config.pong_timeout);
+
+        // Biased so frames already buffered (including a late pong) are read
+        // before the pong deadline is considered
+        tokio::select! {
+            biased;
+
+            msg = read.next() => {
+                let Some(msg) = msg else {
+                    tracing::info!("Backend connection stream ended");
+                    break;
+                };
+                match msg {
+                    Ok(Message::Text(text)) => {
+                        // Emit raw message to frontend for logging
+                        app.emit("ws-message", text.clone()).ok();
+
+                        // Parse and dispatch the message
+                        match serde_json::from_str::<IncomingMessage>(&text) {
+                            Ok(incoming) => {
+                                let envelope = serde_json::from_str::<Envelope>(&text).unwrap_or_default();
+                                dispatch_incoming_message(incoming, envelope, app, ctx, scheduler).await;
+                            }
+                            Err(e) => reply_to_malformed_message(&text, &e, ctx).await,
                         }
-                        Err(e) => {
-                            eprintln!("Failed to parse message: {} - {}", e, text);
+                    }
+                    Ok(Message::Pong(payload)) => {
+                        if let Some((expected, sent_at)) = &outstanding_ping {
+                            if *expected == payload {
+                                let rtt_ms = sent_at.elapsed().as_millis() as u64;
+                                *heartbeat.last_rtt_ms.lock().await = Some(rtt_ms);
+                                outstanding_ping = None;
+                            }
                         }
                     }
+                    Ok(Message::Close(_)) => {
+                        tracing::info!("Backend closed the connection");
+                        break;
+                    }
+                    Err(e) => {
+                        tracing::warn!(error = %e, "WebSocket error");
+                        break;
+                    }
+                    _ => {}
                 }
-                Ok(Message::Close(_)) => {
-                    cleanup_connection(&ws_connected_clone, &ws_sender_clone, &app_clone).await;
-                    break;
-                }
-                Err(e) => {
-                    eprintln!("WebSocket error: {}", e);
-                    cleanup_connection(&ws_connected_clone, &ws_sender_clone, &app_clone).await;
+            }
+
+            _ = sleep_until_deadline(pong_deadline) => {
+                tracing::warn!(
+                    pong_timeout_secs = heartbeat.config.pong_timeout.as_secs(),
+                    "No pong from backend before deadline, dropping connection"
+                );
+                break;
+            }
+
+            _ = ping_timer.tick(), if outstanding_ping.is_none() => {
+                ping_counter = ping_counter.wrapping_add(1);
+                let payload = ping_counter.to_be_bytes().to_vec();
+
+                if ws_sender.lock().await.ping(payload.clone()).await.is_err() {
+                    tracing::warn!("Failed to send heartbeat ping, dropping connection");
                     break;
                 }
-                _ => {}
+                outstanding_ping = Some((payload, Instant::now()));
             }
         }
-    });
+    }
+}
 
-    Ok(())
+/// Sleep until `deadline`, or forever when there is none
+async fn sleep_until_deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
 }
 
-async fn cleanup_connection(
-    ws_connected: &Arc<Mutex<bool>>,
-    ws_sender: &Arc<Mutex<Option<WsSender>>>,
+/// Backoff before reconnection attempt `attempt` (1-based)
+///
+/// Doubles from `RECONNECT_BASE_DELAY_MS` up to `RECONNECT_MAX_DELAY_MS`, then
+/// applies equal jitter: the result lies between half and all of that ceiling so
+/// many bootstrappers dropped by the same redeploy don't reconnect in lockstep.
+/// `jitter` is expected in `[0, 1)`.
+fn reconnect_delay(attempt: u32, jitter: f64) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(16);
+    let ceiling = RECONNECT_BASE_DELAY_MS
+        .saturating_mul(1u64 << exponent)
+        .min(RECONNECT_MAX_DELAY_MS);
+    let half = ceiling / 2;
+    let jittered = half + (half as f64 * jitter.clamp(0.0, 1.0)) as u64;
+    Duration::from_millis(jittered)
+}
+
+/// Forget a socket that stopped serving; queued and future tool messages wait for the next one
+pub(crate) async fn end_session(app: &AppHandle, ctx: &ConnectionContext) {
+    *ctx.heartbeat.last_rtt_ms.lock().await = None;
+    *ctx.ws_connected.lock().await = false;
+    ctx.ws_sender.lock().await.detach();
+    app.emit("ws-connected", false).ok();
+}
+
+/// Answer a message that didn't parse, so its caller isn't left waiting for a timeout
+///
+/// Only messages carrying a string `id` from the paired session get a reply;
+/// anything else is just logged.
+async fn reply_to_malformed_message(text: &str, error: &serde_json::Error, ctx: &ConnectionContext) {
+    let value = serde_json::from_str::<serde_json::Value>(text).ok();
+    let field = |name: &str| {
+        value
+            .as_ref()
+            .and_then(|v| v.get(name))
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+    };
+    let (id, kind) = (field("id"), field("type"));
+    let (bad_field, expected, description) = parse_error_details(kind.as_deref(), value.as_ref(), error);
+    tracing::warn!(
+        id = id.as_deref().unwrap_or(""),
+        tool = kind.as_deref().unwrap_or(""),
+        field = bad_field.as_deref().unwrap_or(""),
+        expected = expected.as_deref().unwrap_or(""),
+        error = %error,
+        "Failed to parse message from backend"
+    );
+
+    let Some(id) = id else { return };
+    let error = if ctx.pairing.lock().await.authorize(field("session_token").as_deref()) {
+        DispatchError::parse_error(bad_field, expected, description)
+    } else {
+        DispatchError::NotPaired
+    };
+    let reply = tool_result_message(id, Err(error), false);
+    // Answers this connection's message, so it is never held for a later session
+    if let Err(e) = ctx.ws_sender.lock().await.send_now(reply).await {
+        eprintln!("Failed to send tool result: {}", e);
+    }
+}
+
+/// Work out which field of a message made `error` happen, and what it should have been
+///
+/// Returns the field, the expected type and a description for the reply.
+fn parse_error_details(
+    kind: Option<&str>,
+    value: Option<&serde_json::Value>,
+    error: &serde_json::Error,
+) -> (Option<String>, Option<String>, String) {
+    let message = error_text(error);
+    let expected = message
+        .split_once(", expected ")
+        .map(|(_, expected)| expected.to_string());
+
+    let field = if let Some(rest) = message.strip_prefix("missing field `") {
+        rest.split('`').next().map(str::to_string)
+    } else {
+        // serde doesn't name the field behind a type error inside a tagged enum, so
+        // find the field whose removal makes this particular error go away
+        value.and_then(|v| v.as_object()).and_then(|object| {
+            object
+                .keys()
+                .filter(|key| key.as_str() != "type")
+                .find(|key| {
+                    let mut probe = object.clone();
+                    probe.remove(key.as_str());
+                    match serde_json::from_value::<IncomingMessage>(serde_json::Value::Object(probe)) {
+                        Ok(_) => true,
+                        Err(e) => error_text(&e) != message,
+                    }
+                })
+                .cloned()
+        })
+    };
+
+    let description = format!("Invalid {} request: {}", kind.unwrap_or("tool"), message);
+    (field, expected, description)
+}
+
+/// Error message without the "at line X column Y" suffix serde_json adds
+fn error_text(error: &serde_json::Error) -> String {
+    let text = error.to_string();
+    match text.rsplit_once(" at line ") {
+        Some((message, _)) => message.to_string(),
+        None => text,
+    }
+}
+
+/// Run a tool call on its own task so the read loop keeps serving other calls
+/// and heartbeats while it executes
+///
+/// Only pairing messages and status updates are accepted without the session
+/// token; everything else drives this machine and must come from the paired session.
+async fn dispatch_incoming_message(
+    message: IncomingMessage,
+    envelope: Envelope,
     app: &AppHandle,
+    ctx: &ConnectionContext,
+    scheduler: &mut ToolScheduler,
 ) {
-    *ws_connected.lock().await = false;
-    *ws_sender.lock().await = None;
-    app.emit("ws-connected", false).ok();
+    match &message {
+        IncomingMessage::Pair { proof } => {
+            handle_pair(proof, app, ctx).await;
+            return;
+        }
+        IncomingMessage::PairingRequired => {
+            tracing::info!("Backend does not recognize our session, pairing required");
+            let status = {
+                let mut pairing = ctx.pairing.lock().await;
+                pairing.forget_session();
+                pairing.status()
+            };
+            ctx.ws_sender.lock().await.discard_queue();
+            app.emit("pairing-updated", status).ok();
+            return;
+        }
+        IncomingMessage::StatusUpdate { .. } => return,
+        // Without an id there is no caller waiting for an answer
+        IncomingMessage::Unknown if envelope.id.is_none() => return,
+        _ => {}
+    }
+
+    if !ctx.pairing.lock().await.authorize(envelope.session_token.as_deref()) {
+        tracing::warn!("Rejecting message from a session that is not paired");
+        if let Some(id) = message.call_id().or(envelope.id.as_deref()) {
+            let reply = tool_result_message(
+                id.to_string(),
+                Err(DispatchError::NotPaired),
+                false,
+            );
+            // Unpaired, so the session isn't ready and a queued reply would wait for the next one
+            if let Err(e) = ctx.ws_sender.lock().await.send_now(reply).await {
+                eprintln!("Failed to send tool result: {}", e);
+            }
+        }
+        return;
+    }
+
+    // A request from a newer backend: say so now rather than letting it time out
+    if let IncomingMessage::Unknown = &message {
+        let kind = envelope.kind.unwrap_or_default();
+        tracing::warn!(tool = %kind, "Received request for unsupported tool");
+        let reply = tool_result_message(
+            envelope.id.unwrap_or_default(),
+            Err(DispatchError::unsupported_tool(&kind)),
+            false,
+        );
+        if let Err(e) = send_message(&ctx.ws_sender, reply).await {
+            eprintln!("Failed to send tool result: {}", e);
+        }
+        return;
+    }
+
+    // Cancellation must not queue behind the call it targets
+    if let IncomingMessage::Cancel { id } = &message {
+        if scheduler.cancel(id) {
+            tracing::info!("Cancelling tool call {}", id);
+        } else {
+            tracing::info!("Ignoring cancel for unknown or finished tool call {}", id);
+        }
+        return;
+    }
+
+    let Some(id) = message.call_id().map(str::to_string) else {
+        return;
+    };
+
+    // A retry of a call we already have must not run it twice
+    let replay = ctx.replay.lock().unwrap().begin(&id);
+    match replay {
+        Replay::Start => {}
+        Replay::Attach => {
+            tracing::info!("Tool call {} is already running, attaching to it", id);
+            return;
+        }
+        Replay::Finished(result) => {
+            tracing::info!("Replaying result of finished tool call {}", id);
+            if let Err(e) = send_message(&ctx.ws_sender, result).await {
+                eprintln!("Failed to send tool result: {}", e);
+            }
+            return;
+        }
+    }
+
+    let resources = message.resources();
+    let app = app.clone();
+    let sender = ctx.ws_sender.clone();
+    let replay = ctx.replay.clone();
+    let process_state = ctx.process_state.clone();
+    scheduler.spawn(id.clone(), resources, move |cancel| async move {
+        // Cancelled while queued: reply without starting the work
+        if cancel.is_cancelled() {
+            send_tool_result(&sender, &replay, id, Err(DispatchError::Cancelled), &cancel).await;
+            return;
+        }
+        handle_incoming_message(message, &app, &sender, &replay, &process_state, &cancel).await;
+    });
+}
+
+/// Check a pairing proof and report the outcome to the backend and the window
+async fn handle_pair(proof: &str, app: &AppHandle, ctx: &ConnectionContext) {
+    let (result, pairing_nonce, status) = {
+        let mut pairing = ctx.pairing.lock().await;
+        let result = pairing.complete(proof);
+        (result, pairing.nonce().to_string(), pairing.status())
+    };
+
+    match &result {
+        Ok(()) => tracing::info!("Paired with backend session"),
+        Err(e) => tracing::warn!(error = %e, "Pairing attempt failed"),
+    }
+    app.emit("pairing-updated", status).ok();
+
+    let paired = result.is_ok();
+    let reply = OutgoingMessage::PairResult {
+        success: result.is_ok(),
+        error: result.err(),
+        pairing_nonce,
+    };
+    if let Err(e) = send_message(&ctx.ws_sender, reply).await {
+        eprintln!("Failed to send pair result: {}", e);
+        return;
+    }
+
+    if paired {
+        if let Err(e) = ctx.ws_sender.lock().await.resume().await {
+            eprintln!("Failed to send queued messages: {}", e);
+        }
+    }
 }
 
 async fn handle_incoming_message(
     message: IncomingMessage,
     app: &AppHandle,
-    sender: &Arc<Mutex<Option<WsSender>>>,
+    sender: &Arc<Mutex<Outbound>>,
+    replay: &SharedReplayCache,
     process_state: &ProcessState,
+    cancel: &CancellationToken,
 ) {
     match message {
         IncomingMessage::DetectSystem { id } => {
             let result = dispatcher::detect_system().await;
-            send_tool_result(sender, id, result).await;
+            send_tool_result(sender, replay, id, result, cancel).await;
         }
 
-        IncomingMessage::InstallConda { id } => {
+        IncomingMessage::InstallConda { id, sha256 } => {
             app.emit("command-executing", "Installing Miniconda").ok();
-            let result = dispatcher::install_conda(id.clone(), sender.clone()).await;
-            send_tool_result(sender, id, result).await;
+            let result = install_state::journaled(
+                "install_conda",
+                &id,
+                dispatcher::install_conda(sha256, id.clone(), sender.clone(), cancel),
+            )
+            .await;
+            send_tool_result(sender, replay, id, result, cancel).await;
         }
 
-        IncomingMessage::InstallGit { id } => {
+        IncomingMessage::InstallGit { id, sha256 } => {
             app.emit("command-executing", "Installing Git").ok();
-            let result = dispatcher::install_git(id.clone(), sender.clone()).await;
-            send_tool_result(sender, id, result).await;
+            let result = install_state::journaled(
+                "install_git",
+                &id,
+                dispatcher::install_git(sha256, id.clone(), sender.clone(), cancel),
+            )
+            .await;
+            send_tool_result(sender, replay, id, result, cancel).await;
         }
 
         IncomingMessage::InstallCondaEnv {
@@ -299,20 +1144,35 @@ async fn handle_incoming_message(
         } => {
             app.emit("command-executing", format!("Installing Conda env {}", env_name))
                 .ok();
-            let result = dispatcher::install_conda_env(&env_name, repo_path, environment_file).await;
-            send_tool_result(sender, id, result).await;
+            let result = install_state::journaled(
+                "install_conda_env",
+                &id,
+                dispatcher::install_conda_env(&env_name, repo_path, environment_file, cancel),
+            )
+            .await;
+            send_tool_result(sender, replay, id, result, cancel).await;
         }
 
         IncomingMessage::InstallOllama { id } => {
             app.emit("command-executing", "Installing Ollama").ok();
-            let result = dispatcher::install_ollama().await;
-            send_tool_result(sender, id, result).await;
+            let result = install_state::journaled(
+                "install_ollama",
+                &id,
+                dispatcher::install_ollama(),
+            )
+            .await;
+            send_tool_result(sender, replay, id, result, cancel).await;
         }
 
         IncomingMessage::StartOllama { id } => {
             app.emit("command-executing", "Starting Ollama service").ok();
-            let result = dispatcher::start_ollama().await;
-            send_tool_result(sender, id, result).await;
+            let result = install_state::journaled(
+                "start_ollama",
+                &id,
+                dispatcher::start_ollama(),
+            )
+            .await;
+            send_tool_result(sender, replay, id, result, cancel).await;
         }
 
         IncomingMessage::PullOllamaModel {
@@ -324,35 +1184,61 @@ async fn handle_incoming_message(
             app.emit("command-executing", format!("Pulling model {}", model))
                 .ok();
             // Use streaming version that sends progress updates
-            let result = dispatcher::pull_ollama_model_with_progress(
-                &model,
-                registry,
-                force.unwrap_or(false),
-                id.clone(),
-                sender.clone(),
-            ).await;
-            send_tool_result(sender, id, result).await;
+            let result = install_state::journaled(
+                "pull_ollama_model",
+                &id,
+                dispatcher::pull_ollama_model_with_progress(
+                    &model,
+                    registry,
+                    force.unwrap_or(false),
+                    id.clone(),
+                    sender.clone(),
+                    cancel,
+                ),
+            )
+            .await;
+            send_tool_result(sender, replay, id, result, cancel).await;
         }
 
         IncomingMessage::CheckPort { id, port } => {
             let result = dispatcher::check_port(port).await;
-            send_tool_result(sender, id, result).await;
+            send_tool_result(sender, replay, id, result, cancel).await;
         }
 
         IncomingMessage::CloneRepo {
             id,
             repo_url,
             target_path,
+            git_ref,
+            depth,
+            submodules,
+            token,
         } => {
             app.emit("command-executing", "Cloning BrainDrive repository").ok();
-            let result = dispatcher::clone_repo(repo_url, target_path).await;
-            send_tool_result(sender, id, result).await;
+            let options = CloneOptions {
+                git_ref,
+                depth,
+                submodules: submodules.unwrap_or(false),
+                token,
+            };
+            let result = install_state::journaled(
+                "clone_repo",
+                &id,
+                dispatcher::clone_repo(repo_url, target_path, options, cancel),
+            )
+            .await;
+            send_tool_result(sender, replay, id, result, cancel).await;
         }
 
         IncomingMessage::CreateCondaEnv { id, env_name, force_recreate } => {
             app.emit("command-executing", "Creating Conda environment").ok();
-            let result = dispatcher::create_conda_env(env_name, force_recreate).await;
-            send_tool_result(sender, id, result).await;
+            let result = install_state::journaled(
+                "create_conda_env",
+                &id,
+                dispatcher::create_conda_env(env_name, force_recreate, cancel),
+            )
+            .await;
+            send_tool_result(sender, replay, id, result, cancel).await;
         }
 
         IncomingMessage::InstallBackendDeps {
@@ -361,19 +1247,29 @@ async fn handle_incoming_message(
             repo_path,
         } => {
             app.emit("command-executing", "Installing backend dependencies").ok();
-            let result = dispatcher::install_backend_deps(env_name, repo_path).await;
-            send_tool_result(sender, id, result).await;
+            let result = install_state::journaled(
+                "install_backend_deps",
+                &id,
+                dispatcher::install_backend_deps(env_name, repo_path, cancel),
+            )
+            .await;
+            send_tool_result(sender, replay, id, result, cancel).await;
         }
 
-        IncomingMessage::InstallFrontendDeps {
-            id,
-            env_name,
-            repo_path,
-        } => {
-            app.emit("command-executing", "Installing frontend dependencies").ok();
-            let result = dispatcher::install_frontend_deps(env_name, repo_path).await;
-            send_tool_result(sender, id, result).await;
-        }
+        IncomingMessage::InstallFrontendDeps {
+            id,
+            env_name,
+            repo_path,
+        } => {
+            app.emit("command-executing", "Installing frontend dependencies").ok();
+            let result = install_state::journaled(
+                "install_frontend_deps",
+                &id,
+                dispatcher::install_frontend_deps(env_name, repo_path, cancel),
+            )
+            .await;
+            send_tool_result(sender, replay, id, result, cancel).await;
+        }
 
         IncomingMessage::InstallAllDeps {
             id,
@@ -381,14 +1277,24 @@ async fn handle_incoming_message(
             repo_path,
         } => {
             app.emit("command-executing", "Installing all dependencies (parallel)").ok();
-            let result = dispatcher::install_all_deps(env_name, repo_path).await;
-            send_tool_result(sender, id, result).await;
+            let result = install_state::journaled(
+                "install_all_deps",
+                &id,
+                dispatcher::install_all_deps(env_name, repo_path, cancel),
+            )
+            .await;
+            send_tool_result(sender, replay, id, result, cancel).await;
         }
 
         IncomingMessage::SetupEnvFile { id, repo_path } => {
             app.emit("command-executing", "Setting up environment file").ok();
-            let result = dispatcher::setup_env_file(repo_path).await;
-            send_tool_result(sender, id, result).await;
+            let result = install_state::journaled(
+                "setup_env_file",
+                &id,
+                dispatcher::setup_env_file(repo_path),
+            )
+            .await;
+            send_tool_result(sender, replay, id, result, cancel).await;
         }
 
         IncomingMessage::StartBraindrive {
@@ -397,14 +1303,19 @@ async fn handle_incoming_message(
             backend_port,
         } => {
             app.emit("braindrive-starting", ()).ok();
-            let result = dispatcher::start_braindrive(frontend_port, backend_port, process_state).await;
-            send_tool_result(sender, id, result).await;
+            let result = install_state::journaled(
+                "start_braindrive",
+                &id,
+                dispatcher::start_braindrive(frontend_port, backend_port, process_state),
+            )
+            .await;
+            send_tool_result(sender, replay, id, result, cancel).await;
         }
 
         IncomingMessage::StopBraindrive { id } => {
             app.emit("braindrive-stopping", ()).ok();
             let result = dispatcher::stop_braindrive(process_state).await;
-            send_tool_result(sender, id, result).await;
+            send_tool_result(sender, replay, id, result, cancel).await;
         }
 
         IncomingMessage::RestartBraindrive { id } => {
@@ -417,7 +1328,149 @@ async fn handle_incoming_message(
                 (fp, bp)
             };
             let result = dispatcher::restart_braindrive(frontend_port, backend_port, process_state).await;
-            send_tool_result(sender, id, result).await;
+            send_tool_result(sender, replay, id, result, cancel).await;
+        }
+
+        IncomingMessage::RunInstallPlan { id, steps, resume } => {
+            app.emit("command-executing", "Running install plan").ok();
+            let result = install_plan::run_install_plan(
+                steps,
+                resume.unwrap_or(true),
+                id.clone(),
+                sender.clone(),
+                cancel,
+                process_state,
+            )
+            .await;
+            send_tool_result(sender, replay, id, result, cancel).await;
+        }
+
+        IncomingMessage::GetInstallState { id } => {
+            let result = serde_json::to_value(install_state::load())
+                .map_err(|e| DispatchError::internal(e.to_string()));
+            send_tool_result(sender, replay, id, result, cancel).await;
+        }
+
+        IncomingMessage::UninstallBraindrive {
+            id,
+            dry_run,
+            keep_user_data,
+            keep_models,
+            repo_path,
+        } => {
+            let options = UninstallOptions {
+                dry_run: dry_run.unwrap_or(false),
+                keep_user_data: keep_user_data.unwrap_or(false),
+                keep_models: keep_models.unwrap_or(false),
+                repo_path,
+            };
+            if !options.dry_run {
+                app.emit("command-executing", "Uninstalling BrainDrive").ok();
+            }
+            let result = uninstall::uninstall_braindrive(options, process_state, cancel).await;
+            send_tool_result(sender, replay, id, result, cancel).await;
+        }
+
+        IncomingMessage::VerifyInstallation { id } => {
+            app.emit("command-executing", "Verifying installation").ok();
+            let result = verify::verify_installation(process_state, cancel).await;
+            send_tool_result(sender, replay, id, result, cancel).await;
+        }
+
+        IncomingMessage::RepairInstallation { id, checks, sha256 } => {
+            app.emit("command-executing", "Repairing installation").ok();
+            let result =
+                verify::repair_installation(checks, sha256, id.clone(), sender.clone(), process_state, cancel).await;
+            send_tool_result(sender, replay, id, result, cancel).await;
+        }
+
+        IncomingMessage::UpdateBraindrive { id, tag, repo_path } => {
+            app.emit("command-executing", "Updating BrainDrive").ok();
+            let (frontend_port, backend_port) = {
+                let state = process_state.lock().await;
+                let fp = state.frontend.as_ref().map(|f| f.port).unwrap_or(5173);
+                let bp = state.backend.as_ref().map(|b| b.port).unwrap_or(8005);
+                (fp, bp)
+            };
+            let result = install_state::journaled(
+                "update_braindrive",
+                &id,
+                update::update_braindrive(
+                    tag,
+                    repo_path,
+                    frontend_port,
+                    backend_port,
+                    id.clone(),
+                    sender.clone(),
+                    process_state,
+                    cancel,
+                ),
+            )
+            .await;
+            send_tool_result(sender, replay, id, result, cancel).await;
+        }
+
+        IncomingMessage::BuildOfflineBundle {
+            id,
+            output_path,
+            repo_url,
+            git_ref,
+            token,
+            models,
+            tarball,
+            sha256,
+        } => {
+            app.emit("command-executing", "Building offline bundle").ok();
+            let options = BundleOptions {
+                output_path,
+                repo_url,
+                git_ref,
+                token,
+                models: models.unwrap_or_default(),
+                tarball: tarball.unwrap_or(false),
+                sha256,
+            };
+            let result = offline_bundle::build_offline_bundle(options, id.clone(), sender.clone(), cancel).await;
+            send_tool_result(sender, replay, id, result, cancel).await;
+        }
+
+        IncomingMessage::InstallOfflineBundle { id, bundle_path } => {
+            app.emit("command-executing", "Installing BrainDrive from offline bundle").ok();
+            let result = install_state::journaled(
+                "install_offline_bundle",
+                &id,
+                offline_bundle::install_offline_bundle(bundle_path, id.clone(), sender.clone(), cancel),
+            )
+            .await;
+            send_tool_result(sender, replay, id, result, cancel).await;
+        }
+
+        IncomingMessage::ClearDownloadCache { id } => {
+            let result = download_cache::clear_download_cache().await;
+            send_tool_result(sender, replay, id, result, cancel).await;
+        }
+
+        IncomingMessage::ConfigureNetwork { id, http_proxy, https_proxy, no_proxy, ca_bundle, clear } => {
+            let settings = NetworkSettings { http_proxy, https_proxy, no_proxy, ca_bundle };
+            let result = network::configure_network(settings, clear.unwrap_or(false)).await;
+            send_tool_result(sender, replay, id, result, cancel).await;
+        }
+
+        IncomingMessage::ConfigureMirrors { id, miniconda, conda, pypi, npm, clear } => {
+            let update = MirrorUpdate { miniconda, conda, pypi, npm };
+            let result = mirrors::configure_mirrors(update, clear.unwrap_or(false)).await;
+            send_tool_result(sender, replay, id, result, cancel).await;
+        }
+
+        IncomingMessage::SetDownloadLimit { id, limit_mbps } => {
+            let result = bandwidth::set_download_limit(limit_mbps).await;
+            send_tool_result(sender, replay, id, result, cancel).await;
+        }
+
+        IncomingMessage::Cancel { .. }
+        | IncomingMessage::Pair { .. }
+        | IncomingMessage::PairingRequired => {
+            // Handled in dispatch_incoming_message
         }
 
         IncomingMessage::StatusUpdate { .. } => {
@@ -430,27 +1483,215 @@ async fn handle_incoming_message(
     }
 }
 
+/// Send a call's result, keeping a copy for replay if the backend asks again
 async fn send_tool_result(
-    sender: &Arc<Mutex<Option<WsSender>>>,
+    sender: &Arc<Mutex<Outbound>>,
+    replay: &SharedReplayCache,
     id: String,
-    result: Result<serde_json::Value, String>,
+    result: Result<serde_json::Value, DispatchError>,
+    cancel: &CancellationToken,
 ) {
-    let message = match result {
+    if let Err(e) = &result {
+        tracing::warn!(id = %id, kind = e.kind(), error = %e, "Tool call failed");
+    }
+    let message = tool_result_message(id.clone(), result, cancel.is_cancelled());
+    replay.lock().unwrap().finish(&id, message.clone());
+
+    if let Err(e) = send_message(sender, message).await {
+        eprintln!("Failed to send tool result: {}", e);
+    }
+}
+
+/// A call that fails after being cancelled reports `cancelled`, whatever it failed with
+fn tool_result_message(id: String, result: Result<serde_json::Value, DispatchError>, cancelled: bool) -> OutgoingMessage {
+    match result {
         Ok(data) => OutgoingMessage::ToolResult {
             id,
             success: true,
+            status: ToolStatus::Ok,
             data: Some(data),
             error: None,
+            error_details: None,
         },
-        Err(e) => OutgoingMessage::ToolResult {
-            id,
-            success: false,
-            data: None,
-            error: Some(e),
-        },
-    };
+        Err(e) => {
+            let e = if cancelled { DispatchError::Cancelled } else { e };
+            let status = match e {
+                DispatchError::Cancelled => ToolStatus::Cancelled,
+                DispatchError::UnsupportedTool { .. } => ToolStatus::UnsupportedTool,
+                _ => ToolStatus::Error,
+            };
+            OutgoingMessage::ToolResult {
+                id,
+                success: false,
+                status,
+                data: None,
+                error: Some(e.to_string()),
+                error_details: Some(e),
+            }
+        }
+    }
+}
 
-    if let Err(e) = send_message(sender, message).await {
-        eprintln!("Failed to send tool result: {}", e);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_message_parses() {
+        let message: IncomingMessage = serde_json::from_str(r#"{"type":"cancel","id":"call_1"}"#).unwrap();
+        assert!(matches!(message, IncomingMessage::Cancel { ref id } if id == "call_1"));
+        assert_eq!(message.call_id(), None);
+    }
+
+    #[test]
+    fn test_cancelled_failure_reports_cancelled_status() {
+        // The killed process may surface as any error; the cancel is what matters
+        let error = DispatchError::process_failed("pip install", -1, "");
+        let message = tool_result_message("call_1".to_string(), Err(error), true);
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["status"], "cancelled");
+        assert_eq!(json["success"], false);
+        assert_eq!(json["error"], crate::error::CANCELLED_MESSAGE);
+        assert_eq!(json["error_details"]["kind"], "cancelled");
+
+        let message = tool_result_message("call_2".to_string(), Err(DispatchError::internal("boom")), false);
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["status"], "error");
+        assert_eq!(json["error"], "boom");
+        assert_eq!(json["error_details"]["kind"], "internal");
+
+        // Work that finished before the cancel landed still counts as a success
+        let message = tool_result_message("call_3".to_string(), Ok(serde_json::json!({})), true);
+        assert_eq!(serde_json::to_value(&message).unwrap()["status"], "ok");
+    }
+
+    #[test]
+    fn test_session_token_is_read_alongside_message() {
+        let text = r#"{"type":"check_port","id":"call_1","port":8005,"session_token":"abc"}"#;
+        let message: IncomingMessage = serde_json::from_str(text).unwrap();
+        assert_eq!(message.call_id(), Some("call_1"));
+
+        let envelope: Envelope = serde_json::from_str(text).unwrap();
+        assert_eq!(envelope.session_token.as_deref(), Some("abc"));
+
+        let envelope: Envelope = serde_json::from_str(r#"{"type":"pairing_required"}"#).unwrap();
+        assert!(envelope.session_token.is_none());
+    }
+
+    #[test]
+    fn test_bootstrapper_connect_omits_missing_token() {
+        let message = OutgoingMessage::BootstrapperConnect {
+            protocol_version: PROTOCOL_VERSION,
+            bootstrapper_version: "0.1.0",
+            os: "linux",
+            arch: "x86_64",
+            capabilities: SUPPORTED_TOOLS,
+            pairing_nonce: "nonce".to_string(),
+            session_token: None,
+        };
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["type"], "bootstrapper_connect");
+        assert_eq!(json["protocol_version"], PROTOCOL_VERSION);
+        assert_eq!(json["capabilities"][0], "detect_system");
+        assert_eq!(json["pairing_nonce"], "nonce");
+        assert!(json.get("session_token").is_none());
+    }
+
+    #[test]
+    fn test_supported_tools_match_incoming_messages() {
+        for tool in SUPPORTED_TOOLS {
+            // Superset of every tool's required fields; extra fields are ignored
+            let text = serde_json::json!({
+                "type": tool,
+                "id": "call_1",
+                "env_name": "BrainDriveDev",
+                "model": "llama3",
+                "port": 8005,
+                "output_path": "~/braindrive-offline",
+                "bundle_path": "~/braindrive-offline",
+            })
+            .to_string();
+            let message: IncomingMessage = serde_json::from_str(&text).unwrap();
+            assert_eq!(message.call_id(), Some("call_1"), "{} is advertised but not handled", tool);
+        }
+    }
+
+    fn parse_failure(text: &str) -> serde_json::Value {
+        let error = serde_json::from_str::<IncomingMessage>(text).unwrap_err();
+        let value = serde_json::from_str::<serde_json::Value>(text).ok();
+        let kind = value.as_ref().and_then(|v| v["type"].as_str());
+        let (field, expected, description) = parse_error_details(kind, value.as_ref(), &error);
+        serde_json::to_value(DispatchError::parse_error(field, expected, description)).unwrap()
+    }
+
+    #[test]
+    fn test_parse_error_names_field_and_expected_type() {
+        let details = parse_failure(r#"{"type":"check_port","id":"call_1","port":"abc"}"#);
+        assert_eq!(
+            details,
+            serde_json::json!({ "kind": "parse_error", "field": "port", "expected": "u16" })
+        );
+
+        let details = parse_failure(r#"{"type":"check_port","id":"call_1","port":70000}"#);
+        assert_eq!(
+            details,
+            serde_json::json!({ "kind": "parse_error", "field": "port", "expected": "u16" })
+        );
+
+        let details = parse_failure(r#"{"type":"pull_ollama_model","id":"call_2"}"#);
+        assert_eq!(details, serde_json::json!({ "kind": "parse_error", "field": "model" }));
+    }
+
+    #[test]
+    fn test_parse_error_for_invalid_json() {
+        let details = parse_failure(r#"{"type":"check_port","id":"call_1""#);
+        assert_eq!(details, serde_json::json!({ "kind": "parse_error" }));
+    }
+
+    #[test]
+    fn test_unknown_tool_gets_unsupported_reply() {
+        let text = r#"{"type":"install_docker","id":"call_9","session_token":"abc"}"#;
+        let message: IncomingMessage = serde_json::from_str(text).unwrap();
+        assert!(matches!(message, IncomingMessage::Unknown));
+
+        let envelope: Envelope = serde_json::from_str(text).unwrap();
+        assert_eq!(envelope.kind.as_deref(), Some("install_docker"));
+        let reply = tool_result_message(
+            envelope.id.unwrap(),
+            Err(DispatchError::unsupported_tool("install_docker")),
+            false,
+        );
+        let json = serde_json::to_value(&reply).unwrap();
+        assert_eq!(json["id"], "call_9");
+        assert_eq!(json["status"], "unsupported_tool");
+        assert_eq!(json["success"], false);
+        assert_eq!(json["error_details"]["tool"], "install_docker");
+    }
+
+    #[test]
+    fn test_heartbeat_defaults() {
+        let config = HeartbeatConfig::default();
+        assert_eq!(config.ping_interval, Duration::from_secs(DEFAULT_PING_INTERVAL_SECS));
+        assert_eq!(config.pong_timeout, Duration::from_secs(DEFAULT_PONG_TIMEOUT_SECS));
+    }
+
+    #[test]
+    fn test_reconnect_delay_grows_exponentially() {
+        assert_eq!(reconnect_delay(1, 1.0), Duration::from_millis(1_000));
+        assert_eq!(reconnect_delay(2, 1.0), Duration::from_millis(2_000));
+        assert_eq!(reconnect_delay(3, 1.0), Duration::from_millis(4_000));
+    }
+
+    #[test]
+    fn test_reconnect_delay_is_capped() {
+        assert_eq!(reconnect_delay(10, 1.0), Duration::from_millis(RECONNECT_MAX_DELAY_MS));
+        assert_eq!(reconnect_delay(u32::MAX, 1.0), Duration::from_millis(RECONNECT_MAX_DELAY_MS));
+    }
+
+    #[test]
+    fn test_reconnect_delay_jitter_stays_above_half() {
+        assert_eq!(reconnect_delay(3, 0.0), Duration::from_millis(2_000));
+        let delay = reconnect_delay(3, 0.5);
+        assert!(delay >= Duration::from_millis(2_000) && delay <= Duration::from_millis(4_000));
     }
 }
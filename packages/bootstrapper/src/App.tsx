@@ -28,12 +28,25 @@ interface SystemInfo {
 interface ConnectionStatus {
   connected: boolean;
   url: string;
+  latency_ms?: number;
+}
+
+interface PairingStatus {
+  code: string;
+  paired: boolean;
+}
+
+interface ReconnectingPayload {
+  attempt: number;
+  delay_ms: number;
 }
 
 type BrainDriveStatus = 'unknown' | 'stopped' | 'starting' | 'running' | 'stopping';
 
 function App() {
   const [wsConnected, setWsConnected] = useState(false);
+  const [reconnecting, setReconnecting] = useState(false);
+  const [pairing, setPairing] = useState<PairingStatus | null>(null);
   const [systemInfo, setSystemInfo] = useState<SystemInfo | null>(null);
   const [braindriveStatus, setBraindriveStatus] = useState<BrainDriveStatus>('unknown');
   const [logs, setLogs] = useState<string[]>([]);
@@ -46,6 +59,8 @@ function App() {
   useEffect(() => {
     let unlistenConnectedFn: (() => void) | null = null;
     let unlistenMessageFn: (() => void) | null = null;
+    let unlistenReconnectingFn: (() => void) | null = null;
+    let unlistenPairingFn: (() => void) | null = null;
 
     // Initial setup - wait for listeners before connecting
     const init = async () => {
@@ -53,9 +68,21 @@ function App() {
         // Set up listeners FIRST, before attempting connection
         unlistenConnectedFn = await listen<boolean>('ws-connected', (event) => {
           setWsConnected(event.payload);
+          if (event.payload) setReconnecting(false);
           addLog(event.payload ? 'Connected to backend' : 'Disconnected from backend');
         });
 
+        unlistenReconnectingFn = await listen<ReconnectingPayload>('ws-reconnecting', (event) => {
+          setReconnecting(true);
+          const seconds = (event.payload.delay_ms / 1000).toFixed(1);
+          addLog(`Reconnecting in ${seconds}s (attempt ${event.payload.attempt})`);
+        });
+
+        unlistenPairingFn = await listen<PairingStatus>('pairing-updated', (event) => {
+          setPairing(event.payload);
+          addLog(event.payload.paired ? 'Paired with installation session' : 'Waiting for pairing code');
+        });
+
         unlistenMessageFn = await listen<string>('ws-message', (event) => {
           try {
             const msg = JSON.parse(event.payload);
@@ -65,6 +92,8 @@ function App() {
           }
         });
 
+        setPairing(await invoke<PairingStatus>('get_pairing_status'));
+
         // Get system info
         const info = await invoke<SystemInfo>('get_system_info');
         setSystemInfo(info);
@@ -93,6 +122,8 @@ function App() {
     return () => {
       if (unlistenConnectedFn) unlistenConnectedFn();
       if (unlistenMessageFn) unlistenMessageFn();
+      if (unlistenReconnectingFn) unlistenReconnectingFn();
+      if (unlistenPairingFn) unlistenPairingFn();
     };
   }, []);
 
@@ -134,7 +165,7 @@ function App() {
   };
 
   const getStatusColor = () => {
-    if (!wsConnected) return 'status-disconnected';
+    if (!wsConnected) return reconnecting ? 'status-pending' : 'status-disconnected';
     switch (braindriveStatus) {
       case 'running': return 'status-running';
       case 'starting':
@@ -144,7 +175,7 @@ function App() {
   };
 
   const getStatusText = () => {
-    if (!wsConnected) return 'Disconnected';
+    if (!wsConnected) return reconnecting ? 'Reconnecting...' : 'Disconnected';
     switch (braindriveStatus) {
       case 'running': return 'Running';
       case 'starting': return 'Starting...';
@@ -170,9 +201,15 @@ function App() {
           <span className="status-dot"></span>
           <span className="status-text">{getStatusText()}</span>
         </div>
-        {wsConnected && (
+        {wsConnected && pairing?.paired && (
           <p className="status-message">Connected to installation server</p>
         )}
+        {wsConnected && pairing && !pairing.paired && (
+          <div className="pairing-panel">
+            <p className="status-message">Enter this code in the installer chat to pair:</p>
+            <p className="pairing-code">{pairing.code}</p>
+          </div>
+        )}
         {!wsConnected && (
           <p className="status-message warning">
             Open braindrive.ai/install to begin
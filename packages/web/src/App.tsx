@@ -27,7 +27,8 @@ interface OllamaInstallPrompt {
 }
 
 type ServerMessage =
-  | { type: 'status_update'; bootstrapper_connected: boolean }
+  | { type: 'status_update'; bootstrapper_connected: boolean; pairing_required?: boolean }
+  | { type: 'pairing_result'; success: boolean; error?: string }
   | { type: 'ai_message'; content: string }
   | { type: 'ai_message_start' }
   | { type: 'ai_message_delta'; content: string }
@@ -54,6 +55,10 @@ function App() {
   const [input, setInput] = useState('');
   const [connectionStatus, setConnectionStatus] = useState<ConnectionStatus>('disconnected');
   const [bootstrapperConnected, setBootstrapperConnected] = useState(false);
+  const [pairingRequired, setPairingRequired] = useState(false);
+  const [pairingCode, setPairingCode] = useState('');
+  const [pairingError, setPairingError] = useState<string | null>(null);
+  const [isPairing, setIsPairing] = useState(false);
   const [isTyping, setIsTyping] = useState(false);
   const [activeProgress, setActiveProgress] = useState<ProgressInfo | null>(null);
   const [ollamaInstallPrompt, setOllamaInstallPrompt] = useState<OllamaInstallPrompt | null>(null);
@@ -94,12 +99,24 @@ function App() {
       case 'status_update': {
         const connected = Boolean(data.bootstrapper_connected);
         setBootstrapperConnected(connected);
+        setPairingRequired(Boolean(data.pairing_required));
         if (connected) {
           addMessage('system', 'Bootstrapper connected! You can now start the installation.');
         }
         break;
       }
 
+      case 'pairing_result': {
+        setIsPairing(false);
+        if (data.success) {
+          setPairingCode('');
+          setPairingError(null);
+        } else {
+          setPairingError(String(data.error ?? 'Pairing failed'));
+        }
+        break;
+      }
+
       case 'ai_message': {
         addMessage('assistant', String(data.content ?? ''));
         break;
@@ -241,6 +258,8 @@ function App() {
     ws.onclose = () => {
       setConnectionStatus('disconnected');
       setBootstrapperConnected(false);
+      setPairingRequired(false);
+      setIsPairing(false);
       addMessage('system', 'Disconnected from server. Reconnecting...');
       setTimeout(() => reconnectRef.current(), 3000);
     };
@@ -277,6 +296,32 @@ function App() {
     }));
   }, [addMessage, connectionStatus]);
 
+  const submitPairingCode = useCallback(() => {
+    const code = pairingCode.replace(/\D/g, '');
+    if (!code || !wsRef.current || connectionStatus !== 'connected') {
+      return;
+    }
+
+    setIsPairing(true);
+    setPairingError(null);
+    wsRef.current.send(JSON.stringify({
+      type: 'pair_bootstrapper',
+      code,
+    }));
+  }, [pairingCode, connectionStatus]);
+
+  const cancelActiveOperation = useCallback(() => {
+    if (!activeProgress || !wsRef.current || connectionStatus !== 'connected') {
+      return;
+    }
+
+    wsRef.current.send(JSON.stringify({
+      type: 'cancel_tool_call',
+      id: activeProgress.id,
+    }));
+    setActiveProgress(null);
+  }, [activeProgress, connectionStatus]);
+
   const sendMessage = useCallback(() => {
     const content = input.trim();
     if (!content) {
@@ -302,7 +347,7 @@ function App() {
   const getStatusText = () => {
     if (connectionStatus === 'disconnected') return 'Disconnected';
     if (connectionStatus === 'connecting') return 'Connecting...';
-    if (!bootstrapperConnected) return 'Waiting for Bootstrapper';
+    if (!bootstrapperConnected) return pairingRequired ? 'Pairing Required' : 'Waiting for Bootstrapper';
     return 'Ready';
   };
 
@@ -319,7 +364,39 @@ function App() {
         </div>
       </header>
 
-      {!bootstrapperConnected && connectionStatus === 'connected' && (
+      {pairingRequired && connectionStatus === 'connected' && (
+        <div className="action-banner">
+          <h3>Pair your bootstrapper</h3>
+          <p>Enter the 6-digit code shown in the BrainDrive Bootstrapper window.</p>
+          <div className="action-buttons">
+            <input
+              className="pairing-input"
+              value={pairingCode}
+              onChange={(e) => setPairingCode(e.target.value)}
+              onKeyDown={(e) => {
+                if (e.key === 'Enter') {
+                  e.preventDefault();
+                  submitPairingCode();
+                }
+              }}
+              placeholder="123456"
+              inputMode="numeric"
+              maxLength={7}
+              disabled={isPairing}
+            />
+            <button
+              className="download-btn"
+              onClick={submitPairingCode}
+              disabled={isPairing || pairingCode.replace(/\D/g, '').length === 0}
+            >
+              {isPairing ? 'Pairing...' : 'Pair'}
+            </button>
+          </div>
+          {pairingError && <p className="pairing-error">{pairingError}</p>}
+        </div>
+      )}
+
+      {!bootstrapperConnected && !pairingRequired && connectionStatus === 'connected' && (
         <div className="download-banner">
           <p>To begin installation, download and open the BrainDrive Bootstrapper:</p>
           <div className="download-buttons">
@@ -374,9 +451,20 @@ function App() {
               <span className="progress-operation">
                 {activeProgress.operation === 'pull_ollama_model' ? 'Downloading Model' : activeProgress.operation}
               </span>
-              {activeProgress.percent !== null && (
-                <span className="progress-percent">{activeProgress.percent}%</span>
-              )}
+              <span className="progress-actions">
+                {activeProgress.percent !== null && (
+                  <span className="progress-percent">{activeProgress.percent}%</span>
+                )}
+                {activeProgress.percent !== 100 && (
+                  <button
+                    className="progress-cancel"
+                    onClick={cancelActiveOperation}
+                    disabled={connectionStatus !== 'connected'}
+                  >
+                    Cancel
+                  </button>
+                )}
+              </span>
             </div>
             <div className="progress-bar-track">
               <div